@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use degiro_rs::{
+    api::{
+        account::AccountInfo,
+        company_ratios::{CurrentRatios, RatiosMap},
+        financial_statements::FinancialReports,
+        portfolio::Portfolio,
+        quotes::Quotes,
+    },
+    client::Client,
+    money::{Currency, Money, MoneyBag},
+    risk::{RiskCalculator, RiskData, RiskPosition},
+    util::Period,
+};
+use serde_json::{json, Value};
+
+fn client() -> Client {
+    Client::new("", "", reqwest::Client::new(), Arc::default())
+}
+
+/// Update endpoint response with `n` product positions and two cash rows.
+fn portfolio_fixture(n: usize) -> Vec<u8> {
+    let row = |name: &str, value: Value| json!({ "name": name, "value": value });
+    let mut positions = (0..n)
+        .map(|i| {
+            let currency = if i % 3 == 0 { "USD" } else { "EUR" };
+            json!({ "value": [
+                row("id", json!((1_000_000 + i).to_string())),
+                row("positionType", json!("PRODUCT")),
+                row("size", json!(10.0 + i as f64)),
+                row("price", json!(100.0 + i as f64 / 10.0)),
+                row("value", json!(1000.0 + i as f64)),
+                row("plBase", json!({ currency: -950.0 - i as f64 })),
+                row("todayPlBase", json!({ currency: -990.0 })),
+                row("portfolioValueCorrection", json!(0.0)),
+                row("breakEvenPrice", json!(95.0)),
+                row("averageFxRate", json!(1.0)),
+                row("realizedProductPl", json!(12.5)),
+                row("realizedFxPl", json!(0.5)),
+                row("todayRealizedProductPl", json!(0.0)),
+                row("todayRealizedFxPl", json!(0.0)),
+            ]})
+        })
+        .collect::<Vec<_>>();
+    for currency in ["EUR", "USD"] {
+        positions.push(json!({ "value": [
+            row("id", json!(currency)),
+            row("positionType", json!("CASH")),
+            row("size", json!(2500.0)),
+            row("price", json!(1.0)),
+            row("value", json!(2500.0)),
+            row("plBase", json!({ currency: -2500.0 })),
+            row("todayPlBase", json!({ currency: -2500.0 })),
+            row("breakEvenPrice", json!(0.0)),
+            row("averageFxRate", json!(1.0)),
+            row("realizedProductPl", json!(0.0)),
+            row("realizedFxPl", json!(0.0)),
+        ]}));
+    }
+    serde_json::to_vec(&json!({ "portfolio": { "value": positions } })).unwrap()
+}
+
+/// Daily chart response with `n` candles.
+fn chart_fixture(n: usize) -> Value {
+    let data = (0..n)
+        .map(|i| {
+            let c = 100.0 + (i as f64 / 7.0).sin() * 10.0;
+            json!([i, c - 0.5, c + 1.0, c - 1.0, c])
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "start": "2020-01-01T00:00:00",
+        "end": "2024-01-01T00:00:00",
+        "series": [{ "data": data }],
+    })
+}
+
+fn ratios_fixture() -> Value {
+    let ids = [
+        "NPRICE",
+        "NHIG",
+        "NLOW",
+        "VOL10DAVG",
+        "MKTCAP",
+        "VOL3MAVG",
+        "BETA",
+        "PR1DAYPRC",
+        "PR13WKPCT",
+        "PR26WKPCT",
+        "PR5DAYPRC",
+    ];
+    let items = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| json!({ "id": id, "name": id, "value": format!("{}.5", i * 10) }))
+        .collect::<Vec<_>>();
+    json!({
+        "currency": "USD",
+        "priceCurrency": "USD",
+        "ratiosGroups": [{ "items": items.clone() }, { "items": items }],
+    })
+}
+
+/// `years` of annual and quarterly statements.
+fn statements_fixture(years: i32) -> Value {
+    let items = |codes: &[&str]| {
+        codes
+            .iter()
+            .map(|code| json!({ "code": code, "meaning": code, "value": 1000.0 }))
+            .collect::<Vec<_>>()
+    };
+    let report = |year: i32, month: u32| {
+        json!({
+            "fiscalYear": year,
+            "endDate": format!("{year}-{month:02}-28"),
+            "statements": [
+                { "type": "INC", "source": "10-K", "periodType": "M", "periodLength": 12,
+                  "items": items(&["SREV", "RTLR", "SCOR", "SGRP", "NINC", "XNEW"]) },
+                { "type": "BAL", "source": "10-K",
+                  "items": items(&["ACAE", "SCSI", "ATCA", "ATOT", "LTLL"]) },
+                { "type": "CAS", "source": "10-K", "periodType": "M", "periodLength": 12,
+                  "items": items(&["ONET", "SDED", "OTLO", "ITLI", "FTLF"]) },
+            ],
+        })
+    };
+    json!({
+        "currency": "USD",
+        "annual": (0..years).map(|y| report(2000 + y, 12)).collect::<Vec<_>>(),
+        "interim": (0..years * 4)
+            .map(|q| report(2000 + q / 4, (q % 4 + 1) as u32 * 3))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// `n` positions over ten sectors, every fourth without volume data.
+fn risk_fixture(n: usize) -> RiskData {
+    RiskData::new(
+        (0..n)
+            .map(|i| RiskPosition {
+                product_id: (1_000_000 + i).to_string(),
+                value: if i % 5 == 0 {
+                    -500.0
+                } else {
+                    1000.0 + i as f64
+                },
+                sector: Some(format!("sector{}", i % 10)),
+                has_event: i % 7 == 0,
+                avg_daily_value: (i % 4 != 0).then_some(50_000.0 * (i + 1) as f64),
+                liquidity_score: Some(0.5),
+            })
+            .collect(),
+    )
+}
+
+fn portfolio(c: &mut Criterion) {
+    let client = client();
+    let body = portfolio_fixture(200);
+    c.bench_function("portfolio/parse_200", |b| {
+        b.iter(|| Portfolio::from_response(black_box(&body), &client).unwrap())
+    });
+
+    let portfolio = Portfolio::from_response(&body, &client).unwrap();
+    c.bench_function("portfolio/value", |b| {
+        b.iter(|| black_box(&portfolio).value())
+    });
+    c.bench_function("portfolio/snapshot_roundtrip", |b| {
+        b.iter(|| {
+            let json = black_box(&portfolio).to_json().unwrap();
+            Portfolio::from_json(&json, client.clone()).unwrap()
+        })
+    });
+    c.bench_function("portfolio/cash_balances", |b| {
+        b.iter(|| black_box(&portfolio).cash_balances()[&Currency::EUR])
+    });
+}
+
+fn candles(c: &mut Criterion) {
+    let body = chart_fixture(5 * 252);
+    c.bench_function("quotes/from_chart_5y_daily", |b| {
+        b.iter(|| Quotes::from_chart("332111", black_box(&body), Period::P1D).unwrap())
+    });
+
+    let quotes = Quotes::from_chart("332111", &body, Period::P1D).unwrap();
+    c.bench_function("quotes/annualized_volatility_5y", |b| {
+        b.iter(|| black_box(&quotes).annualized_volatility())
+    });
+    c.bench_function("quotes/max_drawdown_5y", |b| {
+        b.iter(|| black_box(&quotes).max_drawdown())
+    });
+    c.bench_function("quotes/sharpe_5y", |b| {
+        b.iter(|| black_box(&quotes).sharpe(0.02))
+    });
+}
+
+fn risk(c: &mut Criterion) {
+    let data = risk_fixture(200);
+    let calculator = RiskCalculator::default();
+    c.bench_function("risk/breakdown_200", |b| {
+        b.iter(|| calculator.breakdown(black_box(&data)))
+    });
+}
+
+fn money(c: &mut Criterion) {
+    let currencies = [Currency::EUR, Currency::USD, Currency::GBP];
+    let amounts = (0..1000)
+        .map(|i| Money::new(currencies[i % 3], i as f64 / 4.0))
+        .collect::<Vec<_>>();
+    c.bench_function("money/bag_sum_1000", |b| {
+        b.iter(|| black_box(&amounts).iter().sum::<MoneyBag>())
+    });
+
+    let bag = amounts.iter().sum::<MoneyBag>();
+    let info = serde_json::from_value::<AccountInfo>(json!({
+        "baseCurrency": "EUR",
+        "marginType": "CASH",
+        "currencyPairs": {
+            "EURUSD": { "id": 1, "price": 1.25 },
+            "EURGBP": { "id": 2, "price": 0.85 },
+        },
+    }))
+    .unwrap();
+    c.bench_function("money/bag_total_in", |b| {
+        b.iter(|| black_box(&bag).total_in_with(Currency::EUR, &info).unwrap())
+    });
+}
+
+fn ratios(c: &mut Criterion) {
+    let body = ratios_fixture();
+    c.bench_function("ratios/current_ratios", |b| {
+        b.iter(|| CurrentRatios::from(black_box(&body).clone()))
+    });
+    c.bench_function("ratios/ratios_map", |b| {
+        b.iter(|| RatiosMap::from(black_box(&body)))
+    });
+}
+
+fn statements(c: &mut Criterion) {
+    let body = statements_fixture(10);
+    c.bench_function("financial_statements/from_json_10y", |b| {
+        b.iter(|| FinancialReports::from_json("332111", black_box(&body)).unwrap())
+    });
+}
+
+criterion_group!(benches, portfolio, candles, risk, money, ratios, statements);
+criterion_main!(benches);
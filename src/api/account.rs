@@ -1,10 +1,16 @@
-use chrono::{DateTime, FixedOffset, NaiveDate};
-use std::collections::HashMap;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashMap};
 
 use reqwest::{header, Url};
 use serde::Deserialize;
 
-use crate::client::{Client, ClientError, ClientStatus};
+use crate::{
+    client::{Client, ClientError, ClientStatus},
+    events::ClientEvent,
+    http::{read_json, Data, Endpoint},
+    money::{Currency, Money, MoneyBag},
+    util::Period,
+};
 
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -39,8 +45,170 @@ pub struct AccountData {
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountInfo {
+    #[serde(default)]
+    pub client_id: Option<i64>,
     pub base_currency: String,
     pub margin_type: String,
+    /// FX pairs keyed by their code, e.g. `EURUSD`.
+    #[serde(default)]
+    pub currency_pairs: HashMap<String, CurrencyPair>,
+    /// Money market funds cash can be swept into, per currency.
+    #[serde(default)]
+    pub cash_funds: HashMap<String, Vec<CashFundInfo>>,
+    /// Maximum loss the account may run into before positions get closed.
+    #[serde(default, deserialize_with = "opt_f64_from_str_or_number")]
+    pub compensation_capping: Option<f64>,
+    /// Order types the account may use, by their api name.
+    #[serde(default)]
+    pub allowed_order_types: Vec<String>,
+    /// Margin parameters and anything else not modeled above, as sent.
+    #[serde(flatten)]
+    pub extras: HashMap<String, serde_json::Value>,
+    /// When the pair prices were read, by the client clock.
+    #[serde(skip)]
+    pub fetched_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CashFundInfo {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub product_ids: Vec<i64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CurrencyPair {
+    pub id: i64,
+    #[serde(deserialize_with = "f64_from_str_or_number")]
+    pub price: f64,
+}
+
+fn f64_from_str_or_number<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(x) => x
+            .as_f64()
+            .ok_or_else(|| serde::de::Error::custom("invalid number")),
+        serde_json::Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+        x => Err(serde::de::Error::custom(format!(
+            "expected number, got {x}"
+        ))),
+    }
+}
+
+fn opt_f64_from_str_or_number<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Null => Ok(None),
+        x => f64_from_str_or_number(x)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateDerivation {
+    Identity,
+    Direct,
+    Inverse,
+    Triangulated { via: Currency },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateInfo {
+    pub from: Currency,
+    pub to: Currency,
+    /// Units of `to` per one unit of `from`.
+    pub rate: f64,
+    /// Pair codes the rate was computed from.
+    pub pairs: Vec<String>,
+    pub derivation: RateDerivation,
+    /// When the pair prices were read, see [`AccountInfo::fetched_at`].
+    pub timestamp: DateTime<Utc>,
+}
+
+impl AccountInfo {
+    /// Margin related values not modeled as fields, e.g. `marginParameters`.
+    pub fn margin_parameters(&self) -> HashMap<&str, &serde_json::Value> {
+        self.extras
+            .iter()
+            .filter(|(k, _)| k.to_lowercase().contains("margin"))
+            .map(|(k, v)| (k.as_str(), v))
+            .collect()
+    }
+
+    /// Ids of the cash fund products held for `currency`.
+    pub fn cash_fund_ids(&self, currency: Currency) -> Vec<i64> {
+        self.cash_funds
+            .get(&currency.to_string())
+            .map(|xs| xs.iter().map(|x| x.id).collect())
+            .unwrap_or_default()
+    }
+    pub fn base_currency(&self) -> Result<Currency, ClientError> {
+        self.base_currency
+            .parse()
+            .map_err(|_| ClientError::ParseError(format!("currency {}", self.base_currency)))
+    }
+
+    /// Converts `money` with the current pair prices.
+    pub fn convert(&self, money: Money, to: Currency) -> Result<Money, ClientError> {
+        let info = self.rate_info(money.currency, to).ok_or_else(|| {
+            ClientError::Descripted(format!("no FX pair for {}/{to}", money.currency))
+        })?;
+        Ok(Money::new(to, money.amount * info.rate))
+    }
+
+    fn pair_rate(&self, from: Currency, to: Currency) -> Option<(f64, String, bool)> {
+        let direct = format!("{from}{to}");
+        if let Some(pair) = self.currency_pairs.get(&direct) {
+            return Some((pair.price, direct, false));
+        }
+        let inverse = format!("{to}{from}");
+        let pair = self.currency_pairs.get(&inverse)?;
+        (pair.price != 0.0).then(|| (1.0 / pair.price, inverse, true))
+    }
+
+    /// Resolves `from -> to` using a direct, inverse or EUR/USD triangulated pair.
+    pub fn rate_info(&self, from: Currency, to: Currency) -> Option<RateInfo> {
+        let info = |rate, pairs, derivation| RateInfo {
+            from,
+            to,
+            rate,
+            pairs,
+            derivation,
+            timestamp: self.fetched_at,
+        };
+        if from == to {
+            return Some(info(1.0, Vec::new(), RateDerivation::Identity));
+        }
+        if let Some((rate, pair, inverse)) = self.pair_rate(from, to) {
+            let derivation = if inverse {
+                RateDerivation::Inverse
+            } else {
+                RateDerivation::Direct
+            };
+            return Some(info(rate, vec![pair], derivation));
+        }
+        [Currency::EUR, Currency::USD]
+            .into_iter()
+            .filter(|via| *via != from && *via != to)
+            .find_map(|via| {
+                let (a, pa, _) = self.pair_rate(from, via)?;
+                let (b, pb, _) = self.pair_rate(via, to)?;
+                Some(info(
+                    a * b,
+                    vec![pa, pb],
+                    RateDerivation::Triangulated { via },
+                ))
+            })
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -64,6 +232,60 @@ pub struct BankAccount {
     pub status: String,
 }
 
+impl BankAccount {
+    pub fn status(&self) -> BankAccountStatus {
+        BankAccountStatus::from(self.status.as_str())
+    }
+
+    pub fn has_valid_iban(&self) -> bool {
+        is_valid_iban(&self.iban)
+    }
+}
+
+/// Verification state of the linked bank account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BankAccountStatus {
+    Verified,
+    /// Waiting for the first deposit or a document check.
+    Pending,
+    Other(String),
+}
+
+impl From<&str> for BankAccountStatus {
+    fn from(s: &str) -> Self {
+        match s.to_uppercase().as_str() {
+            "VERIFIED" => BankAccountStatus::Verified,
+            "PENDING" | "UNVERIFIED" | "NOT_VERIFIED" => BankAccountStatus::Pending,
+            _ => BankAccountStatus::Other(s.to_string()),
+        }
+    }
+}
+
+/// Checks the country code, length and ISO 13616 checksum of an IBAN,
+/// ignoring spaces and case.
+pub fn is_valid_iban(iban: &str) -> bool {
+    let iban = iban
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<Vec<_>>();
+    if !(15..=34).contains(&iban.len())
+        || !iban[..2].iter().all(char::is_ascii_uppercase)
+        || !iban[2..4].iter().all(char::is_ascii_digit)
+        || !iban.iter().all(char::is_ascii_alphanumeric)
+    {
+        return false;
+    }
+    // Country and check digits move to the end, letters count as 10..=35.
+    let mut rest = 0u32;
+    for c in iban[4..].iter().chain(&iban[..4]) {
+        let x = c.to_digit(36).unwrap();
+        let shift = if x < 10 { 10 } else { 100 };
+        rest = (rest * shift + x) % 97;
+    }
+    rest == 1
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -85,6 +307,38 @@ pub struct FlatexBankAccount {
     pub iban: String,
 }
 
+impl FlatexBankAccount {
+    pub fn has_valid_iban(&self) -> bool {
+        is_valid_iban(&self.iban)
+    }
+}
+
+impl AccountData {
+    /// The linked bank account, once it is verified, has a valid IBAN and
+    /// withdrawals are open; check it before moving money out.
+    pub fn withdrawal_account(&self) -> Result<&BankAccount, ClientError> {
+        let account = &self.bank_account;
+        if !self.is_withdrawal_available {
+            return Err(ClientError::Descripted(
+                "withdrawals are not available".to_string(),
+            ));
+        }
+        if account.status() != BankAccountStatus::Verified {
+            return Err(ClientError::Descripted(format!(
+                "bank account is not verified: {}",
+                account.status
+            )));
+        }
+        if !account.has_valid_iban() {
+            return Err(ClientError::Descripted(format!(
+                "invalid IBAN: {}",
+                account.iban
+            )));
+        }
+        Ok(account)
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountConfig {
@@ -134,6 +388,7 @@ pub struct AccountConfig {
 
 impl Client {
     pub async fn account_config(&self) -> Result<(), ClientError> {
+        self.require(Endpoint::AccountConfig)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = "https://trader.degiro.nl/";
@@ -149,49 +404,185 @@ impl Client {
                 .header(header::REFERER, &inner.referer)
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
+        let res = self.send_once(Endpoint::AccountConfig, req).await?;
+
+        let mut body = res.json::<HashMap<String, AccountConfig>>().await?;
+        let data = body
+            .remove("data")
+            .ok_or(ClientError::ParseError("account config data".to_string()))?;
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.client_id = data.client_id;
+            inner.account_config = data;
+            inner.status = ClientStatus::Authorized;
         };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut body = res
-                    .json::<HashMap<String, AccountConfig>>()
-                    .await
-                    .expect("can't parse json data");
-                let data = body.remove("data").expect("data key not found");
-                {
-                    let mut inner = self.inner.lock().unwrap();
-                    inner.client_id = data.client_id;
-                    inner.account_config = data;
-                    inner.status = ClientStatus::Authorized;
-                };
-                let account_data = self.account_data().await.unwrap();
-                {
-                    let mut inner = self.inner.lock().unwrap();
-                    inner.int_account = account_data.int_account;
+        let account_data = match self.account_data().await {
+            Ok(x) => x,
+            Err(err) => {
+                // Without account data the session isn't usable yet.
+                let mut inner = self.inner.lock().unwrap();
+                if inner.status == ClientStatus::Authorized {
+                    inner.status = ClientStatus::Restricted;
                 }
-                Ok(())
+                return Err(err);
+            }
+        };
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.int_account = account_data.int_account;
+        }
+        self.emit(ClientEvent::Authorized);
+        Ok(())
+    }
+}
+
+impl Client {
+    pub async fn exchange_rate(
+        &self,
+        from: Currency,
+        to: Currency,
+    ) -> Result<RateInfo, ClientError> {
+        if from == to {
+            let info = AccountInfo {
+                fetched_at: self.now(),
+                ..Default::default()
+            };
+            return Ok(info
+                .rate_info(from, to)
+                .expect("identity rate always exists"));
+        }
+        self.account_info()
+            .await?
+            .rate_info(from, to)
+            .ok_or_else(|| ClientError::Descripted(format!("no FX pair for {from}/{to}")))
+    }
+}
+
+/// Close of the last candle on or before `date`, weekends fall back to Friday.
+fn close_on(history: &BTreeMap<NaiveDate, f64>, date: NaiveDate) -> Option<f64> {
+    let (last, _) = history.last_key_value()?;
+    if *last < date {
+        return None;
+    }
+    history.range(..=date).next_back().map(|(_, x)| *x)
+}
+
+/// Shortest chart period reaching back from `today` to `date`.
+pub(crate) fn history_period(today: NaiveDate, date: NaiveDate) -> Period {
+    let age = (today - date).num_milliseconds().max(0) as u64;
+    [
+        Period::P1M,
+        Period::P3M,
+        Period::P6M,
+        Period::P1Y,
+        Period::P3Y,
+        Period::P5Y,
+    ]
+    .into_iter()
+    .find(|p| p.to_ms() > age)
+    .unwrap_or(Period::P50Y)
+}
+
+impl Client {
+    async fn pair_close_on(
+        &self,
+        pair: &str,
+        id: i64,
+        date: NaiveDate,
+    ) -> Result<f64, ClientError> {
+        {
+            let inner = self.inner.lock().unwrap();
+            if let Some(x) = inner.fx_history.get(pair).and_then(|h| close_on(h, date)) {
+                return Ok(x);
             }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => {
-                    self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
+        }
+
+        let period = history_period(self.now().date_naive(), date);
+        let quotes = self.quotes(&id.to_string(), period, Period::P1D).await?;
+
+        let mut inner = self.inner.lock().unwrap();
+        let history = inner.fx_history.entry(pair.to_string()).or_default();
+        for (time, close) in quotes.time.iter().zip(&quotes.close) {
+            history.insert(time.date_naive(), *close);
+        }
+        // Fetched quotes run up to today, so the last close before `date` is the right one.
+        history
+            .range(..=date)
+            .next_back()
+            .map(|(_, x)| *x)
+            .ok_or(ClientError::NoData)
+    }
+
+    /// Rate at the daily close of `date`, resolved like [`Client::exchange_rate`].
+    pub async fn historical_rate(
+        &self,
+        from: Currency,
+        to: Currency,
+        date: NaiveDate,
+    ) -> Result<f64, ClientError> {
+        let account_info = self.account_info().await?;
+        self.historical_rate_with(&account_info, from, to, date)
+            .await
+    }
+
+    /// [`Client::historical_rate`] with the FX pairs of `account_info`.
+    pub(crate) async fn historical_rate_with(
+        &self,
+        account_info: &AccountInfo,
+        from: Currency,
+        to: Currency,
+        date: NaiveDate,
+    ) -> Result<f64, ClientError> {
+        let info = account_info
+            .rate_info(from, to)
+            .ok_or_else(|| ClientError::Descripted(format!("no FX pair for {from}/{to}")))?;
+        let pairs = &account_info.currency_pairs;
+        let mut currency = from;
+        let mut rate = 1.0;
+        for code in &info.pairs {
+            let pair = &pairs[code];
+            let close = self.pair_close_on(code, pair.id, date).await?;
+            if code.starts_with(&currency.to_string()) {
+                rate *= close;
+                currency = code[3..]
+                    .parse()
+                    .map_err(|_| ClientError::ParseError(code.clone()))?;
+            } else {
+                if close == 0.0 {
+                    return Err(ClientError::NoData);
                 }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
+                rate /= close;
+                currency = code[..3]
+                    .parse()
+                    .map_err(|_| ClientError::ParseError(code.clone()))?;
+            }
         }
+        Ok(rate)
+    }
+}
+
+impl Money {
+    /// Converts at the historical daily close rate of `date`.
+    pub async fn convert_to_at(
+        &self,
+        to: Currency,
+        date: NaiveDate,
+        client: &Client,
+    ) -> Result<Money, ClientError> {
+        let rate = client.historical_rate(self.currency, to, date).await?;
+        Ok(Money::new(to, self.amount * rate))
+    }
+
+    /// Converts at the current rate from the account FX pairs.
+    pub async fn convert_to(&self, to: Currency, client: &Client) -> Result<Money, ClientError> {
+        let info = client.exchange_rate(self.currency, to).await?;
+        Ok(Money::new(to, self.amount * info.rate))
     }
 }
 
 impl Client {
     pub async fn account_data(&self) -> Result<AccountData, ClientError> {
+        self.require(Endpoint::AccountData)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = &inner.account_config.pa_url;
@@ -203,43 +594,23 @@ impl Client {
             inner
                 .http_client
                 .get(url)
-                .query(&[("sessionId", &inner.session_id)])
                 .header(header::REFERER, &inner.referer)
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
+        let res = self.send_once(Endpoint::AccountData, req).await?;
 
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut body = res
-                    .json::<HashMap<String, AccountData>>()
-                    .await
-                    .expect("can't parse json data");
-                let account = body.remove("data").expect("data key not found");
+        let mut body = res.json::<HashMap<String, AccountData>>().await?;
+        let account = body
+            .remove("data")
+            .ok_or(ClientError::ParseError("account data".to_string()))?;
 
-                Ok(account)
-            }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => {
-                    self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
-                }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
-        }
+        Ok(account)
     }
 }
 
 impl Client {
     pub async fn account_info(&self) -> Result<AccountInfo, ClientError> {
+        self.require(Endpoint::AccountInfo)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = &inner.account_config.trading_url;
@@ -255,55 +626,63 @@ impl Client {
             inner
                 .http_client
                 .get(url)
-                .query(&[("sessionId", &inner.session_id)])
                 .header(header::REFERER, &inner.referer)
         };
 
-        let res = req
-            .send()
+        let res = self.send(Endpoint::AccountInfo, req).await?;
+
+        let mut body = res
+            .json::<HashMap<String, AccountInfo>>()
             .await
-            .map_err(|err| ClientError::UnexpectedError {
-                source: Box::new(err),
-            })?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut body = res
-                    .json::<HashMap<String, AccountInfo>>()
-                    .await
-                    .expect("can't parse json data");
-                let info = body.remove("data").expect("data key not found");
-                Ok(info)
+            .expect("can't parse json data");
+        let mut info = body.remove("data").expect("data key not found");
+        info.fetched_at = self.now();
+        if let Ok(currency) = info.base_currency() {
+            self.observe_base_currency(currency);
+        }
+        Ok(info)
+    }
+
+    /// Remembers the base currency; when it differs from the last one the
+    /// FX history, whose pairs were picked for the old base, is dropped and
+    /// [`ClientEvent::BaseCurrencyChanged`] is sent.
+    fn observe_base_currency(&self, currency: Currency) {
+        let previous = {
+            let mut inner = self.inner.lock().unwrap();
+            let previous = inner.base_currency.replace(currency);
+            if previous.is_some_and(|x| x != currency) {
+                inner.fx_history.clear();
             }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => {
-                    self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
-                }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
+            previous
+        };
+        if let Some(from) = previous.filter(|x| *x != currency) {
+            self.emit(ClientEvent::BaseCurrencyChanged { from, to: currency });
         }
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CashMovement {
-    balance: Balance,
-    change: f64,
-    currency: String,
-    date: DateTime<FixedOffset>,
+    pub balance: Balance,
+    pub change: f64,
+    pub currency: String,
+    pub date: DateTime<FixedOffset>,
     #[serde(rename = "description")]
-    movement_type: CashMovementType,
-    id: i32,
-    order_id: Option<String>,
-    product_id: Option<i32>,
+    pub movement_type: CashMovementType,
+    pub id: i32,
+    pub order_id: Option<String>,
+    pub product_id: Option<i32>,
     #[serde(rename = "type")]
-    transaction_type: TransactionType,
-    value_date: DateTime<FixedOffset>,
+    pub transaction_type: TransactionType,
+    pub value_date: DateTime<FixedOffset>,
+}
+
+impl CashMovement {
+    /// The change as money, `None` for currencies not modelled by [`Currency`].
+    pub fn money(&self) -> Option<Money> {
+        Some(Money::new(self.currency.parse().ok()?, self.change))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -314,6 +693,7 @@ pub enum CashMovementType {
     DividentFee(String),
     FxCredit(String),
     Interest(String),
+    CashSweep(String),
     BankWithdrawal(String),
     Deposit(String),
     TransactionFee(String),
@@ -336,25 +716,45 @@ pub enum TransactionType {
     Payment,
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Balance {
-    cash_fund: Option<Vec<CashFund>>,
-    total: f64,
-    unsettled_cash: f64,
+    pub cash_fund: Option<Vec<CashFund>>,
+    pub total: f64,
+    pub unsettled_cash: f64,
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct CashFund {
-    id: i32,
-    participation: f64,
-    price: f64,
+    pub id: i32,
+    pub participation: f64,
+    pub price: f64,
 }
 
 pub struct ParseMovementTypeError;
 
+impl CashMovementType {
+    /// Description as booked by DEGIRO.
+    pub fn description(&self) -> &str {
+        match self {
+            CashMovementType::Dividend(s)
+            | CashMovementType::FxWithdrawal(s)
+            | CashMovementType::DividentFee(s)
+            | CashMovementType::FxCredit(s)
+            | CashMovementType::Interest(s)
+            | CashMovementType::CashSweep(s)
+            | CashMovementType::BankWithdrawal(s)
+            | CashMovementType::Deposit(s)
+            | CashMovementType::TransactionFee(s)
+            | CashMovementType::TransactionSell(s)
+            | CashMovementType::TransactionBuy(s)
+            | CashMovementType::UnknownFee(s)
+            | CashMovementType::UnknownInteres(s)
+            | CashMovementType::Unknown(s) => s,
+        }
+    }
+}
+
 impl From<String> for CashMovementType {
     fn from(s: String) -> Self {
         if s == "Dywidenda" {
@@ -367,6 +767,12 @@ impl From<String> for CashMovementType {
             CashMovementType::FxCredit(s)
         } else if s == "Odsetki" {
             CashMovementType::Interest(s)
+        } else if s.to_lowercase().contains("cash sweep") || s.to_lowercase().contains("flatex") {
+            if s.to_lowercase().contains("interest") || s.to_lowercase().contains("odsetki") {
+                CashMovementType::Interest(s)
+            } else {
+                CashMovementType::CashSweep(s)
+            }
         } else if s == "Wypłata" {
             CashMovementType::BankWithdrawal(s)
         } else if s == "Depozyt" {
@@ -390,12 +796,291 @@ impl From<String> for CashMovementType {
 #[derive(Debug)]
 pub struct AccountState(Vec<CashMovement>);
 
+/// Borrowed selection of an [`AccountState`]'s movements; filters chain
+/// without copying the movements.
+#[derive(Clone, Debug)]
+pub struct CashMovementsView<'a>(Vec<&'a CashMovement>);
+
+impl<'a> CashMovementsView<'a> {
+    pub fn iter(&self) -> impl Iterator<Item = &'a CashMovement> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn filter(&self, f: impl Fn(&CashMovement) -> bool) -> Self {
+        Self(self.0.iter().copied().filter(|m| f(m)).collect())
+    }
+
+    /// Movements booked between `from` and `to`, both inclusive.
+    pub fn between(&self, from: NaiveDate, to: NaiveDate) -> Self {
+        self.filter(|m| (from..=to).contains(&m.date.date_naive()))
+    }
+
+    /// Movements of the same kind as `kind`; the description it carries is ignored.
+    pub fn by_type(&self, kind: CashMovementType) -> Self {
+        let kind = std::mem::discriminant(&kind);
+        self.filter(|m| std::mem::discriminant(&m.movement_type) == kind)
+    }
+
+    pub fn by_product(&self, product_id: i32) -> Self {
+        self.filter(|m| m.product_id == Some(product_id))
+    }
+
+    /// Sum of the changes per currency.
+    pub fn total(&self) -> MoneyBag {
+        self.iter().filter_map(CashMovement::money).collect()
+    }
+
+    /// Cash balance of every currency at the end of `date`, from the last
+    /// movement booked on or before it.
+    pub fn balances_per_currency_at(&self, date: NaiveDate) -> HashMap<Currency, Money> {
+        let mut last: HashMap<Currency, &CashMovement> = HashMap::new();
+        for m in self.iter().filter(|m| m.date.date_naive() <= date) {
+            let Ok(currency) = m.currency.parse() else {
+                continue;
+            };
+            let entry = last.entry(currency).or_insert(m);
+            if (m.date, m.id) > (entry.date, entry.id) {
+                *entry = m;
+            }
+        }
+        last.into_iter()
+            .map(|(currency, m)| (currency, Money::new(currency, m.balance.total)))
+            .collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a AccountState {
+    type Item = &'a CashMovement;
+    type IntoIter = std::slice::Iter<'a, CashMovement>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CashMovements {
+    #[serde(default)]
+    cash_movements: Vec<CashMovement>,
+}
+
+/// Interest booked in a single month and currency.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MonthlyAccrual {
+    pub interest: f64,
+    /// Net amount moved into (positive) or out of (negative) the cash sweep fund.
+    pub sweep: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InterestReport {
+    /// Keyed by `(year, month, currency)`.
+    pub accruals: BTreeMap<(i32, u32, String), MonthlyAccrual>,
+}
+
+impl InterestReport {
+    pub fn total_interest(&self) -> HashMap<String, f64> {
+        let mut totals = HashMap::new();
+        for ((_, _, currency), accrual) in &self.accruals {
+            *totals.entry(currency.clone()).or_default() += accrual.interest;
+        }
+        totals
+    }
+
+    pub fn month(&self, year: i32, month: u32) -> HashMap<String, MonthlyAccrual> {
+        self.accruals
+            .iter()
+            .filter(|((y, m, _), _)| *y == year && *m == month)
+            .map(|((_, _, currency), accrual)| (currency.clone(), accrual.clone()))
+            .collect()
+    }
+}
+
+/// Kind of trading cost booked on the account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FeeKind {
+    Transaction,
+    /// Yearly exchange connectivity fees.
+    Connectivity,
+    /// AutoFX conversion costs.
+    FxMarkup,
+    Other,
+}
+
+impl FeeKind {
+    /// Kind of fee `movement` books, `None` when it isn't one. Dividend tax
+    /// isn't counted as a trading cost.
+    pub fn of(movement: &CashMovement) -> Option<Self> {
+        let description = movement.movement_type.description().to_lowercase();
+        let any = |words: &[&str]| words.iter().any(|w| description.contains(w));
+        match movement.movement_type {
+            CashMovementType::TransactionFee(_) => Some(FeeKind::Transaction),
+            _ if any(&["autofx", "auto fx"]) => Some(FeeKind::FxMarkup),
+            _ if any(&["connectivity", "łączno", "giełd"]) => Some(FeeKind::Connectivity),
+            CashMovementType::UnknownFee(_) => Some(FeeKind::Other),
+            _ => None,
+        }
+    }
+}
+
+/// Fees paid, positive for costs and negative for refunds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeeTotals {
+    pub transaction: f64,
+    pub connectivity: f64,
+    pub fx_markup: f64,
+    pub other: f64,
+}
+
+impl FeeTotals {
+    fn add(&mut self, kind: FeeKind, amount: f64) {
+        match kind {
+            FeeKind::Transaction => self.transaction += amount,
+            FeeKind::Connectivity => self.connectivity += amount,
+            FeeKind::FxMarkup => self.fx_markup += amount,
+            FeeKind::Other => self.other += amount,
+        }
+    }
+
+    pub fn total(&self) -> f64 {
+        self.transaction + self.connectivity + self.fx_markup + self.other
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeesSummary {
+    /// Keyed by `(year, month, currency)`.
+    pub per_month: BTreeMap<(i32, u32, String), FeeTotals>,
+    /// Keyed by `(product_id, currency)`, for fees booked against a product.
+    pub per_product: BTreeMap<(i32, String), FeeTotals>,
+}
+
+impl FeesSummary {
+    pub fn total(&self) -> HashMap<String, FeeTotals> {
+        let mut totals: HashMap<String, FeeTotals> = HashMap::new();
+        for ((_, _, currency), fees) in &self.per_month {
+            let total = totals.entry(currency.clone()).or_default();
+            total.transaction += fees.transaction;
+            total.connectivity += fees.connectivity;
+            total.fx_markup += fees.fx_markup;
+            total.other += fees.other;
+        }
+        totals
+    }
+}
+
+impl AccountState {
+    pub fn iter(&self) -> std::slice::Iter<'_, CashMovement> {
+        self.0.iter()
+    }
+
+    pub fn into_inner(self) -> Vec<CashMovement> {
+        self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn view(&self) -> CashMovementsView<'_> {
+        CashMovementsView(self.0.iter().collect())
+    }
+
+    pub fn between(&self, from: NaiveDate, to: NaiveDate) -> CashMovementsView<'_> {
+        self.view().between(from, to)
+    }
+
+    pub fn by_type(&self, kind: CashMovementType) -> CashMovementsView<'_> {
+        self.view().by_type(kind)
+    }
+
+    pub fn by_product(&self, product_id: i32) -> CashMovementsView<'_> {
+        self.view().by_product(product_id)
+    }
+
+    pub fn balances_per_currency_at(&self, date: NaiveDate) -> HashMap<Currency, Money> {
+        self.view().balances_per_currency_at(date)
+    }
+
+    /// Unsettled cash as reported by the most recent movement.
+    pub fn unsettled_cash(&self) -> Option<Money> {
+        let last = self.0.iter().max_by_key(|m| (m.date, m.id))?;
+        let currency = last.currency.parse().ok()?;
+        Some(Money::new(currency, last.balance.unsettled_cash))
+    }
+
+    pub fn interest_report(&self) -> InterestReport {
+        let mut report = InterestReport::default();
+        for movement in &self.0 {
+            let is_interest = matches!(
+                movement.movement_type,
+                CashMovementType::Interest(_) | CashMovementType::UnknownInteres(_)
+            );
+            let is_sweep = matches!(movement.movement_type, CashMovementType::CashSweep(_));
+            if !is_interest && !is_sweep {
+                continue;
+            }
+            let date = movement.value_date;
+            let accrual = report
+                .accruals
+                .entry((date.year(), date.month(), movement.currency.clone()))
+                .or_default();
+            if is_interest {
+                accrual.interest += movement.change;
+            } else {
+                // Money leaving the cash balance lands in the sweep fund.
+                accrual.sweep -= movement.change;
+            }
+        }
+        report
+    }
+
+    /// Fees per month and per product, in the currency they were booked in.
+    pub fn fees_summary(&self) -> FeesSummary {
+        let mut summary = FeesSummary::default();
+        for movement in &self.0 {
+            let Some(kind) = FeeKind::of(movement) else {
+                continue;
+            };
+            // Fees are booked as negative changes.
+            let amount = -movement.change;
+            let date = movement.date;
+            summary
+                .per_month
+                .entry((date.year(), date.month(), movement.currency.clone()))
+                .or_default()
+                .add(kind, amount);
+            if let Some(product_id) = movement.product_id {
+                summary
+                    .per_product
+                    .entry((product_id, movement.currency.clone()))
+                    .or_default()
+                    .add(kind, amount);
+            }
+        }
+        summary
+    }
+}
+
 impl Client {
     pub async fn account_state(
         &self,
         from_date: &NaiveDate,
         to_date: &NaiveDate,
     ) -> Result<AccountState, ClientError> {
+        self.require(Endpoint::AccountState)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = &inner.account_config.reporting_url;
@@ -407,46 +1092,114 @@ impl Client {
                 .http_client
                 .get(url)
                 .query(&[
-                    ("sessionId", &inner.session_id),
-                    ("intAccount", &format!("{}", inner.int_account)),
                     ("fromDate", &from_date.format("%d/%m/%Y").to_string()),
                     ("toDate", &to_date.format("%d/%m/%Y").to_string()),
                 ])
                 .header(header::REFERER, &inner.referer)
         };
 
-        let res = req
-            .send()
-            .await
-            // TODO:
-            .map_err(|err| ClientError::UnexpectedError {
-                source: Box::new(err),
-            })?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut body = res
-                    .json::<HashMap<String, HashMap<String, Vec<CashMovement>>>>()
-                    .await
-                    .unwrap();
-                let mut data = body.remove("data").unwrap();
-                let state = data.remove("cashMovements").unwrap();
-                Ok(AccountState(state))
-            }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => Err(ClientError::Unauthorized),
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
+        let res = self.send(Endpoint::AccountState, req).await?;
+
+        let Data { data } = read_json::<Data<CashMovements>>(res).await?;
+        Ok(AccountState(data.cash_movements))
+    }
+
+    pub async fn interest_report(
+        &self,
+        from_date: &NaiveDate,
+        to_date: &NaiveDate,
+    ) -> Result<InterestReport, ClientError> {
+        Ok(self
+            .account_state(from_date, to_date)
+            .await?
+            .interest_report())
+    }
+
+    pub async fn fees_summary(
+        &self,
+        from_date: &NaiveDate,
+        to_date: &NaiveDate,
+    ) -> Result<FeesSummary, ClientError> {
+        Ok(self.account_state(from_date, to_date).await?.fees_summary())
+    }
+}
+
+/// Cash movements as `accountoverview` sends them, for tests across the
+/// crate.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::CashMovement;
+
+    /// EUR movement booked at 10:00 on `day` (`YYYY-MM-DD`), without a
+    /// product and with a zero balance.
+    pub(crate) fn movement(id: i32, day: &str, description: &str, change: f64) -> CashMovement {
+        let date = format!("{day}T10:00:00+01:00");
+        serde_json::from_value(serde_json::json!({
+            "balance": {"total": 0.0, "unsettledCash": 0.0},
+            "change": change,
+            "currency": "EUR",
+            "date": date,
+            "description": description,
+            "id": id,
+            "type": "CASH_TRANSACTION",
+            "valueDate": date,
+        }))
+        .unwrap()
+    }
+
+    impl CashMovement {
+        pub(crate) fn with_total(mut self, total: f64) -> Self {
+            self.balance.total = total;
+            self
+        }
+
+        pub(crate) fn with_product(mut self, product_id: i32) -> Self {
+            self.product_id = Some(product_id);
+            self
+        }
+
+        pub(crate) fn with_currency(mut self, currency: &str) -> Self {
+            self.currency = currency.to_string();
+            self
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::fixtures::movement;
     use super::*;
 
+    #[test]
+    fn base_currency_change_drops_rates() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let mut events = client.events();
+        let fill = || {
+            client
+                .inner
+                .lock()
+                .unwrap()
+                .fx_history
+                .insert("EURUSD".to_string(), BTreeMap::new());
+        };
+
+        fill();
+        client.observe_base_currency(Currency::EUR);
+        client.observe_base_currency(Currency::EUR);
+        assert!(events.try_recv().is_err());
+        assert_eq!(client.inner.lock().unwrap().fx_history.len(), 1);
+
+        client.observe_base_currency(Currency::USD);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ClientEvent::BaseCurrencyChanged {
+                from: Currency::EUR,
+                to: Currency::USD
+            }
+        );
+        assert!(client.inner.lock().unwrap().fx_history.is_empty());
+    }
+
     #[tokio::test]
     async fn account_data() {
         let client = Client::new_from_env();
@@ -482,4 +1235,182 @@ mod test {
             .unwrap();
         dbg!(state);
     }
+
+    #[test]
+    fn monthly_interest_report() {
+        let movements = vec![
+            movement(1, "2024-01-31", "Odsetki", 1.5),
+            movement(2, "2024-01-15", "Degiro Cash Sweep Transfer", -100.0),
+            movement(3, "2024-02-29", "Flatex Interest Income", 2.0),
+            movement(4, "2024-02-10", "Dywidenda", 10.0),
+        ];
+        let report = AccountState(movements).interest_report();
+        let jan = report.month(2024, 1);
+        assert_eq!(jan["EUR"].interest, 1.5);
+        assert_eq!(jan["EUR"].sweep, 100.0);
+        assert_eq!(report.total_interest()["EUR"], 3.5);
+    }
+
+    #[test]
+    fn account_state_views() {
+        let state = AccountState(vec![
+            movement(1, "2024-01-02", "Depozyt", 1000.0).with_total(1000.0),
+            movement(2, "2024-02-10", "Dywidenda", 10.0)
+                .with_total(1010.0)
+                .with_product(332111),
+            movement(3, "2024-02-10", "Podatek Dywidendowy", -1.5).with_total(1008.5),
+            movement(4, "2024-03-01", "Dywidenda", 4.0)
+                .with_total(4.0)
+                .with_product(332111)
+                .with_currency("USD"),
+        ]);
+        let date = |m| NaiveDate::from_ymd_opt(2024, m, 10).unwrap();
+
+        let feb = state.between(date(2), date(2));
+        assert_eq!(feb.len(), 2);
+        let dividends = state.by_type(CashMovementType::Dividend(String::new()));
+        assert_eq!(dividends.len(), 2);
+        assert_eq!(dividends.between(date(1), date(2)).len(), 1);
+        assert_eq!(state.by_product(332111).len(), 2);
+        assert_eq!(
+            dividends.total().get(Currency::USD),
+            Money::new(Currency::USD, 4.0)
+        );
+
+        let balances = state.balances_per_currency_at(date(2));
+        assert_eq!(balances[&Currency::EUR], Money::new(Currency::EUR, 1008.5));
+        assert!(!balances.contains_key(&Currency::USD));
+        assert_eq!(state.balances_per_currency_at(date(3)).len(), 2);
+    }
+
+    #[test]
+    fn fees_summary() {
+        let state = AccountState(vec![
+            movement(1, "2024-01-05", "Opłata transakcyjna", -2.0).with_product(332111),
+            movement(2, "2024-01-05", "AutoFX Fee", -0.5).with_product(332111),
+            movement(
+                3,
+                "2024-01-31",
+                "Opłata za łączność z giełdą 2024 (Xetra)",
+                -2.5,
+            ),
+            movement(4, "2024-02-10", "Podatek Dywidendowy", -1.5),
+            movement(5, "2024-02-12", "Some Fee", -1.0),
+        ]);
+        let summary = state.fees_summary();
+        let jan = &summary.per_month[&(2024, 1, "EUR".to_string())];
+        assert_eq!(
+            jan,
+            &FeeTotals {
+                transaction: 2.0,
+                connectivity: 2.5,
+                fx_markup: 0.5,
+                other: 0.0,
+            }
+        );
+        assert_eq!(summary.per_month[&(2024, 2, "EUR".to_string())].other, 1.0);
+        assert_eq!(
+            summary.per_product[&(332111, "EUR".to_string())].total(),
+            2.5
+        );
+        assert_eq!(summary.total()["EUR"].total(), 6.0);
+    }
+
+    #[test]
+    fn iban_checksum() {
+        assert!(is_valid_iban("DE89 3704 0044 0532 0130 00"));
+        assert!(is_valid_iban("gb82west12345698765432"));
+        assert!(is_valid_iban("PL61109010140000071219812874"));
+        assert!(!is_valid_iban("DE89 3704 0044 0532 0130 01"));
+        assert!(!is_valid_iban("DE89"));
+        assert!(!is_valid_iban("1289 3704 0044 0532 0130 00"));
+
+        let mut data = AccountData {
+            is_withdrawal_available: true,
+            bank_account: BankAccount {
+                iban: "DE89370400440532013000".to_string(),
+                status: "VERIFIED".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(data.withdrawal_account().is_ok());
+        data.bank_account.status = "PENDING".to_string();
+        assert_eq!(data.bank_account.status(), BankAccountStatus::Pending);
+        assert!(data.withdrawal_account().is_err());
+    }
+
+    #[test]
+    fn account_state_envelope() {
+        let body = r#"{"data": {"cashMovements": [{
+            "balance": {"total": 0.0, "unsettledCash": 0.0},
+            "change": 1.5, "currency": "EUR", "date": "2024-01-31T00:00:00+01:00",
+            "description": "Odsetki", "id": 1, "type": "CASH_TRANSACTION",
+            "valueDate": "2024-01-31T00:00:00+01:00"
+        }], "other": {"ignored": [1, 2, 3]}}}"#;
+        let Data { data } = serde_json::from_str::<Data<CashMovements>>(body).unwrap();
+        assert_eq!(data.cash_movements.len(), 1);
+
+        let Data { data } = serde_json::from_str::<Data<CashMovements>>(r#"{"data": {}}"#).unwrap();
+        assert!(data.cash_movements.is_empty());
+    }
+
+    #[test]
+    fn account_info_margin_fields() {
+        let info = serde_json::from_value::<AccountInfo>(serde_json::json!({
+            "clientId": 123,
+            "baseCurrency": "EUR",
+            "marginType": "MARGIN",
+            "cashFunds": {
+                "EUR": [{"id": 11, "name": "Morgan Stanley EUR", "productIds": [5001]}]
+            },
+            "compensationCapping": "2500.5",
+            "allowedOrderTypes": ["LIMIT", "MARKET"],
+            "marginParameters": {"initialMargin": 0.3}
+        }))
+        .unwrap();
+        assert_eq!(info.client_id, Some(123));
+        assert_eq!(info.compensation_capping, Some(2500.5));
+        assert_eq!(info.cash_fund_ids(Currency::EUR), vec![11]);
+        assert!(info.cash_fund_ids(Currency::USD).is_empty());
+        assert_eq!(info.allowed_order_types.len(), 2);
+        assert_eq!(
+            info.margin_parameters()["marginParameters"]["initialMargin"],
+            0.3
+        );
+    }
+
+    #[test]
+    fn rate_derivation() {
+        let info = serde_json::from_value::<AccountInfo>(serde_json::json!({
+            "baseCurrency": "EUR",
+            "marginType": "CASH",
+            "currencyPairs": {
+                "EURUSD": {"id": 705366, "price": "1.25"},
+                "GBPEUR": {"id": 1, "price": 1.2},
+            }
+        }))
+        .unwrap();
+        let rate = info.rate_info(Currency::EUR, Currency::USD).unwrap();
+        assert_eq!((rate.rate, rate.derivation), (1.25, RateDerivation::Direct));
+        let rate = info.rate_info(Currency::USD, Currency::EUR).unwrap();
+        assert_eq!((rate.rate, rate.derivation), (0.8, RateDerivation::Inverse));
+        let rate = info.rate_info(Currency::GBP, Currency::USD).unwrap();
+        assert_eq!(
+            rate.derivation,
+            RateDerivation::Triangulated { via: Currency::EUR }
+        );
+        assert!((rate.rate - 1.5).abs() < 1e-12);
+        assert!(info.rate_info(Currency::JPY, Currency::USD).is_none());
+    }
+
+    #[test]
+    fn fx_close_on_date() {
+        let d = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let history = BTreeMap::from([(d(7), 1.08), (d(8), 1.09), (d(11), 1.10)]);
+        assert_eq!(close_on(&history, d(8)), Some(1.09));
+        assert_eq!(close_on(&history, d(9)), Some(1.09));
+        assert_eq!(close_on(&history, d(12)), None);
+        assert_eq!(close_on(&history, d(1)), None);
+    }
 }
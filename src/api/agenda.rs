@@ -0,0 +1,359 @@
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use reqwest::{header, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::{Client, ClientError};
+use crate::http::{paginate, Endpoint, Page};
+
+/// How far ahead [`Client::upcoming_events`] looks.
+pub const UPCOMING_EVENTS_DAYS: i64 = 90;
+
+/// Events asked for per agenda request.
+pub const AGENDA_PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EarningsEvent {
+    pub isin: String,
+    pub company_name: String,
+    pub country_code: Option<String>,
+    pub date_time: DateTime<Utc>,
+}
+
+impl EarningsEvent {
+    pub fn new(item: &Value) -> Option<Self> {
+        Some(Self {
+            isin: item["isin"].as_str()?.to_string(),
+            company_name: item["companyName"]
+                .as_str()
+                .map(|s| s.to_string())
+                .unwrap_or_default(),
+            country_code: item["countryCode"].as_str().map(|s| s.to_string()),
+            date_time: item["dateTime"].as_str()?.parse().ok()?,
+        })
+    }
+}
+
+fn parse_date(item: &Value, keys: &[&str]) -> Option<DateTime<Utc>> {
+    keys.iter()
+        .find_map(|key| item[*key].as_str()?.parse().ok())
+}
+
+/// Corporate action from the agenda calendars.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum CorporateEvent {
+    ExDividend {
+        isin: String,
+        company_name: String,
+        ex_date: DateTime<Utc>,
+        payment_date: Option<DateTime<Utc>>,
+        amount: Option<f64>,
+        currency: Option<String>,
+    },
+    Earnings(EarningsEvent),
+    Split {
+        isin: String,
+        company_name: String,
+        date_time: DateTime<Utc>,
+        /// As published, e.g. `4:1`.
+        ratio: Option<String>,
+    },
+}
+
+impl CorporateEvent {
+    pub fn dividend(item: &Value) -> Option<Self> {
+        Some(Self::ExDividend {
+            isin: item["isin"].as_str()?.to_string(),
+            company_name: item["companyName"].as_str().unwrap_or_default().to_string(),
+            ex_date: parse_date(item, &["exDividendDate", "dateTime"])?,
+            payment_date: parse_date(item, &["paymentDate"]),
+            amount: item["dividend"].as_f64(),
+            currency: item["currency"].as_str().map(|s| s.to_string()),
+        })
+    }
+
+    pub fn earnings(item: &Value) -> Option<Self> {
+        EarningsEvent::new(item).map(Self::Earnings)
+    }
+
+    pub fn split(item: &Value) -> Option<Self> {
+        let ratio = match &item["ratio"] {
+            Value::String(x) => Some(x.clone()),
+            Value::Number(x) => Some(x.to_string()),
+            _ => None,
+        };
+        Some(Self::Split {
+            isin: item["isin"].as_str()?.to_string(),
+            company_name: item["companyName"].as_str().unwrap_or_default().to_string(),
+            date_time: parse_date(item, &["dateTime"])?,
+            ratio,
+        })
+    }
+
+    pub fn isin(&self) -> &str {
+        match self {
+            Self::ExDividend { isin, .. } | Self::Split { isin, .. } => isin,
+            Self::Earnings(event) => &event.isin,
+        }
+    }
+
+    /// Ex-date for dividends, announcement time for earnings.
+    pub fn date_time(&self) -> DateTime<Utc> {
+        match self {
+            Self::ExDividend { ex_date, .. } => *ex_date,
+            Self::Earnings(event) => event.date_time,
+            Self::Split { date_time, .. } => *date_time,
+        }
+    }
+}
+
+/// Earnings event of a product currently held in the portfolio.
+#[derive(Debug, Clone, Serialize)]
+pub struct HoldingEarnings {
+    pub product_id: String,
+    pub name: String,
+    pub event: EarningsEvent,
+}
+
+impl Client {
+    /// Every item of a calendar between `from` and `to`, fetched
+    /// [`AGENDA_PAGE_SIZE`] at a time.
+    async fn agenda(
+        &self,
+        calendar_type: &str,
+        isin: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Value>, ClientError> {
+        paginate(AGENDA_PAGE_SIZE, |offset, limit| {
+            self.agenda_page(calendar_type, isin, from, to, offset, limit)
+        })
+        .try_collect()
+        .await
+    }
+
+    async fn agenda_page(
+        &self,
+        calendar_type: &str,
+        isin: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Page<Value>, ClientError> {
+        self.require(Endpoint::Agenda)?;
+        let req = {
+            let inner = self.inner.lock().unwrap();
+            let base_url = match inner.account_config.refinitiv_agenda_url.as_str() {
+                "" => "https://trader.degiro.nl/dgtbxdsservice/agenda/v2",
+                url => url,
+            };
+            let url =
+                Url::parse(base_url).unwrap_or_else(|_| panic!("can't parse base_url: {base_url}"));
+            let date_format = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+            let mut query = vec![
+                ("calendarType", calendar_type.to_string()),
+                ("fromDate", from.format(date_format).to_string()),
+                ("toDate", to.format(date_format).to_string()),
+                ("orderBy", "dateTime".to_string()),
+                ("offset", offset.to_string()),
+                ("limit", limit.to_string()),
+            ];
+            if let Some(isin) = isin {
+                query.push(("isin", isin.to_string()));
+            }
+
+            inner
+                .http_client
+                .get(url)
+                .query(&query)
+                .header(header::REFERER, &inner.referer)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
+        };
+
+        let res = self.send(Endpoint::Agenda, req).await?;
+
+        let mut json = res.json::<Value>().await?;
+        let total = json["total"].as_u64().map(|x| x as usize);
+        match json["items"].take() {
+            Value::Array(items) => Ok(Page::new(items, total)),
+            Value::Null => Ok(Page::default()),
+            _ => Err(ClientError::ParseError("agenda items".to_string())),
+        }
+    }
+
+    pub async fn earnings_calendar(
+        &self,
+        isin: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<EarningsEvent>, ClientError> {
+        let items = self.agenda("EarningsCalendar", isin, from, to).await?;
+        Ok(items.iter().filter_map(EarningsEvent::new).collect())
+    }
+
+    /// Ex-dividend dates, earnings and splits of `isin` within the next
+    /// [`UPCOMING_EVENTS_DAYS`], soonest first.
+    pub async fn upcoming_events(&self, isin: &str) -> Result<Vec<CorporateEvent>, ClientError> {
+        let from = self.now();
+        let to = from + Duration::days(UPCOMING_EVENTS_DAYS);
+        let calendars = [
+            (
+                "DividendCalendar",
+                CorporateEvent::dividend as fn(&Value) -> _,
+            ),
+            ("EarningsCalendar", CorporateEvent::earnings),
+            ("SplitCalendar", CorporateEvent::split),
+        ];
+        let mut xs = Vec::new();
+        for (calendar_type, parse) in calendars {
+            let items = self.agenda(calendar_type, Some(isin), from, to).await?;
+            xs.extend(items.iter().filter_map(parse));
+        }
+        xs.sort_by_key(CorporateEvent::date_time);
+        Ok(xs)
+    }
+
+    /// Held products that report earnings within the next `days`, soonest first.
+    pub async fn upcoming_earnings(&self, days: i64) -> Result<Vec<HoldingEarnings>, ClientError> {
+        let ids = self
+            .portfolio()
+            .await?
+            .current()
+            .products()
+            .iter()
+            .map(|p| p.inner.id.clone())
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let products = self.products(&ids).await?;
+
+        let from = self.now();
+        let to = from + Duration::days(days);
+        let results = self
+            .batched(products, 4, |client, (id, product)| async move {
+                let events = client
+                    .earnings_calendar(Some(&product.inner.isin), from, to)
+                    .await?;
+                Ok(events
+                    .into_iter()
+                    .map(|event| HoldingEarnings {
+                        product_id: id.clone(),
+                        name: product.inner.name.clone(),
+                        event,
+                    })
+                    .collect::<Vec<_>>())
+            })
+            .await;
+
+        let mut xs = Vec::new();
+        for res in results {
+            xs.extend(res?);
+        }
+        xs.sort_by_key(|x| x.event.date_time);
+        Ok(xs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{client::ClientStatus, transport::MockTransport};
+
+    #[test]
+    fn corporate_events() {
+        let dividend = CorporateEvent::dividend(&json!({
+            "isin": "US5949181045",
+            "companyName": "Microsoft Corp",
+            "exDividendDate": "2024-05-15T00:00:00Z",
+            "paymentDate": "2024-06-13T00:00:00Z",
+            "dividend": 0.75,
+            "currency": "USD",
+        }))
+        .unwrap();
+        assert_eq!(dividend.isin(), "US5949181045");
+        assert_eq!(
+            dividend.date_time(),
+            "2024-05-15T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(matches!(
+            dividend,
+            CorporateEvent::ExDividend { amount: Some(x), .. } if x == 0.75
+        ));
+
+        let split = CorporateEvent::split(&json!({
+            "isin": "US67066G1040",
+            "dateTime": "2024-06-10T00:00:00Z",
+            "ratio": "10:1",
+        }))
+        .unwrap();
+        assert!(matches!(
+            split,
+            CorporateEvent::Split { ratio: Some(ref x), .. } if x == "10:1"
+        ));
+        assert!(CorporateEvent::split(&json!({"isin": "x"})).is_none());
+    }
+
+    #[tokio::test]
+    async fn upcoming_events() {
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let xs = client.upcoming_events("US5949181045").await.unwrap();
+        dbg!(xs);
+    }
+
+    #[tokio::test]
+    async fn upcoming_earnings() {
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let xs = client.upcoming_earnings(30).await.unwrap();
+        dbg!(xs);
+    }
+
+    #[tokio::test]
+    async fn agenda_pages_past_the_first_hundred() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        let mock = Arc::new(MockTransport::new());
+        let items = (0..AGENDA_PAGE_SIZE)
+            .map(|i| json!({ "isin": format!("US{i:010}"), "dateTime": "2024-06-10T00:00:00Z" }))
+            .collect::<Vec<_>>();
+        mock.json(
+            Method::GET,
+            "agenda/v2",
+            &json!({ "items": items, "total": 150 }),
+        );
+        client.set_transport(mock.clone());
+        client.inner.lock().unwrap().status = ClientStatus::Authorized;
+
+        let now = Utc::now();
+        let events = client
+            .earnings_calendar(None, now, now + Duration::days(30))
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2 * AGENDA_PAGE_SIZE as usize);
+        let offsets = mock
+            .requests()
+            .iter()
+            .map(|x| {
+                x.url
+                    .query_pairs()
+                    .find(|(k, _)| k == "offset")
+                    .unwrap()
+                    .1
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, ["0", "100"]);
+    }
+}
@@ -0,0 +1,168 @@
+use reqwest::{header, Url};
+use serde_json::Value;
+
+use crate::client::{Client, ClientError};
+use crate::http::Endpoint;
+
+use super::{
+    company_profile::CompanyProfile, company_ratios::CompanyRatios,
+    financial_statements::FinancialReports,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::Display)]
+pub enum OverviewPart {
+    Profile,
+    Ratios,
+    Financials,
+    Esg,
+    Estimates,
+}
+
+/// Everything the research endpoints know about a company, parts that
+/// failed to load are `None` and listed in `errors`.
+#[derive(Debug, Default)]
+pub struct CompanyOverview {
+    /// DEGIRO product id.
+    pub id: String,
+    pub isin: String,
+    pub profile: Option<CompanyProfile>,
+    pub ratios: Option<CompanyRatios>,
+    pub financials: Option<FinancialReports>,
+    pub esg: Option<Value>,
+    pub estimates: Option<Value>,
+    pub errors: Vec<(OverviewPart, ClientError)>,
+}
+
+impl CompanyOverview {
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Client {
+    async fn refinitiv_raw(&self, base_url: String, isin: &str) -> Result<Value, ClientError> {
+        let key = format!("{}/{isin}", base_url.trim_end_matches('/'));
+        self.cached(&key, self.fetch_refinitiv(base_url, isin))
+            .await
+    }
+
+    async fn fetch_refinitiv(&self, base_url: String, isin: &str) -> Result<Value, ClientError> {
+        self.require(Endpoint::Refinitiv)?;
+        let url = Url::parse(&format!("{}/{}", base_url.trim_end_matches('/'), isin))
+            .map_err(|err| ClientError::ParseError(err.to_string()))?;
+        let req = {
+            let inner = self.inner.lock().unwrap();
+            inner
+                .http_client
+                .get(url)
+                .header(header::REFERER, &inner.referer)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
+        };
+
+        let res = self.send(Endpoint::Refinitiv, req).await?;
+
+        let mut json = res.json::<Value>().await?;
+        let data = json["data"].take();
+        if data.is_null() {
+            return Err(ClientError::NoData);
+        }
+        Ok(data)
+    }
+
+    /// ESG scores, unparsed.
+    pub async fn company_esg(&self, isin: impl AsRef<str>) -> Result<Value, ClientError> {
+        let base_url = self
+            .inner
+            .lock()
+            .unwrap()
+            .account_config
+            .refinitiv_esgs_url
+            .clone();
+        self.refinitiv_raw(base_url, isin.as_ref()).await
+    }
+
+    /// Analyst estimates summary, unparsed.
+    pub async fn company_estimates(&self, isin: impl AsRef<str>) -> Result<Value, ClientError> {
+        let base_url = self
+            .inner
+            .lock()
+            .unwrap()
+            .account_config
+            .refinitiv_estimates_url
+            .clone();
+        self.refinitiv_raw(base_url, isin.as_ref()).await
+    }
+
+    /// Fetches profile, ratios, financials, ESG and estimates concurrently.
+    /// `id` is the DEGIRO product id the ratios and financials are tagged
+    /// with, the research endpoints themselves are keyed by `isin`.
+    ///
+    /// Individual failures are collected in [`CompanyOverview::errors`], only
+    /// a lost session fails the whole call.
+    pub async fn company_overview(
+        &self,
+        id: impl AsRef<str>,
+        isin: impl AsRef<str>,
+    ) -> Result<CompanyOverview, ClientError> {
+        let (id, isin) = (id.as_ref(), isin.as_ref());
+        let (profile, ratios, financials, esg, estimates) = futures::join!(
+            self.company_profile(isin),
+            self.company_ratios(id, isin),
+            self.financial_statements(id, isin),
+            self.company_esg(isin),
+            self.company_estimates(isin),
+        );
+
+        let mut overview = CompanyOverview {
+            id: id.to_string(),
+            isin: isin.to_string(),
+            ..Default::default()
+        };
+        let mut errors = Vec::new();
+        overview.profile = part(profile, OverviewPart::Profile, &mut errors);
+        overview.ratios = part(ratios, OverviewPart::Ratios, &mut errors);
+        overview.financials = part(financials, OverviewPart::Financials, &mut errors);
+        overview.esg = part(esg, OverviewPart::Esg, &mut errors);
+        overview.estimates = part(estimates, OverviewPart::Estimates, &mut errors);
+
+        if errors
+            .iter()
+            .any(|(_, err)| matches!(err, ClientError::Unauthorized))
+        {
+            return Err(ClientError::Unauthorized);
+        }
+        overview.errors = errors;
+        Ok(overview)
+    }
+}
+
+fn part<T>(
+    res: Result<T, ClientError>,
+    kind: OverviewPart,
+    errors: &mut Vec<(OverviewPart, ClientError)>,
+) -> Option<T> {
+    match res {
+        Ok(x) => Some(x),
+        Err(err) => {
+            errors.push((kind, err));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::client::Client;
+
+    #[tokio::test]
+    async fn company_overview() {
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let overview = client
+            .company_overview("331868", "US0378331005")
+            .await
+            .unwrap();
+        dbg!(&overview.errors);
+    }
+}
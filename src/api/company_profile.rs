@@ -1,33 +1,87 @@
 use core::fmt;
 
 use reqwest::{header, Url};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::client::{Client, ClientError, ClientStatus};
+use crate::client::{Client, ClientError};
+use crate::http::Endpoint;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CompanyProfile {
+    #[serde(default, deserialize_with = "null_default")]
     pub contacts: Contacts,
+    #[serde(default, deserialize_with = "null_default")]
     pub management: Vec<Management>,
+    #[serde(default, deserialize_with = "null_default")]
     pub issues: Vec<Issue>,
+    #[serde(default, deserialize_with = "null_default")]
     pub sector: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub industry: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub employees: i64,
+    #[serde(default, deserialize_with = "null_default")]
     pub business_summary: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub financial_summary: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub business_summary_last_modified: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub financial_summary_last_modified: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub shr_floating: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub shr_outstanding: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub la_interim_data: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub la_annual_data: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub lu_employees: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub lu_shares: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub last_updated: String,
     pub us_irs_no: Option<String>,
     pub us_cik_no: Option<String>,
+    #[serde(default, deserialize_with = "null_default")]
     pub currency: String,
+    /// Competitors listed by the data provider.
+    #[serde(default, alias = "competitors", deserialize_with = "null_default")]
+    pub peers: Vec<Peer>,
+    /// Indices the company is a constituent of.
+    #[serde(default, alias = "indices", deserialize_with = "null_default")]
+    pub index_memberships: Vec<IndexMembership>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Peer {
+    #[serde(default, deserialize_with = "null_default")]
+    pub name: String,
+    pub isin: Option<String>,
+    pub ticker: Option<String>,
+    pub exchange: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexMembership {
+    #[serde(default, deserialize_with = "null_default")]
+    pub name: String,
+    pub isin: Option<String>,
+    pub symbol: Option<String>,
+}
+
+/// Treats `null` like a missing field.
+fn null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 impl fmt::Display for CompanyProfile {
@@ -89,17 +143,37 @@ impl fmt::Display for CompanyProfile {
             self.us_cik_no.as_deref().unwrap_or("Not Available")
         )?;
 
-        write!(f, "\nCurrency: {}", self.currency)
+        writeln!(f, "\nCurrency: {}", self.currency)?;
+
+        if !self.peers.is_empty() {
+            writeln!(f, "\nPeers:")?;
+            for peer in &self.peers {
+                writeln!(f, "{}", peer.name)?;
+            }
+        }
+
+        if !self.index_memberships.is_empty() {
+            writeln!(f, "\nIndex Memberships:")?;
+            for index in &self.index_memberships {
+                writeln!(f, "{}", index.name)?;
+            }
+        }
+        Ok(())
     }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct Contacts {
+    #[serde(default, deserialize_with = "null_default")]
     pub name: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub address: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub postcode: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub city: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub country: String,
     pub telephone: Option<String>,
     pub fax: Option<String>,
@@ -137,8 +211,11 @@ impl fmt::Display for Contacts {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Management {
+    #[serde(default, deserialize_with = "null_default")]
     pub name: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub function: String,
+    #[serde(default, deserialize_with = "null_default")]
     pub long_function: String,
     pub age: Option<i64>,
     pub since: Option<String>,
@@ -166,6 +243,7 @@ impl fmt::Display for Management {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Issue {
+    #[serde(default, deserialize_with = "null_default")]
     pub id: i64,
     pub name: Option<String>,
     pub ticker: Option<String>,
@@ -212,63 +290,70 @@ impl Client {
         &self,
         isin: impl AsRef<str>,
     ) -> Result<CompanyProfile, ClientError> {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        let isin = isin.as_ref();
+        let data = self
+            .cached(
+                &format!("company_profile/{isin}"),
+                self.company_profile_raw(isin),
+            )
+            .await?;
+        let company_profile = serde_json::from_value::<CompanyProfile>(data)?;
+
+        Ok(company_profile)
+    }
+
+    async fn company_profile_raw(&self, isin: &str) -> Result<serde_json::Value, ClientError> {
+        self.require(Endpoint::Refinitiv)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = "https://trader.degiro.nl/";
             let path_url = "dgtbxdsservice/company-profile/v2/";
             let url = Url::parse(base_url)
-                .unwrap()
-                .join(path_url)
-                .unwrap()
-                .join(isin.as_ref())
-                .unwrap();
+                .and_then(|url| url.join(path_url))
+                .and_then(|url| url.join(isin))
+                .map_err(|err| ClientError::ParseError(err.to_string()))?;
 
             inner
                 .http_client
                 .get(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                ])
                 .header(header::REFERER, &inner.referer)
                 .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
+        let res = self.send(Endpoint::Refinitiv, req).await?;
 
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut json = res.json::<serde_json::Value>().await?;
-                let mut data = json["data"].take();
-                if data.is_null() {
-                    return Err(ClientError::NoData);
-                }
-
-                let company_profile = serde_json::from_value::<CompanyProfile>(data.take())?;
-
-                Ok(company_profile)
-            }
-            Err(err) => {
-                eprintln!("error: {}", err);
-                Err(err.into())
-            }
+        let mut json = res.json::<serde_json::Value>().await?;
+        let data = json["data"].take();
+        if data.is_null() {
+            return Err(ClientError::NoData);
         }
+
+        Ok(data)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::CompanyProfile;
     use crate::client::Client;
 
+    #[test]
+    fn parse_partial_profile() {
+        let profile = serde_json::from_value::<CompanyProfile>(serde_json::json!({
+            "contacts": {"NAME": "ACME", "CITY": null},
+            "management": null,
+            "employees": 10,
+            "currency": "USD",
+            "peers": [{"name": "Globex", "isin": "US0000000001"}],
+            "indices": [{"name": "S&P 500"}]
+        }))
+        .unwrap();
+        assert_eq!(profile.contacts.name, "ACME");
+        assert!(profile.management.is_empty());
+        assert_eq!(profile.peers[0].name, "Globex");
+        assert_eq!(profile.index_memberships[0].name, "S&P 500");
+    }
+
     #[tokio::test]
     async fn test_company_profile_success() {
         let client = Client::new_from_env();
@@ -1,11 +1,12 @@
-use std::{any, str::FromStr};
+use std::{any, collections::BTreeMap, str::FromStr};
 
 use chrono::{DateTime, NaiveDateTime, Utc};
 use reqwest::{header, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::client::{Client, ClientError, ClientStatus};
+use crate::client::{Client, ClientError};
+use crate::http::Endpoint;
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -13,6 +14,9 @@ pub struct CompanyRatios {
     pub id: String,
     // pub cons_recommendation_trend: ConsRecommendationTrend,
     pub current_ratios: CurrentRatios,
+    /// Every ratio the endpoint returned, including ones without a struct field.
+    #[serde(default)]
+    pub ratios: RatiosMap,
     // pub forecast_data: ForecastData,
     // pub la_annual: String,
     // pub la_interim: String,
@@ -60,297 +64,360 @@ pub struct CompanyRatios {
 //     pub value: f64,
 // }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CurrentRatios {
-    pub currency: String,
-    pub price_currency: String,
+/// Declares every ratio the company ratios endpoint has a name for, once:
+/// its wire code, [`RatioId`] variant and [`CurrentRatios`] field. Generates
+/// both types and the conversions between them and the codes.
+macro_rules! ratios {
+    ($($(#[doc = $doc:literal])* $code:literal => $variant:ident, $field:ident: $ty:ty;)*) => {
+        #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        pub struct CurrentRatios {
+            pub currency: String,
+            pub price_currency: String,
+            $($(#[doc = $doc])* pub $field: ItemDetail<$ty>,)*
+        }
+
+        fn fill_ratio(current_ratios: &mut CurrentRatios, item: &Value) {
+            match item["id"].as_str().unwrap() {
+                $($code => current_ratios.$field = item.into(),)*
+                // Kept in `RatiosMap`.
+                _ => {}
+            }
+        }
+
+        /// Identifier of a single ratio as sent by the company ratios endpoint.
+        ///
+        /// Ids without a matching variant are kept in [`RatioId::Unknown`].
+        #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(from = "String", into = "String")]
+        pub enum RatioId {
+            $($(#[doc = $doc])* $variant,)*
+            Unknown(String),
+        }
+
+        impl RatioId {
+            pub const KNOWN: &'static [RatioId] = &[$(RatioId::$variant,)*];
+
+            pub fn code(&self) -> &str {
+                match self {
+                    $(RatioId::$variant => $code,)*
+                    RatioId::Unknown(code) => code,
+                }
+            }
+        }
+
+        impl From<&str> for RatioId {
+            fn from(code: &str) -> Self {
+                match code {
+                    $($code => RatioId::$variant,)*
+                    code => RatioId::Unknown(code.to_string()),
+                }
+            }
+        }
+
+        impl From<&CurrentRatios> for RatiosMap {
+            fn from(ratios: &CurrentRatios) -> Self {
+                let items = [$((RatioId::$variant, ratio_value(&ratios.$field)),)*];
+                RatiosMap {
+                    currency: ratios.currency.clone(),
+                    price_currency: ratios.price_currency.clone(),
+                    items: items
+                        .into_iter()
+                        .filter(|(_, x)| x.value.is_some() || !x.meaning.is_empty())
+                        .collect(),
+                }
+            }
+        }
+    };
+}
+
+ratios! {
     /// Price - closing or last bid
-    pub current_price: ItemDetail<f64>,
+    "NPRICE" => CurrentPrice, current_price: f64;
     /// Price - 12 month high
-    pub high_12m: ItemDetail<f64>,
+    "NHIG" => High12m, high_12m: f64;
     /// Price - 12 month low
-    pub low_12m: ItemDetail<f64>,
+    "NLOW" => Low12m, low_12m: f64;
     /// Pricing date
-    pub pricing_date: ItemDetail<NaiveDateTime>,
+    "PDATE" => PricingDate, pricing_date: NaiveDateTime;
     /// Volume - avg. trading volume for the last ten days
-    pub volume_avg_10d: ItemDetail<f64>,
+    "VOL10DAVG" => VolumeAvg10d, volume_avg_10d: f64;
     /// Market capitalization
-    pub market_cap: ItemDetail<f64>,
+    "MKTCAP" => MarketCap, market_cap: f64;
     /// 12 Month High price date
-    pub high_date_12m: ItemDetail<NaiveDateTime>,
+    "NHIGDATE" => HighDate12m, high_date_12m: NaiveDateTime;
     /// 12 Month Low price date
-    pub low_date_12m: ItemDetail<NaiveDateTime>,
+    "NLOWDATE" => LowDate12m, low_date_12m: NaiveDateTime;
     /// Volume - avg. trading volume for the last 3 months
-    pub volume_avg_3m: ItemDetail<f64>,
+    "VOL3MAVG" => VolumeAvg3m, volume_avg_3m: f64;
     /// Beta
-    pub beta: ItemDetail<f64>,
+    "BETA" => Beta, beta: f64;
     /// Price - 1 Day % Change
-    pub price_change_1d: ItemDetail<f64>,
+    "PR1DAYPRC" => PriceChange1d, price_change_1d: f64;
     /// Price - 13 week price percent change
-    pub price_change_13w: ItemDetail<f64>,
+    "PR13WKPCT" => PriceChange13w, price_change_13w: f64;
     /// Price - 26 week price percent change
-    pub price_change_26w: ItemDetail<f64>,
+    "PR26WKPCT" => PriceChange26w, price_change_26w: f64;
     /// Price - 5 Day % Change
-    pub price_change_5d: ItemDetail<f64>,
+    "PR5DAYPRC" => PriceChange5d, price_change_5d: f64;
     /// Price - 52 week price percent change
-    pub price_change_52w: ItemDetail<f64>,
+    "PR52WKPCT" => PriceChange52w, price_change_52w: f64;
     /// Price - YTD price percent change
-    pub price_change_ytd: ItemDetail<f64>,
+    "PRYTDPCT" => PriceChangeYtd, price_change_ytd: f64;
     /// Price % Change Month To Date
-    pub price_change_mtd: ItemDetail<f64>,
+    "ChPctPriceMTD" => PriceChangeMtd, price_change_mtd: f64;
     /// Relative (S&P500) price percent change - 04 week
-    pub relative_price_change_4w: ItemDetail<f64>,
+    "PR04WKPCTR" => RelativePriceChange4w, relative_price_change_4w: f64;
     /// Relative (S&P500) price percent change - 13 week
-    pub relative_price_change_13w: ItemDetail<f64>,
+    "PR13WKPCTR" => RelativePriceChange13w, relative_price_change_13w: f64;
     /// Relative (S&P500) price percent change - 26 week
-    pub relative_price_change_26w: ItemDetail<f64>,
+    "PR26WKPCTR" => RelativePriceChange26w, relative_price_change_26w: f64;
     /// Relative (S&P500) price percent change - 52 week
-    pub relative_price_change_52w: ItemDetail<f64>,
+    "PR52WKPCTR" => RelativePriceChange52w, relative_price_change_52w: f64;
     /// Relative (S&P500) price percent change - Year to Date
-    pub relative_price_change_ytd: ItemDetail<f64>,
+    "PRYTDPCTR" => RelativePriceChangeYtd, relative_price_change_ytd: f64;
     /// EPS excluding extraordinary items - most recent fiscal year
-    pub eps_excluding_extraordinary_items_annual: ItemDetail<f64>,
+    "AEPSXCLXOR" => EpsExcludingExtraordinaryItemsAnnual, eps_excluding_extraordinary_items_annual: f64;
     /// EPS excluding extraordinary items - trailing 12 month
-    pub eps_excluding_extraordinary_items_ttm: ItemDetail<f64>,
+    "TTMEPSXCLX" => EpsExcludingExtraordinaryItemsTtm, eps_excluding_extraordinary_items_ttm: f64;
     /// EPS Normalized - most recent fiscal year
-    pub eps_normalized_annual: ItemDetail<f64>,
+    "AEPSNORM" => EpsNormalizedAnnual, eps_normalized_annual: f64;
     /// Revenue/share - most recent fiscal year
-    pub revenue_per_share_annual: ItemDetail<f64>,
+    "AREVPS" => RevenuePerShareAnnual, revenue_per_share_annual: f64;
     /// Revenue/share - trailing 12 month
-    pub revenue_per_share_ttm: ItemDetail<f64>,
+    "TTMREVPS" => RevenuePerShareTtm, revenue_per_share_ttm: f64;
     /// Book value (Total Equity) per share - most recent fiscal year
-    pub book_value_per_share_annual: ItemDetail<f64>,
+    "ABVPS" => BookValuePerShareAnnual, book_value_per_share_annual: f64;
     /// Book value (Total Equity) per share - most recent quarter
-    pub book_value_per_share_quarterly: ItemDetail<f64>,
+    "QBVPS" => BookValuePerShareQuarterly, book_value_per_share_quarterly: f64;
     /// Book value (tangible) per share - most recent fiscal year
-    pub tangible_book_value_per_share_annual: ItemDetail<f64>,
+    "ATANBVPS" => TangibleBookValuePerShareAnnual, tangible_book_value_per_share_annual: f64;
     /// Book value (tangible) per share - most recent quarter
-    pub tangible_book_value_per_share_quarterly: ItemDetail<f64>,
+    "QTANBVPS" => TangibleBookValuePerShareQuarterly, tangible_book_value_per_share_quarterly: f64;
     /// Cash per share - most recent fiscal year
-    pub cash_per_share_annual: ItemDetail<f64>,
+    "ACSHPS" => CashPerShareAnnual, cash_per_share_annual: f64;
     /// Cash per share - most recent quarter
-    pub cash_per_share_quarterly: ItemDetail<f64>,
+    "QCSHPS" => CashPerShareQuarterly, cash_per_share_quarterly: f64;
     /// Cash Flow per share - most recent fiscal year
-    pub cash_flow_per_share_annual: ItemDetail<f64>,
+    "ACFSHR" => CashFlowPerShareAnnual, cash_flow_per_share_annual: f64;
     /// Cash Flow per share - trailing 12 month
-    pub cash_flow_per_share_ttm: ItemDetail<f64>,
+    "TTMCFSHR" => CashFlowPerShareTtm, cash_flow_per_share_ttm: f64;
     /// Dividend per share - most recent fiscal year
-    pub dividend_per_share_annual: ItemDetail<f64>,
+    "ADIVSHR" => DividendPerShareAnnual, dividend_per_share_annual: f64;
     /// Dividends per share - trailing 12 month
-    pub dividend_per_share_ttm: ItemDetail<f64>,
+    "TTMDIVSHR" => DividendPerShareTtm, dividend_per_share_ttm: f64;
     /// EBITD per share - trailing 12 month
-    pub ebitd_per_share_ttm: ItemDetail<f64>,
+    "TTMEBITDPS" => EbitdPerShareTtm, ebitd_per_share_ttm: f64;
     /// ABEPSXCLXO
-    pub abepsxclxo: ItemDetail<f64>,
+    "ABEPSXCLXO" => Abepsxclxo, abepsxclxo: f64;
     /// EPS Basic excluding extraordinary items - trailing 12 month
-    pub eps_basic_excluding_extraordinary_items_ttm: ItemDetail<f64>,
+    "TTMBEPSXCL" => EpsBasicExcludingExtraordinaryItemsTtm, eps_basic_excluding_extraordinary_items_ttm: f64;
     /// EPS including extraordinary items - most recent fiscal year
-    pub eps_including_extraordinary_items_annual: ItemDetail<f64>,
+    "AEPSINCLXO" => EpsIncludingExtraordinaryItemsAnnual, eps_including_extraordinary_items_annual: f64;
     /// EPS including extraordinary items - trailing 12 month
-    pub eps_including_extraordinary_items_ttm: ItemDetail<f64>,
+    "TTMEPSINCX" => EpsIncludingExtraordinaryItemsTtm, eps_including_extraordinary_items_ttm: f64;
     /// Free Cash Flow per share - trailing 12 month
-    pub free_cash_flow_per_share_ttm: ItemDetail<f64>,
+    "TTMFCFSHR" => FreeCashFlowPerShareTtm, free_cash_flow_per_share_ttm: f64;
     /// Dividend Per Share - 5 year average
-    pub dividend_per_share_5yr_avg: ItemDetail<f64>,
+    "ADIV5YAVG" => DividendPerShare5yrAvg, dividend_per_share_5yr_avg: f64;
     /// P/E excluding extraordinary items, most recent fiscal year
-    pub pe_excluding_extraordinary_items_annual: ItemDetail<f64>,
+    "APEEXCLXOR" => PeExcludingExtraordinaryItemsAnnual, pe_excluding_extraordinary_items_annual: f64;
     /// P/E excluding extraordinary items - TTM
-    pub pe_excluding_extraordinary_items_ttm: ItemDetail<f64>,
+    "PEEXCLXOR" => PeExcludingExtraordinaryItemsTtm, pe_excluding_extraordinary_items_ttm: f64;
     /// P/E Normalized, most recent fiscal year
-    pub pe_normalized_annual: ItemDetail<f64>,
+    "APENORM" => PeNormalizedAnnual, pe_normalized_annual: f64;
     /// Price to sales - most recent fiscal year
-    pub price_to_sales_annual: ItemDetail<f64>,
+    "APR2REV" => PriceToSalesAnnual, price_to_sales_annual: f64;
     /// Price to sales - trailing 12 month
-    pub price_to_sales_ttm: ItemDetail<f64>,
+    "TTMPR2REV" => PriceToSalesTtm, price_to_sales_ttm: f64;
     /// Price to Tangible Book - most fiscal year
-    pub price_to_tangible_book_annual: ItemDetail<f64>,
+    "APR2TANBK" => PriceToTangibleBookAnnual, price_to_tangible_book_annual: f64;
     /// Price to Tangible Book - most recent quarter
-    pub price_to_tangible_book_quarterly: ItemDetail<f64>,
+    "PR2TANBK" => PriceToTangibleBookQuarterly, price_to_tangible_book_quarterly: f64;
     /// Price to Free Cash Flow per Share - most recent fiscal year
-    pub price_to_free_cash_flow_per_share_annual: ItemDetail<f64>,
+    "APRFCFPS" => PriceToFreeCashFlowPerShareAnnual, price_to_free_cash_flow_per_share_annual: f64;
     /// Price to Cash Flow per share - trailing 12 month
-    pub price_to_cash_flow_per_share_ttm: ItemDetail<f64>,
+    "TTMPRCFPS" => PriceToCashFlowPerShareTtm, price_to_cash_flow_per_share_ttm: f64;
     /// Price to Free Cash Flow per Share - trailing 12 months
-    pub price_to_free_cash_flow_per_share_ttm: ItemDetail<f64>,
+    "TTMPRFCFPS" => PriceToFreeCashFlowPerShareTtm, price_to_free_cash_flow_per_share_ttm: f64;
     /// Price to Book - most recent fiscal year
-    pub price_to_book_annual: ItemDetail<f64>,
+    "APRICE2BK" => PriceToBookAnnual, price_to_book_annual: f64;
     /// Price to Book - most recent quarter
-    pub price_to_book_quarterly: ItemDetail<f64>,
+    "PRICE2BK" => PriceToBookQuarterly, price_to_book_quarterly: f64;
     /// P/E Basic excluding extraordinary items - TTM
-    pub pe_basic_excluding_extraordinary_items_ttm: ItemDetail<f64>,
+    "PEBEXCLXOR" => PeBasicExcludingExtraordinaryItemsTtm, pe_basic_excluding_extraordinary_items_ttm: f64;
     /// P/E excluding extraordinary items high, trailing 12 months
-    pub pe_high_excluding_extraordinary_items_ttm: ItemDetail<f64>,
+    "TTMPEHIGH" => PeHighExcludingExtraordinaryItemsTtm, pe_high_excluding_extraordinary_items_ttm: f64;
     /// P/E excluding extraordinary items low, trailing 12 months
-    pub pe_low_excluding_extraordinary_items_ttm: ItemDetail<f64>,
+    "TTMPELOW" => PeLowExcludingExtraordinaryItemsTtm, pe_low_excluding_extraordinary_items_ttm: f64;
     /// P/E including extraordinary items - TTM
-    pub pe_including_extraordinary_items_ttm: ItemDetail<f64>,
+    "PEINCLXOR" => PeIncludingExtraordinaryItemsTtm, pe_including_extraordinary_items_ttm: f64;
     /// Net Debt, LFI
-    pub net_debt_lfi: ItemDetail<f64>,
+    "NetDebt_I" => NetDebtLfi, net_debt_lfi: f64;
     /// Net Debt, LFY
-    pub net_debt_lfy: ItemDetail<f64>,
+    "NetDebt_A" => NetDebtLfy, net_debt_lfy: f64;
     /// Dividend Yield - 5 Year Average
-    pub dividend_yield_5yr_avg: ItemDetail<f64>,
+    "YLD5YAVG" => DividendYield5yrAvg, dividend_yield_5yr_avg: f64;
     /// Dividend Yield - indicated annual dividend divided by closing price
-    pub dividend_yield: ItemDetail<f64>,
+    "YIELD" => DividendYield, dividend_yield: f64;
     /// Current Dividend Yield - Common Stock Primary Issue, LTM
-    pub current_dividend_yield_ttm: ItemDetail<f64>,
+    "DivYield_CurTTM" => CurrentDividendYieldTtm, current_dividend_yield_ttm: f64;
     /// Current ratio - most recent fiscal year
-    pub current_ratio_annual: ItemDetail<f64>,
+    "ACURRATIO" => CurrentRatioAnnual, current_ratio_annual: f64;
     /// Current ratio - most recent quarter
-    pub current_ratio_quarterly: ItemDetail<f64>,
+    "QCURRATIO" => CurrentRatioQuarterly, current_ratio_quarterly: f64;
     /// Quick ratio - most recent fiscal year
-    pub quick_ratio_annual: ItemDetail<f64>,
+    "AQUICKRATI" => QuickRatioAnnual, quick_ratio_annual: f64;
     /// Quick ratio - most recent quarter
-    pub quick_ratio_quarterly: ItemDetail<f64>,
+    "QQUICKRATI" => QuickRatioQuarterly, quick_ratio_quarterly: f64;
     /// LT debt/equity - most recent fiscal year
-    pub long_term_debt_to_equity_annual: ItemDetail<f64>,
+    "ALTD2EQ" => LongTermDebtToEquityAnnual, long_term_debt_to_equity_annual: f64;
     /// LT debt/equity - most recent quarter
-    pub long_term_debt_to_equity_quarterly: ItemDetail<f64>,
+    "QLTD2EQ" => LongTermDebtToEquityQuarterly, long_term_debt_to_equity_quarterly: f64;
     /// Total debt/total equity - most recent fiscal year
-    pub total_debt_to_equity_annual: ItemDetail<f64>,
+    "ATOTD2EQ" => TotalDebtToEquityAnnual, total_debt_to_equity_annual: f64;
     /// Total debt/total equity - most recent quarter
-    pub total_debt_to_equity_quarterly: ItemDetail<f64>,
+    "QTOTD2EQ" => TotalDebtToEquityQuarterly, total_debt_to_equity_quarterly: f64;
     /// Payout ratio - most recent fiscal year
-    pub payout_ratio_annual: ItemDetail<f64>,
+    "APAYRATIO" => PayoutRatioAnnual, payout_ratio_annual: f64;
     /// Payout ratio - trailing 12 month
-    pub payout_ratio_ttm: ItemDetail<f64>,
+    "TTMPAYRAT" => PayoutRatioTtm, payout_ratio_ttm: f64;
     /// Current EV/Free Cash Flow, LFY
-    pub ev_to_free_cash_flow_current_annual: ItemDetail<f64>,
+    "EV2FCF_CurA" => EvToFreeCashFlowCurrentAnnual, ev_to_free_cash_flow_current_annual: f64;
     /// Current EV/Free Cash Flow, LTM
-    pub ev_to_free_cash_flow_current_ttm: ItemDetail<f64>,
+    "EV2FCF_CurTTM" => EvToFreeCashFlowCurrentTtm, ev_to_free_cash_flow_current_ttm: f64;
     /// Interest coverage - most recent fiscal year
-    pub interest_coverage_annual: ItemDetail<f64>,
+    "AINTCOV" => InterestCoverageAnnual, interest_coverage_annual: f64;
     /// Interest coverage - trailing 12 month
-    pub interest_coverage_ttm: ItemDetail<f64>,
+    "TTMINTCOV" => InterestCoverageTtm, interest_coverage_ttm: f64;
     /// Free Cash Flow - 1st historical fiscal year
-    pub free_cash_flow_historical_annual: ItemDetail<f64>,
+    "A1FCF" => FreeCashFlowHistoricalAnnual, free_cash_flow_historical_annual: f64;
     /// Free Cash Flow - trailing 12 month
-    pub free_cash_flow_ttm: ItemDetail<f64>,
+    "TTMFCF" => FreeCashFlowTtm, free_cash_flow_ttm: f64;
     /// Revenue - most recent fiscal year
-    pub revenue_annual: ItemDetail<f64>,
+    "AREV" => RevenueAnnual, revenue_annual: f64;
     /// Revenue - trailing 12 month
-    pub revenue_ttm: ItemDetail<f64>,
+    "TTMREV" => RevenueTtm, revenue_ttm: f64;
     /// EBITD - most recent fiscal year
-    pub ebitd_annual: ItemDetail<f64>,
+    "AEBITD" => EbitdAnnual, ebitd_annual: f64;
     /// EBITD - trailing 12 month
-    pub ebitd_ttm: ItemDetail<f64>,
+    "TTMEBITD" => EbitdTtm, ebitd_ttm: f64;
     /// Earnings before taxes - most recent fiscal year
-    pub earnings_before_taxes_annual: ItemDetail<f64>,
+    "AEBT" => EarningsBeforeTaxesAnnual, earnings_before_taxes_annual: f64;
     /// Earnings before taxes - trailing 12 month
-    pub earnings_before_taxes_ttm: ItemDetail<f64>,
+    "TTMEBT" => EarningsBeforeTaxesTtm, earnings_before_taxes_ttm: f64;
     /// Net Income available to common - most recent fiscal year
-    pub net_income_to_common_annual: ItemDetail<f64>,
+    "ANIAC" => NetIncomeToCommonAnnual, net_income_to_common_annual: f64;
     /// Net Income available to common - trailing 12 months
-    pub net_income_to_common_ttm: ItemDetail<f64>,
+    "TTMNIAC" => NetIncomeToCommonTtm, net_income_to_common_ttm: f64;
     /// Earnings before taxes Normalized - most recent fiscal year
-    pub normalized_earnings_before_taxes_annual: ItemDetail<f64>,
+    "AEBTNORM" => NormalizedEarningsBeforeTaxesAnnual, normalized_earnings_before_taxes_annual: f64;
     /// Net Income Available to Common, Normalized - most recent fiscal year
-    pub normalized_net_income_to_common_annual: ItemDetail<f64>,
+    "ANIACNORM" => NormalizedNetIncomeToCommonAnnual, normalized_net_income_to_common_annual: f64;
     /// Earnings per Share, Normalized, Excluding Extraordinary Items, Avg. Diluted Shares Outstanding, TTM
-    pub normalized_eps_excluding_extraordinary_ttm: ItemDetail<f64>,
+    "VDES_TTM" => NormalizedEpsExcludingExtraordinaryTtm, normalized_eps_excluding_extraordinary_ttm: f64;
     /// Gross Margin - 1st historical fiscal year
-    pub gross_margin_first_historical_year: ItemDetail<f64>,
+    "AGROSMGN" => GrossMarginFirstHistoricalYear, gross_margin_first_historical_year: f64;
     /// Gross Margin - trailing 12 month
-    pub gross_margin_ttm: ItemDetail<f64>,
+    "TTMGROSMGN" => GrossMarginTtm, gross_margin_ttm: f64;
     /// Net Profit Margin % - 1st historical fiscal year
-    pub net_profit_margin_first_historical_year: ItemDetail<f64>,
+    "ANPMGNPCT" => NetProfitMarginFirstHistoricalYear, net_profit_margin_first_historical_year: f64;
     /// Net Profit Margin % - trailing 12 month
-    pub net_profit_margin_ttm: ItemDetail<f64>,
+    "TTMNPMGN" => NetProfitMarginTtm, net_profit_margin_ttm: f64;
     /// Operating margin - 1st historical fiscal year
-    pub operating_margin_first_historical_year: ItemDetail<f64>,
+    "AOPMGNPCT" => OperatingMarginFirstHistoricalYear, operating_margin_first_historical_year: f64;
     /// Operating margin - trailing 12 month
-    pub operating_margin_ttm: ItemDetail<f64>,
+    "TTMOPMGN" => OperatingMarginTtm, operating_margin_ttm: f64;
     /// Pretax margin - trailing 12 month
-    pub pretax_margin_ttm: ItemDetail<f64>,
+    "TTMPTMGN" => PretaxMarginTtm, pretax_margin_ttm: f64;
     /// Pretax margin - 1st historical fiscal year
-    pub pretax_margin_first_historical_year: ItemDetail<f64>,
+    "APTMGNPCT" => PretaxMarginFirstHistoricalYear, pretax_margin_first_historical_year: f64;
     /// Operating Margin - 5 year average
-    pub operating_margin_5yr_avg: ItemDetail<f64>,
+    "OPMGN5YR" => OperatingMargin5yrAvg, operating_margin_5yr_avg: f64;
     /// Pretax Margin - 5 year average
-    pub pretax_margin_5yr_avg: ItemDetail<f64>,
+    "PTMGN5YR" => PretaxMargin5yrAvg, pretax_margin_5yr_avg: f64;
     /// Free Operating Cash Flow/Revenue, 5 Year Average
-    pub free_operating_cash_flow_to_revenue_5yr_avg: ItemDetail<f64>,
+    "Focf2Rev_AAvg5" => FreeOperatingCashFlowToRevenue5yrAvg, free_operating_cash_flow_to_revenue_5yr_avg: f64;
     /// Free Operating Cash Flow/Revenue, TTM
-    pub free_operating_cash_flow_to_revenue_ttm: ItemDetail<f64>,
+    "Focf2Rev_TTM" => FreeOperatingCashFlowToRevenueTtm, free_operating_cash_flow_to_revenue_ttm: f64;
     /// Gross Margin - 5 year average
-    pub gross_margin_5yr_avg: ItemDetail<f64>,
+    "GROSMGN5YR" => GrossMargin5yrAvg, gross_margin_5yr_avg: f64;
     /// Net Profit Margin - 5 year average
-    pub net_profit_margin_5yr_avg: ItemDetail<f64>,
+    "MARGIN5YR" => NetProfitMargin5yrAvg, net_profit_margin_5yr_avg: f64;
     /// Return on average assets - most recent fiscal year
-    pub return_on_average_assets_annual: ItemDetail<f64>,
+    "AROAPCT" => ReturnOnAverageAssetsAnnual, return_on_average_assets_annual: f64;
     /// Return on average assets - trailing 12 month
-    pub return_on_average_assets_ttm: ItemDetail<f64>,
+    "TTMROAPCT" => ReturnOnAverageAssetsTtm, return_on_average_assets_ttm: f64;
     /// Return on average equity - most recent fiscal year
-    pub return_on_average_equity_annual: ItemDetail<f64>,
+    "AROEPCT" => ReturnOnAverageEquityAnnual, return_on_average_equity_annual: f64;
     /// Return on average equity - trailing 12 month
-    pub return_on_average_equity_ttm: ItemDetail<f64>,
+    "TTMROEPCT" => ReturnOnAverageEquityTtm, return_on_average_equity_ttm: f64;
     /// Return on investment - most recent fiscal year
-    pub return_on_investment_annual: ItemDetail<f64>,
+    "AROIPCT" => ReturnOnInvestmentAnnual, return_on_investment_annual: f64;
     /// Return on investment - trailing 12 month
-    pub return_on_investment_ttm: ItemDetail<f64>,
+    "TTMROIPCT" => ReturnOnInvestmentTtm, return_on_investment_ttm: f64;
     /// Return on average assets - 5 year average
-    pub return_on_average_assets_5yr_avg: ItemDetail<f64>,
+    "AROA5YAVG" => ReturnOnAverageAssets5yrAvg, return_on_average_assets_5yr_avg: f64;
     /// Return on average equity - 5 year average
-    pub return_on_average_equity_5yr_avg: ItemDetail<f64>,
+    "AROE5YAVG" => ReturnOnAverageEquity5yrAvg, return_on_average_equity_5yr_avg: f64;
     /// Return on investment - 5 year average
-    pub return_on_investment_5yr_avg: ItemDetail<f64>,
+    "AROI5YRAVG" => ReturnOnInvestment5yrAvg, return_on_investment_5yr_avg: f64;
     /// Asset turnover - most recent fiscal year
-    pub asset_turnover_annual: ItemDetail<f64>,
+    "AASTTURN" => AssetTurnoverAnnual, asset_turnover_annual: f64;
     /// Asset turnover - trailing 12 month
-    pub asset_turnover_ttm: ItemDetail<f64>,
+    "TTMASTTURN" => AssetTurnoverTtm, asset_turnover_ttm: f64;
     /// Inventory turnover - most recent fiscal year
-    pub inventory_turnover_annual: ItemDetail<f64>,
+    "AINVTURN" => InventoryTurnoverAnnual, inventory_turnover_annual: f64;
     /// Inventory turnover - trailing 12 month
-    pub inventory_turnover_ttm: ItemDetail<f64>,
+    "TTMINVTURN" => InventoryTurnoverTtm, inventory_turnover_ttm: f64;
     /// Net Income per employee - most recent fiscal year
-    pub net_income_per_employee_annual: ItemDetail<f64>,
+    "ANIPEREMP" => NetIncomePerEmployeeAnnual, net_income_per_employee_annual: f64;
     /// Net Income per employee - trailing 12 month
-    pub net_income_per_employee_ttm: ItemDetail<f64>,
+    "TTMNIPEREM" => NetIncomePerEmployeeTtm, net_income_per_employee_ttm: f64;
     /// Receivables turnover - most recent fiscal year
-    pub receivables_turnover_annual: ItemDetail<f64>,
+    "ARECTURN" => ReceivablesTurnoverAnnual, receivables_turnover_annual: f64;
     /// Receivables turnover - trailing 12 month
-    pub receivables_turnover_ttm: ItemDetail<f64>,
+    "TTMRECTURN" => ReceivablesTurnoverTtm, receivables_turnover_ttm: f64;
     /// Revenue per employee - most recent fiscal year
-    pub revenue_per_employee_annual: ItemDetail<f64>,
+    "AREVPEREMP" => RevenuePerEmployeeAnnual, revenue_per_employee_annual: f64;
     /// Revenue per employee - trailing 12 month
-    pub revenue_per_employee_ttm: ItemDetail<f64>,
+    "TTMREVPERE" => RevenuePerEmployeeTtm, revenue_per_employee_ttm: f64;
     /// Revenue Change % - most recent quarter 1 year ago
-    pub revenue_change_percent_last_quarter_year_ago: ItemDetail<f64>,
+    "REVCHNGYR" => RevenueChangePercentLastQuarterYearAgo, revenue_change_percent_last_quarter_year_ago: f64;
     /// Revenue growth rate, 5 year
-    pub revenue_growth_rate_5_year: ItemDetail<f64>,
+    "REVTRENDGR" => RevenueGrowthRate5Year, revenue_growth_rate_5_year: f64;
     /// EPS Change % - most recent quarter 1 year ago
-    pub eps_change_percent_last_quarter_year_ago: ItemDetail<f64>,
+    "EPSCHNGYR" => EpsChangePercentLastQuarterYearAgo, eps_change_percent_last_quarter_year_ago: f64;
     /// EPS Change %, TTM over TTM
-    pub eps_change_percent_ttm_over_ttm: ItemDetail<f64>,
+    "TTMEPSCHG" => EpsChangePercentTtmOverTtm, eps_change_percent_ttm_over_ttm: f64;
     /// EPS growth rate, 5 year
-    pub eps_growth_rate_5_year: ItemDetail<f64>,
+    "EPSTRENDGR" => EpsGrowthRate5Year, eps_growth_rate_5_year: f64;
     /// Growth rate% - dividend, 3 year
-    pub dividend_growth_rate_3_year: ItemDetail<f64>,
+    "DIVGRPCT" => DividendGrowthRate3Year, dividend_growth_rate_3_year: f64;
     /// Revenue Change %, TTM over TTM
-    pub revenue_change_percent_ttm_over_ttm: ItemDetail<f64>,
+    "TTMREVCHG" => RevenueChangePercentTtmOverTtm, revenue_change_percent_ttm_over_ttm: f64;
     /// Revenue/share (5 yr growth)
-    pub revenue_per_share_5_year_growth: ItemDetail<f64>,
+    "REVPS5YGR" => RevenuePerShare5YearGrowth, revenue_per_share_5_year_growth: f64;
     /// Growth rate% - Revenue, 3 year
-    pub revenue_growth_rate_3_year: ItemDetail<f64>,
+    "REVGRPCT" => RevenueGrowthRate3Year, revenue_growth_rate_3_year: f64;
     /// Growth rate% - EPS, 3 year
-    pub eps_growth_rate_3_year: ItemDetail<f64>,
+    "EPSGRPCT" => EpsGrowthRate3Year, eps_growth_rate_3_year: f64;
     /// Book value per share growth rate, 5 year
-    pub book_value_per_share_growth_rate_5_year: ItemDetail<f64>,
+    "BVTRENDGR" => BookValuePerShareGrowthRate5Year, book_value_per_share_growth_rate_5_year: f64;
     /// Tangible Book Value, Total Equity, 5 Year CAGR
-    pub tangible_book_value_total_equity_5yr_cagr: ItemDetail<f64>,
+    "TanBV_AYr5CAGR" => TangibleBookValueTotalEquity5yrCagr, tangible_book_value_total_equity_5yr_cagr: f64;
     /// Capital Spending growth rate, 5 year
-    pub capital_spending_growth_rate_5_year: ItemDetail<f64>,
+    "CSPTRENDGR" => CapitalSpendingGrowthRate5Year, capital_spending_growth_rate_5_year: f64;
     /// Earnings Before Interest, Taxes, Depreciation & Amortization, 5 Year CAGR
-    pub ebitda_5_year_cagr: ItemDetail<f64>,
+    "Ebitda_AYr5CAGR" => Ebitda5YearCagr, ebitda_5_year_cagr: f64;
     /// Earnings Before Interest, Taxes, Depreciation & Amortization, 5 Year Interim CAGR
-    pub ebitda_5_year_interim_cagr: ItemDetail<f64>,
+    "Ebitda_TTMY5CAGR" => Ebitda5YearInterimCagr, ebitda_5_year_interim_cagr: f64;
     /// Free Operating Cash Flow, 5 Year CAGR
-    pub free_operating_cash_flow_5_year_cagr: ItemDetail<f64>,
+    "FOCF_AYr5CAGR" => FreeOperatingCashFlow5YearCagr, free_operating_cash_flow_5_year_cagr: f64;
     /// Total Debt, 5 Year CAGR
-    pub total_debt_5_year_cagr: ItemDetail<f64>,
+    "STLD_AYr5CAGR" => TotalDebt5YearCagr, total_debt_5_year_cagr: f64;
     /// Net Profit Margin growth rate, 5 year
-    pub net_profit_margin_growth_rate_5_year: ItemDetail<f64>,
+    "NPMTRENDGR" => NetProfitMarginGrowthRate5Year, net_profit_margin_growth_rate_5_year: f64;
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -373,156 +440,156 @@ where
     }
 }
 
-fn fill_ratio(current_ratios: &mut CurrentRatios, item: &Value) {
-    match item["id"].as_str().unwrap() {
-        "NPRICE" => current_ratios.current_price = item.into(),
-        "NHIG" => current_ratios.high_12m = item.into(),
-        "NLOW" => current_ratios.low_12m = item.into(),
-        "PDATE" => current_ratios.pricing_date = item.into(),
-        "VOL10DAVG" => current_ratios.volume_avg_10d = item.into(),
-        "MKTCAP" => current_ratios.market_cap = item.into(),
-        "NHIGDATE" => current_ratios.high_date_12m = item.into(),
-        "NLOWDATE" => current_ratios.low_date_12m = item.into(),
-        "VOL3MAVG" => current_ratios.volume_avg_3m = item.into(),
-        "BETA" => current_ratios.beta = item.into(),
-        "PR1DAYPRC" => current_ratios.price_change_1d = item.into(),
-        "PR13WKPCT" => current_ratios.price_change_13w = item.into(),
-        "PR26WKPCT" => current_ratios.price_change_26w = item.into(),
-        "PR5DAYPRC" => current_ratios.price_change_5d = item.into(),
-        "PR52WKPCT" => current_ratios.price_change_52w = item.into(),
-        "PRYTDPCT" => current_ratios.price_change_ytd = item.into(),
-        "ChPctPriceMTD" => current_ratios.price_change_mtd = item.into(),
-        "PR04WKPCTR" => current_ratios.relative_price_change_4w = item.into(),
-        "PR13WKPCTR" => current_ratios.relative_price_change_13w = item.into(),
-        "PR26WKPCTR" => current_ratios.relative_price_change_26w = item.into(),
-        "PR52WKPCTR" => current_ratios.relative_price_change_52w = item.into(),
-        "PRYTDPCTR" => current_ratios.relative_price_change_ytd = item.into(),
-        "AEPSXCLXOR" => current_ratios.eps_excluding_extraordinary_items_annual = item.into(),
-        "TTMEPSXCLX" => current_ratios.eps_excluding_extraordinary_items_ttm = item.into(),
-        "AEPSNORM" => current_ratios.eps_normalized_annual = item.into(),
-        "AREVPS" => current_ratios.revenue_per_share_annual = item.into(),
-        "TTMREVPS" => current_ratios.revenue_per_share_ttm = item.into(),
-        "ABVPS" => current_ratios.book_value_per_share_annual = item.into(),
-        "QBVPS" => current_ratios.book_value_per_share_quarterly = item.into(),
-        "ATANBVPS" => current_ratios.tangible_book_value_per_share_annual = item.into(),
-        "QTANBVPS" => current_ratios.tangible_book_value_per_share_quarterly = item.into(),
-        "ACSHPS" => current_ratios.cash_per_share_annual = item.into(),
-        "QCSHPS" => current_ratios.cash_per_share_quarterly = item.into(),
-        "ACFSHR" => current_ratios.cash_flow_per_share_annual = item.into(),
-        "TTMCFSHR" => current_ratios.cash_flow_per_share_ttm = item.into(),
-        "ADIVSHR" => current_ratios.dividend_per_share_annual = item.into(),
-        "TTMDIVSHR" => current_ratios.dividend_per_share_ttm = item.into(),
-        "TTMEBITDPS" => current_ratios.ebitd_per_share_ttm = item.into(),
-        "ABEPSXCLXO" => current_ratios.abepsxclxo = item.into(),
-        "TTMBEPSXCL" => current_ratios.eps_basic_excluding_extraordinary_items_ttm = item.into(),
-        "AEPSINCLXO" => current_ratios.eps_including_extraordinary_items_annual = item.into(),
-        "TTMEPSINCX" => current_ratios.eps_including_extraordinary_items_ttm = item.into(),
-        "TTMFCFSHR" => current_ratios.free_cash_flow_per_share_ttm = item.into(),
-        "ADIV5YAVG" => current_ratios.dividend_per_share_5yr_avg = item.into(),
-        "APEEXCLXOR" => current_ratios.pe_excluding_extraordinary_items_annual = item.into(),
-        "PEEXCLXOR" => current_ratios.pe_excluding_extraordinary_items_ttm = item.into(),
-        "APENORM" => current_ratios.pe_normalized_annual = item.into(),
-        "APR2REV" => current_ratios.price_to_sales_annual = item.into(),
-        "TTMPR2REV" => current_ratios.price_to_sales_ttm = item.into(),
-        "APR2TANBK" => current_ratios.price_to_tangible_book_annual = item.into(),
-        "PR2TANBK" => current_ratios.price_to_tangible_book_quarterly = item.into(),
-        "APRFCFPS" => current_ratios.price_to_free_cash_flow_per_share_annual = item.into(),
-        "TTMPRCFPS" => current_ratios.price_to_cash_flow_per_share_ttm = item.into(),
-        "TTMPRFCFPS" => current_ratios.price_to_free_cash_flow_per_share_ttm = item.into(),
-        "APRICE2BK" => current_ratios.price_to_book_annual = item.into(),
-        "PRICE2BK" => current_ratios.price_to_book_quarterly = item.into(),
-        "PEBEXCLXOR" => current_ratios.pe_basic_excluding_extraordinary_items_ttm = item.into(),
-        "TTMPEHIGH" => current_ratios.pe_high_excluding_extraordinary_items_ttm = item.into(),
-        "TTMPELOW" => current_ratios.pe_low_excluding_extraordinary_items_ttm = item.into(),
-        "PEINCLXOR" => current_ratios.pe_including_extraordinary_items_ttm = item.into(),
-        "NetDebt_I" => current_ratios.net_debt_lfi = item.into(),
-        "NetDebt_A" => current_ratios.net_debt_lfy = item.into(),
-        "YLD5YAVG" => current_ratios.dividend_yield_5yr_avg = item.into(),
-        "YIELD" => current_ratios.dividend_yield = item.into(),
-        "DivYield_CurTTM" => current_ratios.current_dividend_yield_ttm = item.into(),
-        "ACURRATIO" => current_ratios.current_ratio_annual = item.into(),
-        "QCURRATIO" => current_ratios.current_ratio_quarterly = item.into(),
-        "AQUICKRATI" => current_ratios.quick_ratio_annual = item.into(),
-        "QQUICKRATI" => current_ratios.quick_ratio_quarterly = item.into(),
-        "ALTD2EQ" => current_ratios.long_term_debt_to_equity_annual = item.into(),
-        "QLTD2EQ" => current_ratios.long_term_debt_to_equity_quarterly = item.into(),
-        "ATOTD2EQ" => current_ratios.total_debt_to_equity_annual = item.into(),
-        "QTOTD2EQ" => current_ratios.total_debt_to_equity_quarterly = item.into(),
-        "APAYRATIO" => current_ratios.payout_ratio_annual = item.into(),
-        "TTMPAYRAT" => current_ratios.payout_ratio_ttm = item.into(),
-        "EV2FCF_CurA" => current_ratios.ev_to_free_cash_flow_current_annual = item.into(),
-        "EV2FCF_CurTTM" => current_ratios.ev_to_free_cash_flow_current_ttm = item.into(),
-        "AINTCOV" => current_ratios.interest_coverage_annual = item.into(),
-        "TTMINTCOV" => current_ratios.interest_coverage_ttm = item.into(),
-        "A1FCF" => current_ratios.free_cash_flow_historical_annual = item.into(),
-        "TTMFCF" => current_ratios.free_cash_flow_ttm = item.into(),
-        "AREV" => current_ratios.revenue_annual = item.into(),
-        "TTMREV" => current_ratios.revenue_ttm = item.into(),
-        "AEBITD" => current_ratios.ebitd_annual = item.into(),
-        "TTMEBITD" => current_ratios.ebitd_ttm = item.into(),
-        "AEBT" => current_ratios.earnings_before_taxes_annual = item.into(),
-        "TTMEBT" => current_ratios.earnings_before_taxes_ttm = item.into(),
-        "ANIAC" => current_ratios.net_income_to_common_annual = item.into(),
-        "TTMNIAC" => current_ratios.net_income_to_common_ttm = item.into(),
-        "AEBTNORM" => current_ratios.normalized_earnings_before_taxes_annual = item.into(),
-        "ANIACNORM" => current_ratios.normalized_net_income_to_common_annual = item.into(),
-        "VDES_TTM" => current_ratios.normalized_eps_excluding_extraordinary_ttm = item.into(),
-        "AGROSMGN" => current_ratios.gross_margin_first_historical_year = item.into(),
-        "TTMGROSMGN" => current_ratios.gross_margin_ttm = item.into(),
-        "ANPMGNPCT" => current_ratios.net_profit_margin_first_historical_year = item.into(),
-        "TTMNPMGN" => current_ratios.net_profit_margin_ttm = item.into(),
-        "AOPMGNPCT" => current_ratios.operating_margin_first_historical_year = item.into(),
-        "TTMOPMGN" => current_ratios.operating_margin_ttm = item.into(),
-        "TTMPTMGN" => current_ratios.pretax_margin_ttm = item.into(),
-        "APTMGNPCT" => current_ratios.pretax_margin_first_historical_year = item.into(),
-        "OPMGN5YR" => current_ratios.operating_margin_5yr_avg = item.into(),
-        "PTMGN5YR" => current_ratios.pretax_margin_5yr_avg = item.into(),
-        "Focf2Rev_AAvg5" => {
-            current_ratios.free_operating_cash_flow_to_revenue_5yr_avg = item.into()
+impl From<String> for RatioId {
+    fn from(code: String) -> Self {
+        code.as_str().into()
+    }
+}
+
+impl From<RatioId> for String {
+    fn from(id: RatioId) -> Self {
+        id.code().to_string()
+    }
+}
+
+impl std::fmt::Display for RatioId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RatioValue {
+    pub meaning: String,
+    /// Raw value, numbers and dates are both sent as strings.
+    pub value: Option<String>,
+}
+
+impl RatioValue {
+    pub fn as_f64(&self) -> Option<f64> {
+        self.value.as_deref()?.parse().ok()
+    }
+}
+
+/// Ratios keyed by [`RatioId`], retains ratios `CurrentRatios` has no field for.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RatiosMap {
+    pub currency: String,
+    pub price_currency: String,
+    pub items: BTreeMap<RatioId, RatioValue>,
+}
+
+impl RatiosMap {
+    pub fn get(&self, id: &RatioId) -> Option<&RatioValue> {
+        self.items.get(id)
+    }
+    pub fn value(&self, id: &RatioId) -> Option<f64> {
+        self.get(id)?.as_f64()
+    }
+    pub fn insert(&mut self, id: RatioId, value: RatioValue) -> Option<RatioValue> {
+        self.items.insert(id, value)
+    }
+    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, RatioId, RatioValue> {
+        self.items.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    /// Ratios not modeled by [`CurrentRatios`] yet.
+    pub fn unknown(&self) -> impl Iterator<Item = (&RatioId, &RatioValue)> {
+        self.items
+            .iter()
+            .filter(|(id, _)| matches!(id, RatioId::Unknown(_)))
+    }
+}
+
+impl<'a> IntoIterator for &'a RatiosMap {
+    type Item = (&'a RatioId, &'a RatioValue);
+    type IntoIter = std::collections::btree_map::Iter<'a, RatioId, RatioValue>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl From<&Value> for RatiosMap {
+    fn from(value: &Value) -> Self {
+        let mut map = RatiosMap {
+            currency: value["currency"].as_str().unwrap_or_default().to_string(),
+            price_currency: value["priceCurrency"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            ..Default::default()
+        };
+        let groups = value["ratiosGroups"].as_array().map(Vec::as_slice);
+        for group in groups.unwrap_or_default() {
+            for item in group["items"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+            {
+                let Some(id) = item["id"].as_str() else {
+                    continue;
+                };
+                let value = RatioValue {
+                    meaning: item["name"].as_str().unwrap_or_default().to_string(),
+                    value: item["value"].as_str().map(str::to_string),
+                };
+                map.items.insert(id.into(), value);
+            }
         }
-        "Focf2Rev_TTM" => current_ratios.free_operating_cash_flow_to_revenue_ttm = item.into(),
-        "GROSMGN5YR" => current_ratios.gross_margin_5yr_avg = item.into(),
-        "MARGIN5YR" => current_ratios.net_profit_margin_5yr_avg = item.into(),
-        "AROAPCT" => current_ratios.return_on_average_assets_annual = item.into(),
-        "TTMROAPCT" => current_ratios.return_on_average_assets_ttm = item.into(),
-        "AROEPCT" => current_ratios.return_on_average_equity_annual = item.into(),
-        "TTMROEPCT" => current_ratios.return_on_average_equity_ttm = item.into(),
-        "AROIPCT" => current_ratios.return_on_investment_annual = item.into(),
-        "TTMROIPCT" => current_ratios.return_on_investment_ttm = item.into(),
-        "AROA5YAVG" => current_ratios.return_on_average_assets_5yr_avg = item.into(),
-        "AROE5YAVG" => current_ratios.return_on_average_equity_5yr_avg = item.into(),
-        "AROI5YRAVG" => current_ratios.return_on_investment_5yr_avg = item.into(),
-        "AASTTURN" => current_ratios.asset_turnover_annual = item.into(),
-        "TTMASTTURN" => current_ratios.asset_turnover_ttm = item.into(),
-        "AINVTURN" => current_ratios.inventory_turnover_annual = item.into(),
-        "TTMINVTURN" => current_ratios.inventory_turnover_ttm = item.into(),
-        "ANIPEREMP" => current_ratios.net_income_per_employee_annual = item.into(),
-        "TTMNIPEREM" => current_ratios.net_income_per_employee_ttm = item.into(),
-        "ARECTURN" => current_ratios.receivables_turnover_annual = item.into(),
-        "TTMRECTURN" => current_ratios.receivables_turnover_ttm = item.into(),
-        "AREVPEREMP" => current_ratios.revenue_per_employee_annual = item.into(),
-        "TTMREVPERE" => current_ratios.revenue_per_employee_ttm = item.into(),
-        "REVCHNGYR" => current_ratios.revenue_change_percent_last_quarter_year_ago = item.into(),
-        "REVTRENDGR" => current_ratios.revenue_growth_rate_5_year = item.into(),
-        "EPSCHNGYR" => current_ratios.eps_change_percent_last_quarter_year_ago = item.into(),
-        "TTMEPSCHG" => current_ratios.eps_change_percent_ttm_over_ttm = item.into(),
-        "EPSTRENDGR" => current_ratios.eps_growth_rate_5_year = item.into(),
-        "DIVGRPCT" => current_ratios.dividend_growth_rate_3_year = item.into(),
-        "TTMREVCHG" => current_ratios.revenue_change_percent_ttm_over_ttm = item.into(),
-        "REVPS5YGR" => current_ratios.revenue_per_share_5_year_growth = item.into(),
-        "REVGRPCT" => current_ratios.revenue_growth_rate_3_year = item.into(),
-        "EPSGRPCT" => current_ratios.eps_growth_rate_3_year = item.into(),
-        "BVTRENDGR" => current_ratios.book_value_per_share_growth_rate_5_year = item.into(),
-        "TanBV_AYr5CAGR" => current_ratios.tangible_book_value_total_equity_5yr_cagr = item.into(),
-        "CSPTRENDGR" => current_ratios.capital_spending_growth_rate_5_year = item.into(),
-        "Ebitda_AYr5CAGR" => current_ratios.ebitda_5_year_cagr = item.into(),
-        "Ebitda_TTMY5CAGR" => current_ratios.ebitda_5_year_interim_cagr = item.into(),
-        "FOCF_AYr5CAGR" => current_ratios.free_operating_cash_flow_5_year_cagr = item.into(),
-        "STLD_AYr5CAGR" => current_ratios.total_debt_5_year_cagr = item.into(),
-        "NPMTRENDGR" => current_ratios.net_profit_margin_growth_rate_5_year = item.into(),
-        _ => {
-            panic!("Unknown item id: {}", item["id"].as_str().unwrap())
+        map
+    }
+}
+
+trait RatioString {
+    fn ratio_string(&self) -> String;
+}
+
+impl RatioString for f64 {
+    fn ratio_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl RatioString for NaiveDateTime {
+    fn ratio_string(&self) -> String {
+        self.format("%Y-%m-%dT%H:%M:%S").to_string()
+    }
+}
+
+fn ratio_value<T: RatioString>(item: &ItemDetail<T>) -> RatioValue {
+    RatioValue {
+        meaning: item.meaning.clone(),
+        value: item.value.as_ref().map(RatioString::ratio_string),
+    }
+}
+
+impl From<&RatiosMap> for CurrentRatios {
+    fn from(map: &RatiosMap) -> Self {
+        let mut current_ratios = CurrentRatios {
+            currency: map.currency.clone(),
+            price_currency: map.price_currency.clone(),
+            ..Default::default()
+        };
+        for (id, value) in map {
+            if matches!(id, RatioId::Unknown(_)) {
+                continue;
+            }
+            let mut item = serde_json::json!({
+                "id": id.code(),
+                "name": value.meaning,
+            });
+            if let Some(x) = &value.value {
+                item["value"] = Value::from(x.as_str());
+            }
+            fill_ratio(&mut current_ratios, &item);
         }
+        current_ratios
     }
 }
 
@@ -596,9 +663,26 @@ impl Client {
         id: impl AsRef<str>,
         isin: impl AsRef<str>,
     ) -> Result<CompanyRatios, ClientError> {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        let isin = isin.as_ref();
+        let mut data = self
+            .cached(
+                &format!("company_ratios/{isin}"),
+                self.company_ratios_raw(isin),
+            )
+            .await?;
+
+        let ratios = RatiosMap::from(&data["currentRatios"]);
+        let company_ratios = CompanyRatios {
+            id: id.as_ref().to_string(),
+            current_ratios: CurrentRatios::from(data["currentRatios"].take()),
+            ratios,
+        };
+
+        Ok(company_ratios)
+    }
+
+    async fn company_ratios_raw(&self, isin: &str) -> Result<serde_json::Value, ClientError> {
+        self.require(Endpoint::Refinitiv)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = "https://trader.degiro.nl/";
@@ -607,55 +691,32 @@ impl Client {
                 .unwrap()
                 .join(path_url)
                 .unwrap()
-                .join(isin.as_ref())
+                .join(isin)
                 .unwrap();
 
             inner
                 .http_client
                 .get(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                ])
                 .header(header::REFERER, &inner.referer)
                 .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut json = res.json::<serde_json::Value>().await?;
-                let mut data = json["data"].take();
-                if data.is_null() {
-                    return Err(ClientError::NoData);
-                }
-
-                let company_ratios = CompanyRatios {
-                    id: id.as_ref().to_string(),
-                    current_ratios: CurrentRatios::from(data["currentRatios"].take()),
-                };
+        let res = self.send(Endpoint::Refinitiv, req).await?;
 
-                Ok(company_ratios)
-            }
-            Err(err) => {
-                eprintln!("error: {}", err);
-                Err(err.into())
-            }
+        let mut json = res.json::<serde_json::Value>().await?;
+        let data = json["data"].take();
+        if data.is_null() {
+            return Err(ClientError::NoData);
         }
+
+        Ok(data)
     }
 }
 
 #[cfg(test)]
 mod test {
 
-    use crate::client::Client;
+    use super::*;
 
     #[tokio::test]
     async fn company_ratios() {
@@ -666,4 +727,35 @@ mod test {
         let report = client.company_ratios_by_id("15850348").await.unwrap();
         println!("{:#?}", report);
     }
+
+    #[test]
+    fn ratios_map_roundtrip() {
+        let raw = serde_json::json!({
+            "currency": "USD",
+            "priceCurrency": "USD",
+            "ratiosGroups": [{
+                "items": [
+                    {"id": "MKTCAP", "name": "Market capitalization", "value": "1000.5"},
+                    {"id": "PDATE", "name": "Pricing date", "value": "2024-01-05T00:00:00"},
+                    {"id": "BRANDNEW", "name": "New ratio", "value": "7"}
+                ]
+            }]
+        });
+        let map = RatiosMap::from(&raw);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.value(&RatioId::MarketCap), Some(1000.5));
+        assert_eq!(map.unknown().count(), 1);
+
+        let ratios = CurrentRatios::from(&map);
+        assert_eq!(ratios.market_cap.value, Some(1000.5));
+        let back = RatiosMap::from(&ratios);
+        assert_eq!(
+            back.get(&RatioId::PricingDate),
+            map.get(&RatioId::PricingDate)
+        );
+        assert_eq!(RatioId::from("BRANDNEW").code(), "BRANDNEW");
+        for id in RatioId::KNOWN {
+            assert_eq!(&RatioId::from(id.code()), id);
+        }
+    }
 }
@@ -4,15 +4,109 @@ use chrono::{DateTime, Utc};
 use reqwest::{header, Url};
 use serde::{Deserialize, Serialize};
 
-use crate::client::{Client, ClientError, ClientStatus};
-
-#[derive(Debug, Default, Deserialize, Serialize)]
-pub struct CuratedLists {
-    pub last_updated: DateTime<Utc>,
-    pub most_traded_weekly: Vec<u64>,
-    pub most_traded_daily: Vec<u64>,
-    pub largest_world_etfs: Vec<u64>,
-    pub most_held: Vec<u64>,
+use crate::client::{Client, ClientError};
+use crate::http::Endpoint;
+
+use super::product::Products;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum CuratedListKind {
+    MostTradedDaily,
+    MostTradedWeekly,
+    LargestWorldEtfs,
+    MostHeld,
+    Unknown(String),
+}
+
+impl From<&str> for CuratedListKind {
+    fn from(s: &str) -> Self {
+        match s {
+            "MOST_TRADED_DAILY" => Self::MostTradedDaily,
+            "MOST_TRADED_WEEKLY" => Self::MostTradedWeekly,
+            "LARGEST_WORLD_ETFS" => Self::LargestWorldEtfs,
+            "MOST_HELD" => Self::MostHeld,
+            other => Self::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for CuratedListKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MostTradedDaily => write!(f, "Most traded today"),
+            Self::MostTradedWeekly => write!(f, "Most traded this week"),
+            Self::LargestWorldEtfs => write!(f, "Largest world ETFs"),
+            Self::MostHeld => write!(f, "Most held"),
+            Self::Unknown(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CuratedList {
+    pub kind: CuratedListKind,
+    pub name: String,
+    pub description: Option<String>,
+    pub product_ids: Vec<u64>,
+    pub last_updated: Option<DateTime<Utc>>,
+}
+
+impl CuratedList {
+    pub fn new(obj: &serde_json::Value) -> Self {
+        let kind = CuratedListKind::from(obj["type"].as_str().unwrap_or_default());
+        Self {
+            name: obj["name"]
+                .as_str()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| kind.to_string()),
+            kind,
+            description: obj["description"].as_str().map(|s| s.to_string()),
+            product_ids: obj["productIds"]
+                .as_array()
+                .map(|xs| xs.iter().filter_map(|id| id.as_u64()).collect())
+                .unwrap_or_default(),
+            last_updated: obj["lastUpdated"]
+                .as_str()
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc)),
+        }
+    }
+
+    /// Resolves the list entries into full products with a single batch lookup.
+    pub async fn products(&self, client: &Client) -> Result<Products, ClientError> {
+        let ids = self
+            .product_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        client.products(ids).await
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CuratedLists(pub Vec<CuratedList>);
+
+impl CuratedLists {
+    pub fn iter(&self) -> std::slice::Iter<'_, CuratedList> {
+        self.0.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn get(&self, kind: &CuratedListKind) -> Option<&CuratedList> {
+        self.0.iter().find(|list| &list.kind == kind)
+    }
+}
+
+impl IntoIterator for CuratedLists {
+    type Item = CuratedList;
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
 }
 
 impl Client {
@@ -20,9 +114,7 @@ impl Client {
     where
         T: AsRef<str> + fmt::Display,
     {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        self.require(Endpoint::CuratedLists)?;
 
         let req = {
             let inner = self.inner.lock().unwrap();
@@ -35,63 +127,19 @@ impl Client {
             inner
                 .http_client
                 .get(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                ])
                 .header(header::REFERER, &inner.referer)
                 .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
+        let res = self.send(Endpoint::CuratedLists, req).await?;
 
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let json = res.json::<serde_json::Value>().await?;
-                let mut list = CuratedLists::default();
-
-                if let Some(first_obj) = json.as_array().and_then(|arr| arr.first()) {
-                    if let Some(last_updated_str) = first_obj["lastUpdated"].as_str() {
-                        if let Ok(last_updated) = DateTime::parse_from_rfc3339(last_updated_str) {
-                            list.last_updated = last_updated.with_timezone(&Utc);
-                        }
-                    }
-                }
-
-                for obj in json.as_array().unwrap_or(&Vec::new()) {
-                    if let Some(product_ids) = obj["productIds"].as_array() {
-                        let ids: Vec<u64> =
-                            product_ids.iter().filter_map(|id| id.as_u64()).collect();
-
-                        match obj["type"].as_str() {
-                            Some("MOST_TRADED_DAILY") => list.most_traded_daily = ids,
-                            Some("MOST_TRADED_WEEKLY") => list.most_traded_weekly = ids,
-                            Some("LARGEST_WORLD_ETFS") => list.largest_world_etfs = ids,
-                            Some("MOST_HELD") => list.most_held = ids,
-                            _ => panic!("Unknown list type: {obj:#?}"),
-                        }
-                    }
-                }
-
-                Ok(list)
-            }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => {
-                    self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
-                }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
-        }
+        let json = res.json::<serde_json::Value>().await?;
+        let lists = json
+            .as_array()
+            .map(|xs| xs.iter().map(CuratedList::new).collect())
+            .unwrap_or_default();
+
+        Ok(CuratedLists(lists))
     }
 }
 
@@ -0,0 +1,193 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use strum::Display;
+
+use crate::{
+    client::{Client, ClientError, ClientStatus},
+    util::Period,
+};
+
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Check {
+    Login,
+    AccountConfig,
+    Portfolio,
+    Chart,
+    Refinitiv,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheckOutcome {
+    Passed,
+    Failed(String),
+    /// Not run, because a check it depends on failed or nothing could be probed.
+    Skipped(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckResult {
+    pub check: Check,
+    pub outcome: CheckOutcome,
+    pub elapsed: Duration,
+}
+
+/// Which subsystems work, as found by [`Client::diagnose`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiagnosticReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl DiagnosticReport {
+    pub fn is_healthy(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|x| !matches!(x.outcome, CheckOutcome::Failed(_)))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks
+            .iter()
+            .filter(|x| matches!(x.outcome, CheckOutcome::Failed(_)))
+    }
+
+    pub fn get(&self, check: Check) -> Option<&CheckResult> {
+        self.checks.iter().find(|x| x.check == check)
+    }
+
+    fn passed(&self, check: Check) -> bool {
+        self.get(check)
+            .is_some_and(|x| x.outcome == CheckOutcome::Passed)
+    }
+
+    fn skip(&mut self, check: Check, reason: impl Into<String>) {
+        self.checks.push(CheckResult {
+            check,
+            outcome: CheckOutcome::Skipped(reason.into()),
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    fn record<T>(
+        &mut self,
+        check: Check,
+        started: Instant,
+        res: Result<T, ClientError>,
+    ) -> Option<T> {
+        let (outcome, value) = match res {
+            Ok(x) => (CheckOutcome::Passed, Some(x)),
+            Err(err) => (CheckOutcome::Failed(err.to_string()), None),
+        };
+        self.checks.push(CheckResult {
+            check,
+            outcome,
+            elapsed: started.elapsed(),
+        });
+        value
+    }
+}
+
+impl fmt::Display for DiagnosticReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for x in &self.checks {
+            match &x.outcome {
+                CheckOutcome::Passed => writeln!(f, "[ok]   {} ({:?})", x.check, x.elapsed)?,
+                CheckOutcome::Failed(err) => {
+                    writeln!(f, "[FAIL] {} ({:?}): {}", x.check, x.elapsed, err)?
+                }
+                CheckOutcome::Skipped(why) => writeln!(f, "[skip] {}: {}", x.check, why)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Runs read-only checks of every subsystem and reports which ones work.
+    ///
+    /// Logs in only when there is no session yet; a check whose prerequisite
+    /// failed is skipped. Chart and Refinitiv are probed with the first
+    /// product of the portfolio.
+    pub async fn diagnose(&self) -> DiagnosticReport {
+        let mut report = DiagnosticReport::default();
+        let status = self.inner.lock().unwrap().status;
+
+        let started = Instant::now();
+        if status == ClientStatus::Unauthorized {
+            let res = self.login().await;
+            report.record(Check::Login, started, res);
+        } else {
+            report.record(Check::Login, started, Ok(()));
+        }
+        if !report.passed(Check::Login) {
+            report.skip(Check::AccountConfig, "login failed");
+        } else {
+            let started = Instant::now();
+            let res = if self.inner.lock().unwrap().status == ClientStatus::Authorized {
+                Ok(())
+            } else {
+                self.account_config().await
+            };
+            report.record(Check::AccountConfig, started, res);
+        }
+
+        if !report.passed(Check::AccountConfig) {
+            for check in [Check::Portfolio, Check::Chart, Check::Refinitiv] {
+                report.skip(check, "account config unavailable");
+            }
+            return report;
+        }
+
+        let started = Instant::now();
+        let res = self.portfolio().await;
+        let product_id = report
+            .record(Check::Portfolio, started, res)
+            .and_then(|p| p.products().current().first().map(|p| p.inner.id.clone()));
+        let Some(product_id) = product_id else {
+            for check in [Check::Chart, Check::Refinitiv] {
+                report.skip(check, "no product in the portfolio to probe with");
+            }
+            return report;
+        };
+
+        let started = Instant::now();
+        let res = self.quotes(&product_id, Period::P1W, Period::P1D).await;
+        report.record(Check::Chart, started, res);
+
+        let started = Instant::now();
+        let res = match self.product(&product_id).await {
+            Ok(product) => self.company_profile(&product.inner.isin).await,
+            Err(err) => Err(err),
+        };
+        report.record(Check::Refinitiv, started, res);
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn diagnose() {
+        let client = Client::new_from_env();
+        let report = client.diagnose().await;
+        println!("{report}");
+    }
+
+    #[test]
+    fn report_health() {
+        let mut report = DiagnosticReport::default();
+        report.record(Check::Login, Instant::now(), Ok(()));
+        report.skip(Check::Chart, "no product");
+        assert!(report.is_healthy());
+
+        report.record::<()>(Check::Portfolio, Instant::now(), Err(ClientError::NoData));
+        assert!(!report.is_healthy());
+        assert_eq!(report.failed().count(), 1);
+        assert!(report.to_string().contains("[FAIL] Portfolio"));
+    }
+}
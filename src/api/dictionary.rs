@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use reqwest::{header, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{Client, ClientError},
+    http::Endpoint,
+    util::Exchange,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct CountryId(pub i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct IndexId(pub i32);
+
+/// Same ids as `exchangeId` of products, see [`StockMarket::exchange`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct StockMarketId(pub i32);
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Country {
+    pub id: CountryId,
+    /// ISO 3166 code, e.g. `NL`.
+    pub name: String,
+    #[serde(default)]
+    pub translation: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+    pub id: IndexId,
+    /// Short name, e.g. `AEX`.
+    pub name: String,
+    #[serde(default)]
+    pub product_id: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StockMarket {
+    pub id: StockMarketId,
+    pub name: String,
+    /// DEGIRO's code, e.g. `EAM`.
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub mic_code: Option<String>,
+    /// ISO code of the country.
+    #[serde(default)]
+    pub country: Option<String>,
+}
+
+impl StockMarket {
+    pub fn exchange(&self) -> Exchange {
+        Exchange::from(self.id.0)
+    }
+}
+
+/// Lookup tables behind the ids used in product search filters.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dictionary {
+    #[serde(default)]
+    pub countries: Vec<Country>,
+    #[serde(default)]
+    pub indices: Vec<Index>,
+    #[serde(default, rename = "exchanges")]
+    pub stock_markets: Vec<StockMarket>,
+}
+
+impl Dictionary {
+    /// Country by ISO code, ignoring case.
+    pub fn country(&self, code: &str) -> Option<&Country> {
+        self.countries
+            .iter()
+            .find(|x| x.name.eq_ignore_ascii_case(code))
+    }
+
+    pub fn country_by_id(&self, id: CountryId) -> Option<&Country> {
+        self.countries.iter().find(|x| x.id == id)
+    }
+
+    /// Index by short name, ignoring case.
+    pub fn index(&self, name: &str) -> Option<&Index> {
+        self.indices
+            .iter()
+            .find(|x| x.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn index_by_id(&self, id: IndexId) -> Option<&Index> {
+        self.indices.iter().find(|x| x.id == id)
+    }
+
+    /// Stock market by DEGIRO or MIC code, ignoring case.
+    pub fn stock_market(&self, code: &str) -> Option<&StockMarket> {
+        self.stock_markets.iter().find(|x| {
+            [&x.code, &x.mic_code]
+                .into_iter()
+                .flatten()
+                .any(|c| c.eq_ignore_ascii_case(code))
+        })
+    }
+
+    pub fn stock_market_by_id(&self, id: StockMarketId) -> Option<&StockMarket> {
+        self.stock_markets.iter().find(|x| x.id == id)
+    }
+
+    /// Markets of a country, by its ISO code.
+    pub fn stock_markets_in(&self, country: &str) -> impl Iterator<Item = &StockMarket> + '_ {
+        let country = country.to_string();
+        self.stock_markets.iter().filter(move |x| {
+            x.country
+                .as_deref()
+                .is_some_and(|c| c.eq_ignore_ascii_case(&country))
+        })
+    }
+}
+
+impl Client {
+    /// DEGIRO's dictionary, fetched once and kept for the client's lifetime.
+    pub async fn dictionary(&self) -> Result<Arc<Dictionary>, ClientError> {
+        if let Some(dictionary) = self.inner.lock().unwrap().dictionary.clone() {
+            return Ok(dictionary);
+        }
+        self.require(Endpoint::Dictionary)?;
+        let req = {
+            let inner = self.inner.lock().unwrap();
+            let base_url = &inner.account_config.dictionary_url;
+            let url =
+                Url::parse(base_url).unwrap_or_else(|_| panic!("can't parse base_url: {base_url}"));
+
+            inner
+                .http_client
+                .get(url)
+                .header(header::REFERER, &inner.referer)
+        };
+
+        let res = self.send(Endpoint::Dictionary, req).await?;
+        let dictionary = Arc::new(res.json::<Dictionary>().await?);
+        self.inner.lock().unwrap().dictionary = Some(dictionary.clone());
+        Ok(dictionary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookups() {
+        let dictionary: Dictionary = serde_json::from_value(serde_json::json!({
+            "countries": [
+                {"id": 846, "name": "NL", "region": {"id": 1}, "translation": "list.country.528"},
+                {"id": 906, "name": "DE"},
+            ],
+            "indices": [{"id": 5, "name": "AEX", "productId": 360114899}],
+            "exchanges": [
+                {"id": 200, "name": "Euronext Amsterdam", "code": "EAM", "micCode": "XAMS", "country": "NL"},
+                {"id": 194, "name": "Xetra", "code": "XET", "micCode": "XETR", "country": "DE"},
+            ],
+            "productTypes": [],
+        }))
+        .unwrap();
+
+        assert_eq!(dictionary.country("nl").unwrap().id, CountryId(846));
+        assert_eq!(dictionary.index("aex").unwrap().id, IndexId(5));
+        let xetra = dictionary.stock_market("XETR").unwrap();
+        assert_eq!(xetra.id, StockMarketId(194));
+        assert!(matches!(xetra.exchange(), Exchange::XET));
+        assert_eq!(dictionary.stock_markets_in("NL").count(), 1);
+        assert!(dictionary.country_by_id(CountryId(1)).is_none());
+    }
+
+    #[tokio::test]
+    async fn dictionary() {
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let dictionary = client.dictionary().await.unwrap();
+        dbg!(dictionary.country("NL"));
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::{
+    client::{Client, ClientError},
+    money::Currency,
+    util::{Period, TransactionType},
+};
+
+use super::{
+    account::{history_period, CashMovement, CashMovementType},
+    portfolio::PositionType,
+    transactions::TransactionDetails,
+};
+
+/// Account value at the end of a day, in the base currency.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct EquityPoint {
+    pub date: NaiveDate,
+    pub cash: f64,
+    pub positions: f64,
+    /// Deposits less withdrawals since the previous point; time weighted
+    /// returns take these out of the value change.
+    pub net_flow: f64,
+}
+
+impl EquityPoint {
+    pub fn value(&self) -> f64 {
+        self.cash + self.positions
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EquityCurve {
+    pub currency: Currency,
+    pub points: Vec<EquityPoint>,
+}
+
+/// Signed product sizes at the end of `date`, undoing the fills booked after
+/// it from the `current` sizes.
+fn sizes_at(
+    current: &HashMap<i32, f64>,
+    transactions: &[TransactionDetails],
+    date: NaiveDate,
+) -> HashMap<i32, f64> {
+    let mut sizes = current.clone();
+    for tx in transactions.iter().filter(|tx| tx.date.date_naive() > date) {
+        let quantity = f64::from(tx.quantity.abs());
+        let size = sizes.entry(tx.product_id).or_default();
+        match tx.transaction_type {
+            TransactionType::Buy => *size -= quantity,
+            TransactionType::Sell => *size += quantity,
+        }
+    }
+    sizes.retain(|_, size| *size != 0.0);
+    sizes
+}
+
+/// Cash per currency at the end of `date`: the balance after the last
+/// movement up to it, else the balance before the first one after it, else
+/// the `current` balance.
+fn cash_at(
+    current: &HashMap<Currency, f64>,
+    movements: &[CashMovement],
+    date: NaiveDate,
+) -> HashMap<Currency, f64> {
+    let mut cash = current.clone();
+    let mut before: HashMap<Currency, &CashMovement> = HashMap::new();
+    let mut after: HashMap<Currency, &CashMovement> = HashMap::new();
+    for m in movements {
+        let Ok(currency) = m.currency.parse() else {
+            continue;
+        };
+        let key = (m.date, m.id);
+        if m.date.date_naive() <= date {
+            let entry = before.entry(currency).or_insert(m);
+            if key > (entry.date, entry.id) {
+                *entry = m;
+            }
+        } else {
+            let entry = after.entry(currency).or_insert(m);
+            if key < (entry.date, entry.id) {
+                *entry = m;
+            }
+        }
+    }
+    for (currency, m) in after {
+        cash.insert(currency, m.balance.total - m.change);
+    }
+    for (currency, m) in before {
+        cash.insert(currency, m.balance.total);
+    }
+    cash
+}
+
+/// Deposits less withdrawals per currency booked after `after` up to `to`.
+fn net_flows(
+    movements: &[CashMovement],
+    after: Option<NaiveDate>,
+    to: NaiveDate,
+) -> HashMap<Currency, f64> {
+    let mut flows = HashMap::new();
+    for m in movements {
+        let date = m.date.date_naive();
+        if after.is_some_and(|after| date <= after) || date > to {
+            continue;
+        }
+        if !matches!(
+            m.movement_type,
+            CashMovementType::Deposit(_) | CashMovementType::BankWithdrawal(_)
+        ) {
+            continue;
+        }
+        if let Ok(currency) = m.currency.parse() {
+            *flows.entry(currency).or_default() += m.change;
+        }
+    }
+    flows
+}
+
+impl Client {
+    /// Account value every `interval` from `from` to `to`, rebuilt from the
+    /// current portfolio by undoing later fills and cash movements, with
+    /// positions valued at daily closes and converted at daily FX closes.
+    ///
+    /// Products without a close on a date are left out of that point.
+    pub async fn equity_curve(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        interval: Period,
+    ) -> Result<EquityCurve, ClientError> {
+        let info = self.account_info().await?;
+        let base = info.base_currency()?;
+        let today = self.now().date_naive();
+        let to = to.min(today);
+
+        let portfolio = self.portfolio().await?;
+        let current_sizes = portfolio
+            .iter()
+            .filter(|p| p.inner.position_type == PositionType::Product)
+            .filter_map(|p| Some((p.inner.id.parse::<i32>().ok()?, p.inner.size)))
+            .collect::<HashMap<_, _>>();
+        let current_cash = portfolio
+            .cash_balances()
+            .into_iter()
+            .map(|(currency, money)| (currency, money.amount))
+            .collect::<HashMap<_, _>>();
+        let transactions = self.transactions(from, today).await?.into_details();
+        let movements = self.account_state(&from, &today).await?.into_inner();
+
+        let mut dates = Vec::new();
+        let mut date = from;
+        while date <= to {
+            dates.push(date);
+            let next = date + interval;
+            if next <= date {
+                break;
+            }
+            date = next;
+        }
+        let sizes = dates
+            .iter()
+            .map(|date| sizes_at(&current_sizes, &transactions, *date))
+            .collect::<Vec<_>>();
+
+        let ids = sizes
+            .iter()
+            .flat_map(|x| x.keys().map(|id| id.to_string()))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut prices = HashMap::new();
+        if !ids.is_empty() {
+            let period = history_period(today, from);
+            for (id, product) in self.products(&ids).await? {
+                let Some(currency) = product.currency() else {
+                    continue;
+                };
+                let quotes = match self.quotes(&id, period, Period::P1D).await {
+                    Ok(quotes) => quotes,
+                    Err(ClientError::NoData) => continue,
+                    Err(err) => return Err(err),
+                };
+                if let Ok(id) = id.parse::<i32>() {
+                    prices.insert(id, (currency, quotes));
+                }
+            }
+        }
+
+        let mut points = Vec::with_capacity(dates.len());
+        let mut previous = None;
+        for (date, sizes) in dates.into_iter().zip(sizes) {
+            let end = Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN))
+                + chrono::Duration::days(1)
+                - chrono::Duration::seconds(1);
+            let cash = cash_at(&current_cash, &movements, date);
+            let mut positions = HashMap::new();
+            for (id, size) in &sizes {
+                let Some((currency, quotes)) = prices.get(id) else {
+                    continue;
+                };
+                if let Some(close) = quotes.close_at(end) {
+                    *positions.entry(*currency).or_insert(0.0) += size * close;
+                }
+            }
+            let flows = net_flows(&movements, previous, date);
+
+            let mut rates = HashMap::new();
+            for currency in cash.keys().chain(positions.keys()).chain(flows.keys()) {
+                if !rates.contains_key(currency) {
+                    let rate = self
+                        .historical_rate_with(&info, *currency, base, date)
+                        .await?;
+                    rates.insert(*currency, rate);
+                }
+            }
+            let in_base =
+                |xs: &HashMap<Currency, f64>| xs.iter().map(|(c, x)| x * rates[c]).sum::<f64>();
+            let point = EquityPoint {
+                date,
+                cash: in_base(&cash),
+                positions: in_base(&positions),
+                net_flow: in_base(&flows),
+            };
+            points.push(point);
+            previous = Some(date);
+        }
+
+        Ok(EquityCurve {
+            currency: base,
+            points,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::account::fixtures::movement;
+
+    fn fill(id: i32, date: &str, buysell: &str, quantity: i32) -> TransactionDetails {
+        serde_json::from_value(json!({
+            "autoFxFeeInBaseCurrency": 0.0,
+            "buysell": buysell,
+            "date": format!("{date}T10:00:00+01:00"),
+            "fxRate": 1.0,
+            "grossFxRate": 1.0,
+            "id": id,
+            "nettFxRate": 1.0,
+            "price": 10.0,
+            "productId": 7,
+            "quantity": quantity,
+            "total": 0.0,
+            "totalFeesInBaseCurrency": 0.0,
+            "totalInBaseCurrency": 0.0,
+            "totalPlusAllFeesInBaseCurrency": 0.0,
+            "totalPlusFeeInBaseCurrency": 0.0,
+            "transactionTypeId": 0,
+            "transfered": false,
+        }))
+        .unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn rebuilds_holdings_and_cash() {
+        // Bought 10 on the 5th, sold 4 on the 20th, 6 held now.
+        let txs = [
+            fill(1, "2024-01-05", "B", 10),
+            fill(2, "2024-01-20", "S", -4),
+        ];
+        let current = HashMap::from([(7, 6.0)]);
+        assert!(sizes_at(&current, &txs, date("2024-01-04")).is_empty());
+        assert_eq!(sizes_at(&current, &txs, date("2024-01-10"))[&7], 10.0);
+        assert_eq!(sizes_at(&current, &txs, date("2024-01-31"))[&7], 6.0);
+
+        let movements = [
+            movement(1, "2024-01-02", "Depozyt", 500.0).with_total(500.0),
+            movement(2, "2024-01-05", "Kupno", -100.0).with_total(400.0),
+        ];
+        let cash = HashMap::from([(Currency::EUR, 400.0)]);
+        assert_eq!(
+            cash_at(&cash, &movements, date("2024-01-01"))[&Currency::EUR],
+            0.0
+        );
+        assert_eq!(
+            cash_at(&cash, &movements, date("2024-01-03"))[&Currency::EUR],
+            500.0
+        );
+        assert_eq!(
+            cash_at(&cash, &movements, date("2024-01-06"))[&Currency::EUR],
+            400.0
+        );
+
+        let flows = net_flows(&movements, None, date("2024-01-06"));
+        assert_eq!(flows[&Currency::EUR], 500.0);
+        assert!(net_flows(&movements, Some(date("2024-01-02")), date("2024-01-06")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn equity_curve() {
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let today = client.now().date_naive();
+        let curve = client
+            .equity_curve(today - chrono::Duration::days(30), today, Period::P1D)
+            .await
+            .unwrap();
+        dbg!(curve);
+    }
+}
@@ -1,9 +1,13 @@
+use std::{collections::HashMap, sync::OnceLock};
+
 use chrono::NaiveDate;
 use reqwest::{header, Url};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 
-use crate::client::{Client, ClientError, ClientStatus};
+use crate::client::{Client, ClientError};
+use crate::http::Endpoint;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FinancialReports {
@@ -26,6 +30,125 @@ impl FinancialReports {
             .iter()
             .find(|report| report.fiscal_year == fiscal_year && report.end_date == end_date)
     }
+
+    /// Synthetic trailing twelve month report built from interim statements.
+    ///
+    /// Income statements are summed over the latest discrete periods covering
+    /// twelve months (quarters or halves), except the diluted weighted average
+    /// shares, averaged over the periods, and the per share items (EPS, DPS),
+    /// taken from the latest period. Cash flow statements are often
+    /// reported year-to-date, in which case TTM is `latest YTD + last annual -
+    /// previous year's YTD`. The balance sheet is taken from the latest interim.
+    /// Returns `None` when the interims don't cover twelve months.
+    pub fn ttm(&self) -> Option<Report> {
+        let mut interim = self.interim.0.iter().collect::<Vec<_>>();
+        interim.sort_by_key(|r| std::cmp::Reverse(r.end_date));
+        let latest = *interim.first()?;
+
+        let mut months = 0;
+        let mut incomes = Vec::new();
+        for report in &interim {
+            let length = report.income_report.period_length;
+            if length <= 0 {
+                return None;
+            }
+            months += length;
+            incomes.push((&*report.income_report.statement, length));
+            if months >= 12 {
+                break;
+            }
+        }
+        if months != 12 {
+            return None;
+        }
+        let mut income = combine(&incomes.iter().map(|(x, _)| (*x, 1.0)).collect::<Vec<_>>())?;
+        // Share counts and per share items don't add up over periods.
+        income.sdws.value = incomes
+            .iter()
+            .map(|(x, length)| x.sdws.value * f64::from(*length) / 12.0)
+            .sum();
+        let last = &latest.income_report.statement;
+        income.sdbf = last.sdbf.clone();
+        income.ddps = last.ddps.clone();
+        income.vdes = last.vdes.clone();
+
+        let cash_length = latest.cash_flow.period_length;
+        let cash_flow = if cash_length == 12 {
+            (*latest.cash_flow.statement).clone()
+        } else if cash_length > latest.income_report.period_length {
+            // Year to date figures.
+            let annual = self
+                .annual
+                .0
+                .iter()
+                .filter(|r| r.end_date < latest.end_date)
+                .max_by_key(|r| r.end_date)?;
+            let previous = interim.iter().find(|r| {
+                r.cash_flow.period_length == cash_length
+                    && r.end_date < latest.end_date
+                    && (latest.end_date - r.end_date).num_days().abs_diff(365) <= 10
+            })?;
+            combine(&[
+                (&*latest.cash_flow.statement, 1.0),
+                (&*annual.cash_flow.statement, 1.0),
+                (&*previous.cash_flow.statement, -1.0),
+            ])?
+        } else {
+            let mut months = 0;
+            let mut xs = Vec::new();
+            for report in &interim {
+                months += report.cash_flow.period_length.max(0);
+                xs.push((&*report.cash_flow.statement, 1.0));
+                if months >= 12 {
+                    break;
+                }
+            }
+            if months != 12 {
+                return None;
+            }
+            combine(&xs)?
+        };
+
+        Some(Report {
+            fiscal_year: latest.fiscal_year,
+            end_date: latest.end_date,
+            income_report: IncomeStatementReport {
+                source: latest.income_report.source.clone(),
+                period_type: "TTM".to_string(),
+                period_length: 12,
+                statement: Box::new(income),
+            },
+            balance_sheet: latest.balance_sheet.clone(),
+            cash_flow: CashFlowReport {
+                source: latest.cash_flow.source.clone(),
+                period_type: "TTM".to_string(),
+                period_length: 12,
+                statement: Box::new(cash_flow),
+            },
+            extras: HashMap::new(),
+        })
+    }
+}
+
+/// Weighted sum of the `value` of every [`ItemDetail`] in the statements,
+/// other fields are taken from the first one.
+fn combine<T: Serialize + DeserializeOwned>(xs: &[(&T, f64)]) -> Option<T> {
+    let values = xs
+        .iter()
+        .map(|(x, weight)| Some((serde_json::to_value(x).ok()?, *weight)))
+        .collect::<Option<Vec<_>>>()?;
+    let mut acc = values.first()?.0.clone();
+    for (key, item) in acc.as_object_mut()? {
+        let Some(value) = item.get_mut("value") else {
+            continue;
+        };
+        let total = values
+            .iter()
+            .map(|(x, weight)| x[key.as_str()]["value"].as_f64().unwrap_or_default() * weight)
+            .sum::<f64>();
+        *value = Value::from(total);
+    }
+    serde_json::from_value(acc).ok()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -36,6 +159,9 @@ pub struct Report {
     pub income_report: IncomeStatementReport,
     pub balance_sheet: BalanceSheetReport,
     pub cash_flow: CashFlowReport,
+    /// Items the statement structs don't model, keyed by lowercase code.
+    #[serde(default)]
+    pub extras: HashMap<String, ItemDetail>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -385,6 +511,48 @@ impl From<&serde_json::Value> for ItemDetail {
     }
 }
 
+static INCOME_FIELDS: OnceLock<Vec<String>> = OnceLock::new();
+static BALANCE_FIELDS: OnceLock<Vec<String>> = OnceLock::new();
+static CASH_FLOW_FIELDS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Lowercased serialized field names of `T`, computed once per statement type.
+fn field_names<T: Serialize + Default>(cell: &'static OnceLock<Vec<String>>) -> &'static [String] {
+    cell.get_or_init(|| {
+        serde_json::to_value(T::default())
+            .ok()
+            .and_then(|v| {
+                v.as_object()
+                    .map(|m| m.keys().map(|k| k.to_lowercase()).collect())
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Items of `items` whose code is not in `known`.
+fn unmodeled_items(known: &[String], items: &Value) -> Vec<(String, ItemDetail)> {
+    items
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            let code = item["code"].as_str()?.to_lowercase();
+            // `ddps1` is stored in the `ddps` field.
+            let field = code.trim_end_matches('1');
+            if known.iter().any(|k| k == &code || k == field) {
+                return None;
+            }
+            Some((
+                code,
+                ItemDetail {
+                    meaning: item["meaning"].as_str().unwrap_or_default().to_string(),
+                    value: item["value"].as_f64()?,
+                },
+            ))
+        })
+        .collect()
+}
+
 fn process_reports(data: &serde_json::Value) -> Result<Vec<Report>, ClientError> {
     data.as_array()
         .map(|reports| {
@@ -428,6 +596,10 @@ fn process_reports(data: &serde_json::Value) -> Result<Vec<Report>, ClientError>
                                         as i32,
                                     statement: Box::new((&statement["items"]).into()),
                                 };
+                                report.extras.extend(unmodeled_items(
+                                    field_names::<IncomeStatement>(&INCOME_FIELDS),
+                                    &statement["items"],
+                                ));
                                 report.income_report = income_report;
                             }
                             "BAL" => {
@@ -435,6 +607,10 @@ fn process_reports(data: &serde_json::Value) -> Result<Vec<Report>, ClientError>
                                     source: statement["source"].as_str().unwrap().to_string(),
                                     statement: Box::new((&statement["items"]).into()),
                                 };
+                                report.extras.extend(unmodeled_items(
+                                    field_names::<BalanceSheet>(&BALANCE_FIELDS),
+                                    &statement["items"],
+                                ));
                                 report.balance_sheet = balance_report;
                             }
                             "CAS" => {
@@ -448,6 +624,10 @@ fn process_reports(data: &serde_json::Value) -> Result<Vec<Report>, ClientError>
                                         as i32,
                                     statement: Box::new((&statement["items"]).into()),
                                 };
+                                report.extras.extend(unmodeled_items(
+                                    field_names::<CashFlow>(&CASH_FLOW_FIELDS),
+                                    &statement["items"],
+                                ));
                                 report.cash_flow = cash_flow_report;
                             }
                             code => Err(ClientError::UnexpectedStatementType(code.to_string()))?,
@@ -461,7 +641,106 @@ fn process_reports(data: &serde_json::Value) -> Result<Vec<Report>, ClientError>
         .unwrap_or(Err(ClientError::ParseError("Can't get data".to_string())))
 }
 
+/// Requests in flight for [`Client::financial_statements_bulk`].
+const BULK_CONCURRENCY: usize = 4;
+
+#[derive(Debug)]
+pub enum StatementsOutcome {
+    Fetched(FinancialReports),
+    /// Not requested, the fetch policy remembers an earlier failure.
+    Skipped,
+    /// The company has no statements.
+    NoData,
+    Failed(ClientError),
+}
+
+/// Outcome of every isin of [`Client::financial_statements_bulk`], in input
+/// order.
+#[derive(Debug, Default)]
+pub struct BulkStatements {
+    pub outcomes: Vec<(String, StatementsOutcome)>,
+}
+
+impl BulkStatements {
+    pub fn reports(&self) -> impl Iterator<Item = &FinancialReports> {
+        self.outcomes.iter().filter_map(|(_, x)| match x {
+            StatementsOutcome::Fetched(reports) => Some(reports),
+            _ => None,
+        })
+    }
+
+    /// Isins that failed in a way another attempt may fix.
+    pub fn failed(&self) -> Vec<&str> {
+        self.outcomes
+            .iter()
+            .filter(|(_, x)| matches!(x, StatementsOutcome::Failed(_)))
+            .map(|(isin, _)| isin.as_str())
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.failed().is_empty()
+    }
+
+    /// Fetches the failed isins again, keeping what already succeeded.
+    pub async fn resume(&mut self, client: &Client) {
+        let failed = self
+            .failed()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let retried = client.financial_statements_bulk(failed).await;
+        let mut retried = retried.outcomes.into_iter().collect::<HashMap<_, _>>();
+        for (isin, outcome) in &mut self.outcomes {
+            if let Some(x) = retried.remove(isin) {
+                *outcome = x;
+            }
+        }
+    }
+}
+
 impl Client {
+    /// Financial statements of many companies, at most [`BULK_CONCURRENCY`]
+    /// requests at a time through the shared rate limiter, transient errors
+    /// retried per the [`crate::batch::RetryPolicy`].
+    ///
+    /// One failure doesn't stop the others; see [`BulkStatements::resume`].
+    /// With a fetch policy set, isins that failed before are skipped and
+    /// new failures are remembered. Reports are keyed by isin.
+    pub async fn financial_statements_bulk<T: AsRef<str>>(
+        &self,
+        isins: impl IntoIterator<Item = T>,
+    ) -> BulkStatements {
+        let mut unique = Vec::new();
+        for isin in isins {
+            let isin = isin.as_ref().trim();
+            if !isin.is_empty() && !unique.iter().any(|x| x == isin) {
+                unique.push(isin.to_string());
+            }
+        }
+        let results = self
+            .batched(
+                unique.clone(),
+                BULK_CONCURRENCY,
+                |client, isin| async move { client.financial_statements(&isin, &isin).await },
+            )
+            .await;
+        let outcomes = unique
+            .into_iter()
+            .zip(results)
+            .map(|(isin, res)| {
+                let outcome = match res {
+                    Ok(reports) => StatementsOutcome::Fetched(reports),
+                    Err(ClientError::Skipped(_)) => StatementsOutcome::Skipped,
+                    Err(ClientError::NoData) => StatementsOutcome::NoData,
+                    Err(err) => StatementsOutcome::Failed(err),
+                };
+                (isin, outcome)
+            })
+            .collect();
+        BulkStatements { outcomes }
+    }
+
     pub async fn financial_statements_by_id(
         &self,
         id: impl AsRef<str>,
@@ -474,9 +753,37 @@ impl Client {
         id: impl AsRef<str>,
         isin: impl AsRef<str>,
     ) -> Result<FinancialReports, ClientError> {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        let data = self.financial_statements_raw(isin).await?;
+        FinancialReports::from_json(id, &data)
+    }
+
+    /// Raw statement JSON together with the typed parse, so nothing is lost
+    /// when the schema changes upstream.
+    pub async fn financial_statements_with_raw(
+        &self,
+        id: impl AsRef<str>,
+        isin: impl AsRef<str>,
+    ) -> Result<(Value, Result<FinancialReports, ClientError>), ClientError> {
+        let data = self.financial_statements_raw(isin).await?;
+        let reports = FinancialReports::from_json(id, &data);
+        Ok((data, reports))
+    }
+
+    /// The `data` object of the financial statements endpoint, unparsed.
+    pub async fn financial_statements_raw(
+        &self,
+        isin: impl AsRef<str>,
+    ) -> Result<Value, ClientError> {
+        let isin = isin.as_ref();
+        self.cached(
+            &format!("financial_statements/{isin}"),
+            self.fetch_financial_statements(isin),
+        )
+        .await
+    }
+
+    async fn fetch_financial_statements(&self, isin: &str) -> Result<Value, ClientError> {
+        self.require(Endpoint::Refinitiv)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = "https://trader.degiro.nl/";
@@ -486,59 +793,45 @@ impl Client {
                 .unwrap()
                 .join(path_url)
                 .unwrap()
-                .join(isin.as_ref())
+                .join(isin)
                 .unwrap();
 
             inner
                 .http_client
                 .get(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                ])
                 .header(header::REFERER, &inner.referer)
                 .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
+        let res = self.send(Endpoint::Refinitiv, req).await?;
 
-        let res = req.send().await?;
+        let mut json = res.json::<serde_json::Value>().await?;
+        let data = json["data"].take();
 
-        match res.error_for_status() {
-            Ok(res) => {
-                let json = res.json::<serde_json::Value>().await?;
-                let data = &json["data"];
-
-                if data.is_null() {
-                    return Err(ClientError::NoData);
-                };
+        if data.is_null() {
+            return Err(ClientError::NoData);
+        };
 
-                let currency = data["currency"]
-                    .as_str()
-                    .ok_or(ClientError::ParseError("Can't get currency".to_string()))?
-                    .to_string();
+        Ok(data)
+    }
+}
 
-                let annual_reports = process_reports(&data["annual"])?;
-                let interim_reports = process_reports(&data["interim"])?;
+impl FinancialReports {
+    pub fn from_json(id: impl AsRef<str>, data: &Value) -> Result<Self, ClientError> {
+        let currency = data["currency"]
+            .as_str()
+            .ok_or(ClientError::ParseError("Can't get currency".to_string()))?
+            .to_string();
 
-                let financial_report = FinancialReports {
-                    id: id.as_ref().to_string(),
-                    currency,
-                    annual: annual_reports.into(),
-                    interim: interim_reports.into(),
-                };
+        let annual_reports = process_reports(&data["annual"])?;
+        let interim_reports = process_reports(&data["interim"])?;
 
-                Ok(financial_report)
-            }
-            Err(err) => {
-                eprintln!("error: {}", err);
-                Err(err.into())
-            }
-        }
+        Ok(FinancialReports {
+            id: id.as_ref().to_string(),
+            currency,
+            annual: annual_reports.into(),
+            interim: interim_reports.into(),
+        })
     }
 }
 
@@ -550,6 +843,8 @@ pub enum FinancialError {
     TotalEquity(String),
     #[error("Total Assets error: {0}")]
     TotalAssets(String),
+    #[error("Shares Outstanding error: {0}")]
+    SharesOutstanding(String),
 }
 
 impl Report {
@@ -965,6 +1260,28 @@ impl Report {
         }
     }
 
+    fn per_share(&self, value: f64) -> Result<f64, FinancialError> {
+        let shares = self.total_common_shares_outstanding();
+        if !shares.is_finite() || shares <= 0.0 {
+            return Err(FinancialError::SharesOutstanding(format!(
+                "invalid shares outstanding: {shares}"
+            )));
+        }
+        Ok(value / shares)
+    }
+    pub fn eps(&self) -> Result<f64, FinancialError> {
+        self.per_share(self.net_income())
+    }
+    pub fn fcf_per_share(&self) -> Result<f64, FinancialError> {
+        self.per_share(self.free_cash_flow())
+    }
+    pub fn book_value_per_share(&self) -> Result<f64, FinancialError> {
+        self.per_share(self.total_equity())
+    }
+    pub fn revenue_per_share(&self) -> Result<f64, FinancialError> {
+        self.per_share(self.revenue())
+    }
+
     pub fn days_payables_outstanding(&self) -> f64 {
         let accounts_payable = self.accounts_payable();
         let cost_of_revenue = self.cost_of_revenue();
@@ -975,3 +1292,150 @@ impl Report {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bulk_statements_skip_remembered_failures() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let cache = std::sync::Arc::new(crate::cache::NegativeCache::default());
+        crate::cache::FetchPolicy::record_failure(
+            cache.as_ref(),
+            "financial_statements/US0000000001",
+            &ClientError::NoData,
+            client.now(),
+        );
+        client.set_fetch_policy(cache);
+        let bulk = client
+            .financial_statements_bulk(["US0000000001", " US0000000001", ""])
+            .await;
+        assert_eq!(bulk.outcomes.len(), 1);
+        assert!(matches!(bulk.outcomes[0].1, StatementsOutcome::Skipped));
+        assert!(bulk.is_complete());
+        assert_eq!(bulk.reports().count(), 0);
+    }
+
+    fn report(
+        end_date: &str,
+        income_months: i32,
+        cash_months: i32,
+        revenue: f64,
+        ocf: f64,
+    ) -> Report {
+        let mut report = Report {
+            end_date: end_date.parse().unwrap(),
+            ..Default::default()
+        };
+        report.income_report.period_length = income_months;
+        report.income_report.statement.srev.value = revenue;
+        report.cash_flow.period_length = cash_months;
+        report.cash_flow.statement.otlo.value = ocf;
+        report
+    }
+
+    #[test]
+    fn ttm_from_quarters_and_ytd_cash_flow() {
+        let reports = FinancialReports {
+            annual: vec![report("2023-12-31", 12, 12, 400.0, 100.0)].into(),
+            interim: vec![
+                report("2024-06-30", 3, 6, 120.0, 60.0),
+                report("2024-03-31", 3, 3, 110.0, 25.0),
+                report("2023-12-31", 3, 12, 100.0, 100.0),
+                report("2023-09-30", 3, 9, 100.0, 75.0),
+                report("2023-06-30", 3, 6, 100.0, 50.0),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let ttm = reports.ttm().unwrap();
+        assert_eq!(ttm.revenue(), 430.0);
+        assert_eq!(ttm.cash_from_operating_activities(), 110.0);
+        assert_eq!(ttm.income_report.period_length, 12);
+    }
+
+    #[test]
+    fn ttm_keeps_share_counts_out_of_the_sum() {
+        let quarter = |end, shares, eps| {
+            let mut report = report(end, 3, 3, 100.0, 25.0);
+            report.income_report.statement.sdws.value = shares;
+            report.income_report.statement.sdbf.value = eps;
+            report
+        };
+        let reports = FinancialReports {
+            interim: vec![
+                quarter("2024-06-30", 110.0, 0.5),
+                quarter("2024-03-31", 110.0, 0.4),
+                quarter("2023-12-31", 100.0, 0.3),
+                quarter("2023-09-30", 100.0, 0.2),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let ttm = reports.ttm().unwrap();
+        assert_eq!(ttm.revenue(), 400.0);
+        assert_eq!(ttm.diluted_weighted_average_shares(), 105.0);
+        assert_eq!(ttm.income_report.statement.sdbf.value, 0.5);
+    }
+
+    #[test]
+    fn unmodeled_items_go_to_extras() {
+        let data = serde_json::json!({
+            "currency": "USD",
+            "annual": [{
+                "fiscalYear": 2023,
+                "endDate": "2023-12-31",
+                "statements": [{
+                    "type": "INC",
+                    "source": "10-K",
+                    "periodType": "M",
+                    "periodLength": 12,
+                    "items": [
+                        {"code": "SREV", "meaning": "Revenue", "value": 10.0},
+                        {"code": "DDPS1", "meaning": "DPS", "value": 1.0},
+                        {"code": "XNEW", "meaning": "Brand New", "value": 3.0}
+                    ]
+                }]
+            }],
+            "interim": []
+        });
+        let reports = FinancialReports::from_json("1", &data).unwrap();
+        let report = reports.get_annual(2023).unwrap();
+        assert_eq!(report.revenue(), 10.0);
+        assert_eq!(report.extras.len(), 1);
+        assert_eq!(report.extras["xnew"].value, 3.0);
+    }
+
+    #[test]
+    fn per_share_metrics() {
+        let mut report = report("2024-06-30", 12, 12, 1000.0, 300.0);
+        assert!(matches!(
+            report.eps(),
+            Err(FinancialError::SharesOutstanding(_))
+        ));
+        report.balance_sheet.statement.qtco.value = 100.0;
+        report.balance_sheet.statement.qtle.value = 500.0;
+        report.income_report.statement.ninc.value = 150.0;
+        report.cash_flow.statement.scex.value = 100.0;
+        assert_eq!(report.eps().unwrap(), 1.5);
+        assert_eq!(report.fcf_per_share().unwrap(), 2.0);
+        assert_eq!(report.book_value_per_share().unwrap(), 5.0);
+        assert_eq!(report.revenue_per_share().unwrap(), 10.0);
+    }
+
+    #[test]
+    fn ttm_from_halves() {
+        let reports = FinancialReports {
+            interim: vec![
+                report("2024-06-30", 6, 6, 200.0, 40.0),
+                report("2023-12-31", 6, 6, 220.0, 50.0),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let ttm = reports.ttm().unwrap();
+        assert_eq!(ttm.revenue(), 420.0);
+        assert_eq!(ttm.cash_from_operating_activities(), 90.0);
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use reqwest::{header, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    client::{Client, ClientError},
+    http::{paginate, Endpoint, Page},
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Gossip {
+    pub id: String,
+    pub date: DateTime<Utc>,
+    pub title: String,
+    pub content: String,
+    pub author: Option<String>,
+    pub isins: Vec<String>,
+}
+
+impl Gossip {
+    pub fn new(item: &Value) -> Self {
+        Self {
+            id: match &item["id"] {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                _ => String::new(),
+            },
+            date: item["date"]
+                .as_str()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_else(Utc::now),
+            title: item["title"]
+                .as_str()
+                .map(|s| s.to_string())
+                .unwrap_or_default(),
+            content: item["content"]
+                .as_str()
+                .or_else(|| item["body"].as_str())
+                .map(|s| s.to_string())
+                .unwrap_or_default(),
+            author: item["author"].as_str().map(|s| s.to_string()),
+            isins: item["isins"]
+                .as_array()
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|isin| isin.as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// One page of market commentary from the vwd gossips feed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Gossips {
+    pub total: usize,
+    pub offset: u32,
+    pub items: Vec<Gossip>,
+}
+
+impl Client {
+    /// Every gossip in the feed, newest first, fetched `page_size` at a time.
+    pub fn gossips_stream(
+        &self,
+        page_size: u32,
+    ) -> impl Stream<Item = Result<Gossip, ClientError>> + '_ {
+        paginate(page_size, move |offset, limit| async move {
+            let page = self.gossips(offset, limit).await?;
+            Ok(Page::new(page.items, Some(page.total)))
+        })
+    }
+
+    pub async fn gossips(&self, offset: u32, limit: u32) -> Result<Gossips, ClientError> {
+        self.require(Endpoint::Gossips)?;
+        let req = {
+            let inner = self.inner.lock().unwrap();
+            let base_url = &inner.account_config.vwd_gossips_url;
+            let url =
+                Url::parse(base_url).unwrap_or_else(|_| panic!("can't parse base_url: {base_url}"));
+
+            inner
+                .http_client
+                .get(url)
+                .query(&[
+                    ("offset", &offset.to_string()),
+                    ("limit", &limit.to_string()),
+                ])
+                .header(header::REFERER, &inner.referer)
+        };
+
+        let res = self.send(Endpoint::Gossips, req).await?;
+
+        let mut json = res.json::<Value>().await?;
+        let data = match json.get_mut("data") {
+            Some(data) => data.take(),
+            None => json,
+        };
+        let items: Vec<Gossip> = data["items"]
+            .as_array()
+            .or_else(|| data.as_array())
+            .ok_or(ClientError::NoData)?
+            .iter()
+            .map(Gossip::new)
+            .collect();
+        let total = data["total"]
+            .as_u64()
+            .map(|x| x as usize)
+            .unwrap_or(offset as usize + items.len());
+
+        Ok(Gossips {
+            total,
+            offset,
+            items,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+
+    #[tokio::test]
+    async fn gossips() {
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let page = client.gossips(0, 10).await.unwrap();
+        dbg!(page);
+    }
+}
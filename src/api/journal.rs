@@ -0,0 +1,322 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Serialize;
+
+use crate::{
+    client::{Client, ClientError},
+    export::{Cell, Csv, Export, Json},
+    util::TransactionType,
+};
+
+use super::transactions::TransactionDetails;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Long,
+    Short,
+}
+
+/// One closed round trip: a fill opening a position matched with the fill
+/// closing (part of) it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub product_id: i32,
+    pub product: Option<String>,
+    pub side: Side,
+    pub size: f64,
+    pub entry_id: i32,
+    pub entry_date: DateTime<FixedOffset>,
+    pub entry_price: f64,
+    pub exit_id: i32,
+    pub exit_date: DateTime<FixedOffset>,
+    pub exit_price: f64,
+    /// Fees of both legs prorated to `size`, in the account base currency.
+    pub fees: f64,
+    pub notes: Vec<String>,
+}
+
+impl JournalEntry {
+    pub fn holding_days(&self) -> i64 {
+        (self.exit_date - self.entry_date).num_days()
+    }
+
+    /// Profit in the product currency, before fees.
+    pub fn pnl(&self) -> f64 {
+        let diff = self.exit_price - self.entry_price;
+        match self.side {
+            Side::Long => diff * self.size,
+            Side::Short => -diff * self.size,
+        }
+    }
+
+    /// Return on the entry value, before fees.
+    pub fn return_pct(&self) -> f64 {
+        if self.entry_price == 0.0 {
+            return 0.0;
+        }
+        self.pnl() / (self.entry_price * self.size) * 100.0
+    }
+}
+
+/// Part of a fill not matched yet.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenLot {
+    pub product_id: i32,
+    pub side: Side,
+    pub size: f64,
+    pub id: i32,
+    pub date: DateTime<FixedOffset>,
+    pub price: f64,
+    /// Fee per unit, in the account base currency.
+    fee_per_unit: f64,
+}
+
+/// Round trips built from fills matched first in, first out per product.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeJournal {
+    pub entries: Vec<JournalEntry>,
+    pub open: Vec<OpenLot>,
+}
+
+impl TradeJournal {
+    pub fn from_transactions<'a>(
+        transactions: impl IntoIterator<Item = &'a TransactionDetails>,
+    ) -> Self {
+        let mut txs = transactions.into_iter().collect::<Vec<_>>();
+        txs.sort_by_key(|tx| (tx.date, tx.id));
+
+        let mut journal = TradeJournal::default();
+        let mut lots: BTreeMap<i32, VecDeque<OpenLot>> = BTreeMap::new();
+        for tx in txs {
+            let side = match tx.transaction_type {
+                TransactionType::Buy => Side::Long,
+                TransactionType::Sell => Side::Short,
+            };
+            let size = f64::from(tx.quantity.abs());
+            if size == 0.0 {
+                continue;
+            }
+            let fee_per_unit = tx.total_fees_in_base_currency.abs() / size;
+            let open = lots.entry(tx.product_id).or_default();
+            let mut left = size;
+            while left > 0.0 {
+                let Some(lot) = open.front_mut().filter(|lot| lot.side != side) else {
+                    break;
+                };
+                let matched = lot.size.min(left);
+                journal.entries.push(JournalEntry {
+                    product_id: tx.product_id,
+                    product: None,
+                    side: lot.side,
+                    size: matched,
+                    entry_id: lot.id,
+                    entry_date: lot.date,
+                    entry_price: lot.price,
+                    exit_id: tx.id,
+                    exit_date: tx.date,
+                    exit_price: tx.price,
+                    fees: (lot.fee_per_unit + fee_per_unit) * matched,
+                    notes: Vec::new(),
+                });
+                lot.size -= matched;
+                left -= matched;
+                if lot.size <= 0.0 {
+                    open.pop_front();
+                }
+            }
+            if left > 0.0 {
+                open.push_back(OpenLot {
+                    product_id: tx.product_id,
+                    side,
+                    size: left,
+                    id: tx.id,
+                    date: tx.date,
+                    price: tx.price,
+                    fee_per_unit,
+                });
+            }
+        }
+        journal.open = lots.into_values().flatten().collect();
+        journal
+    }
+
+    /// Fills in product names, keyed by product id.
+    pub fn with_names(mut self, names: &HashMap<i32, String>) -> Self {
+        for entry in &mut self.entries {
+            entry.product = names.get(&entry.product_id).cloned();
+        }
+        self
+    }
+
+    /// Adds `note` to every round trip of `product_id`.
+    pub fn annotate(&mut self, product_id: i32, note: impl Into<String>) {
+        let note = note.into();
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|x| x.product_id == product_id)
+        {
+            entry.notes.push(note.clone());
+        }
+    }
+
+    /// Adds `note` to round trips closed on `date`.
+    pub fn annotate_exit(&mut self, date: NaiveDate, note: impl Into<String>) {
+        let note = note.into();
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|x| x.exit_date.date_naive() == date)
+        {
+            entry.notes.push(note.clone());
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, ClientError> {
+        Json.to_string(self)
+    }
+
+    pub fn to_csv(&self) -> String {
+        Csv.to_string(self)
+    }
+}
+
+impl Export for TradeJournal {
+    fn header(&self) -> &'static [&'static str] {
+        &[
+            "product_id",
+            "product",
+            "side",
+            "size",
+            "entry_date",
+            "entry_price",
+            "exit_date",
+            "exit_price",
+            "holding_days",
+            "pnl",
+            "return_pct",
+            "fees",
+            "notes",
+        ]
+    }
+
+    fn rows(&self) -> Vec<Vec<Cell>> {
+        self.entries
+            .iter()
+            .map(|x| {
+                let side = match x.side {
+                    Side::Long => "long",
+                    Side::Short => "short",
+                };
+                vec![
+                    Cell::value(x.product_id),
+                    Cell::text(x.product.as_deref().unwrap_or_default()),
+                    Cell::value(side),
+                    Cell::value(x.size),
+                    Cell::value(x.entry_date.to_rfc3339()),
+                    Cell::value(x.entry_price),
+                    Cell::value(x.exit_date.to_rfc3339()),
+                    Cell::value(x.exit_price),
+                    Cell::value(x.holding_days()),
+                    Cell::value(format!("{:.2}", x.pnl())),
+                    Cell::value(format!("{:.2}", x.return_pct())),
+                    Cell::value(format!("{:.2}", x.fees)),
+                    Cell::text(x.notes.join("; ")),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Client {
+    /// Round trips closed between `from` and `to`, named after their products.
+    ///
+    /// Only fills inside the window are matched, so a position opened before
+    /// `from` shows its closing fill as an open lot.
+    pub async fn trade_journal(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<TradeJournal, ClientError> {
+        let transactions = self.transactions(from, to).await?;
+        let journal = TradeJournal::from_transactions(transactions.iter().map(|x| &x.inner));
+        let mut ids = journal
+            .entries
+            .iter()
+            .map(|x| x.product_id.to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        if ids.is_empty() {
+            return Ok(journal);
+        }
+        let names = self
+            .products(&ids)
+            .await?
+            .iter()
+            .filter_map(|(id, p)| Some((id.parse().ok()?, p.inner.name.clone())))
+            .collect();
+        Ok(journal.with_names(&names))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fill(id: i32, date: &str, buysell: &str, quantity: i32, price: f64) -> serde_json::Value {
+        serde_json::json!({
+            "autoFxFeeInBaseCurrency": 0.0,
+            "buysell": buysell,
+            "date": date,
+            "fxRate": 1.0,
+            "grossFxRate": 1.0,
+            "id": id,
+            "nettFxRate": 1.0,
+            "price": price,
+            "productId": 1,
+            "quantity": quantity,
+            "total": -price * quantity as f64,
+            "totalFeesInBaseCurrency": -2.0,
+            "totalInBaseCurrency": 0.0,
+            "totalPlusAllFeesInBaseCurrency": 0.0,
+            "totalPlusFeeInBaseCurrency": 0.0,
+            "transactionTypeId": 0,
+            "transfered": false,
+        })
+    }
+
+    #[test]
+    fn fifo_round_trips() {
+        let txs: Vec<TransactionDetails> = serde_json::from_value(serde_json::json!([
+            fill(1, "2024-01-02T10:00:00+01:00", "B", 10, 100.0),
+            fill(2, "2024-01-05T10:00:00+01:00", "B", 10, 110.0),
+            fill(3, "2024-02-01T10:00:00+01:00", "S", -15, 120.0),
+        ]))
+        .unwrap();
+        let mut journal = TradeJournal::from_transactions(&txs);
+        journal.annotate(1, "breakout");
+
+        assert_eq!(journal.entries.len(), 2);
+        let first = &journal.entries[0];
+        assert_eq!((first.entry_id, first.exit_id, first.size), (1, 3, 10.0));
+        assert_eq!(first.holding_days(), 30);
+        assert_eq!(first.pnl(), 200.0);
+        assert_eq!(first.return_pct(), 20.0);
+        assert!((first.fees - (2.0 + 2.0 * 10.0 / 15.0)).abs() < 1e-9);
+        assert_eq!(journal.entries[1].size, 5.0);
+        assert_eq!(journal.open.len(), 1);
+        assert_eq!(journal.open[0].size, 5.0);
+        assert!(journal
+            .to_csv()
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with("\"breakout\""));
+    }
+}
@@ -1,27 +1,198 @@
-use crate::client::{Client, ClientError, ClientStatus};
+use crate::{
+    client::{Client, ClientError, ClientStatus},
+    events::{session_expiry_from_headers, ClientEvent, DEFAULT_SESSION_TTL, SESSION_COOKIE},
+    http::{Endpoint, ResponseError},
+};
+
+use std::{collections::HashMap, time::Duration};
 
 use mime;
-use reqwest::{header, Url};
+use reqwest::{header, StatusCode, Url};
 use serde::Deserialize;
-use serde_json::json;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Something the account holder has to do, reported by the login endpoint.
+///
+/// Incomplete: password expiry warnings and legal document prompts have no
+/// variants of their own yet. No login response carrying them has been
+/// captured, so their keys and payloads are unknown; until one is, they
+/// come through as [`RequiredAction::Other`] if their key follows the
+/// `must...`/`requires...` pattern, and only in [`LoginOutcome::flags`]
+/// otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RequiredAction {
+    /// A `must...` or `requires...` flag set in the response, by its key.
+    Other(String),
+}
 
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
+/// Parsed login response, including the warnings the web trader shows after
+/// signing in.
+#[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct LoginResponse {
-    locale: Option<String>,
-    session_id: Option<String>,
-    status: i32,
-    status_text: String,
+pub struct LoginOutcome {
+    pub locale: Option<String>,
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub status: i32,
+    #[serde(default)]
+    pub status_text: String,
+    #[serde(default)]
+    pub is_pass_code_enabled: bool,
+    /// Every other key of the response, kept as sent.
+    #[serde(flatten)]
+    pub flags: HashMap<String, Value>,
+}
+
+impl LoginOutcome {
+    pub fn is_success(&self) -> bool {
+        self.session_id.is_some()
+    }
+
+    /// Actions the application should surface to the user.
+    pub fn required_actions(&self) -> Vec<RequiredAction> {
+        let mut xs = Vec::new();
+        for (key, value) in &self.flags {
+            let lower = key.to_lowercase();
+            if (lower.starts_with("must") || lower.starts_with("requires"))
+                && value.as_bool().unwrap_or_default()
+            {
+                xs.push(RequiredAction::Other(key.clone()));
+            }
+        }
+        xs
+    }
 }
 
 impl Client {
-    pub async fn authorize(&self) -> Result<(), ClientError> {
-        self.login().await?;
+    pub async fn authorize(&self) -> Result<LoginOutcome, ClientError> {
+        let outcome = self.login().await?;
         self.account_config().await?;
-        Ok(())
+        Ok(outcome)
+    }
+
+    /// Authorizes unless already authorized, giving up after `timeout`.
+    /// A session the server rotated is repaired first, see
+    /// [`Client::repair_session`], and only then a full login is done.
+    ///
+    /// Callers racing each other wait for a single login instead of each
+    /// starting their own; the wait counts against `timeout` too.
+    pub async fn ensure_authorized(&self, timeout: Duration) -> Result<(), ClientError> {
+        let auth_lock = self.inner.lock().unwrap().auth_lock.clone();
+        let fut = async {
+            let _guard = auth_lock.lock().await;
+            if self.inner.lock().unwrap().status != ClientStatus::Authorized
+                && !self.repair_session().await
+            {
+                self.authorize().await?;
+            }
+            Ok(())
+        };
+        tokio::time::timeout(timeout, fut)
+            .await
+            .unwrap_or(Err(ClientError::AuthTimeout(timeout)))
+    }
+
+    /// Session id from the `JSESSIONID` cookie when the server rotated it
+    /// away from the one we send.
+    fn rotated_session_id(&self) -> Option<String> {
+        let inner = self.inner.lock().unwrap();
+        let id = inner
+            .cookie_jar
+            .lock()
+            .unwrap()
+            .iter_unexpired()
+            .find(|c| c.name() == SESSION_COOKIE)?
+            .value()
+            .to_string();
+        (!id.is_empty() && id != inner.session_id).then_some(id)
+    }
+
+    /// Switches to a session id the server rotated through its cookie and
+    /// reloads the account config with it. Cheaper than a full login and not
+    /// counted against the login lockout. Returns whether the session works
+    /// again.
+    pub async fn repair_session(&self) -> bool {
+        let Some(id) = self.rotated_session_id() else {
+            return false;
+        };
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.session_id = id;
+            inner.status = ClientStatus::Restricted;
+        }
+        self.account_config().await.is_ok()
+    }
+
+    /// Waits until someone else authorizes the client, without logging in.
+    pub async fn wait_until_authorized(&self, timeout: Duration) -> Result<(), ClientError> {
+        let mut events = self.events();
+        if self.inner.lock().unwrap().status == ClientStatus::Authorized {
+            return Ok(());
+        }
+        let fut = async {
+            loop {
+                match events.recv().await {
+                    Ok(ClientEvent::Authorized) => return,
+                    Ok(_) | Err(RecvError::Lagged(_)) => {
+                        if self.inner.lock().unwrap().status == ClientStatus::Authorized {
+                            return;
+                        }
+                    }
+                    // The sender lives in the client itself, so this is unreachable
+                    // while `self` exists; wait out the timeout anyway.
+                    Err(RecvError::Closed) => std::future::pending::<()>().await,
+                }
+            }
+        };
+        tokio::time::timeout(timeout, fut)
+            .await
+            .map_err(|_| ClientError::AuthTimeout(timeout))
     }
-    pub async fn login(&self) -> Result<(), ClientError> {
+    /// Logs in, refusing to while a cool-down after repeated failed logins
+    /// runs; see [`crate::batch::RetryPolicy::max_login_failures`].
+    pub async fn login(&self) -> Result<LoginOutcome, ClientError> {
+        self.check_login_allowed()?;
+        let outcome = self.try_login().await;
+        match &outcome {
+            Ok(_) => {
+                let mut inner = self.inner.lock().unwrap();
+                inner.login_failures = 0;
+                inner.login_blocked_until = None;
+            }
+            // Only rejected credentials count, not outages or network trouble.
+            Err(ClientError::LoginError { .. }) => self.record_login_failure(),
+            Err(_) => {}
+        }
+        outcome
+    }
+
+    /// Failed logins in a row.
+    pub fn login_failures(&self) -> u32 {
+        self.inner.lock().unwrap().login_failures
+    }
+
+    fn check_login_allowed(&self) -> Result<(), ClientError> {
+        let now = self.now();
+        match self.inner.lock().unwrap().login_blocked_until {
+            Some(retry_at) if retry_at > now => Err(ClientError::TooManyLoginAttempts { retry_at }),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_login_failure(&self) {
+        let now = self.now();
+        let mut inner = self.inner.lock().unwrap();
+        inner.login_failures += 1;
+        let policy = inner.retry_policy;
+        if policy.max_login_failures > 0 && inner.login_failures >= policy.max_login_failures {
+            let cooldown = chrono::Duration::from_std(policy.login_cooldown)
+                .unwrap_or_else(|_| chrono::Duration::days(365));
+            inner.login_blocked_until = Some(now + cooldown);
+        }
+    }
+
+    async fn try_login(&self) -> Result<LoginOutcome, ClientError> {
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = &inner.base_api_url;
@@ -47,36 +218,57 @@ impl Client {
                 .query(&[("reason", "session_expired")])
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
+        let res = self.send_unchecked(Endpoint::Login, req).await?;
+        let status = res.status();
+        if is_credential_rejection(status) {
+            let headers = res.headers().clone();
+            let body = res.text().await.unwrap_or_default();
+            let err = ClientError::Http(ResponseError::new(status, &headers, &body));
+            return Err(ClientError::LoginError {
+                source: Box::new(err),
+            });
+        }
+        // Outages and rate limiting say nothing about the credentials.
+        let res = self.check_status(res).await?;
 
-        match res.error_for_status() {
-            Ok(res) => {
-                let body = res.json::<LoginResponse>().await.expect("can't parse json");
+        let now = self.now();
+        let expires_at =
+            session_expiry_from_headers(res.headers(), now).unwrap_or(now + DEFAULT_SESSION_TTL);
+        let body = res.json::<LoginOutcome>().await?;
+        let Some(session_id) = body.session_id.clone() else {
+            return Err(ClientError::LoginError {
+                source: format!("{} ({})", body.status_text, body.status).into(),
+            });
+        };
 
-                {
-                    let mut inner = self.inner.lock().unwrap();
-                    inner.session_id = body.session_id.unwrap();
-                    inner.status = ClientStatus::Restricted;
-                };
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.session_id = session_id;
+            inner.status = ClientStatus::Restricted;
+            inner.session_expires_at = Some(expires_at);
+            inner.expiry_warned = false;
+        };
 
-                Ok(())
-            }
-            Err(err) => Err(ClientError::LoginError {
-                source: Box::new(err),
-            }),
-        }
+        Ok(body)
     }
 }
 
+/// Statuses the login endpoint answers bad credentials with.
+fn is_credential_rejection(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    )
+}
+
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+
     use super::*;
+    use crate::{batch::RetryPolicy, clock::ManualClock, transport::MockTransport};
 
     #[tokio::test]
     async fn login() {
@@ -84,4 +276,138 @@ mod test {
         client.login().await.unwrap();
         dbg!(&client);
     }
+
+    #[tokio::test]
+    async fn wait_until_authorized_times_out() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        let timeout = Duration::from_millis(10);
+        assert!(matches!(
+            client.wait_until_authorized(timeout).await,
+            Err(ClientError::AuthTimeout(t)) if t == timeout
+        ));
+
+        let waiter = {
+            let client = client.clone();
+            tokio::spawn(async move { client.wait_until_authorized(Duration::from_secs(5)).await })
+        };
+        tokio::task::yield_now().await;
+        client.inner.lock().unwrap().status = ClientStatus::Authorized;
+        client.emit(ClientEvent::Authorized);
+        assert!(waiter.await.unwrap().is_ok());
+    }
+
+    #[test]
+    fn lockout_after_failed_logins() {
+        let now = chrono::Utc::now();
+        let clock = Arc::new(ManualClock::new(now));
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        client.set_clock(clock.clone());
+        client.set_retry_policy(RetryPolicy::default().login_lockout(2, Duration::from_secs(60)));
+
+        client.record_login_failure();
+        assert!(client.check_login_allowed().is_ok());
+        client.record_login_failure();
+        assert!(matches!(
+            client.check_login_allowed(),
+            Err(ClientError::TooManyLoginAttempts { retry_at })
+                if retry_at == now + chrono::Duration::seconds(60)
+        ));
+        clock.advance(chrono::Duration::seconds(61));
+        assert!(client.check_login_allowed().is_ok());
+        assert_eq!(client.login_failures(), 2);
+    }
+
+    #[tokio::test]
+    async fn outages_dont_count_as_failed_logins() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        let mock = Arc::new(MockTransport::new());
+        mock.on(Method::POST, "login/secure/login", 503, "");
+        client.set_transport(mock.clone());
+        assert!(matches!(
+            client.login().await,
+            Err(ClientError::Http(err)) if err.status == StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert_eq!(client.login_failures(), 0);
+
+        mock.on(
+            Method::POST,
+            "login/secure/login",
+            400,
+            json!({ "status": 3, "statusText": "badCredentials" }).to_string(),
+        );
+        assert!(matches!(
+            client.login().await,
+            Err(ClientError::LoginError { .. })
+        ));
+        assert_eq!(client.login_failures(), 1);
+    }
+
+    #[test]
+    fn detects_rotated_session() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        client.inner.lock().unwrap().session_id = "old".to_string();
+        assert_eq!(client.rotated_session_id(), None);
+
+        let jar = client.inner.lock().unwrap().cookie_jar.clone();
+        let url = Url::parse("https://trader.degiro.nl/login/secure/config").unwrap();
+        jar.lock()
+            .unwrap()
+            .parse("JSESSIONID=old; Path=/", &url)
+            .unwrap();
+        assert_eq!(client.rotated_session_id(), None);
+        jar.lock()
+            .unwrap()
+            .parse("JSESSIONID=new; Path=/", &url)
+            .unwrap();
+        assert_eq!(client.rotated_session_id().as_deref(), Some("new"));
+    }
+
+    #[tokio::test]
+    async fn repair_with_bad_account_data_fails() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        let mock = Arc::new(MockTransport::new());
+        mock.json(Method::GET, "login/secure/config", &json!({}))
+            .json(Method::GET, "pa/secure/client", &json!({}));
+        client.set_transport(mock);
+        client.inner.lock().unwrap().session_id = "old".to_string();
+        let jar = client.inner.lock().unwrap().cookie_jar.clone();
+        let url = Url::parse("https://trader.degiro.nl/login/secure/config").unwrap();
+        jar.lock()
+            .unwrap()
+            .parse("JSESSIONID=new; Path=/", &url)
+            .unwrap();
+
+        // Errors instead of panics, so the caller can log in from scratch.
+        assert!(!client.repair_session().await);
+        assert_eq!(
+            client.inner.lock().unwrap().status,
+            ClientStatus::Restricted
+        );
+        {
+            let mut inner = client.inner.lock().unwrap();
+            inner.status = ClientStatus::Authorized;
+            inner.account_config.pa_url = "https://trader.degiro.nl/pa/secure/".into();
+        }
+        assert!(matches!(
+            client.account_data().await,
+            Err(ClientError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn login_outcome_actions() {
+        let outcome: LoginOutcome = serde_json::from_value(json!({
+            "locale": "nl_NL",
+            "sessionId": "abc",
+            "status": 0,
+            "statusText": "success",
+            "mustAcceptNewsletter": true,
+        }))
+        .unwrap();
+        assert!(outcome.is_success());
+        assert_eq!(
+            outcome.required_actions(),
+            vec![RequiredAction::Other("mustAcceptNewsletter".into())]
+        );
+    }
 }
@@ -1,13 +1,23 @@
 pub mod account;
+pub mod agenda;
+pub mod company_overview;
 pub mod company_profile;
 pub mod company_ratios;
 pub mod curated_lists;
+pub mod diagnose;
+pub mod dictionary;
+pub mod equity;
 pub mod financial_statements;
+pub mod gossips;
+pub mod journal;
 pub mod login;
 pub mod news;
 pub mod orders;
 pub mod portfolio;
 pub mod product;
+pub mod quotecast;
 pub mod quotes;
+pub mod reconcile;
 pub mod search;
 pub mod transactions;
+pub mod withholding;
@@ -1,8 +1,15 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    time::Duration,
+};
+
 use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
 use reqwest::{header, Url};
 use serde::{Deserialize, Serialize};
 
-use crate::client::{Client, ClientError, ClientStatus};
+use crate::client::{Client, ClientError};
+use crate::http::Endpoint;
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -76,15 +83,32 @@ pub enum Source {
     Unknown(String),
 }
 
+/// Languages requested when the caller has no preference.
+pub const DEFAULT_NEWS_LANGUAGES: &[&str] = &["en"];
+
+/// Items [`Client::company_news`] asks for; the endpoint returns the newest ones.
+const COMPANY_NEWS_LIMIT: usize = 10;
+
+/// Polls' worth of ids per ISIN [`Client::news_stream`] remembers. Older
+/// items have dropped off the endpoint by then and can't come back.
+const SEEN_POLLS: usize = 4;
+
 impl Client {
-    pub async fn company_news_by_id<T: AsRef<str>>(&self, id: T) -> Result<Vec<News>, ClientError> {
+    pub async fn company_news_by_id<T: AsRef<str>>(
+        &self,
+        id: T,
+        languages: &[&str],
+    ) -> Result<Vec<News>, ClientError> {
         let isin = &self.product(id.as_ref()).await?.inner.isin;
-        self.company_news(isin).await
+        self.company_news(isin, languages).await
     }
-    pub async fn company_news<T: AsRef<str>>(&self, isin: T) -> Result<Vec<News>, ClientError> {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+    /// News summaries for a company, limited to the given language codes (e.g. `["en", "de"]`).
+    pub async fn company_news<T: AsRef<str>>(
+        &self,
+        isin: T,
+        languages: &[&str],
+    ) -> Result<Vec<News>, ClientError> {
+        self.require(Endpoint::News)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = "https://trader.degiro.nl/";
@@ -96,44 +120,167 @@ impl Client {
                 .get(url)
                 .query(&[
                     ("isin", isin.as_ref()),
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                    ("limit", "10"),
+                    ("limit", &COMPANY_NEWS_LIMIT.to_string()),
                     ("offset", "0"),
-                    ("languages", "en,pl"),
+                    ("languages", &languages.join(",")),
                 ])
                 .header(header::REFERER, &inner.referer)
                 .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
         };
 
-        let rate_limiter = {
+        let res = self.send(Endpoint::News, req).await?;
+
+        let mut json = res.json::<serde_json::Value>().await?;
+        let data = json["data"].take();
+        if data.is_null() {
+            return Err(ClientError::NoData);
+        }
+        let items = data["items"]
+            .as_array()
+            .ok_or(ClientError::NoData)?
+            .iter()
+            .map(News::new)
+            .collect();
+        Ok(items)
+    }
+}
+
+impl Client {
+    /// Fetches a single article with its full body, the list endpoints only carry summaries.
+    pub async fn news_article<T: AsRef<str>>(
+        &self,
+        id: T,
+        languages: &[&str],
+    ) -> Result<News, ClientError> {
+        self.require(Endpoint::News)?;
+        let req = {
             let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
+            let base_url = "https://trader.degiro.nl/";
+            let path_url = "dgtbxdsservice/newsfeed/v2/news/";
+            let url = Url::parse(base_url)
+                .unwrap()
+                .join(path_url)
+                .unwrap()
+                .join(id.as_ref())
+                .unwrap();
+
+            inner
+                .http_client
+                .get(url)
+                .query(&[("languages", &languages.join(","))])
+                .header(header::REFERER, &inner.referer)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
         };
-        rate_limiter.acquire_one().await;
 
-        let res = req.send().await?;
+        let res = self.send(Endpoint::News, req).await?;
+
+        let mut json = res.json::<serde_json::Value>().await?;
+        let data = json["data"].take();
+        if data.is_null() {
+            return Err(ClientError::NoData);
+        }
+        Ok(News::new(&data))
+    }
+}
+
+/// Most recent `capacity` ids, forgetting the oldest first.
+struct SeenIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl SeenIds {
+    fn new(capacity: usize) -> Self {
+        Self {
+            ids: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records `id`, returning `false` if it was already seen.
+    fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
+}
+
+struct NewsStreamState {
+    client: Client,
+    isins: Vec<String>,
+    languages: Vec<String>,
+    seen: SeenIds,
+    pending: VecDeque<Result<News, ClientError>>,
+    started: bool,
+}
+
+impl Client {
+    /// Polls company news for `isins` every `interval` and yields each item only once.
+    ///
+    /// Items are de-duplicated by id across polls and emitted oldest first
+    /// within a poll. Only the ids of the last few polls are kept, so memory
+    /// stays bounded however long the stream runs. Request errors are yielded and polling continues.
+    pub fn news_stream<I, S>(
+        &self,
+        isins: I,
+        languages: &[&str],
+        interval: Duration,
+    ) -> impl Stream<Item = Result<News, ClientError>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let isins = isins
+            .into_iter()
+            .map(|s| s.as_ref().to_string())
+            .collect::<Vec<_>>();
+        let state = NewsStreamState {
+            client: self.clone(),
+            seen: SeenIds::new(isins.len().max(1) * COMPANY_NEWS_LIMIT * SEEN_POLLS),
+            isins,
+            languages: languages.iter().map(|s| s.to_string()).collect(),
+            pending: VecDeque::new(),
+            started: false,
+        };
 
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut json = res.json::<serde_json::Value>().await?;
-                let data = json["data"].take();
-                if data.is_null() {
-                    return Err(ClientError::NoData);
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(item) = state.pending.pop_front() {
+                    return Some((item, state));
+                }
+                if state.started {
+                    tokio::time::sleep(interval).await;
                 }
-                let items = data["items"]
-                    .as_array()
-                    .ok_or(ClientError::NoData)?
+                state.started = true;
+
+                let languages = state
+                    .languages
                     .iter()
-                    .map(News::new)
-                    .collect();
-                Ok(items)
-            }
-            Err(err) => {
-                eprintln!("error: {}", err);
-                Err(err.into())
+                    .map(String::as_str)
+                    .collect::<Vec<_>>();
+                let mut fresh = Vec::new();
+                for isin in &state.isins {
+                    match state.client.company_news(isin, &languages).await {
+                        Ok(items) => fresh
+                            .extend(items.into_iter().filter(|news| state.seen.insert(&news.id))),
+                        Err(ClientError::NoData) => {}
+                        Err(err) => state.pending.push_back(Err(err)),
+                    }
+                }
+                fresh.sort_by_key(|news| news.date);
+                state.pending.extend(fresh.into_iter().map(Ok));
             }
-        }
+        })
     }
 }
 
@@ -141,12 +288,28 @@ impl Client {
 mod tests {
     use super::*;
     use crate::client::Client;
+
+    #[test]
+    fn seen_ids_forget_the_oldest() {
+        let mut seen = SeenIds::new(2);
+        assert!(seen.insert("a"));
+        assert!(seen.insert("b"));
+        assert!(!seen.insert("a"));
+        assert!(seen.insert("c"));
+        assert_eq!(seen.ids.len(), 2);
+        assert!(!seen.insert("c"));
+        assert!(seen.insert("a"));
+    }
+
     #[tokio::test]
     async fn test_news_by_company_success() {
         let client = Client::new_from_env();
         client.login().await.unwrap();
         client.account_config().await.unwrap();
-        let news = client.company_news("US7433151039").await.unwrap();
+        let news = client
+            .company_news("US7433151039", DEFAULT_NEWS_LANGUAGES)
+            .await
+            .unwrap();
         for x in &news {
             println!("{}", serde_json::to_string_pretty(x).unwrap());
         }
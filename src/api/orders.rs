@@ -1,12 +1,22 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
 use derivative::Derivative;
 use reqwest::{header, Url};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    client::{Client, ClientError, ClientStatus},
-    money::Currency,
-    util::{OrderTimeType, OrderType, TransactionType},
+    client::{Client, ClientError},
+    http::{read_json, Data, Endpoint},
+    money::{Currency, Money, MoneyBag},
+    named_values::NamedValues,
+    util::{round_to_tick, OrderTimeType, OrderType, TransactionType},
+};
+
+use super::{
+    portfolio::{PositionDetails, PositionType},
+    product::Product,
+    quotecast::QuoteSnapshot,
 };
 #[derive(Derivative, Clone, Deserialize)]
 #[derivative(Debug, Default)]
@@ -19,19 +29,57 @@ pub struct OrderDetails {
     pub contract_size: f64,
     pub currency: Currency,
     pub transaction_type: TransactionType,
+    /// Taken to be the size still open on the market. Unverified, no
+    /// recorded response of a partially filled order backs it yet.
     pub size: f64,
+    /// Taken to be the size originally ordered, unverified like `size`.
     pub quantity: f64,
     pub price: f64,
     pub stop_price: f64,
     pub total_order_value: f64,
+    #[serde(rename = "orderTypeId", with = "crate::util::order_type_id")]
     pub order_type: OrderType,
-    pub order_type_id: u64,
+    #[serde(rename = "orderTimeTypeId", with = "crate::util::order_time_type_id")]
     pub order_time_type: OrderTimeType,
-    pub order_time_type_id: u64,
     pub is_modifiable: bool,
     pub is_deletable: bool,
 }
 
+// Fill tracking assumes `size` is what's left and `quantity` what was
+// ordered. Neither is checked against a partially filled order from
+// `v5/update` yet, so treat the numbers as a best guess.
+impl OrderDetails {
+    /// Size originally ordered, the larger of `quantity` and `size` in case
+    /// the assumption about them is wrong.
+    pub fn ordered_size(&self) -> f64 {
+        self.quantity.max(self.size)
+    }
+
+    /// Size still waiting to be filled.
+    pub fn remaining_size(&self) -> f64 {
+        self.size.clamp(0.0, self.ordered_size())
+    }
+
+    /// Size already executed.
+    pub fn filled_size(&self) -> f64 {
+        self.ordered_size() - self.remaining_size()
+    }
+
+    /// Executed share of the order, from 0.0 to 1.0.
+    pub fn fill_ratio(&self) -> f64 {
+        let ordered = self.ordered_size();
+        if ordered > 0.0 {
+            self.filled_size() / ordered
+        } else {
+            0.0
+        }
+    }
+
+    pub fn is_partially_filled(&self) -> bool {
+        self.filled_size() > 0.0 && self.remaining_size() > 0.0
+    }
+}
+
 #[derive(Derivative, Clone)]
 #[derivative(Debug, Default)]
 pub struct Order {
@@ -39,19 +87,21 @@ pub struct Order {
     client: Option<Client>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateOrderRequest {
     product_id: String,
     #[serde(rename = "buySell")]
     transaction_type: TransactionType,
-    order_type: u8,
+    #[serde(with = "crate::util::order_type_id")]
+    order_type: OrderType,
     #[serde(skip_serializing_if = "Option::is_none")]
     price: Option<f64>,
     size: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     stop_price: Option<f64>,
-    time_type: u8,
+    #[serde(with = "crate::util::order_time_type_id")]
+    time_type: OrderTimeType,
     #[serde(skip)]
     client: Client,
 }
@@ -80,15 +130,63 @@ pub enum OrderRequestBuilderError {
     OrderTypeNotSet,
     #[error("Price is required")]
     PriceNotSet,
+    #[error("Stop price is required")]
+    StopPriceNotSet,
     #[error("Size is required")]
     SizeNotSet,
     #[error("TimeType is required")]
     TimeTypeNotSet,
     #[error("Client is required")]
     ClientNotSet,
+    #[error("Price {price} is not a multiple of tick size {tick_size}")]
+    PriceNotOnTick { price: f64, tick_size: f64 },
+    #[error("Size {size} is not a multiple of lot size {lot_size}")]
+    SizeNotOnLot { size: u64, lot_size: f64 },
+    #[error("{0} orders can't be placed through the API")]
+    UnsupportedOrderType(OrderType),
+    #[error("Time type {0} is not allowed for this product")]
+    TimeTypeNotAllowed(OrderTimeType),
 }
 
 impl CreateOrderRequestBuilder {
+    /// Checks prices against the product tick size when DEGIRO sends one,
+    /// size against its lot size and the time type against the ones the
+    /// product allows.
+    pub fn validate(&self, product: &Product) -> Result<(), OrderRequestBuilderError> {
+        if let Some(time_type) = self.time_type {
+            let allowed = product
+                .inner
+                .order_time_types
+                .as_ref()
+                .is_none_or(|xs| xs.has(time_type));
+            if !allowed {
+                return Err(OrderRequestBuilderError::TimeTypeNotAllowed(time_type));
+            }
+        }
+        if let Some(tick_size) = product.tick_size() {
+            for price in [self.price, self.stop_price].into_iter().flatten() {
+                if !product.is_price_on_tick(price) {
+                    return Err(OrderRequestBuilderError::PriceNotOnTick { price, tick_size });
+                }
+            }
+        }
+        if let Some(size) = self.size {
+            let lot_size = product.lot_size();
+            let lots = size as f64 / lot_size;
+            if (lots - lots.round()).abs() > 1e-9 {
+                return Err(OrderRequestBuilderError::SizeNotOnLot { size, lot_size });
+            }
+        }
+        Ok(())
+    }
+
+    /// Snaps price and stop price onto the product tick grid.
+    pub fn round_to_tick(mut self, product: &Product) -> Self {
+        self.price = self.price.map(|x| product.round_price_to_tick(x));
+        self.stop_price = self.stop_price.map(|x| product.round_price_to_tick(x));
+        self
+    }
+
     pub fn product_id(mut self, product_id: impl ToString) -> Self {
         self.product_id = Some(product_id.to_string());
         self
@@ -124,6 +222,21 @@ impl CreateOrderRequestBuilder {
         self
     }
 
+    /// Sets the order type with the prices it takes. Trailing stops only
+    /// set the order type, [`CreateOrderRequestBuilder::build`] rejects them.
+    pub fn style(self, style: OrderStyle) -> Self {
+        match style {
+            OrderStyle::Market => self.order_type(OrderType::Market),
+            OrderStyle::Limit(price) => self.order_type(OrderType::Limit).price(price),
+            OrderStyle::StopLoss(stop) => self.order_type(OrderType::StopLoss).stop_price(stop),
+            OrderStyle::StopLimit { stop, limit } => self
+                .order_type(OrderType::StopLimit)
+                .stop_price(stop)
+                .price(limit),
+            OrderStyle::TrailingStop { .. } => self.order_type(OrderType::TrailingStop),
+        }
+    }
+
     pub fn client(mut self, client: Client) -> Self {
         self.client = Some(client);
         self
@@ -139,6 +252,23 @@ impl CreateOrderRequestBuilder {
         let order_type = self
             .order_type
             .ok_or(OrderRequestBuilderError::OrderTypeNotSet)?;
+        // How DEGIRO takes the trailing distance is unknown, so they're left
+        // to the paper broker.
+        if order_type == OrderType::TrailingStop {
+            return Err(OrderRequestBuilderError::UnsupportedOrderType(order_type));
+        }
+        let (needs_price, needs_stop) = match order_type {
+            OrderType::Limit => (true, false),
+            OrderType::StopLimit => (true, true),
+            OrderType::StopLoss => (false, true),
+            _ => (false, false),
+        };
+        if needs_price && self.price.is_none() {
+            return Err(OrderRequestBuilderError::PriceNotSet);
+        }
+        if needs_stop && self.stop_price.is_none_or(|x| x <= 0.0) {
+            return Err(OrderRequestBuilderError::StopPriceNotSet);
+        }
         let size = self.size.ok_or(OrderRequestBuilderError::SizeNotSet)?;
         let time_type = self
             .time_type
@@ -148,11 +278,11 @@ impl CreateOrderRequestBuilder {
         let order_request = CreateOrderRequest {
             product_id,
             transaction_type,
-            order_type: order_type.into(),
+            order_type,
             price: self.price,
             size,
             stop_price: self.stop_price,
-            time_type: time_type.into(),
+            time_type,
             client,
         };
 
@@ -161,7 +291,41 @@ impl CreateOrderRequestBuilder {
 }
 
 impl CreateOrderRequest {
-    pub async fn send(&self) -> Result<serde_json::Value, ClientError> {
+    pub fn product_id(&self) -> &str {
+        &self.product_id
+    }
+
+    pub fn transaction_type(&self) -> TransactionType {
+        self.transaction_type
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Limit price, `None` for market and stop loss orders.
+    pub fn price(&self) -> Option<f64> {
+        self.price
+    }
+
+    /// Sends the order to `checkOrder`, nothing is placed. Same as
+    /// [`CreateOrderRequest::check`], use [`CreateOrderRequest::place`] to
+    /// trade.
+    pub async fn send(&self) -> Result<OrderPreview, ClientError> {
+        self.check().await
+    }
+
+    /// Checks and then places the order, see [`Client::check_order`] to
+    /// look at the costs in between. Orders breaking the limits set with
+    /// [`Client::set_risk_limits`] are refused.
+    pub async fn place(&self) -> Result<OrderCreated, ClientError> {
+        let preview = self.check().await?;
+        self.client.confirm_checked_order(preview).await
+    }
+
+    /// Has DEGIRO validate the order and price it without placing it.
+    pub async fn check(&self) -> Result<OrderPreview, ClientError> {
+        self.client.require(Endpoint::PlaceOrder)?;
         let req = {
             let inner = self.client.inner.lock().unwrap();
             let base_url = &inner.account_config.trading_url;
@@ -175,29 +339,319 @@ impl CreateOrderRequest {
             inner
                 .http_client
                 .post(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                ])
                 .header(header::REFERER, &inner.referer)
                 .json(&self)
         };
 
-        let rate_limiter = {
-            let inner = self.client.inner.lock().unwrap();
-            inner.rate_limiter.clone()
+        let res = self.client.send_order(req).await?;
+        let Data { data } = read_json::<Data<OrderCheck>>(res).await?;
+        Ok(OrderPreview {
+            request: self.clone(),
+            check: data,
+        })
+    }
+}
+
+/// Fee, tax or surcharge from an order check.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct OrderFee {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub amount: f64,
+    pub currency: Currency,
+}
+
+impl OrderFee {
+    pub fn money(&self) -> Money {
+        Money::new(self.currency, self.amount)
+    }
+}
+
+/// What `checkOrder` answers for a valid order.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderCheck {
+    pub confirmation_id: String,
+    /// Free space left once the order is placed, in the base currency.
+    #[serde(default)]
+    pub free_space_new: Option<f64>,
+    #[serde(default)]
+    pub transaction_fees: Vec<OrderFee>,
+    /// Fees of the closing trade, shown for leveraged products.
+    #[serde(default)]
+    pub transaction_opposite_fees: Vec<OrderFee>,
+    #[serde(default)]
+    pub transaction_taxes: Vec<OrderFee>,
+    /// Rate used when the trade is settled through auto FX.
+    #[serde(default)]
+    pub auto_fx_conversion_rate: Option<f64>,
+    #[serde(default)]
+    pub transaction_auto_fx_surcharges: Vec<OrderFee>,
+}
+
+/// A checked order waiting to be confirmed with
+/// [`Client::confirm_checked_order`].
+#[derive(Clone, Debug)]
+pub struct OrderPreview {
+    pub request: CreateOrderRequest,
+    pub check: OrderCheck,
+}
+
+impl OrderPreview {
+    /// Fees, taxes and auto FX surcharges of placing the order.
+    pub fn costs(&self) -> MoneyBag {
+        let check = &self.check;
+        check
+            .transaction_fees
+            .iter()
+            .chain(&check.transaction_taxes)
+            .chain(&check.transaction_auto_fx_surcharges)
+            .map(OrderFee::money)
+            .collect()
+    }
+
+    pub fn uses_auto_fx(&self) -> bool {
+        self.check.auto_fx_conversion_rate.is_some()
+    }
+}
+
+/// Placed order and what it costs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OrderCreated {
+    pub order_id: String,
+    /// Fees, taxes and auto FX surcharges from the check.
+    pub fees: Vec<OrderFee>,
+    pub free_space_new: Option<f64>,
+}
+
+impl OrderCreated {
+    pub fn costs(&self) -> MoneyBag {
+        self.fees.iter().map(OrderFee::money).collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OrderModified {
+    pub order_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OrderDeleted {
+    pub order_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderConfirmation {
+    order_id: String,
+}
+
+impl Client {
+    /// First phase of placing an order: validation and costs, nothing is
+    /// sent to the market yet.
+    pub async fn check_order(
+        &self,
+        request: &CreateOrderRequest,
+    ) -> Result<OrderPreview, ClientError> {
+        request.check().await
+    }
+
+    /// Places an order checked by [`Client::check_order`]. Confirmation ids
+    /// are short lived, check again when this fails after a while.
+    ///
+    /// With [`Client::set_risk_limits`] in place the order is simulated
+    /// against them first, before it counts towards the kill switch.
+    pub async fn confirm_checked_order(
+        &self,
+        preview: OrderPreview,
+    ) -> Result<OrderCreated, ClientError> {
+        self.require(Endpoint::PlaceOrder)?;
+        self.check_order_risk(&preview.request).await?;
+        self.guard_order()?;
+        let req = {
+            let inner = self.inner.lock().unwrap();
+            let base_url = &inner.account_config.trading_url;
+            let path_url = format!(
+                "v5/order/{};jsessionid={}",
+                preview.check.confirmation_id, inner.session_id
+            );
+            let url = Url::parse(base_url).unwrap().join(&path_url).unwrap();
+
+            inner
+                .http_client
+                .post(url)
+                .header(header::REFERER, &inner.referer)
+                .json(&preview.request)
         };
-        rate_limiter.acquire_one().await;
 
-        let res = req.send().await?;
+        let res = self.send_order(req).await?;
+        let Data { data } = read_json::<Data<OrderConfirmation>>(res).await?;
+        let OrderCheck {
+            free_space_new,
+            transaction_fees,
+            transaction_taxes,
+            transaction_auto_fx_surcharges,
+            ..
+        } = preview.check;
+        Ok(OrderCreated {
+            order_id: data.order_id,
+            fees: transaction_fees
+                .into_iter()
+                .chain(transaction_taxes)
+                .chain(transaction_auto_fx_surcharges)
+                .collect(),
+            free_space_new,
+        })
+    }
 
-        match res.error_for_status() {
-            Ok(res) => {
-                let json = res.json::<serde_json::Value>().await?;
-                Ok(json)
-            }
-            Err(err) => Err(err.into()),
+    /// Sends an order request, turning a 400 into the rejection it carries.
+    async fn send_order(
+        &self,
+        req: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, ClientError> {
+        let res = self.send_unchecked(Endpoint::PlaceOrder, req).await?;
+        if res.status() == reqwest::StatusCode::BAD_REQUEST {
+            let json = res.json::<serde_json::Value>().await?;
+            return Err(match OrderRejection::from_response(&json) {
+                Some(rejection) => ClientError::OrderRejected(rejection),
+                None => ClientError::Descripted(json.to_string()),
+            });
         }
+        self.check_status(res).await
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// Limit price too far from the last price.
+    PriceOutOfBandwidth,
+    InsufficientFunds,
+    MarketClosed,
+    InvalidSize,
+    NotTradable,
+    Expired,
+    Other,
+}
+
+/// Why an order was refused or ended without a fill, with the server's text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderRejection {
+    pub reason: RejectionReason,
+    pub message: String,
+}
+
+impl OrderRejection {
+    pub fn from_message(message: impl Into<String>) -> Self {
+        let message = message.into();
+        let lower = message.to_lowercase();
+        let has = |xs: &[&str]| xs.iter().any(|x| lower.contains(x));
+        let reason = if has(&[
+            "bandwidth",
+            "price limit",
+            "price range",
+            "outside the allowed",
+        ]) {
+            RejectionReason::PriceOutOfBandwidth
+        } else if has(&["insufficient", "not enough", "free space", "buying power"]) {
+            RejectionReason::InsufficientFunds
+        } else if has(&["market closed", "market is closed", "exchange is closed"]) {
+            RejectionReason::MarketClosed
+        } else if has(&["size", "quantity", "lot"]) {
+            RejectionReason::InvalidSize
+        } else if has(&["not tradable", "not allowed", "not available"]) {
+            RejectionReason::NotTradable
+        } else if has(&["expired"]) {
+            RejectionReason::Expired
+        } else {
+            RejectionReason::Other
+        };
+        Self { reason, message }
+    }
+
+    /// First entry of an `errors` array as sent by `checkOrder` and friends.
+    pub fn from_response(json: &serde_json::Value) -> Option<Self> {
+        let error = json["errors"].as_array()?.first()?;
+        let message = error["text"]
+            .as_str()
+            .or_else(|| error["message"].as_str())
+            .or_else(|| error.as_str())?;
+        Some(Self::from_message(message))
+    }
+}
+
+/// One event of the order history: creation, modification or deletion.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoricalOrder {
+    pub created: Option<DateTime<FixedOffset>>,
+    pub order_id: String,
+    pub product_id: u64,
+    pub size: f64,
+    pub price: f64,
+    #[serde(rename = "buysell")]
+    pub transaction_type: TransactionType,
+    #[serde(rename = "orderTypeId", with = "crate::util::order_type_id")]
+    pub order_type: OrderType,
+    #[serde(rename = "orderTimeTypeId", with = "crate::util::order_time_type_id")]
+    pub order_time_type: OrderTimeType,
+    pub stop_price: f64,
+    pub total_traded_size: f64,
+    /// `CREATE`, `MODIFY` or `DELETE`.
+    #[serde(rename = "type")]
+    pub action: String,
+    /// `CONFIRMED`, `REJECTED`, `EXPIRED`...
+    pub status: String,
+    pub is_active: bool,
+    #[serde(flatten)]
+    pub extras: HashMap<String, serde_json::Value>,
+}
+
+impl HistoricalOrder {
+    /// The reason when the order was rejected or expired.
+    pub fn rejection(&self) -> Option<OrderRejection> {
+        let status = self.status.to_uppercase();
+        if status != "REJECTED" && status != "EXPIRED" {
+            return None;
+        }
+        let message = ["rejectionReason", "reason", "statusText", "message"]
+            .iter()
+            .find_map(|k| self.extras.get(*k).and_then(|v| v.as_str()))
+            .unwrap_or(&self.status);
+        let mut rejection = OrderRejection::from_message(message);
+        if rejection.reason == RejectionReason::Other && status == "EXPIRED" {
+            rejection.reason = RejectionReason::Expired;
+        }
+        Some(rejection)
+    }
+}
+
+impl Client {
+    pub async fn order_history(
+        &self,
+        from_date: &NaiveDate,
+        to_date: &NaiveDate,
+    ) -> Result<Vec<HistoricalOrder>, ClientError> {
+        self.require(Endpoint::OrderHistory)?;
+        let req = {
+            let inner = self.inner.lock().unwrap();
+            let base_url = &inner.account_config.reporting_url;
+            let url = Url::parse(base_url)
+                .and_then(|url| url.join("v4/order-history"))
+                .map_err(|err| ClientError::ParseError(err.to_string()))?;
+            inner
+                .http_client
+                .get(url)
+                .query(&[
+                    ("fromDate", &from_date.format("%d/%m/%Y").to_string()),
+                    ("toDate", &to_date.format("%d/%m/%Y").to_string()),
+                ])
+                .header(header::REFERER, &inner.referer)
+        };
+
+        let res = self.send(Endpoint::OrderHistory, req).await?;
+
+        let Data { data } = read_json(res).await?;
+        Ok(data)
     }
 }
 
@@ -209,13 +663,15 @@ pub struct ModifyOrderRequest {
     pub product_id: String,
     #[serde(rename = "buySell")]
     pub transaction_type: TransactionType,
-    pub order_type: u8,
+    #[serde(with = "crate::util::order_type_id")]
+    pub order_type: OrderType,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub price: Option<f64>,
     pub size: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_price: Option<f64>,
-    pub time_type: u8,
+    #[serde(with = "crate::util::order_time_type_id")]
+    pub time_type: OrderTimeType,
     #[serde(skip)]
     pub client: Client,
 }
@@ -310,11 +766,11 @@ impl ModifyOrderRequestBuilder {
             id,
             product_id,
             transaction_type,
-            order_type: order_type.into(),
+            order_type,
             price: self.price,
             size,
             stop_price: self.stop_price,
-            time_type: time_type.into(),
+            time_type,
             client,
         };
 
@@ -339,7 +795,9 @@ impl From<&Order> for ModifyOrderRequestBuilder {
 }
 
 impl ModifyOrderRequest {
-    pub async fn send(&self) -> Result<serde_json::Value, ClientError> {
+    pub async fn send(&self) -> Result<OrderModified, ClientError> {
+        self.client.require(Endpoint::PlaceOrder)?;
+        self.client.guard_order()?;
         let req = {
             let inner = self.client.inner.lock().unwrap();
             let base_url = &inner.account_config.trading_url;
@@ -355,34 +813,18 @@ impl ModifyOrderRequest {
                     inner.session_id
                 ))
                 .unwrap();
-            dbg!(&url);
 
             inner
                 .http_client
                 .put(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                ])
                 .header(header::REFERER, &inner.referer)
                 .json(&self)
         };
 
-        let rate_limiter = {
-            let inner = self.client.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let json = res.json::<serde_json::Value>().await?;
-                Ok(json)
-            }
-            Err(err) => Err(err.into()),
-        }
+        self.client.send(Endpoint::PlaceOrder, req).await?;
+        Ok(OrderModified {
+            order_id: self.id.clone(),
+        })
     }
 }
 
@@ -435,7 +877,8 @@ impl From<&Order> for DeleteOrderRequestBuilder {
 }
 
 impl DeleteOrderRequest {
-    pub async fn send(&self) -> Result<serde_json::Value, ClientError> {
+    pub async fn send(&self) -> Result<OrderDeleted, ClientError> {
+        self.client.require(Endpoint::PlaceOrder)?;
         let req = {
             let inner = self.client.inner.lock().unwrap();
             let base_url = &inner.account_config.trading_url;
@@ -451,29 +894,14 @@ impl DeleteOrderRequest {
             inner
                 .http_client
                 .delete(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                ])
                 .header(header::REFERER, &inner.referer)
                 .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
         };
 
-        let rate_limiter = {
-            let inner = self.client.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let json = res.json::<serde_json::Value>().await?;
-                Ok(json)
-            }
-            Err(err) => Err(err.into()),
-        }
+        self.client.send(Endpoint::PlaceOrder, req).await?;
+        Ok(OrderDeleted {
+            order_id: self.id.clone(),
+        })
     }
 }
 
@@ -488,47 +916,34 @@ impl Order {
 }
 
 fn parse_order_from_value(value: &serde_json::Value) -> Result<Order, ClientError> {
-    let xs = value["value"].as_array().ok_or(ClientError::ParseError(
-        "Value must be an array".to_string(),
-    ))?;
-
-    let find_key = |k: &str| {
-        xs.iter()
-            .find(|v| v["name"] == k)
-            .map(|v| v["value"].clone())
-            .ok_or_else(|| ClientError::ParseError(format!("Cannot find key: {}", k)))
-    };
+    let xs = NamedValues::deserialize(&value["value"])?;
 
-    let date_str = find_key("date")?
-        .as_str()
-        .ok_or(ClientError::ParseError("Invalid date".to_string()))?
-        .to_owned();
-
-    let date = NaiveDateTime::parse_from_str(&date_str, "%Y-%m-%dT%H:%M:%S")
+    let date_str: &str = xs.field("date")?;
+    let date = NaiveDateTime::parse_from_str(date_str, "%Y-%m-%dT%H:%M:%S")
         .map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc))
-        .map_err(|e| ClientError::ParseError(format!("Failed to parse date: {}", e)))?
-        .with_timezone(&Utc);
+        .map_err(|e| ClientError::ParseError(format!("Failed to parse date: {}", e)))?;
 
     let details = OrderDetails {
-        id: serde_json::from_value(find_key("id")?)?,
+        id: xs.field("id")?,
         date,
-        product: serde_json::from_value(find_key("product")?)?,
-        product_id: serde_json::from_value(find_key("productId")?)?,
-        contract_type: serde_json::from_value(find_key("contractType")?)?,
-        contract_size: serde_json::from_value(find_key("contractSize")?)?,
-        currency: serde_json::from_value(find_key("currency")?)?,
-        transaction_type: serde_json::from_value(find_key("buysell")?)?,
-        size: serde_json::from_value(find_key("size")?)?,
-        quantity: serde_json::from_value(find_key("quantity")?)?,
-        price: serde_json::from_value(find_key("price")?)?,
-        stop_price: serde_json::from_value(find_key("stopPrice")?)?,
-        total_order_value: serde_json::from_value(find_key("totalOrderValue")?)?,
-        order_type: serde_json::from_value(find_key("orderType")?)?,
-        order_type_id: serde_json::from_value(find_key("orderTypeId")?)?,
-        order_time_type: serde_json::from_value(find_key("orderTimeType")?)?,
-        order_time_type_id: serde_json::from_value(find_key("orderTimeTypeId")?)?,
-        is_modifiable: serde_json::from_value(find_key("isModifiable")?)?,
-        is_deletable: serde_json::from_value(find_key("isDeletable")?)?,
+        product: xs.field("product")?,
+        product_id: xs.field("productId")?,
+        contract_type: xs.field("contractType")?,
+        contract_size: xs.field("contractSize")?,
+        currency: xs.field("currency")?,
+        transaction_type: xs.field("buysell")?,
+        size: xs.field("size")?,
+        quantity: xs.field("quantity")?,
+        price: xs.field("price")?,
+        stop_price: xs.field("stopPrice")?,
+        total_order_value: xs.field("totalOrderValue")?,
+        order_type: xs.field_with("orderTypeId", crate::util::order_type_id::deserialize)?,
+        order_time_type: xs.field_with(
+            "orderTimeTypeId",
+            crate::util::order_time_type_id::deserialize,
+        )?,
+        is_modifiable: xs.field("isModifiable")?,
+        is_deletable: xs.field("isDeletable")?,
     };
 
     Ok(Order {
@@ -559,6 +974,13 @@ impl FromIterator<Order> for Orders {
     }
 }
 
+impl Order {
+    /// Executed share of the order, from 0.0 to 1.0.
+    pub fn fill_ratio(&self) -> f64 {
+        self.inner.fill_ratio()
+    }
+}
+
 impl Orders {
     pub fn iter(&self) -> std::slice::Iter<Order> {
         self.0.iter()
@@ -615,9 +1037,7 @@ impl Client {
     }
 
     pub async fn orders(&self) -> Result<Orders, ClientError> {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        self.require(Endpoint::Orders)?;
 
         let req = {
             let inner = self.inner.lock().unwrap();
@@ -640,31 +1060,171 @@ impl Client {
                 .header(header::REFERER, &inner.referer)
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
+        let res = self.send(Endpoint::Orders, req).await?;
 
-        let res = req.send().await?;
+        let json = res.json::<serde_json::Value>().await?;
+        let raw_orders = json["orders"]["value"].as_array().unwrap().as_slice();
+        let mut orders = parse_orders_from_values(raw_orders)?;
 
-        match res.error_for_status() {
-            Ok(res) => {
-                let json = res.json::<serde_json::Value>().await?;
-                let raw_orders = json["orders"]["value"].as_array().unwrap().as_slice();
-                let mut orders = parse_orders_from_values(raw_orders)?;
+        orders.iter_mut().for_each(|order| {
+            order.client = Some(self.clone());
+        });
 
-                orders.iter_mut().for_each(|order| {
-                    order.client = Some(self.clone());
-                });
+        Ok(orders.into())
+    }
+}
 
-                Ok(orders.into())
-            }
-            Err(err) => {
-                eprintln!("error: {}", err);
-                Err(err.into())
-            }
-        }
+/// Order type and prices used to close or reduce a position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderStyle {
+    Market,
+    Limit(f64),
+    StopLoss(f64),
+    StopLimit {
+        stop: f64,
+        limit: f64,
+    },
+    /// Stop following the best price since placing at `distance` from it,
+    /// in the product currency. Only simulated by
+    /// [`crate::strategy::PaperBroker`], live orders can't use it.
+    TrailingStop {
+        distance: f64,
+    },
+}
+
+/// Builder for the order taking `fraction` of `position` off the table.
+///
+/// Only ever trades against what is held, so a long position is sold down
+/// to zero at most and never turned short, and a short one is bought back.
+pub fn reduce_order(
+    position: &PositionDetails,
+    fraction: f64,
+    style: OrderStyle,
+) -> Result<CreateOrderRequestBuilder, ClientError> {
+    if position.position_type != PositionType::Product {
+        return Err(ClientError::InvalidPosition(format!(
+            "{} is not a product position",
+            position.id
+        )));
+    }
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return Err(ClientError::InvalidPosition(format!(
+            "fraction {fraction} is not in (0, 1]"
+        )));
+    }
+    let held = position.size.abs().floor();
+    let size = if fraction == 1.0 {
+        held
+    } else {
+        (held * fraction).floor()
+    };
+    if size < 1.0 {
+        return Err(ClientError::InvalidPosition(format!(
+            "nothing to trade: holding {} of {}",
+            position.size, position.id
+        )));
+    }
+    let side = if position.size > 0.0 {
+        TransactionType::Sell
+    } else {
+        TransactionType::Buy
+    };
+    let builder = CreateOrderRequestBuilder::default()
+        .product_id(&position.id)
+        .transaction_type(side)
+        .size(size as u64)
+        .time_type(OrderTimeType::Day);
+    Ok(builder.style(style))
+}
+
+impl Client {
+    /// Order closing the whole position in `product_id`, checked against the
+    /// current portfolio. Build and send it, or adjust it first.
+    pub async fn close_position(
+        &self,
+        product_id: impl AsRef<str>,
+        style: OrderStyle,
+    ) -> Result<CreateOrderRequestBuilder, ClientError> {
+        self.reduce_position(product_id, 1.0, style).await
+    }
+
+    /// Order taking `fraction` (0, 1] of the position in `product_id` off,
+    /// rounded down to whole units.
+    pub async fn reduce_position(
+        &self,
+        product_id: impl AsRef<str>,
+        fraction: f64,
+        style: OrderStyle,
+    ) -> Result<CreateOrderRequestBuilder, ClientError> {
+        let product_id = product_id.as_ref();
+        let portfolio = self.portfolio().await?;
+        let position = portfolio
+            .0
+            .iter()
+            .find(|p| p.inner.id == product_id)
+            .ok_or_else(|| ClientError::InvalidPosition(format!("no position in {product_id}")))?;
+        Ok(reduce_order(&position.inner, fraction, style)?.client(self.clone()))
+    }
+}
+
+/// How hard a limit order should lean into the book.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aggressiveness {
+    /// Join our own side of the book: bid when buying, ask when selling.
+    Passive,
+    /// Midpoint, rounded away from the other side.
+    Mid,
+    /// Take the opposite touch.
+    Touch,
+    /// Cross the opposite touch by N ticks.
+    Cross(u32),
+}
+
+pub fn limit_price_from_snapshot(
+    snapshot: &QuoteSnapshot,
+    side: TransactionType,
+    aggressiveness: Aggressiveness,
+    tick_size: f64,
+) -> Option<f64> {
+    let (own, other) = match side {
+        TransactionType::Buy => (snapshot.bid, snapshot.ask),
+        TransactionType::Sell => (snapshot.ask, snapshot.bid),
+    };
+    let sign = match side {
+        TransactionType::Buy => 1.0,
+        TransactionType::Sell => -1.0,
+    };
+    let (price, aggressive) = match aggressiveness {
+        Aggressiveness::Passive => (own.or(snapshot.last)?, false),
+        Aggressiveness::Mid => (snapshot.mid().or(snapshot.last)?, false),
+        Aggressiveness::Touch => (other.or(snapshot.last)?, true),
+        Aggressiveness::Cross(n) => (other.or(snapshot.last)? + sign * n as f64 * tick_size, true),
+    };
+    // Passive prices never round through the spread, aggressive ones never fall short of it.
+    let up = (side == TransactionType::Buy) == aggressive;
+    Some(round_to_tick(price, tick_size, up))
+}
+
+impl Client {
+    pub async fn suggest_limit_price(
+        &self,
+        product: &Product,
+        side: TransactionType,
+        aggressiveness: Aggressiveness,
+    ) -> Result<f64, ClientError> {
+        let Some(vwd_id) = product.inner.vwd_id.as_deref() else {
+            return Err(ClientError::NoData);
+        };
+        // Without a tick size prices aren't rounded, and there is no tick to
+        // cross the spread by.
+        let tick_size = match (product.tick_size(), aggressiveness) {
+            (Some(x), _) => x,
+            (None, Aggressiveness::Cross(_)) => return Err(ClientError::NoData),
+            (None, _) => 0.0,
+        };
+        let snapshot = self.quote_snapshot(vwd_id).await?;
+        limit_price_from_snapshot(&snapshot, side, aggressiveness, tick_size)
+            .ok_or(ClientError::NoData)
     }
 }
 
@@ -779,4 +1339,239 @@ mod test {
     //     let resp = client.create_order(order_request).await;
     //     dbg!(resp);
     // }
+
+    #[test]
+    fn order_preview_costs() {
+        let check: Data<OrderCheck> = serde_json::from_value(serde_json::json!({
+            "data": {
+                "confirmationId": "8f1e0e33-3c2c-4a5e-9c3b-1d2f0c2b7a10",
+                "freeSpaceNew": 1234.5,
+                "transactionFees": [
+                    {"id": 2, "amount": 0.5, "currency": "EUR"},
+                    {"id": 3, "amount": 1.0, "currency": "EUR"}
+                ],
+                "transactionTaxes": [{"amount": 0.35, "currency": "EUR"}],
+                "autoFxConversionRate": 1.0862,
+                "showExAnteReportLink": true
+            }
+        }))
+        .unwrap();
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let request = client
+            .create_order()
+            .product_id("331868")
+            .transaction_type(TransactionType::Buy)
+            .order_type(OrderType::Market)
+            .size(1)
+            .time_type(OrderTimeType::Day)
+            .build()
+            .unwrap();
+        let preview = OrderPreview {
+            request,
+            check: check.data,
+        };
+        assert!((preview.costs().get(Currency::EUR).amount - 1.85).abs() < 1e-9);
+        assert!(preview.uses_auto_fx());
+        assert_eq!(preview.check.free_space_new, Some(1234.5));
+    }
+
+    #[test]
+    fn order_confirmation() {
+        let Data { data } = serde_json::from_str::<Data<OrderConfirmation>>(
+            r#"{"data": {"orderId": "6126ef1a-1258-424a-b2d7-7930d44ac56a"}}"#,
+        )
+        .unwrap();
+        assert_eq!(data.order_id, "6126ef1a-1258-424a-b2d7-7930d44ac56a");
+    }
+
+    #[test]
+    fn reduce_never_oversells() {
+        let position = PositionDetails {
+            id: "331868".to_string(),
+            size: 15.0,
+            ..Default::default()
+        };
+        let half = reduce_order(&position, 0.5, OrderStyle::Limit(10.0)).unwrap();
+        assert_eq!(half.size, Some(7));
+        assert_eq!(half.transaction_type, Some(TransactionType::Sell));
+        assert_eq!(half.price, Some(10.0));
+
+        let all = reduce_order(&position, 1.0, OrderStyle::Market).unwrap();
+        assert_eq!(all.size, Some(15));
+        assert_eq!(all.order_type, Some(OrderType::Market));
+
+        assert!(reduce_order(&position, 1.5, OrderStyle::Market).is_err());
+        assert!(reduce_order(&position, 0.01, OrderStyle::Market).is_err());
+
+        let short = PositionDetails {
+            size: -4.0,
+            ..position.clone()
+        };
+        let cover = reduce_order(&short, 1.0, OrderStyle::Market).unwrap();
+        assert_eq!(cover.transaction_type, Some(TransactionType::Buy));
+        assert_eq!(cover.size, Some(4));
+
+        let cash = PositionDetails {
+            position_type: PositionType::Cash,
+            ..position
+        };
+        assert!(reduce_order(&cash, 1.0, OrderStyle::Market).is_err());
+    }
+
+    #[test]
+    fn rejection_reasons() {
+        let json = serde_json::json!({
+            "errors": [{ "text": "The price is outside the allowed bandwidth" }]
+        });
+        assert_eq!(
+            OrderRejection::from_response(&json).unwrap().reason,
+            RejectionReason::PriceOutOfBandwidth
+        );
+
+        let history = serde_json::from_value::<Vec<HistoricalOrder>>(serde_json::json!([
+            {
+                "created": "2024-03-01T10:00:00+01:00",
+                "orderId": "a",
+                "productId": 331868,
+                "buysell": "B",
+                "orderTypeId": 0,
+                "orderTimeTypeId": 1,
+                "type": "CREATE",
+                "status": "REJECTED",
+                "statusText": "Insufficient funds"
+            },
+            { "orderId": "b", "type": "CREATE", "status": "EXPIRED" },
+            { "orderId": "c", "type": "CREATE", "status": "CONFIRMED" }
+        ]))
+        .unwrap();
+        assert_eq!(
+            history[0].rejection().unwrap().reason,
+            RejectionReason::InsufficientFunds
+        );
+        assert_eq!(
+            history[1].rejection().unwrap().reason,
+            RejectionReason::Expired
+        );
+        assert!(history[2].rejection().is_none());
+    }
+
+    #[test]
+    fn order_styles() {
+        let builder = || {
+            CreateOrderRequestBuilder::default()
+                .product_id("331868")
+                .transaction_type(TransactionType::Sell)
+                .size(1)
+                .time_type(OrderTimeType::Gtc)
+                .client(Client::new(
+                    "",
+                    "",
+                    reqwest::Client::new(),
+                    Default::default(),
+                ))
+        };
+        let json = |style| serde_json::to_value(builder().style(style).build().unwrap()).unwrap();
+
+        let stop_limit = json(OrderStyle::StopLimit {
+            stop: 9.5,
+            limit: 9.4,
+        });
+        assert_eq!(stop_limit["orderType"], 1);
+        assert_eq!(stop_limit["stopPrice"], 9.5);
+        assert_eq!(stop_limit["price"], 9.4);
+
+        assert!(matches!(
+            builder()
+                .style(OrderStyle::TrailingStop { distance: 0.5 })
+                .build(),
+            Err(OrderRequestBuilderError::UnsupportedOrderType(
+                OrderType::TrailingStop
+            ))
+        ));
+
+        assert!(matches!(
+            builder()
+                .order_type(OrderType::StopLimit)
+                .price(9.4)
+                .build(),
+            Err(OrderRequestBuilderError::StopPriceNotSet)
+        ));
+        assert!(matches!(
+            builder().order_type(OrderType::Limit).build(),
+            Err(OrderRequestBuilderError::PriceNotSet)
+        ));
+    }
+
+    #[test]
+    fn partial_fill_tracking() {
+        let mut details = OrderDetails {
+            quantity: 100.0,
+            size: 100.0,
+            ..Default::default()
+        };
+        assert_eq!(details.fill_ratio(), 0.0);
+        assert!(!details.is_partially_filled());
+
+        details.size = 40.0;
+        assert_eq!(details.filled_size(), 60.0);
+        assert_eq!(details.remaining_size(), 40.0);
+        assert_eq!(details.fill_ratio(), 0.6);
+        assert!(details.is_partially_filled());
+
+        details.size = 0.0;
+        assert_eq!(details.fill_ratio(), 1.0);
+        assert!(!details.is_partially_filled());
+
+        let empty = OrderDetails::default();
+        assert_eq!(empty.fill_ratio(), 0.0);
+    }
+
+    #[test]
+    fn order_enums_use_wire_ids() {
+        let req = CreateOrderRequest {
+            product_id: "331868".to_string(),
+            transaction_type: TransactionType::Buy,
+            order_type: OrderType::StopLimit,
+            price: Some(10.0),
+            size: 1,
+            stop_price: Some(9.5),
+            time_type: OrderTimeType::Gtc,
+            client: Client::new("", "", reqwest::Client::new(), Default::default()),
+        };
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["orderType"], 1);
+        assert_eq!(json["timeType"], 3);
+
+        for x in [
+            OrderType::Limit,
+            OrderType::StopLimit,
+            OrderType::Market,
+            OrderType::StopLoss,
+            OrderType::TrailingStop,
+            OrderType::StandardAmount,
+            OrderType::StandardSize,
+        ] {
+            assert_eq!(OrderType::from_id(x.id()), Some(x));
+        }
+        assert!(OrderType::try_from(99).is_err());
+        assert_eq!(OrderTimeType::from_id(1), Some(OrderTimeType::Day));
+    }
+
+    #[test]
+    fn limit_price_suggestion() {
+        let snapshot = QuoteSnapshot {
+            vwd_id: "1".to_string(),
+            bid: Some(10.00),
+            ask: Some(10.05),
+            last: Some(10.02),
+        };
+        let buy = |a| limit_price_from_snapshot(&snapshot, TransactionType::Buy, a, 0.01);
+        let sell = |a| limit_price_from_snapshot(&snapshot, TransactionType::Sell, a, 0.01);
+        assert_eq!(buy(Aggressiveness::Passive), Some(10.00));
+        assert_eq!(buy(Aggressiveness::Mid), Some(10.02));
+        assert_eq!(sell(Aggressiveness::Mid), Some(10.03));
+        assert_eq!(buy(Aggressiveness::Touch), Some(10.05));
+        assert_eq!(buy(Aggressiveness::Cross(2)), Some(10.07));
+        assert_eq!(sell(Aggressiveness::Cross(2)), Some(9.98));
+    }
 }
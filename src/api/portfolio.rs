@@ -1,83 +1,176 @@
 use reqwest::{header, Url};
-use serde::Deserialize;
-use serde_json::Value;
-use std::{collections::HashMap, convert::TryInto};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use strum::EnumString;
-use thiserror::Error;
 
 use crate::{
-    client::{Client, ClientError, ClientStatus},
-    money::{Currency, Money},
+    client::{Client, ClientError},
+    export::read_csv,
+    http::{read_json, Endpoint},
+    money::{Currency, Money, MoneyBag},
+    named_values::NamedValues,
 };
 
-use super::product::Product;
+use super::{account::AccountInfo, product::Product};
 
 #[derive(Debug, Deserialize)]
-struct PortfolioObject {
-    value: Vec<ValueObject>,
+struct PortfolioResponse {
+    portfolio: PortfolioObjects,
 }
 
 #[derive(Debug, Deserialize)]
-struct ValueObject {
-    #[serde(rename = "name")]
-    elem_type: ElemType,
-    value: Option<Value>,
+struct PortfolioObjects {
+    value: Vec<PortfolioObject>,
 }
 
-#[derive(Debug, Deserialize, EnumString)]
-#[serde(rename_all = "camelCase")]
-enum ElemType {
-    Id,
-    PositionType,
-    Size,
-    Price,
-    Value,
-    AccruedInterest,
-    PlBase,
-    TodayPlBase,
-    PortfolioValueCorrection,
-    BreakEvenPrice,
-    AverageFxRate,
-    RealizedProductPl,
-    RealizedFxPl,
-    TodayRealizedProductPl,
-    TodayRealizedFxPl,
+#[derive(Debug, Deserialize)]
+struct PortfolioObject {
+    value: NamedValues,
 }
 
+/// Serialized field names are part of the portfolio schema, see
+/// [`PORTFOLIO_SCHEMA_VERSION`]; keep every `rename` when renaming fields.
 #[allow(dead_code)]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PositionDetails {
+    #[serde(rename = "id")]
     pub id: String,
+    #[serde(rename = "positionType")]
     pub position_type: PositionType,
+    #[serde(rename = "size")]
     pub size: f64,
+    #[serde(rename = "price")]
     pub price: f64,
+    /// Currency `value` and the profits are reported in; the product's
+    /// trading currency is [`super::product::Product::currency`].
+    #[serde(rename = "currency")]
     pub currency: Currency,
+    #[serde(rename = "value")]
     pub value: Money,
+    #[serde(rename = "accruedInterest")]
     pub accrued_interest: Option<f64>,
+    #[serde(rename = "baseValue")]
     pub base_value: Money,
+    #[serde(rename = "todayValue")]
     pub today_value: Money,
+    #[serde(rename = "portfolioValueCorrection")]
     pub portfolio_value_correction: f64,
+    #[serde(rename = "breakEvenPrice")]
     pub break_even_price: f64,
+    #[serde(rename = "averageFxRate")]
     pub average_fx_rate: f64,
+    #[serde(rename = "realizedProductProfit")]
     pub realized_product_profit: Money,
+    #[serde(rename = "realizedFxProfit")]
     pub realized_fx_profit: Money,
+    #[serde(rename = "todayRealizedProductPl")]
     pub today_realized_product_pl: Money,
+    #[serde(rename = "todayRealizedFxPl")]
     pub today_realized_fx_pl: Money,
+    #[serde(rename = "totalProfit")]
     pub total_profit: Money,
+    #[serde(rename = "productProfit")]
     pub product_profit: Money,
+    #[serde(rename = "fxProfit")]
     pub fx_profit: Money,
 }
 
+/// Cash row of the portfolio, which carries its currency in the id.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CashPosition {
+    pub currency: Currency,
+    pub amount: Money,
+}
+
 #[derive(Clone, Debug)]
 pub struct Position {
     pub inner: PositionDetails,
     pub client: Client,
 }
 
+impl PositionDetails {
+    /// Dividend per share divided by the break-even (cost) price.
+    pub fn yield_on_cost(&self, dividend_per_share: f64) -> Option<f64> {
+        (self.break_even_price != 0.0).then(|| dividend_per_share / self.break_even_price)
+    }
+
+    /// The cash balance, for cash rows in a supported currency.
+    ///
+    /// Cash ids are the currency code, `FLATEX_` prefixed for the sweep
+    /// account balance.
+    pub fn cash_position(&self) -> Option<CashPosition> {
+        if self.position_type != PositionType::Cash {
+            return None;
+        }
+        let currency = self.id.trim_start_matches("FLATEX_").parse().ok()?;
+        Some(CashPosition {
+            currency,
+            amount: Money::new(currency, self.size),
+        })
+    }
+
+    /// Sizes are signed: derivatives accounts can hold short positions.
+    pub fn is_long(&self) -> bool {
+        self.size > 0.0
+    }
+
+    pub fn is_short(&self) -> bool {
+        self.size < 0.0
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.size != 0.0
+    }
+
+    /// Yearly dividend income in the position's value currency.
+    ///
+    /// `value` is reported in the base currency while `price` is quoted in the
+    /// product currency, so their ratio converts the dividend without a rate lookup.
+    /// Negative for short positions, which pay the dividend instead.
+    pub fn annual_income(&self, dividend_per_share: f64) -> Money {
+        let amount = if self.price == 0.0 {
+            0.0
+        } else {
+            dividend_per_share * self.value.amount / self.price
+        };
+        Money::new(self.value.currency, amount)
+    }
+}
+
 impl Position {
     pub fn new(inner: PositionDetails, client: Client) -> Self {
         Self { inner, client }
     }
+
+    /// Value converted into `to` with the session FX rates, failing when
+    /// there is no pair for the position currency.
+    pub async fn value_in(&self, to: Currency, client: &Client) -> Result<Money, ClientError> {
+        MoneyBag::from(self.inner.value).total_in(to, client).await
+    }
+
+    /// Trailing twelve month dividend per share, `None` when the company doesn't pay any.
+    pub async fn dividend_per_share(&self) -> Result<Option<f64>, ClientError> {
+        match self.client.company_ratios_by_id(&self.inner.id).await {
+            Ok(ratios) => Ok(ratios.current_ratios.dividend_per_share_ttm.value),
+            Err(ClientError::NoData) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub async fn yield_on_cost(&self) -> Result<Option<f64>, ClientError> {
+        let dps = self.dividend_per_share().await?;
+        Ok(dps.and_then(|dps| self.inner.yield_on_cost(dps)))
+    }
+
+    pub async fn projected_annual_income(&self) -> Result<Money, ClientError> {
+        let dps = self.dividend_per_share().await?.unwrap_or_default();
+        Ok(self.inner.annual_income(dps))
+    }
+    /// Loads the product behind this position on demand.
+    ///
+    /// Backed by the client's product cache, so enriching only the positions
+    /// you care about costs at most one request per product.
     pub async fn product(&self) -> Result<Product, ClientError> {
         self.client.product(&self.inner.id).await
     }
@@ -86,6 +179,304 @@ impl Position {
 #[derive(Clone, Debug, Default)]
 pub struct Portfolio(pub Vec<Position>);
 
+/// Version of the serialized portfolio layout.
+///
+/// Adding fields is a minor change and keeps the version: older readers
+/// ignore unknown fields and newer ones default missing fields. Renaming or
+/// removing a field, or changing its meaning, bumps it.
+pub const PORTFOLIO_SCHEMA_VERSION: u32 = 1;
+
+/// Portfolio as stored by downstream tools, tagged with its schema version.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioSnapshot {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "positions", default)]
+    pub positions: Vec<PositionDetails>,
+}
+
+impl Portfolio {
+    /// Parses a raw `portfolio` response body, as returned by the update endpoint.
+    pub fn from_response(body: &[u8], client: &Client) -> Result<Self, ClientError> {
+        let body = serde_json::from_slice::<PortfolioResponse>(body)?;
+        Self::from_objects(body.portfolio.value, client)
+    }
+
+    fn from_objects(objs: Vec<PortfolioObject>, client: &Client) -> Result<Self, ClientError> {
+        let mut xs: Vec<_> = Vec::with_capacity(objs.len());
+        for obj in objs {
+            let p = PositionDetails::try_from(&obj.value)?;
+            xs.push(Position::new(p, client.clone()));
+        }
+        Ok(Portfolio::new(xs))
+    }
+
+    pub fn snapshot(&self) -> PortfolioSnapshot {
+        PortfolioSnapshot {
+            schema_version: PORTFOLIO_SCHEMA_VERSION,
+            positions: self.0.iter().map(|p| p.inner.clone()).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, ClientError> {
+        Ok(serde_json::to_string(&self.snapshot())?)
+    }
+
+    /// Rebuilds a portfolio from a snapshot, refusing newer schema versions.
+    pub fn from_snapshot(snapshot: PortfolioSnapshot, client: Client) -> Result<Self, ClientError> {
+        if snapshot.schema_version > PORTFOLIO_SCHEMA_VERSION {
+            return Err(ClientError::ParseError(format!(
+                "portfolio schema version {} is newer than supported {}",
+                snapshot.schema_version, PORTFOLIO_SCHEMA_VERSION
+            )));
+        }
+        let xs = snapshot
+            .positions
+            .into_iter()
+            .map(|inner| Position::new(inner, client.clone()))
+            .collect::<Vec<_>>();
+        Ok(Portfolio::new(xs))
+    }
+
+    pub fn from_json(json: &str, client: Client) -> Result<Self, ClientError> {
+        Self::from_snapshot(serde_json::from_str(json)?, client)
+    }
+
+    /// Loads a portfolio kept elsewhere, e.g. a model portfolio or another
+    /// broker's export, from CSV with a header row.
+    ///
+    /// Rows whose id is a currency code become cash positions. Without a
+    /// value column positions are valued at `price * size`.
+    pub fn from_csv(
+        mut reader: impl std::io::Read,
+        mapping: &CsvMapping,
+        client: Client,
+    ) -> Result<Self, ClientError> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .map_err(|err| ClientError::ParseError(format!("can't read csv: {err}")))?;
+        let mut rows = read_csv(&text).into_iter();
+        let header = rows.next().unwrap_or_default();
+        let column = |name: &str| header.iter().position(|x| x.trim() == name);
+        let required = |name: &str| {
+            column(name)
+                .ok_or_else(|| ClientError::ParseError(format!("csv has no `{name}` column")))
+        };
+        let id_col = required(&mapping.id)?;
+        let size_col = required(&mapping.size)?;
+        let price_col = column(&mapping.price);
+        let currency_col = column(&mapping.currency);
+        let value_col = column(&mapping.value);
+
+        let mut xs = Vec::new();
+        for (i, row) in rows.enumerate() {
+            let line = i + 2;
+            let field = |col: Option<usize>| {
+                col.and_then(|col| row.get(col))
+                    .map(|x| x.trim())
+                    .filter(|x| !x.is_empty())
+            };
+            let number = |col: Option<usize>, name: &str| {
+                field(col)
+                    .map(|x| {
+                        x.parse::<f64>().map_err(|_| {
+                            ClientError::ParseError(format!("line {line}: bad {name} `{x}`"))
+                        })
+                    })
+                    .transpose()
+            };
+            let id = field(Some(id_col))
+                .ok_or_else(|| ClientError::ParseError(format!("line {line}: missing id")))?
+                .to_string();
+            let size = number(Some(size_col), "size")?
+                .ok_or_else(|| ClientError::ParseError(format!("line {line}: missing size")))?;
+            let cash = id.parse::<Currency>().ok();
+            let currency = match field(currency_col) {
+                Some(x) => x.parse::<Currency>().map_err(|_| {
+                    ClientError::ParseError(format!("line {line}: unknown currency `{x}`"))
+                })?,
+                None => cash.unwrap_or(mapping.default_currency),
+            };
+            let (position_type, price) = match cash {
+                Some(_) => (PositionType::Cash, 1.0),
+                None => (
+                    PositionType::Product,
+                    number(price_col, "price")?.unwrap_or_default(),
+                ),
+            };
+            let value = number(value_col, "value")?.unwrap_or(price * size);
+            let inner = PositionDetails {
+                id,
+                position_type,
+                size,
+                price,
+                currency,
+                value: Money::new(currency, value),
+                ..Default::default()
+            };
+            xs.push(Position::new(inner, client.clone()));
+        }
+        Ok(Portfolio::new(xs))
+    }
+}
+
+/// Column names [`Portfolio::from_csv`] reads; only `id` and `size` must be
+/// present.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvMapping {
+    pub id: String,
+    pub size: String,
+    pub price: String,
+    pub currency: String,
+    pub value: String,
+    /// Currency of rows without a currency column.
+    pub default_currency: Currency,
+}
+
+impl Default for CsvMapping {
+    fn default() -> Self {
+        Self {
+            id: "id".to_string(),
+            size: "size".to_string(),
+            price: "price".to_string(),
+            currency: "currency".to_string(),
+            value: "value".to_string(),
+            default_currency: Currency::EUR,
+        }
+    }
+}
+
+impl CsvMapping {
+    pub fn id(mut self, column: impl Into<String>) -> Self {
+        self.id = column.into();
+        self
+    }
+
+    pub fn size(mut self, column: impl Into<String>) -> Self {
+        self.size = column.into();
+        self
+    }
+
+    pub fn price(mut self, column: impl Into<String>) -> Self {
+        self.price = column.into();
+        self
+    }
+
+    pub fn currency(mut self, column: impl Into<String>) -> Self {
+        self.currency = column.into();
+        self
+    }
+
+    pub fn value(mut self, column: impl Into<String>) -> Self {
+        self.value = column.into();
+        self
+    }
+
+    pub fn default_currency(mut self, currency: Currency) -> Self {
+        self.default_currency = currency;
+        self
+    }
+}
+
+/// Account balance split into its components, all in the base currency
+/// except `per_currency` which holds the native cash balances.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalanceBreakdown {
+    pub cash: Money,
+    pub invested: Money,
+    pub unsettled: Money,
+    pub total: Money,
+    pub per_currency: HashMap<Currency, Money>,
+}
+
+impl BalanceBreakdown {
+    pub fn new(
+        portfolio: &Portfolio,
+        unsettled: Option<Money>,
+        base: Currency,
+        info: &AccountInfo,
+    ) -> Result<Self, ClientError> {
+        let per_currency = portfolio.cash_balances();
+        let invested = portfolio.clone().products().value_in(base, info)?;
+        let cash = portfolio.clone().cash().value_in(base, info)?;
+        let unsettled = match unsettled {
+            Some(money) => info.convert(money, base)?,
+            None => Money::new(base, 0.0),
+        };
+        let total = ((cash + invested)? + unsettled)?;
+        Ok(Self {
+            cash,
+            invested,
+            unsettled,
+            total,
+            per_currency,
+        })
+    }
+}
+
+/// Unhedged exposure to a single foreign currency.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FxExposure {
+    pub currency: Currency,
+    /// Exposure in the foreign currency itself.
+    pub native: f64,
+    /// The same exposure valued in the account base currency.
+    pub base: Money,
+}
+
+/// FX conversion that offsets (part of) an exposure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FxHedge {
+    pub sell: Money,
+    pub buy: Money,
+}
+
+impl FxExposure {
+    /// Base currency units per unit of the foreign currency, implied by the position values.
+    pub fn implied_rate(&self) -> Option<f64> {
+        (self.native != 0.0).then(|| self.base.amount / self.native)
+    }
+
+    /// Conversion that removes `ratio` (0.0..=1.0) of the exposure.
+    pub fn hedge(&self, ratio: f64) -> FxHedge {
+        FxHedge {
+            sell: Money::new(self.currency, self.native * ratio),
+            buy: Money::new(self.base.currency, self.base.amount * ratio),
+        }
+    }
+}
+
+/// Groups positions by their native currency, skipping the base currency.
+///
+/// Both `value` and `size` of cash positions are used as-is, product positions
+/// are converted with `price * size` which is quoted in the product currency.
+fn fx_exposure_from<'a>(
+    positions: impl IntoIterator<Item = (Currency, &'a PositionDetails)>,
+) -> Vec<FxExposure> {
+    let mut m: HashMap<Currency, FxExposure> = HashMap::new();
+    for (currency, p) in positions {
+        let base_currency = p.value.currency;
+        if currency == base_currency {
+            continue;
+        }
+        let native = match p.position_type {
+            PositionType::Cash => p.size,
+            PositionType::Product => p.price * p.size,
+        };
+        let exposure = m.entry(currency).or_insert_with(|| FxExposure {
+            currency,
+            native: 0.0,
+            base: Money::new(base_currency, 0.0),
+        });
+        exposure.native += native;
+        exposure.base.amount += p.value.amount;
+    }
+    let mut xs = m.into_values().collect::<Vec<_>>();
+    xs.sort_by(|a, b| b.base.amount.abs().total_cmp(&a.base.amount.abs()));
+    xs
+}
+
 impl Portfolio {
     pub fn new(xs: impl Into<Vec<Position>>) -> Self {
         Self(xs.into())
@@ -133,6 +524,29 @@ impl Portfolio {
         m
     }
 
+    /// Native cash balance per currency, sweep account balances included.
+    pub fn cash_balances(&self) -> HashMap<Currency, Money> {
+        let mut m: HashMap<Currency, Money> = HashMap::new();
+        for cash in self.0.iter().filter_map(|p| p.inner.cash_position()) {
+            m.entry(cash.currency)
+                .or_insert_with(|| Money::new(cash.currency, 0.0))
+                .amount += cash.amount.amount;
+        }
+        m
+    }
+
+    /// Like [`Portfolio::value`] but adding up absolute values, so short
+    /// positions add to the exposure instead of netting it out.
+    pub fn gross_value(&self) -> HashMap<Currency, f64> {
+        let mut m = HashMap::default();
+        for p in &self.0 {
+            let money = &p.inner.value;
+            let x = m.entry(money.currency).or_insert(0.0);
+            *x += money.amount.abs();
+        }
+        m
+    }
+
     pub fn base_value(&self) -> HashMap<Currency, f64> {
         let mut m = HashMap::default();
         for p in &self.0 {
@@ -143,11 +557,123 @@ impl Portfolio {
         m
     }
 
+    /// Values per original currency, each converted into `to`.
+    pub fn value_breakdown_in(
+        &self,
+        to: Currency,
+        info: &AccountInfo,
+    ) -> Result<HashMap<Currency, Money>, ClientError> {
+        self.value()
+            .into_iter()
+            .map(|(currency, amount)| {
+                Ok((currency, info.convert(Money::new(currency, amount), to)?))
+            })
+            .collect()
+    }
+
+    /// Total value with every position converted into `to`.
+    pub fn value_in(&self, to: Currency, info: &AccountInfo) -> Result<Money, ClientError> {
+        let amount = self
+            .value_breakdown_in(to, info)?
+            .values()
+            .map(|money| money.amount)
+            .sum();
+        Ok(Money::new(to, amount))
+    }
+
+    /// Like [`Portfolio::value_in`], fetching the account info from `client`
+    /// only when some position is held in another currency.
+    pub async fn total_value_in(
+        &self,
+        to: Currency,
+        client: &Client,
+    ) -> Result<Money, ClientError> {
+        self.0
+            .iter()
+            .map(|p| p.inner.value)
+            .collect::<MoneyBag>()
+            .total_in(to, client)
+            .await
+    }
+
+    /// Today's profit of product positions, realized and unrealized, with
+    /// every position converted into `to`.
+    pub fn today_pl_in(&self, to: Currency, info: &AccountInfo) -> Result<Money, ClientError> {
+        let mut amount = 0.0;
+        for p in self
+            .0
+            .iter()
+            .filter(|p| p.inner.position_type == PositionType::Product)
+        {
+            let pl = (p.inner.value + p.inner.today_value)?;
+            amount += info.convert(pl, to)?.amount;
+        }
+        Ok(Money::new(to, amount))
+    }
+
+    /// Sum of the projected dividend income of all held products, in base currency.
+    pub async fn projected_annual_income(&self) -> Result<Money, ClientError> {
+        let mut total: Option<Money> = None;
+        for p in &self.0 {
+            if p.inner.position_type != PositionType::Product || p.inner.size == 0.0 {
+                continue;
+            }
+            let income = p.projected_annual_income().await?;
+            total = Some(match total {
+                Some(total) => (total + income)?,
+                None => income,
+            });
+        }
+        Ok(total.unwrap_or_default())
+    }
+
+    /// Exposure per foreign currency, largest first.
+    ///
+    /// Products are resolved through the product cache to learn their trading
+    /// currency; products in an unsupported currency are skipped.
+    pub async fn fx_exposure(&self) -> Result<Vec<FxExposure>, ClientError> {
+        let mut xs = Vec::new();
+        for p in &self.0 {
+            if p.inner.size == 0.0 {
+                continue;
+            }
+            let currency = match p.inner.position_type {
+                PositionType::Cash => p.inner.cash_position().map(|cash| cash.currency),
+                PositionType::Product => p.product().await?.currency(),
+            };
+            if let Some(currency) = currency {
+                xs.push((currency, &p.inner));
+            }
+        }
+        Ok(fx_exposure_from(xs))
+    }
+
+    /// Open positions, long and short.
     pub fn current(self) -> Self {
         let xs = self
             .0
             .into_iter()
-            .filter(|p| p.inner.size > 0.0)
+            .filter(|p| p.inner.is_open())
+            .collect::<Vec<_>>();
+
+        Portfolio::new(xs)
+    }
+
+    pub fn long(self) -> Self {
+        let xs = self
+            .0
+            .into_iter()
+            .filter(|p| p.inner.is_long())
+            .collect::<Vec<_>>();
+
+        Portfolio::new(xs)
+    }
+
+    pub fn short(self) -> Self {
+        let xs = self
+            .0
+            .into_iter()
+            .filter(|p| p.inner.is_short())
             .collect::<Vec<_>>();
 
         Portfolio::new(xs)
@@ -184,123 +710,54 @@ impl Portfolio {
     }
 }
 
-#[derive(Clone, Debug, Default, EnumString, PartialEq)]
+#[derive(Clone, Debug, Default, EnumString, PartialEq, Serialize, Deserialize)]
 #[strum(ascii_case_insensitive)]
 pub enum PositionType {
+    #[serde(rename = "cash")]
     Cash,
     #[default]
+    #[serde(rename = "product")]
     Product,
 }
 
-#[derive(Debug, Error)]
-#[error("can't parse object {:#?}", 0)]
-pub struct ParsePositionError(PortfolioObject);
-
-impl TryFrom<PortfolioObject> for PositionDetails {
-    type Error = ParsePositionError;
-
-    fn try_from(obj: PortfolioObject) -> Result<Self, Self::Error> {
-        let mut position = PositionDetails::default();
-        let mut value = 0.0;
-        for row in &obj.value {
-            match row.elem_type {
-                ElemType::Id => {
-                    position.id = row.value.as_ref().unwrap().as_str().unwrap().to_string();
-                }
-                ElemType::PositionType => {
-                    match row.value.as_ref().unwrap().as_str().unwrap().parse() {
-                        Ok(val) => position.position_type = val,
-                        Err(_) => return Err(ParsePositionError(obj)),
-                    };
-                }
-                ElemType::Size => {
-                    let val = row.value.as_ref().unwrap().as_f64().unwrap();
-                    position.size = val;
-                }
-                ElemType::Price => {
-                    position.price = row.value.as_ref().unwrap().as_f64().unwrap();
-                }
-                ElemType::Value => {
-                    value = row.value.as_ref().unwrap().as_f64().unwrap();
-                }
-                ElemType::AccruedInterest => {
-                    if let Some(s) = &row.value {
-                        let val = s.as_f64().unwrap();
-                        if val > 0.0 {
-                            position.accrued_interest = Some(val);
-                        }
-                    }
-                }
-                ElemType::PlBase => {
-                    match serde_json::from_value::<HashMap<String, f64>>(
-                        row.value.as_ref().unwrap().clone(),
-                    ) {
-                        Ok(m) => match TryInto::<Money>::try_into(m) {
-                            Ok(val) => {
-                                position.currency = val.currency;
-                                position.base_value = -val;
-                            }
-                            Err(_) => return Err(ParsePositionError(obj)),
-                        },
-                        Err(_) => return Err(ParsePositionError(obj)),
-                    }
-                }
-                ElemType::TodayPlBase => {
-                    match serde_json::from_value::<HashMap<String, f64>>(
-                        row.value.as_ref().unwrap().clone(),
-                    ) {
-                        Ok(m) => match m.try_into() {
-                            Ok(val) => position.today_value = val,
-                            Err(_) => return Err(ParsePositionError(obj)),
-                        },
-                        Err(_) => return Err(ParsePositionError(obj)),
-                    }
-                }
-                ElemType::PortfolioValueCorrection => {
-                    if let Some(s) = &row.value {
-                        let val = s.as_f64().unwrap();
-                        position.portfolio_value_correction = val;
-                    }
-                }
-                ElemType::BreakEvenPrice => {
-                    if let Some(s) = &row.value {
-                        let val = s.as_f64().unwrap();
-                        position.break_even_price = val;
-                    }
-                }
-                ElemType::AverageFxRate => {
-                    if let Some(s) = &row.value {
-                        let val = s.as_f64().unwrap();
-                        position.average_fx_rate = val;
-                    }
-                }
-                ElemType::RealizedProductPl => {
-                    if let Some(s) = &row.value {
-                        let val = s.as_f64().unwrap();
-                        position.realized_product_profit = Money::new(position.currency, val);
-                    }
-                }
-                ElemType::RealizedFxPl => {
-                    if let Some(s) = &row.value {
-                        let val = s.as_f64().unwrap();
-                        position.realized_fx_profit = Money::new(position.currency, val);
-                    }
-                }
-                ElemType::TodayRealizedProductPl => {
-                    if let Some(s) = &row.value {
-                        let val = s.as_f64().unwrap();
-                        position.today_realized_product_pl = Money::new(position.currency, val);
-                    }
-                }
-                ElemType::TodayRealizedFxPl => {
-                    if let Some(s) = &row.value {
-                        let val = s.as_f64().unwrap();
-                        position.today_realized_fx_pl = Money::new(position.currency, val);
-                    }
-                }
-            }
+impl TryFrom<&NamedValues> for PositionDetails {
+    type Error = ClientError;
+
+    fn try_from(row: &NamedValues) -> Result<Self, Self::Error> {
+        let mut position = PositionDetails {
+            id: row.field("id")?,
+            position_type: row.parse("positionType")?,
+            size: row.field("size")?,
+            price: row.field("price")?,
+            accrued_interest: row.optional::<f64>("accruedInterest")?.filter(|x| *x > 0.0),
+            portfolio_value_correction: row
+                .optional("portfolioValueCorrection")?
+                .unwrap_or_default(),
+            break_even_price: row.optional("breakEvenPrice")?.unwrap_or_default(),
+            average_fx_rate: row.optional("averageFxRate")?.unwrap_or_default(),
+            ..Default::default()
+        };
+        let value: f64 = row.field("value")?;
+        if let Some(m) = row.optional::<HashMap<String, f64>>("plBase")? {
+            let val: Money = m.try_into()?;
+            position.currency = val.currency;
+            position.base_value = -val;
         }
-        let currency = position.total_profit.currency;
+        if let Some(m) = row.optional::<HashMap<String, f64>>("todayPlBase")? {
+            position.today_value = m.try_into()?;
+        }
+        let currency = position.currency;
+        let money = |name: &str| -> Result<Money, ClientError> {
+            Ok(Money::new(
+                currency,
+                row.optional(name)?.unwrap_or_default(),
+            ))
+        };
+        position.realized_product_profit = money("realizedProductPl")?;
+        position.realized_fx_profit = money("realizedFxPl")?;
+        position.today_realized_product_pl = money("todayRealizedProductPl")?;
+        position.today_realized_fx_pl = money("todayRealizedFxPl")?;
+
         position.total_profit = Money::new(
             currency,
             (position.price * position.size - position.break_even_price * position.size)
@@ -319,10 +776,37 @@ impl TryFrom<PortfolioObject> for PositionDetails {
 }
 
 impl Client {
+    /// Value of all open positions, in the account base currency.
+    pub async fn total_portfolio_value(&self) -> Result<Money, ClientError> {
+        let portfolio = self.portfolio().await?.current();
+        let info = self.account_info().await?;
+        portfolio.value_in(info.base_currency()?, &info)
+    }
+
+    /// Cash, invested and unsettled amounts from the current portfolio and
+    /// the last week of cash movements.
+    pub async fn balance(&self) -> Result<BalanceBreakdown, ClientError> {
+        let portfolio = self.portfolio().await?.current();
+        let info = self.account_info().await?;
+        let today = self.now().date_naive();
+        let unsettled = self
+            .account_state(&(today - chrono::Duration::days(7)), &today)
+            .await?
+            .unsettled_cash();
+        BalanceBreakdown::new(&portfolio, unsettled, info.base_currency()?, &info)
+    }
+
+    /// Like [`Client::total_portfolio_value`], split by the currency positions are held in.
+    pub async fn total_portfolio_value_by_currency(
+        &self,
+    ) -> Result<HashMap<Currency, Money>, ClientError> {
+        let portfolio = self.portfolio().await?.current();
+        let info = self.account_info().await?;
+        portfolio.value_breakdown_in(info.base_currency()?, &info)
+    }
+
     pub async fn portfolio(&self) -> Result<Portfolio, ClientError> {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        self.require(Endpoint::Portfolio)?;
 
         let req = {
             let inner = self.inner.lock().unwrap();
@@ -345,43 +829,23 @@ impl Client {
                 .header(header::REFERER, &inner.referer)
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let json = res.json::<Value>().await.unwrap();
-                let body = json.get("portfolio").unwrap().get("value").unwrap();
-                let objs: Vec<PortfolioObject> = serde_json::from_value(body.clone()).unwrap();
-                let mut xs: Vec<_> = Vec::new();
-                for obj in objs {
-                    let p: PositionDetails = obj.try_into().unwrap();
-                    let position = Position::new(p, self.clone());
-                    xs.push(position)
-                }
-                Ok(Portfolio::new(xs))
-            }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => {
-                    self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
-                }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
-        }
+        let res = self.send(Endpoint::Portfolio, req).await?;
+
+        let body = read_json::<PortfolioResponse>(res).await?;
+        Portfolio::from_objects(body.portfolio.value, self)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::client::Client;
+    use super::{
+        fx_exposure_from, AccountInfo, BalanceBreakdown, CsvMapping, Portfolio, Position,
+        PositionDetails, PositionType, PORTFOLIO_SCHEMA_VERSION,
+    };
+    use crate::{
+        client::Client,
+        money::{Currency, Money},
+    };
 
     #[tokio::test]
     async fn current_portfolio() {
@@ -393,4 +857,349 @@ mod test {
         dbg!(&xs.value());
         dbg!(&xs.base_value());
     }
+
+    #[test]
+    fn dividend_income_in_base_currency() {
+        let p = PositionDetails {
+            size: 10.0,
+            price: 50.0,
+            break_even_price: 40.0,
+            value: Money::new(Currency::EUR, 450.0),
+            ..Default::default()
+        };
+        assert_eq!(p.yield_on_cost(2.0), Some(0.05));
+        assert_eq!(p.annual_income(2.0), Money::new(Currency::EUR, 18.0));
+    }
+
+    #[test]
+    fn cash_balances_per_currency() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let cash = |id: &str, size: f64| PositionDetails {
+            id: id.to_string(),
+            position_type: PositionType::Cash,
+            size,
+            ..Default::default()
+        };
+        let stock = PositionDetails {
+            id: "EUR".to_string(),
+            size: 3.0,
+            ..Default::default()
+        };
+        assert_eq!(stock.cash_position(), None);
+        assert_eq!(cash("XYZ", 1.0).cash_position(), None);
+
+        let portfolio = Portfolio::new(
+            [
+                cash("EUR", 100.0),
+                cash("FLATEX_EUR", 50.0),
+                cash("USD", -20.0),
+                stock,
+            ]
+            .into_iter()
+            .map(|p| Position::new(p, client.clone()))
+            .collect::<Vec<_>>(),
+        );
+        let balances = portfolio.cash_balances();
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances[&Currency::EUR], Money::new(Currency::EUR, 150.0));
+        assert_eq!(balances[&Currency::USD], Money::new(Currency::USD, -20.0));
+    }
+
+    #[test]
+    fn portfolio_from_csv() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let csv = "Ticker,Shares,Price,Ccy\n331868,10,12.5,USD\n\n332111,4,30,\nEUR,100,,\n";
+        let mapping = CsvMapping::default()
+            .id("Ticker")
+            .size("Shares")
+            .price("Price")
+            .currency("Ccy");
+        let portfolio = Portfolio::from_csv(csv.as_bytes(), &mapping, client.clone()).unwrap();
+        assert_eq!(portfolio.len(), 3);
+        let value = portfolio.value();
+        assert_eq!(value[&Currency::USD], 125.0);
+        assert_eq!(value[&Currency::EUR], 220.0);
+        assert_eq!(
+            portfolio.cash_balances()[&Currency::EUR],
+            Money::new(Currency::EUR, 100.0)
+        );
+
+        let bad = "Ticker,Shares\n331868,ten\n";
+        let err = Portfolio::from_csv(bad.as_bytes(), &mapping, client.clone()).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        assert!(Portfolio::from_csv("id\n1\n".as_bytes(), &CsvMapping::default(), client).is_err());
+    }
+
+    #[tokio::test]
+    async fn total_value_in_single_currency() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let position = |amount| {
+            let inner = PositionDetails {
+                value: Money::new(Currency::EUR, amount),
+                ..Default::default()
+            };
+            Position::new(inner, client.clone())
+        };
+        let portfolio = Portfolio::new(vec![position(100.0), position(-20.0)]);
+        // Nothing to convert, so no account info is needed.
+        assert_eq!(
+            portfolio
+                .total_value_in(Currency::EUR, &client)
+                .await
+                .unwrap(),
+            Money::new(Currency::EUR, 80.0)
+        );
+        assert_eq!(
+            portfolio.0[0]
+                .value_in(Currency::EUR, &client)
+                .await
+                .unwrap(),
+            Money::new(Currency::EUR, 100.0)
+        );
+    }
+
+    #[test]
+    fn parse_update_rows() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let body = serde_json::json!({ "portfolio": { "value": [
+            { "name": "positionrow", "value": [
+                { "name": "id", "value": "332111" },
+                { "name": "positionType", "value": "PRODUCT" },
+                { "name": "size", "value": 10.0 },
+                { "name": "price", "value": 12.0 },
+                { "name": "value", "value": 120.0 },
+                { "name": "realizedProductPl", "value": 3.0 },
+                { "name": "plBase", "value": { "EUR": -100.0 } },
+                { "name": "todayPlBase", "value": { "EUR": -115.0 } },
+                { "name": "breakEvenPrice", "value": 10.0 },
+                { "name": "averageFxRate", "value": 1.0 },
+                { "name": "accruedInterest" },
+                { "name": "someNewField", "value": true },
+            ]},
+        ]}});
+        let portfolio =
+            Portfolio::from_response(&serde_json::to_vec(&body).unwrap(), &client).unwrap();
+        let p = &portfolio.0[0].inner;
+        assert_eq!(p.position_type, PositionType::Product);
+        assert_eq!(p.base_value, Money::new(Currency::EUR, 100.0));
+        assert_eq!(p.realized_product_profit, Money::new(Currency::EUR, 3.0));
+        assert_eq!(p.accrued_interest, None);
+        assert_eq!(p.total_profit.amount, 20.0);
+    }
+
+    #[test]
+    fn profits_in_position_currency() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let body = serde_json::json!({ "portfolio": { "value": [
+            { "name": "positionrow", "value": [
+                { "name": "id", "value": "331868" },
+                { "name": "positionType", "value": "PRODUCT" },
+                { "name": "size", "value": 10.0 },
+                { "name": "price", "value": 12.0 },
+                { "name": "value", "value": 120.0 },
+                { "name": "plBase", "value": { "USD": -100.0 } },
+                { "name": "breakEvenPrice", "value": 10.0 },
+                { "name": "averageFxRate", "value": 1.0 },
+            ]},
+        ]}});
+        let portfolio =
+            Portfolio::from_response(&serde_json::to_vec(&body).unwrap(), &client).unwrap();
+        let p = &portfolio.0[0].inner;
+        assert_eq!(p.total_profit, Money::new(Currency::USD, 20.0));
+        assert_eq!(p.product_profit.currency, Currency::USD);
+        assert_eq!(p.value, Money::new(Currency::USD, 120.0));
+    }
+
+    #[test]
+    fn short_positions() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let long = PositionDetails {
+            id: "1".to_string(),
+            size: 10.0,
+            price: 50.0,
+            value: Money::new(Currency::EUR, 500.0),
+            ..Default::default()
+        };
+        let short = PositionDetails {
+            id: "2".to_string(),
+            size: -5.0,
+            price: 20.0,
+            break_even_price: 25.0,
+            value: Money::new(Currency::EUR, -100.0),
+            ..Default::default()
+        };
+        let closed = PositionDetails {
+            id: "3".to_string(),
+            ..Default::default()
+        };
+        let portfolio = Portfolio::new(
+            [long, short.clone(), closed]
+                .into_iter()
+                .map(|p| Position::new(p, client.clone()))
+                .collect::<Vec<_>>(),
+        );
+
+        assert_eq!(portfolio.clone().current().len(), 2);
+        assert_eq!(portfolio.clone().long().len(), 1);
+        assert_eq!(portfolio.clone().short().len(), 1);
+        assert_eq!(portfolio.value()[&Currency::EUR], 400.0);
+        assert_eq!(portfolio.gross_value()[&Currency::EUR], 600.0);
+
+        // A short pays the dividend and its exposure is negative.
+        assert_eq!(short.annual_income(1.0), Money::new(Currency::EUR, -5.0));
+        let xs = fx_exposure_from([(Currency::USD, &short)]);
+        assert_eq!(xs[0].native, -100.0);
+    }
+
+    #[test]
+    fn fx_exposure_per_currency() {
+        let stock = PositionDetails {
+            size: 10.0,
+            price: 50.0,
+            value: Money::new(Currency::EUR, 450.0),
+            ..Default::default()
+        };
+        let cash = PositionDetails {
+            position_type: PositionType::Cash,
+            size: 100.0,
+            value: Money::new(Currency::EUR, 90.0),
+            ..Default::default()
+        };
+        let local = PositionDetails {
+            size: 1.0,
+            price: 10.0,
+            value: Money::new(Currency::EUR, 10.0),
+            ..Default::default()
+        };
+        let xs = fx_exposure_from([
+            (Currency::USD, &stock),
+            (Currency::USD, &cash),
+            (Currency::EUR, &local),
+        ]);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].native, 600.0);
+        assert_eq!(xs[0].base, Money::new(Currency::EUR, 540.0));
+        assert_eq!(xs[0].implied_rate(), Some(0.9));
+        assert_eq!(xs[0].hedge(0.5).sell, Money::new(Currency::USD, 300.0));
+    }
+
+    #[test]
+    fn value_in_base_currency() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let position = |currency, amount| {
+            Position::new(
+                PositionDetails {
+                    value: Money::new(currency, amount),
+                    ..Default::default()
+                },
+                client.clone(),
+            )
+        };
+        let portfolio = Portfolio::new(vec![
+            position(Currency::EUR, 100.0),
+            position(Currency::USD, 50.0),
+        ]);
+        let info = serde_json::from_value::<AccountInfo>(serde_json::json!({
+            "baseCurrency": "EUR",
+            "marginType": "CASH",
+            "currencyPairs": {"EURUSD": {"id": 1, "price": 1.25}}
+        }))
+        .unwrap();
+        assert_eq!(
+            portfolio.value_in(Currency::EUR, &info).unwrap(),
+            Money::new(Currency::EUR, 140.0)
+        );
+    }
+
+    #[test]
+    fn balance_breakdown() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let cash = Position::new(
+            PositionDetails {
+                id: "USD".to_string(),
+                position_type: PositionType::Cash,
+                size: 125.0,
+                value: Money::new(Currency::USD, 125.0),
+                ..Default::default()
+            },
+            client.clone(),
+        );
+        let stock = Position::new(
+            PositionDetails {
+                size: 1.0,
+                value: Money::new(Currency::EUR, 200.0),
+                ..Default::default()
+            },
+            client,
+        );
+        let info = serde_json::from_value::<AccountInfo>(serde_json::json!({
+            "baseCurrency": "EUR",
+            "marginType": "CASH",
+            "currencyPairs": {"EURUSD": {"id": 1, "price": 1.25}}
+        }))
+        .unwrap();
+        let balance = BalanceBreakdown::new(
+            &Portfolio::new(vec![cash, stock]),
+            Some(Money::new(Currency::EUR, -10.0)),
+            Currency::EUR,
+            &info,
+        )
+        .unwrap();
+        assert_eq!(balance.cash, Money::new(Currency::EUR, 100.0));
+        assert_eq!(balance.invested, Money::new(Currency::EUR, 200.0));
+        assert_eq!(balance.total, Money::new(Currency::EUR, 290.0));
+        assert_eq!(
+            balance.per_currency[&Currency::USD],
+            Money::new(Currency::USD, 125.0)
+        );
+    }
+
+    #[test]
+    fn snapshot_schema_v1_compat() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        // Stored by schema version 1; must keep parsing as long as the version stays 1.
+        let v1 = r#"{
+            "schemaVersion": 1,
+            "positions": [{
+                "id": "332111",
+                "positionType": "product",
+                "size": 10.0,
+                "price": 150.5,
+                "currency": "USD",
+                "value": { "currency": "EUR", "amount": 1400.0 },
+                "accruedInterest": null,
+                "baseValue": { "currency": "EUR", "amount": 1300.0 },
+                "todayValue": { "currency": "EUR", "amount": 1390.0 },
+                "portfolioValueCorrection": 0.0,
+                "breakEvenPrice": 140.0,
+                "averageFxRate": 1.08,
+                "realizedProductProfit": { "currency": "EUR", "amount": 0.0 },
+                "realizedFxProfit": { "currency": "EUR", "amount": 0.0 },
+                "todayRealizedProductPl": { "currency": "EUR", "amount": 0.0 },
+                "todayRealizedFxPl": { "currency": "EUR", "amount": 0.0 },
+                "totalProfit": { "currency": "EUR", "amount": 100.0 },
+                "productProfit": { "currency": "EUR", "amount": 90.0 },
+                "fxProfit": { "currency": "EUR", "amount": 10.0 },
+                "addedInAMinorVersion": true
+            }, {
+                "id": "EUR",
+                "positionType": "cash",
+                "size": 250.0
+            }]
+        }"#;
+        let portfolio = Portfolio::from_json(v1, client.clone()).unwrap();
+        assert_eq!(portfolio.0.len(), 2);
+        let first = &portfolio.0[0].inner;
+        assert_eq!(first.currency, Currency::USD);
+        assert_eq!(first.value, Money::new(Currency::EUR, 1400.0));
+        assert_eq!(portfolio.0[1].inner.position_type, PositionType::Cash);
+
+        let json = portfolio.to_json().unwrap();
+        let again = Portfolio::from_json(&json, client.clone()).unwrap();
+        assert_eq!(again.snapshot(), portfolio.snapshot());
+        assert_eq!(again.snapshot().schema_version, PORTFOLIO_SCHEMA_VERSION);
+
+        let newer = r#"{ "schemaVersion": 2, "positions": [] }"#;
+        assert!(Portfolio::from_json(newer, client).is_err());
+    }
 }
@@ -1,18 +1,34 @@
 use std::{
+    cmp::Ordering,
     collections::HashMap,
     fmt::{self, Debug},
 };
 
-use chrono::NaiveDate;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use derivative::Derivative;
 use reqwest::{header, Url};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
-    client::{Client, ClientError, ClientStatus},
-    util::{AllowedOrderTypes, OrderTimeTypes, ProductCategory},
+    client::{Client, ClientError},
+    http::Endpoint,
+    money::{Currency, Money},
+    util::{round_to_tick, AllowedOrderTypes, OrderTimeTypes, ProductCategory},
 };
 
+use super::company_ratios::CompanyRatios;
+
+/// How long [`Client::product`] answers from memory; the close price and
+/// tradability change while the client runs.
+pub const DEFAULT_PRODUCT_TTL: Duration = Duration::minutes(15);
+
+/// Product info with the time it was fetched.
+#[derive(Clone, Debug)]
+pub(crate) struct CachedProduct {
+    pub(crate) stored_at: DateTime<Utc>,
+    pub(crate) details: ProductDetails,
+}
+
 #[derive(Clone, Debug, Deserialize, Derivative, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProductDetails {
@@ -20,9 +36,14 @@ pub struct ProductDetails {
     pub active: bool,
     pub buy_order_types: Option<AllowedOrderTypes>,
     pub category: ProductCategory,
+    /// Last close in the trading currency, prefer
+    /// [`Product::close_price_money`] which keeps the two together.
     pub close_price: f64,
     pub close_price_date: NaiveDate,
     pub contract_size: f64,
+    /// Trading currency, `None` when missing or not supported by [`Currency`].
+    #[serde(default, deserialize_with = "known_currency")]
+    pub currency: Option<Currency>,
     pub exchange_id: String,
     pub feed_quality: Option<String>,
     pub feed_quality_secondary: Option<String>,
@@ -55,6 +76,19 @@ pub struct ProductDetails {
     pub vwd_identifier_type_secondary: Option<String>,
     pub vwd_module_id: Option<i32>,
     pub vwd_module_id_secondary: Option<i32>,
+    /// Minimum price increment, only present for some instruments.
+    #[serde(default)]
+    pub tick_size: Option<f64>,
+    /// Minimum tradable quantity, only present for some instruments.
+    #[serde(default)]
+    pub min_lot_size: Option<f64>,
+}
+
+/// Reads a currency code, taking codes [`Currency`] doesn't know as missing
+/// rather than failing the whole product.
+fn known_currency<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Currency>, D::Error> {
+    let code = Option::<String>::deserialize(d)?;
+    Ok(code.and_then(|code| code.parse().ok()))
 }
 
 impl fmt::Display for ProductDetails {
@@ -150,6 +184,121 @@ pub struct Product {
     pub client: Client,
 }
 
+impl Product {
+    pub fn currency(&self) -> Option<Currency> {
+        self.inner.currency
+    }
+
+    /// Last close price in the trading currency, `None` when the currency
+    /// is unknown.
+    pub fn close_price_money(&self) -> Option<Money> {
+        Some(Money::new(self.currency()?, self.inner.close_price))
+    }
+
+    /// Tick size from product info, `None` when DEGIRO doesn't send one.
+    /// Tick grids differ by venue and price, so none is guessed.
+    pub fn tick_size(&self) -> Option<f64> {
+        self.inner.tick_size.filter(|x| *x > 0.0)
+    }
+
+    pub fn lot_size(&self) -> f64 {
+        self.inner.min_lot_size.filter(|x| *x > 0.0).unwrap_or(1.0)
+    }
+
+    /// Rounds to the nearest valid price, unchanged without a tick size.
+    pub fn round_price_to_tick(&self, price: f64) -> f64 {
+        let Some(tick) = self.tick_size() else {
+            return price;
+        };
+        let down = round_to_tick(price, tick, false);
+        let up = round_to_tick(price, tick, true);
+        if price - down < up - price {
+            down
+        } else {
+            up
+        }
+    }
+
+    /// Always `true` without a tick size, `checkOrder` has the last word.
+    pub fn is_price_on_tick(&self, price: f64) -> bool {
+        (self.round_price_to_tick(price) - price).abs() < 1e-9
+    }
+
+    /// Combines order book depth, the ten day average volume and the current
+    /// spread. Inputs that can't be fetched are left out of the score.
+    pub async fn liquidity_score(&self) -> Result<LiquidityScore, ClientError> {
+        let p = &self.inner;
+        let snapshot = match p.vwd_id.as_deref() {
+            Some(vwd_id) => match self.client.quote_snapshot(vwd_id).await {
+                Ok(x) => Some(x),
+                Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+                Err(_) => None,
+            },
+            None => None,
+        };
+        let volume = match self.client.company_ratios(&p.id, &p.isin).await {
+            // Reported in millions of shares.
+            Ok(ratios) => ratios.current_ratios.volume_avg_10d.value.map(|v| v * 1e6),
+            Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+            Err(_) => None,
+        };
+        let mid = snapshot.as_ref().and_then(|x| x.mid());
+        let price = mid
+            .or_else(|| snapshot.as_ref()?.last)
+            .unwrap_or(p.close_price);
+        let spread = snapshot
+            .as_ref()
+            .and_then(|x| Some(x.spread()? / mid.filter(|m| *m > 0.0)?));
+        Ok(LiquidityScore::new(
+            p.order_book_depth,
+            volume.map(|v| v * price),
+            spread,
+        ))
+    }
+}
+
+/// Liquidity of a product on a 0 to 1 scale, from whatever inputs are known.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LiquidityScore {
+    pub order_book_depth: Option<i32>,
+    /// Average value traded per day, in the trading currency.
+    pub avg_daily_value: Option<f64>,
+    /// Spread relative to the mid price.
+    pub spread: Option<f64>,
+    /// Weighted mean of the known components, `None` when none is.
+    pub score: Option<f64>,
+}
+
+impl LiquidityScore {
+    pub fn new(
+        order_book_depth: Option<i32>,
+        avg_daily_value: Option<f64>,
+        spread: Option<f64>,
+    ) -> Self {
+        // Ten levels of depth, 100M a day and a tight spread each score 1;
+        // value traded is compared on a log scale starting at 10k.
+        let parts = [
+            (0.2, order_book_depth.map(|x| x as f64 / 10.0)),
+            (
+                0.5,
+                avg_daily_value.map(|x| (x.max(1.0).log10() - 4.0) / 4.0),
+            ),
+            (0.3, spread.map(|x| 1.0 - x / 0.02)),
+        ];
+        let (sum, weights) = parts
+            .into_iter()
+            .filter_map(|(w, x)| Some((w * x?.clamp(0.0, 1.0), w)))
+            .fold((0.0, 0.0), |(s, ws), (x, w)| (s + x, ws + w));
+        Self {
+            order_book_depth,
+            avg_daily_value,
+            spread,
+            score: (weights > 0.0).then(|| sum / weights),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Products(pub HashMap<String, Product>);
 
@@ -172,6 +321,39 @@ impl Products {
     pub fn insert(&mut self, id: String, product: Product) -> Option<Product> {
         self.0.insert(id, product)
     }
+    pub fn get_by_id(&self, id: &str) -> Option<&Product> {
+        self.0.get(id)
+    }
+    pub fn get_by_isin(&self, isin: &str) -> Option<&Product> {
+        self.0
+            .values()
+            .find(|p| p.inner.isin.eq_ignore_ascii_case(isin))
+    }
+    pub fn filter_tradable(self) -> Self {
+        self.into_iter().filter(|(_, p)| p.inner.tradable).collect()
+    }
+    /// Products ordered by market capitalization, largest first.
+    ///
+    /// Capitalization comes from the matching [`CompanyRatios`] (by product id),
+    /// products without ratios or without a market cap are placed last.
+    pub fn sort_by_market_cap(&self, ratios: &[CompanyRatios]) -> Vec<&Product> {
+        let caps = ratios
+            .iter()
+            .filter_map(|r| Some((r.id.as_str(), r.current_ratios.market_cap.value?)))
+            .collect::<HashMap<_, _>>();
+        let mut xs = self.0.values().collect::<Vec<_>>();
+        xs.sort_by(|a, b| {
+            let a = caps.get(a.inner.id.as_str());
+            let b = caps.get(b.inner.id.as_str());
+            match (a, b) {
+                (Some(a), Some(b)) => b.total_cmp(a),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        });
+        xs
+    }
 }
 
 impl IntoIterator for Products {
@@ -182,14 +364,32 @@ impl IntoIterator for Products {
     }
 }
 
+impl<'a> IntoIterator for &'a Products {
+    type Item = (&'a String, &'a Product);
+    type IntoIter = std::collections::hash_map::Iter<'a, String, Product>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl FromIterator<(String, Product)> for Products {
+    fn from_iter<T: IntoIterator<Item = (String, Product)>>(iter: T) -> Self {
+        Products(iter.into_iter().collect())
+    }
+}
+
+impl FromIterator<Product> for Products {
+    fn from_iter<T: IntoIterator<Item = Product>>(iter: T) -> Self {
+        Products(iter.into_iter().map(|p| (p.inner.id.clone(), p)).collect())
+    }
+}
+
 impl Client {
     pub async fn products<T>(&self, ids: T) -> Result<Products, ClientError>
     where
         T: Debug + Serialize + Sized + Send + Sync,
     {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        self.require(Endpoint::Products)?;
 
         let req = {
             let inner = self.inner.lock().unwrap();
@@ -203,68 +403,185 @@ impl Client {
             inner
                 .http_client
                 .post(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                ])
                 .json(&ids)
                 .header(header::REFERER, &inner.referer)
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut body = res
-                    .json::<HashMap<String, HashMap<String, ProductDetails>>>()
-                    .await
-                    .map_err(ClientError::RequestError)?;
-                let m = body.remove("data").unwrap();
-                let mut hm = HashMap::new();
-                for (k, v) in m.into_iter() {
-                    let product = Product {
-                        inner: v,
-                        client: self.clone(),
-                    };
-                    hm.insert(k, product);
-                }
-                Ok(Products(hm))
+        // A lookup despite the POST, so identical concurrent ones can share a response.
+        let json = self.send_json_coalesced(Endpoint::Products, req).await?;
+        let mut body =
+            serde_json::from_value::<HashMap<String, HashMap<String, ProductDetails>>>(json)?;
+        let m = body.remove("data").unwrap();
+        let now = self.now();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            for (k, v) in m.iter() {
+                inner.product_index.insert(v);
+                let entry = CachedProduct {
+                    stored_at: now,
+                    details: v.clone(),
+                };
+                inner.product_cache.insert(k.clone(), entry);
             }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => {
-                    self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
-                }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
         }
+        let mut hm = HashMap::new();
+        for (k, v) in m.into_iter() {
+            let product = Product {
+                inner: v,
+                client: self.clone(),
+            };
+            hm.insert(k, product);
+        }
+        Ok(Products(hm))
     }
 }
 
 impl Client {
+    /// Returns the product from the local cache or fetches it when missing
+    /// or older than the product ttl, see [`Client::set_product_ttl`].
+    ///
+    /// Every product returned by [`Client::products`] lands in the cache, so
+    /// repeated lookups of the same id don't hit the API again.
     pub async fn product(
         &self,
         id: impl Into<String> + Send + Clone,
     ) -> Result<Product, ClientError> {
         let id: String = id.into();
+        if let Some(product) = self.fresh_product(&id) {
+            return Ok(product);
+        }
         match self.products(vec![id.clone()]).await {
-            Ok(mut xs) => Ok(xs.0.remove(&id).unwrap()),
+            Ok(mut xs) => xs.0.remove(&id).ok_or(ClientError::ProductSearchError),
             Err(err) => Err(err),
         }
     }
+
+    /// Product from the local cache however old it is, prices included.
+    pub fn cached_product(&self, id: &str) -> Option<Product> {
+        let inner = self.inner.lock().unwrap();
+        inner.product_cache.get(id).map(|x| Product {
+            inner: x.details.clone(),
+            client: self.clone(),
+        })
+    }
+
+    /// Product from the local cache when younger than the product ttl.
+    pub(crate) fn fresh_product(&self, id: &str) -> Option<Product> {
+        let now = self.now();
+        let inner = self.inner.lock().unwrap();
+        inner
+            .product_cache
+            .get(id)
+            .filter(|x| now - x.stored_at < inner.product_ttl)
+            .map(|x| Product {
+                inner: x.details.clone(),
+                client: self.clone(),
+            })
+    }
+
+    /// How long [`Client::product`] serves products from memory,
+    /// [`DEFAULT_PRODUCT_TTL`] unless set.
+    pub fn set_product_ttl(&self, ttl: Duration) {
+        self.inner.lock().unwrap().product_ttl = ttl;
+    }
+
+    pub fn clear_product_cache(&self) {
+        self.inner.lock().unwrap().product_cache.clear();
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
+    use chrono::TimeZone;
+
     use super::*;
+    use crate::clock::ManualClock;
+
+    fn details(tick_size: Option<f64>) -> ProductDetails {
+        serde_json::from_value(serde_json::json!({
+            "category": "A",
+            "closePrice": 600.0,
+            "closePriceDate": "2024-01-31",
+            "contractSize": 1.0,
+            "exchangeId": "663",
+            "id": "1",
+            "isin": "US0000000001",
+            "name": "1",
+            "productType": "STOCK",
+            "productTypeId": 1,
+            "symbol": "1",
+            "tickSize": tick_size,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn tick_size_only_from_product_info() {
+        let product = |tick_size: Option<f64>| {
+            let client = Client::new("", "", reqwest::Client::new(), Default::default());
+            Product {
+                inner: details(tick_size),
+                client,
+            }
+        };
+        let unknown = product(None);
+        assert_eq!(unknown.tick_size(), None);
+        assert!(unknown.is_price_on_tick(600.01));
+        assert_eq!(unknown.round_price_to_tick(600.01), 600.01);
+
+        let known = product(Some(0.05));
+        assert!(!known.is_price_on_tick(600.01));
+        assert_eq!(known.round_price_to_tick(600.01), 600.0);
+        assert_eq!(round_to_tick(42.017, 0.01, false), 42.01);
+    }
+
+    #[test]
+    fn product_cache_expires() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        client.set_clock(clock.clone());
+        client.inner.lock().unwrap().product_cache.insert(
+            "1".to_string(),
+            CachedProduct {
+                stored_at: start,
+                details: details(None),
+            },
+        );
+        assert!(client.fresh_product("1").is_some());
+
+        clock.advance(DEFAULT_PRODUCT_TTL);
+        assert!(client.fresh_product("1").is_none());
+        assert!(client.cached_product("1").is_some());
+
+        client.set_product_ttl(Duration::days(1));
+        assert!(client.fresh_product("1").is_some());
+    }
+
+    #[test]
+    fn known_currency_codes() {
+        #[derive(Deserialize)]
+        struct Row {
+            #[serde(default, deserialize_with = "known_currency")]
+            currency: Option<Currency>,
+        }
+        let parse = |json| serde_json::from_str::<Row>(json).unwrap().currency;
+        assert_eq!(parse(r#"{"currency": "USD"}"#), Some(Currency::USD));
+        assert_eq!(parse(r#"{"currency": "SEK"}"#), None);
+        assert_eq!(parse(r#"{}"#), None);
+    }
+
+    #[test]
+    fn liquidity_score_components() {
+        assert_eq!(LiquidityScore::new(None, None, None).score, None);
+        assert_eq!(LiquidityScore::new(Some(10), None, None).score, Some(1.0));
+        let x = LiquidityScore::new(Some(5), Some(1e6), Some(0.01));
+        assert!((x.score.unwrap() - (0.2 * 0.5 + 0.5 * 0.5 + 0.3 * 0.5)).abs() < 1e-12);
+        let illiquid = LiquidityScore::new(Some(0), Some(100.0), Some(0.05));
+        assert_eq!(illiquid.score, Some(0.0));
+    }
 
     #[tokio::test]
     async fn products_ids() {
@@ -0,0 +1,931 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    hash::Hash,
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
+use reqwest::{header, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use strum::{Display, EnumString};
+use tokio::sync::{broadcast, Mutex};
+
+use crate::{
+    client::{Client, ClientError, StreamHealth},
+    http::Endpoint,
+    util::Period,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, Display, EnumString, Eq, Hash, PartialEq, Serialize)]
+pub enum QuoteField {
+    LastPrice,
+    LastVolume,
+    LastDate,
+    LastTime,
+    BidPrice,
+    AskPrice,
+    BidVolume,
+    AskVolume,
+    OpenPrice,
+    HighPrice,
+    LowPrice,
+    CumulativeVolume,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum TickValue {
+    Number(f64),
+    Text(String),
+}
+
+impl TickValue {
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(x) => Some(*x),
+            Self::Text(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct QuoteTick {
+    pub vwd_id: String,
+    pub field: QuoteField,
+    pub value: TickValue,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuotecastMessage {
+    Tick(QuoteTick),
+    Heartbeat,
+    /// The server dropped the session, every subscription must be requested again.
+    SessionExpired,
+}
+
+/// Raw vwd quotecast session: subscriptions are sent as control data and
+/// updates are collected by long-polling the session endpoint.
+#[derive(Debug)]
+pub struct QuotecastSession {
+    client: Client,
+    session_id: String,
+    references: HashMap<i64, (String, QuoteField)>,
+}
+
+fn quotecast_url(client: &Client, path: &str) -> Url {
+    let inner = client.inner.lock().unwrap();
+    let base_url = inner
+        .account_config
+        .vwd_quotecast_service_url
+        .trim_end_matches('/');
+    Url::parse(&format!("{base_url}/{path}"))
+        .unwrap_or_else(|_| panic!("can't parse quotecast url: {base_url}/{path}"))
+}
+
+/// Control data in vwd's `a_req(<vwd id>.<field>);` form, one call per key.
+fn control_data(action: &str, keys: &[(String, QuoteField)]) -> String {
+    keys.iter()
+        .map(|(vwd_id, field)| format!("{action}({vwd_id}.{field});"))
+        .collect()
+}
+
+impl Client {
+    pub async fn quotecast_session(&self) -> Result<QuotecastSession, ClientError> {
+        self.require(Endpoint::Quotecast)?;
+        let url = quotecast_url(self, "request_session");
+        let req = {
+            let inner = self.inner.lock().unwrap();
+            inner
+                .http_client
+                .post(url)
+                .query(&[
+                    ("version", "1.0.20201211".to_string()),
+                    ("userToken", inner.client_id.to_string()),
+                ])
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
+                .header(header::REFERER, &inner.referer)
+                .json(&json!({ "referrer": "https://trader.degiro.nl" }))
+        };
+
+        let res = self.send(Endpoint::Quotecast, req).await?;
+
+        let json = res.json::<Value>().await?;
+        let session_id = json["sessionId"]
+            .as_str()
+            .ok_or(ClientError::ParseError("quotecast sessionId".to_string()))?
+            .to_string();
+        Ok(QuotecastSession {
+            client: self.clone(),
+            session_id,
+            references: HashMap::new(),
+        })
+    }
+}
+
+impl QuotecastSession {
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    async fn control(&self, data: String) -> Result<(), ClientError> {
+        let url = quotecast_url(&self.client, &self.session_id);
+        let req = {
+            let inner = self.client.inner.lock().unwrap();
+            inner
+                .http_client
+                .post(url)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
+                .header(header::REFERER, &inner.referer)
+                .json(&json!({ "controlData": data }))
+        };
+
+        self.client.send(Endpoint::Quotecast, req).await?;
+        Ok(())
+    }
+
+    pub async fn subscribe(&self, keys: &[(String, QuoteField)]) -> Result<(), ClientError> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        self.control(control_data("a_req", keys)).await
+    }
+
+    pub async fn unsubscribe(&self, keys: &[(String, QuoteField)]) -> Result<(), ClientError> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        self.control(control_data("a_rel", keys)).await
+    }
+
+    /// Long-polls the session once and returns everything the server queued.
+    ///
+    /// Polls are paced by the server, so they don't consume rate limiter tokens.
+    pub async fn poll(&mut self) -> Result<Vec<QuotecastMessage>, ClientError> {
+        let url = quotecast_url(&self.client, &self.session_id);
+        let req = {
+            let inner = self.client.inner.lock().unwrap();
+            inner
+                .http_client
+                .get(url)
+                .header(header::REFERER, &inner.referer)
+        };
+
+        let res = self.client.execute(req).await?;
+        let json = res.json::<Value>().await?;
+        Ok(self.parse_messages(&json))
+    }
+
+    fn parse_messages(&mut self, json: &Value) -> Vec<QuotecastMessage> {
+        let now = self.client.now();
+        let mut xs = Vec::new();
+        for msg in json.as_array().map(Vec::as_slice).unwrap_or_default() {
+            let v = &msg["v"];
+            match msg["m"].as_str() {
+                Some("a_req") => {
+                    let key = v[0].as_str().and_then(|s| s.split_once('.'));
+                    if let (Some((vwd_id, field)), Some(reference)) = (key, v[1].as_i64()) {
+                        if let Ok(field) = field.parse() {
+                            self.references
+                                .insert(reference, (vwd_id.to_string(), field));
+                        }
+                    }
+                }
+                Some(m @ ("un" | "us")) => {
+                    let Some((vwd_id, field)) = v[0].as_i64().and_then(|r| self.references.get(&r))
+                    else {
+                        continue;
+                    };
+                    let value = if m == "un" {
+                        match v[1].as_f64() {
+                            Some(x) => TickValue::Number(x),
+                            None => continue,
+                        }
+                    } else {
+                        TickValue::Text(v[1].as_str().unwrap_or_default().to_string())
+                    };
+                    xs.push(QuotecastMessage::Tick(QuoteTick {
+                        vwd_id: vwd_id.clone(),
+                        field: *field,
+                        value,
+                        received_at: now,
+                    }));
+                }
+                Some("h") => xs.push(QuotecastMessage::Heartbeat),
+                Some("sr") => xs.push(QuotecastMessage::SessionExpired),
+                _ => {}
+            }
+        }
+        xs
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionKey {
+    pub vwd_id: String,
+    pub field: QuoteField,
+}
+
+#[derive(Debug, Default)]
+struct ManagerState {
+    /// Idle session, taken out while a poll waits on it.
+    session: Option<QuotecastSession>,
+    desired: HashMap<SubscriptionKey, usize>,
+    /// Keys requested from the current session.
+    subscribed: HashSet<SubscriptionKey>,
+    reconnects: u64,
+}
+
+/// Keeps the set of wanted quotecast subscriptions alive across sessions.
+///
+/// Subscriptions are reference counted, so several consumers asking for the
+/// same instrument result in a single upstream request. When the session
+/// expires or the transport fails, the next poll opens a new session and
+/// requests every desired subscription again. Ticks are fanned out to all
+/// receivers returned by [`SubscriptionManager::ticks`].
+///
+/// A poll doesn't hold the subscriptions while it waits on the server;
+/// changes made in the meantime are sent before the next poll.
+#[derive(Debug)]
+pub struct SubscriptionManager {
+    client: Client,
+    state: Mutex<ManagerState>,
+    /// One poll at a time, so only one session is ever open.
+    polling: Mutex<()>,
+    ticks: broadcast::Sender<QuoteTick>,
+}
+
+fn keys(vwd_ids: &[&str], fields: &[QuoteField]) -> Vec<SubscriptionKey> {
+    vwd_ids
+        .iter()
+        .flat_map(|vwd_id| {
+            fields.iter().map(|field| SubscriptionKey {
+                vwd_id: vwd_id.to_string(),
+                field: *field,
+            })
+        })
+        .collect()
+}
+
+fn wire(keys: &[SubscriptionKey]) -> Vec<(String, QuoteField)> {
+    keys.iter().map(|k| (k.vwd_id.clone(), k.field)).collect()
+}
+
+impl SubscriptionManager {
+    pub fn new(client: &Client) -> Self {
+        let (ticks, _) = broadcast::channel(1024);
+        Self {
+            client: client.clone(),
+            state: Mutex::new(ManagerState::default()),
+            polling: Mutex::new(()),
+            ticks,
+        }
+    }
+
+    pub fn ticks(&self) -> broadcast::Receiver<QuoteTick> {
+        self.ticks.subscribe()
+    }
+
+    pub async fn reconnects(&self) -> u64 {
+        self.state.lock().await.reconnects
+    }
+
+    pub async fn subscriptions(&self) -> Vec<SubscriptionKey> {
+        self.state.lock().await.desired.keys().cloned().collect()
+    }
+
+    pub async fn subscribe(
+        &self,
+        vwd_ids: &[&str],
+        fields: &[QuoteField],
+    ) -> Result<(), ClientError> {
+        let mut state = self.state.lock().await;
+        for key in keys(vwd_ids, fields) {
+            *state.desired.entry(key).or_default() += 1;
+        }
+        self.sync(&mut state).await
+    }
+
+    pub async fn unsubscribe(
+        &self,
+        vwd_ids: &[&str],
+        fields: &[QuoteField],
+    ) -> Result<(), ClientError> {
+        let mut state = self.state.lock().await;
+        for key in keys(vwd_ids, fields) {
+            if let Some(count) = state.desired.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    state.desired.remove(&key);
+                }
+            }
+        }
+        self.sync(&mut state).await
+    }
+
+    /// Requests and releases whatever the idle session is missing from or
+    /// has on top of the desired set; a polling session is synced once the
+    /// poll returns. When that fails the session is dropped, so the next
+    /// poll opens a new one with every subscription.
+    async fn sync(&self, state: &mut ManagerState) -> Result<(), ClientError> {
+        let Some(session) = &state.session else {
+            return Ok(());
+        };
+        let added = state
+            .desired
+            .keys()
+            .filter(|key| !state.subscribed.contains(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        let removed = state
+            .subscribed
+            .iter()
+            .filter(|key| !state.desired.contains_key(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        let res = match session.subscribe(&wire(&added)).await {
+            Ok(()) => session.unsubscribe(&wire(&removed)).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            state.session = None;
+            state.subscribed.clear();
+            self.update_stream(|x| {
+                x.connected = false;
+                x.errors += 1;
+            });
+            return Err(err);
+        }
+        state.subscribed.extend(added);
+        for key in &removed {
+            state.subscribed.remove(key);
+        }
+        Ok(())
+    }
+
+    /// Runs one poll cycle, reconnecting and re-subscribing when needed.
+    /// Returns the number of ticks broadcast.
+    pub async fn poll(&self) -> Result<usize, ClientError> {
+        let _polling = self.polling.lock().await;
+        let opened = if self.state.lock().await.session.is_none() {
+            Some(self.client.quotecast_session().await?)
+        } else {
+            None
+        };
+
+        let mut session = {
+            let mut state = self.state.lock().await;
+            let reconnected = opened.is_some();
+            if let Some(session) = opened {
+                state.session = Some(session);
+                state.subscribed.clear();
+            }
+            self.sync(&mut state).await?;
+            if reconnected {
+                state.reconnects += 1;
+                self.update_stream(|x| {
+                    x.connected = true;
+                    x.reconnects += 1;
+                });
+            }
+            match state.session.take() {
+                Some(session) => session,
+                None => return Ok(0),
+            }
+        };
+
+        let messages = match session.poll().await {
+            Ok(messages) => messages,
+            Err(err) => {
+                self.state.lock().await.subscribed.clear();
+                self.update_stream(|x| {
+                    x.connected = false;
+                    x.errors += 1;
+                });
+                return Err(err);
+            }
+        };
+
+        let expired = messages
+            .iter()
+            .any(|m| matches!(m, QuotecastMessage::SessionExpired));
+        if !messages.is_empty() {
+            let now = self.client.now();
+            let ticked = messages
+                .iter()
+                .any(|m| matches!(m, QuotecastMessage::Tick(_)));
+            self.update_stream(|x| {
+                x.connected = !expired;
+                x.last_message_at = Some(now);
+                if ticked {
+                    x.last_tick_at = Some(now);
+                }
+            });
+        }
+        {
+            let mut state = self.state.lock().await;
+            if expired {
+                state.subscribed.clear();
+            } else {
+                state.session = Some(session);
+            }
+        }
+
+        let mut n = 0;
+        for msg in messages {
+            if let QuotecastMessage::Tick(tick) = msg {
+                n += 1;
+                // No receivers is fine, ticks are simply dropped.
+                let _ = self.ticks.send(tick);
+            }
+        }
+        Ok(n)
+    }
+
+    fn update_stream(&self, f: impl FnOnce(&mut StreamHealth)) {
+        f(&mut self.client.inner.lock().unwrap().stream);
+    }
+
+    /// Polls forever, backing off briefly after transport errors.
+    pub async fn run(&self) -> Result<(), ClientError> {
+        loop {
+            match self.poll().await {
+                Ok(_) => {}
+                Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+    }
+}
+
+/// Top of book at a single point in time. vwd doesn't publish depth for
+/// retail sessions, so only the touch is available.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuoteSnapshot {
+    pub vwd_id: String,
+    pub bid: Option<f64>,
+    pub ask: Option<f64>,
+    pub last: Option<f64>,
+}
+
+impl QuoteSnapshot {
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.bid? + self.ask?) / 2.0)
+    }
+
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.ask? - self.bid?)
+    }
+
+    fn apply(&mut self, tick: &QuoteTick) {
+        let Some(x) = tick.value.as_f64() else {
+            return;
+        };
+        match tick.field {
+            QuoteField::BidPrice => self.bid = Some(x),
+            QuoteField::AskPrice => self.ask = Some(x),
+            QuoteField::LastPrice => self.last = Some(x),
+            _ => {}
+        }
+    }
+}
+
+impl Client {
+    /// Opens a short lived quotecast session and waits until bid and ask are known.
+    pub async fn quote_snapshot(&self, vwd_id: &str) -> Result<QuoteSnapshot, ClientError> {
+        let mut session = self.quotecast_session().await?;
+        let keys = [
+            QuoteField::BidPrice,
+            QuoteField::AskPrice,
+            QuoteField::LastPrice,
+        ]
+        .into_iter()
+        .map(|field| (vwd_id.to_string(), field))
+        .collect::<Vec<_>>();
+        session.subscribe(&keys).await?;
+
+        let mut snapshot = QuoteSnapshot {
+            vwd_id: vwd_id.to_string(),
+            ..Default::default()
+        };
+        for _ in 0..5 {
+            for msg in session.poll().await? {
+                if let QuotecastMessage::Tick(tick) = msg {
+                    snapshot.apply(&tick);
+                }
+            }
+            if snapshot.bid.is_some() && snapshot.ask.is_some() {
+                break;
+            }
+        }
+        session.unsubscribe(&keys).await.ok();
+
+        if snapshot.bid.is_none() && snapshot.ask.is_none() && snapshot.last.is_none() {
+            return Err(ClientError::NoData);
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Transport agnostic source of price ticks.
+#[async_trait]
+pub trait PriceStream: Send {
+    /// Waits for the next tick. `None` means the stream has ended.
+    async fn next_tick(&mut self) -> Option<Result<QuoteTick, ClientError>>;
+}
+
+/// Push based stream fed by a shared [`SubscriptionManager`].
+#[derive(Debug)]
+pub struct QuotecastStream {
+    manager: Arc<SubscriptionManager>,
+    ticks: broadcast::Receiver<QuoteTick>,
+}
+
+impl QuotecastStream {
+    pub fn new(manager: Arc<SubscriptionManager>) -> Self {
+        let ticks = manager.ticks();
+        Self { manager, ticks }
+    }
+}
+
+#[async_trait]
+impl PriceStream for QuotecastStream {
+    async fn next_tick(&mut self) -> Option<Result<QuoteTick, ClientError>> {
+        loop {
+            match self.ticks.try_recv() {
+                Ok(tick) => return Some(Ok(tick)),
+                Err(broadcast::error::TryRecvError::Closed) => return None,
+                Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                Err(broadcast::error::TryRecvError::Empty) => {}
+            }
+            if let Err(err) = self.manager.poll().await {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// Fields [`Client::stream_quotes`] subscribes to.
+pub const STREAM_FIELDS: [QuoteField; 4] = [
+    QuoteField::LastPrice,
+    QuoteField::BidPrice,
+    QuoteField::AskPrice,
+    QuoteField::LastVolume,
+];
+
+/// Pause before reconnecting after a failed poll.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+impl Client {
+    /// Live [`STREAM_FIELDS`] ticks of `product_ids`, through a
+    /// [`SubscriptionManager`] of its own. Ticks carry the vwd id of the
+    /// product, not its DEGIRO id.
+    ///
+    /// Expired sessions and transport errors are handled by reconnecting and
+    /// subscribing again, so the stream only yields
+    /// [`ClientError::Unauthorized`], after which it ends. Products without a
+    /// vwd id fail with [`ClientError::NoData`].
+    pub async fn stream_quotes<I>(
+        &self,
+        product_ids: I,
+    ) -> Result<impl Stream<Item = Result<QuoteTick, ClientError>>, ClientError>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let ids = product_ids.into_iter().map(Into::into).collect::<Vec<_>>();
+        let state = if ids.is_empty() {
+            None
+        } else {
+            let products = self.products(&ids).await?;
+            let vwd_ids = ids
+                .iter()
+                .map(|id| {
+                    products
+                        .get(id)
+                        .and_then(|p| p.inner.vwd_id.clone())
+                        .ok_or(ClientError::NoData)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let vwd_ids = vwd_ids.iter().map(String::as_str).collect::<Vec<_>>();
+            let manager = SubscriptionManager::new(self);
+            manager.subscribe(&vwd_ids, &STREAM_FIELDS).await?;
+            let ticks = manager.ticks();
+            Some((manager, ticks))
+        };
+
+        Ok(stream::unfold(state, |state| async move {
+            let (manager, mut ticks) = state?;
+            loop {
+                match ticks.try_recv() {
+                    Ok(tick) => return Some((Ok(tick), Some((manager, ticks)))),
+                    Err(broadcast::error::TryRecvError::Closed) => return None,
+                    Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::TryRecvError::Empty) => {}
+                }
+                match manager.poll().await {
+                    Ok(_) => {}
+                    Err(ClientError::Unauthorized) => {
+                        return Some((Err(ClientError::Unauthorized), None))
+                    }
+                    // The failed session is dropped, the next poll opens a new one.
+                    Err(_) => tokio::time::sleep(RECONNECT_DELAY).await,
+                }
+            }
+        }))
+    }
+}
+
+/// Fallback for environments where the quotecast endpoint is blocked. Last
+/// prices are read from the chart service every `interval` and only emitted
+/// when they moved by at least `min_change` (relative, e.g. `0.001` = 0.1%).
+#[derive(Debug)]
+pub struct PollingPriceStream {
+    client: Client,
+    product_ids: Vec<String>,
+    interval: Duration,
+    min_change: f64,
+    last: HashMap<String, f64>,
+    pending: VecDeque<QuoteTick>,
+    started: bool,
+}
+
+impl PollingPriceStream {
+    pub fn new(client: &Client, product_ids: &[&str], interval: Duration) -> Self {
+        Self {
+            client: client.clone(),
+            product_ids: product_ids.iter().map(|id| id.to_string()).collect(),
+            interval,
+            min_change: 0.0,
+            last: HashMap::new(),
+            pending: VecDeque::new(),
+            started: false,
+        }
+    }
+
+    pub fn min_change(mut self, min_change: f64) -> Self {
+        self.min_change = min_change;
+        self
+    }
+
+    fn changed(&self, vwd_id: &str, price: f64) -> bool {
+        match self.last.get(vwd_id) {
+            None => true,
+            Some(last) if *last == 0.0 => price != 0.0,
+            Some(last) => {
+                let change = ((price - last) / last).abs();
+                change > 0.0 && change >= self.min_change
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PriceStream for PollingPriceStream {
+    async fn next_tick(&mut self) -> Option<Result<QuoteTick, ClientError>> {
+        loop {
+            if let Some(tick) = self.pending.pop_front() {
+                return Some(Ok(tick));
+            }
+            if self.product_ids.is_empty() {
+                return None;
+            }
+            if self.started {
+                tokio::time::sleep(self.interval).await;
+            }
+            self.started = true;
+
+            for id in self.product_ids.clone() {
+                let product = match self.client.product(&id).await {
+                    Ok(product) => product,
+                    Err(err) => return Some(Err(err)),
+                };
+                let vwd_id = product.inner.vwd_id.clone().unwrap_or(id);
+                let price = match product.quotes(Period::P1D, Period::PT1M).await {
+                    Ok(quotes) => quotes.close.last().copied(),
+                    Err(ClientError::NoData) => None,
+                    Err(err) => return Some(Err(err)),
+                };
+                let Some(price) = price else {
+                    continue;
+                };
+                if self.changed(&vwd_id, price) {
+                    self.last.insert(vwd_id.clone(), price);
+                    self.pending.push_back(QuoteTick {
+                        vwd_id,
+                        field: QuoteField::LastPrice,
+                        value: TickValue::Number(price),
+                        received_at: self.client.now(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::Method;
+
+    use super::*;
+    use crate::{client::ClientStatus, transport::MockTransport};
+
+    fn mocked_session(mock: &Arc<MockTransport>) -> QuotecastSession {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        client.set_transport(mock.clone());
+        {
+            let mut inner = client.inner.lock().unwrap();
+            inner.status = ClientStatus::Authorized;
+            inner.account_config.vwd_quotecast_service_url =
+                "https://degiro.quotecast.vwdservices.com/CORS/".into();
+        }
+        QuotecastSession {
+            client,
+            session_id: "abc".to_string(),
+            references: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn control_data_body() {
+        let mock = Arc::new(MockTransport::new());
+        mock.json(Method::POST, "/CORS/abc", &json!({}));
+        let session = mocked_session(&mock);
+        let keys = [
+            ("360015751".to_string(), QuoteField::LastPrice),
+            ("360015751".to_string(), QuoteField::BidPrice),
+        ];
+        session.subscribe(&keys).await.unwrap();
+        session.unsubscribe(&keys[..1]).await.unwrap();
+
+        let bodies = mock
+            .requests()
+            .into_iter()
+            .map(|x| serde_json::from_slice::<Value>(&x.body.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bodies,
+            [
+                json!({ "controlData": "a_req(360015751.LastPrice);a_req(360015751.BidPrice);" }),
+                json!({ "controlData": "a_rel(360015751.LastPrice);" }),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_quotecast_messages() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let mut session = QuotecastSession {
+            client,
+            session_id: "x".to_string(),
+            references: HashMap::new(),
+        };
+        let json = json!([
+            {"m": "a_req", "v": ["360015751.LastPrice", 12]},
+            {"m": "un", "v": [12, 101.5]},
+            {"m": "h"},
+            {"m": "sr"}
+        ]);
+        let xs = session.parse_messages(&json);
+        assert_eq!(xs.len(), 3);
+        match &xs[0] {
+            QuotecastMessage::Tick(tick) => {
+                assert_eq!(tick.vwd_id, "360015751");
+                assert_eq!(tick.field, QuoteField::LastPrice);
+                assert_eq!(tick.value, TickValue::Number(101.5));
+            }
+            msg => panic!("unexpected message: {msg:?}"),
+        }
+        assert_eq!(xs[2], QuotecastMessage::SessionExpired);
+    }
+
+    #[tokio::test]
+    async fn failed_subscribe_drops_session() {
+        let mock = Arc::new(MockTransport::new());
+        mock.on(Method::POST, "/CORS/abc", 500, "");
+        let session = mocked_session(&mock);
+        let manager = SubscriptionManager::new(&session.client);
+        manager.state.lock().await.session = Some(session);
+
+        let res = manager
+            .subscribe(&["360015751"], &[QuoteField::LastPrice])
+            .await;
+        assert!(res.is_err());
+        let state = manager.state.lock().await;
+        assert!(state.session.is_none());
+        assert_eq!(state.desired.len(), 1);
+    }
+
+    /// Holds polls open until released, like vwd does while nothing ticks.
+    #[derive(Debug)]
+    struct HeldPoll {
+        mock: Arc<MockTransport>,
+        release: tokio::sync::Notify,
+    }
+
+    #[async_trait]
+    impl crate::transport::Transport for HeldPoll {
+        async fn execute(&self, req: reqwest::Request) -> Result<reqwest::Response, ClientError> {
+            if req.method() == Method::GET {
+                self.release.notified().await;
+            }
+            self.mock.execute(req).await
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_during_poll() {
+        let mock = Arc::new(MockTransport::new());
+        mock.json(Method::POST, "/CORS/abc", &json!({}))
+            .json(Method::GET, "/CORS/abc", &json!([]));
+        let session = mocked_session(&mock);
+        let held = Arc::new(HeldPoll {
+            mock: mock.clone(),
+            release: tokio::sync::Notify::new(),
+        });
+        session.client.set_transport(held.clone());
+        let manager = Arc::new(SubscriptionManager::new(&session.client));
+        manager.state.lock().await.session = Some(session);
+
+        let polling = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.poll().await }
+        });
+        tokio::task::yield_now().await;
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            manager.subscribe(&["360015751"], &[QuoteField::LastPrice]),
+        )
+        .await
+        .expect("subscribe waited for the poll")
+        .unwrap();
+        // Nothing is sent while the session is polling.
+        assert!(mock.requests().is_empty());
+
+        held.release.notify_one();
+        polling.await.unwrap().unwrap();
+        let next = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.poll().await }
+        });
+        held.release.notify_one();
+        next.await.unwrap().unwrap();
+
+        let requests = mock
+            .requests()
+            .into_iter()
+            .map(|x| x.method)
+            .collect::<Vec<_>>();
+        assert_eq!(requests, [Method::GET, Method::POST, Method::GET]);
+    }
+
+    #[test]
+    fn stream_staleness() {
+        let now = Utc::now();
+        let max_age = chrono::Duration::seconds(30);
+        let mut health = StreamHealth::default();
+        assert!(!health.is_stale(now, max_age));
+        health.connected = true;
+        assert!(health.is_stale(now, max_age));
+        health.last_tick_at = Some(now - chrono::Duration::seconds(10));
+        assert!(!health.is_stale(now, max_age));
+        assert!(health.is_stale(now + max_age, max_age));
+    }
+
+    #[tokio::test]
+    async fn stream_quotes_without_products_ends() {
+        use futures::StreamExt;
+
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let ticks = client.stream_quotes(Vec::<String>::new()).await.unwrap();
+        assert_eq!(ticks.collect::<Vec<_>>().await.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn stream_quotes() {
+        use futures::StreamExt;
+
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let ticks = client.stream_quotes(["332111"]).await.unwrap();
+        let ticks = ticks.take(5).collect::<Vec<_>>().await;
+        dbg!(ticks);
+    }
+
+    #[test]
+    fn polling_min_change_filter() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let mut stream =
+            PollingPriceStream::new(&client, &["1"], Duration::from_secs(1)).min_change(0.01);
+        assert!(stream.changed("1", 100.0));
+        stream.last.insert("1".to_string(), 100.0);
+        assert!(!stream.changed("1", 100.5));
+        assert!(stream.changed("1", 101.0));
+        assert!(!stream.changed("1", 100.0));
+    }
+}
@@ -1,16 +1,100 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::{Bound, RangeBounds},
+    str::FromStr,
+};
+
+use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc};
 #[cfg(feature = "erfurt")]
 use erfurt::candle::{Candle, Candles, CandlesExt};
 use reqwest::{header, Url};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::{
-    client::{Client, ClientError, ClientStatus},
+    cache::CacheEntry,
+    client::{Client, ClientError},
+    http::Endpoint,
+    strategy::Candle as Bar,
     util::Period,
 };
 
-use super::product::Product;
+use super::product::{Product, ProductDetails};
+
+/// Instrument part of a vwd chart series, written `issueid:<id>` or
+/// `vwdkey:<key>`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SeriesIdentifier {
+    IssueId(String),
+    VwdKey(String),
+}
+
+/// What a chart series holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SeriesKind {
+    Ohlc,
+    Price,
+    Volume,
+}
+
+impl fmt::Display for SeriesKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ohlc => write!(f, "ohlc"),
+            Self::Price => write!(f, "price"),
+            Self::Volume => write!(f, "volume"),
+        }
+    }
+}
+
+impl SeriesIdentifier {
+    /// From the product's vwd id; `issueid` unless the product says otherwise.
+    pub fn from_product(product: &ProductDetails) -> Option<Self> {
+        let id = product.vwd_id.clone().filter(|x| !x.is_empty())?;
+        match product.vwd_identifier_type.as_deref() {
+            Some(t) if t.eq_ignore_ascii_case("vwdkey") => Some(Self::VwdKey(id)),
+            _ => Some(Self::IssueId(id)),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        match self {
+            Self::IssueId(x) | Self::VwdKey(x) => x,
+        }
+    }
+
+    /// Value of the chart `series` query parameter, e.g. `ohlc:issueid:123`.
+    pub fn series(&self, kind: SeriesKind) -> String {
+        format!("{kind}:{self}")
+    }
+}
+
+impl fmt::Display for SeriesIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IssueId(x) => write!(f, "issueid:{x}"),
+            Self::VwdKey(x) => write!(f, "vwdkey:{x}"),
+        }
+    }
+}
+
+impl FromStr for SeriesIdentifier {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ClientError::ParseError(format!("invalid series identifier: {s}"));
+        let (kind, id) = s.trim().split_once(':').ok_or_else(err)?;
+        if id.is_empty() || id.contains(':') {
+            return Err(err());
+        }
+        match kind.to_ascii_lowercase().as_str() {
+            "issueid" => Ok(Self::IssueId(id.to_string())),
+            "vwdkey" => Ok(Self::VwdKey(id.to_string())),
+            _ => Err(err()),
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
 struct CandlesData(Vec<Ohlc>);
@@ -24,7 +108,7 @@ struct Ohlc {
     c: f64,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Quotes {
     pub id: String,
     pub open: Vec<f64>,
@@ -188,15 +272,74 @@ impl Client {
         period: Period,
         interval: Period,
     ) -> Result<Quotes, ClientError> {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        self.guarded(
+            &format!("quotes/{id}"),
+            self.fetch_quotes(id, period, interval),
+        )
+        .await
+    }
+
+    /// Serves closed bars from the cache backend, when one is set, and only
+    /// fetches the shortest period covering the open tail. Closed bars never
+    /// change, so they are stored without expiry.
+    async fn fetch_quotes(
+        &self,
+        id: &str,
+        period: Period,
+        interval: Period,
+    ) -> Result<Quotes, ClientError> {
+        self.require(Endpoint::Quotes)?;
 
         let product = self.product(id).await?;
-        let Some(vwd_id) = product.inner.vwd_id else {
-            return Err(ClientError::NoData);
+        let series = SeriesIdentifier::from_product(&product.inner).ok_or(ClientError::NoData)?;
+        let Some(cache) = self.cache() else {
+            return self
+                .fetch_chart(&product.inner.id, &series, period, interval)
+                .await;
         };
 
+        let key = format!("quotes/{series}/{interval}");
+        let now = self.now();
+        let from = now - period;
+        let cached = cache
+            .get(&key)
+            .and_then(|entry| serde_json::from_value::<Quotes>(entry.value).ok());
+        let fetch_period = cached
+            .as_ref()
+            .filter(|x| x.time.first().is_some_and(|t| *t <= from))
+            .and_then(|x| tail_period(now, *x.time.last()?))
+            .filter(|p| p.to_duration() < period.to_duration())
+            .unwrap_or(period);
+        let fresh = self
+            .fetch_chart(&product.inner.id, &series, fetch_period, interval)
+            .await?;
+        let quotes = cached
+            .and_then(|mut cached| cached.merge(fresh.clone()).is_ok().then_some(cached))
+            .unwrap_or(fresh);
+
+        let today = now.date_naive().and_time(NaiveTime::MIN).and_utc();
+        let closed = quotes.slice(..closed_len(&quotes, interval, today));
+        if !closed.is_empty() {
+            if let Ok(value) = serde_json::to_value(&closed) {
+                cache.put(
+                    &key,
+                    &CacheEntry {
+                        stored_at: now,
+                        value,
+                    },
+                );
+            }
+        }
+        Ok(quotes.slice(quotes.index_at(from).unwrap_or(0)..))
+    }
+
+    async fn fetch_chart(
+        &self,
+        id: &str,
+        series: &SeriesIdentifier,
+        period: Period,
+        interval: Period,
+    ) -> Result<Quotes, ClientError> {
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = "https://charting.vwdservices.com/hchart/v1/deGiro/data.js";
@@ -210,54 +353,241 @@ impl Client {
                     ("format", "json".to_string()),
                     ("resolution", interval.to_string()),
                     ("period", period.to_string()),
-                    ("series", format!("ohlc:issueid:{}", vwd_id)),
+                    ("series", series.series(SeriesKind::Ohlc)),
                     ("userToken", inner.client_id.to_string()),
                 ])
                 .header(header::REFERER, &inner.referer)
         };
 
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
+        let body = self.send_json_coalesced(Endpoint::Quotes, req).await?;
+        Quotes::from_chart(id, &body, interval)
+    }
+}
+
+/// Chart periods usable for a tail refresh, shortest first.
+const TAIL_PERIODS: [Period; 9] = [
+    Period::P1D,
+    Period::P1W,
+    Period::P1M,
+    Period::P3M,
+    Period::P6M,
+    Period::P1Y,
+    Period::P3Y,
+    Period::P5Y,
+    Period::P50Y,
+];
+
+/// Shortest chart period reaching back to the bar at `last`.
+fn tail_period(now: DateTime<Utc>, last: DateTime<Utc>) -> Option<Period> {
+    TAIL_PERIODS.into_iter().find(|p| now - *p <= last)
+}
+
+/// Number of leading bars that ended before `today`.
+fn closed_len(quotes: &Quotes, interval: Period, today: DateTime<Utc>) -> usize {
+    quotes.time.partition_point(|t| *t + interval <= today)
+}
+
+impl Quotes {
+    /// Parses a chart response body with a single `ohlc` series.
+    pub fn from_chart(
+        id: impl Into<String>,
+        body: &Value,
+        interval: Period,
+    ) -> Result<Self, ClientError> {
+        let series = body["series"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let Some(first) = series.first() else {
+            return Err(ClientError::NoData);
         };
-        rate_limiter.acquire_one().await;
-
-        let res = req.send().await?;
-
-        match res.error_for_status() {
-            Ok(res) => {
-                let body = res.json::<Value>().await?;
-                let error = body
-                    .get("series")
-                    .and_then(|v| v.as_array())
-                    .and_then(|arr| arr.first())
-                    .and_then(|obj| obj.get("error"))
-                    .and_then(|error| error.as_str());
-
-                if let Some(error) = error {
-                    return Err(ClientError::Descripted(error.to_string()));
-                }
+        if let Some(error) = first.get("error").and_then(|error| error.as_str()) {
+            return Err(ClientError::Descripted(error.to_string()));
+        }
+
+        let start = NaiveDateTime::deserialize(&body["start"])?;
+        let start: DateTime<Utc> = DateTime::from_naive_utc_and_offset(start, Utc);
+        let end = NaiveDateTime::deserialize(&body["end"])?;
+        let end: DateTime<Utc> = DateTime::from_naive_utc_and_offset(end, Utc);
+        let candles = CandlesData::deserialize(&first["data"])?;
+        Ok(candles.as_quotes(id, start, end, interval))
+    }
+
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    /// Index of the last bar starting at or before `time`, by binary search
+    /// over the sorted times.
+    pub fn index_at(&self, time: DateTime<Utc>) -> Option<usize> {
+        self.time.partition_point(|t| *t <= time).checked_sub(1)
+    }
+
+    /// The bar in force at `time`, i.e. the last one starting at or before it.
+    pub fn at(&self, time: DateTime<Utc>) -> Option<Bar> {
+        let i = self.index_at(time)?;
+        Some(Bar {
+            product_id: self.id.clone(),
+            time: self.time[i],
+            open: self.open[i],
+            high: self.high[i],
+            low: self.low[i],
+            close: self.close[i],
+        })
+    }
+
+    /// Last close at or before `time`, for valuation at a past date.
+    pub fn close_at(&self, time: DateTime<Utc>) -> Option<f64> {
+        self.index_at(time).map(|i| self.close[i])
+    }
+
+    /// Copy of the bars in `range` of indices.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Quotes {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&x) => x,
+            Bound::Excluded(&x) => x + 1,
+            Bound::Unbounded => 0,
+        }
+        .min(len);
+        let end = match range.end_bound() {
+            Bound::Included(&x) => x + 1,
+            Bound::Excluded(&x) => x,
+            Bound::Unbounded => len,
+        }
+        .clamp(start, len);
+        Quotes {
+            id: self.id.clone(),
+            open: self.open[start..end].to_vec(),
+            high: self.high[start..end].to_vec(),
+            low: self.low[start..end].to_vec(),
+            close: self.close[start..end].to_vec(),
+            volume: self.volume.as_ref().map(|x| x[start..end].to_vec()),
+            time: self.time[start..end].to_vec(),
+        }
+    }
+
+    /// Bars starting from `start` up to, but not including, `end`.
+    pub fn between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Quotes {
+        let from = self.time.partition_point(|t| *t < start);
+        let to = self.time.partition_point(|t| *t < end);
+        self.slice(from..to.max(from))
+    }
 
-                let start = serde_json::from_value::<NaiveDateTime>(body["start"].clone())?;
-                let start: DateTime<Utc> = DateTime::from_naive_utc_and_offset(start, Utc);
-                let end = serde_json::from_value::<NaiveDateTime>(body["end"].clone())?;
-                let end: DateTime<Utc> = DateTime::from_naive_utc_and_offset(end, Utc);
-                let series = body["series"].as_array().unwrap();
-                let data = series.first().unwrap()["data"].clone();
-                let candles = serde_json::from_value::<CandlesData>(data)?;
-                let quotes = candles.as_quotes(&product.inner.id, start, end, interval);
-                Ok(quotes)
+    /// Smallest gap between bars, a stand-in for the bar interval.
+    fn min_step(&self) -> Option<chrono::Duration> {
+        self.time
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .filter(|d| *d > chrono::Duration::zero())
+            .min()
+    }
+
+    /// Splices `other` in, e.g. an incremental update. Bars at the same time
+    /// are taken from `other` as the fresher data. Fails when the ids differ
+    /// or the bar intervals are more than 10% apart, which leaves room for
+    /// months of different length. Volume is kept only when both have it.
+    pub fn merge(&mut self, other: Quotes) -> Result<(), ClientError> {
+        if !self.id.is_empty() && !other.id.is_empty() && self.id != other.id {
+            return Err(ClientError::Descripted(format!(
+                "can't merge quotes of {} into {}",
+                other.id, self.id
+            )));
+        }
+        if let (Some(a), Some(b)) = (self.min_step(), other.min_step()) {
+            let (a, b) = (a.num_seconds() as f64, b.num_seconds() as f64);
+            if (a - b).abs() > a.max(b) * 0.1 {
+                return Err(ClientError::Descripted(format!(
+                    "can't merge quotes of {} with different intervals",
+                    self.id
+                )));
             }
-            Err(err) => match err.status() {
-                Some(status) if status.as_u16() == 401 => {
-                    self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
-                }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
         }
+
+        let with_volume = (self.volume.is_some() || self.is_empty())
+            && (other.volume.is_some() || other.is_empty());
+        let mut bars = BTreeMap::new();
+        for quotes in [&*self, &other] {
+            for (i, time) in quotes.time.iter().enumerate() {
+                let volume = quotes.volume.as_ref().map_or(0.0, |x| x[i]);
+                let bar = (
+                    quotes.open[i],
+                    quotes.high[i],
+                    quotes.low[i],
+                    quotes.close[i],
+                    volume,
+                );
+                bars.insert(*time, bar);
+            }
+        }
+
+        if self.id.is_empty() {
+            self.id = other.id;
+        }
+        self.time = bars.keys().copied().collect();
+        self.open = bars.values().map(|x| x.0).collect();
+        self.high = bars.values().map(|x| x.1).collect();
+        self.low = bars.values().map(|x| x.2).collect();
+        self.close = bars.values().map(|x| x.3).collect();
+        self.volume = with_volume.then(|| bars.values().map(|x| x.4).collect());
+        Ok(())
+    }
+
+    /// Close to close log returns; pairs with a non-positive close are
+    /// skipped.
+    pub fn log_returns(&self) -> Vec<f64> {
+        self.close
+            .windows(2)
+            .filter(|w| w[0] > 0.0 && w[1] > 0.0)
+            .map(|w| (w[1] / w[0]).ln())
+            .collect()
+    }
+
+    /// Bars per year, measured from the time span of the series so that
+    /// daily bars count trading days only.
+    pub fn periods_per_year(&self) -> Option<f64> {
+        let (first, last) = (self.time.first()?, self.time.last()?);
+        let years = (*last - *first).num_seconds() as f64 / (365.25 * 86_400.0);
+        (years > 0.0).then(|| (self.time.len() - 1) as f64 / years)
+    }
+
+    /// Sample standard deviation of log returns, annualized with
+    /// [`Quotes::periods_per_year`]. `None` with fewer than two returns.
+    pub fn annualized_volatility(&self) -> Option<f64> {
+        let returns = self.log_returns();
+        if returns.len() < 2 {
+            return None;
+        }
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let var = returns.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        Some(var.sqrt() * self.periods_per_year()?.sqrt())
+    }
+
+    /// Largest fall from a running peak of the close, as a positive fraction.
+    pub fn max_drawdown(&self) -> f64 {
+        let mut peak = f64::MIN;
+        let mut max = 0.0f64;
+        for &close in &self.close {
+            peak = peak.max(close);
+            if peak > 0.0 {
+                max = max.max((peak - close) / peak);
+            }
+        }
+        max
+    }
+
+    /// Annualized mean log return over `rf`, the yearly risk free rate,
+    /// divided by [`Quotes::annualized_volatility`].
+    pub fn sharpe(&self, rf: f64) -> Option<f64> {
+        let returns = self.log_returns();
+        let vol = self.annualized_volatility().filter(|x| *x > 0.0)?;
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        Some((mean * self.periods_per_year()? - rf) / vol)
     }
 }
 
@@ -269,7 +599,126 @@ impl Product {
 
 #[cfg(test)]
 mod test {
-    use crate::{client::Client, util::Period};
+    use super::*;
+
+    #[test]
+    fn return_stats() {
+        let start = chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 1, 1, 0, 0, 0).unwrap();
+        let close = vec![100.0, 110.0, 99.0, 108.9, 120.0];
+        let quotes = Quotes {
+            time: (0..close.len() as i64)
+                .map(|i| start + chrono::Duration::days(i * 73))
+                .collect(),
+            close,
+            ..Default::default()
+        };
+        let returns = quotes.log_returns();
+        assert_eq!(returns.len(), 4);
+        assert!((returns[0] - 1.1f64.ln()).abs() < 1e-12);
+        // Four bars over 292 days.
+        assert!((quotes.periods_per_year().unwrap() - 4.0 * 365.25 / 292.0).abs() < 1e-9);
+        assert!((quotes.max_drawdown() - 0.1).abs() < 1e-12);
+        let vol = quotes.annualized_volatility().unwrap();
+        let sharpe = quotes.sharpe(0.0).unwrap();
+        let growth = (120.0f64 / 100.0).ln() / 4.0 * quotes.periods_per_year().unwrap();
+        assert!((sharpe - growth / vol).abs() < 1e-9);
+        assert!(Quotes::default().annualized_volatility().is_none());
+    }
+
+    #[test]
+    fn date_lookup() {
+        let start = chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 1, 1, 0, 0, 0).unwrap();
+        let day = |i: i64| start + chrono::Duration::days(i);
+        let quotes = Quotes {
+            id: "1".into(),
+            open: vec![1.0, 2.0, 3.0, 4.0],
+            high: vec![1.0, 2.0, 3.0, 4.0],
+            low: vec![1.0, 2.0, 3.0, 4.0],
+            close: vec![1.0, 2.0, 3.0, 4.0],
+            volume: Some(vec![10.0, 20.0, 30.0, 40.0]),
+            time: vec![day(0), day(1), day(3), day(4)],
+        };
+        assert_eq!(quotes.close_at(day(-1)), None);
+        assert_eq!(quotes.close_at(day(2)), Some(2.0));
+        assert_eq!(quotes.at(day(3)).unwrap().close, 3.0);
+        assert_eq!(quotes.close_at(day(9)), Some(4.0));
+
+        let xs = quotes.between(day(1), day(4));
+        assert_eq!(xs.close, vec![2.0, 3.0]);
+        assert_eq!(xs.volume, Some(vec![20.0, 30.0]));
+        assert!(quotes.between(day(4), day(1)).is_empty());
+        assert_eq!(quotes.slice(2..).time, vec![day(3), day(4)]);
+        assert_eq!(quotes.slice(..=0).close, vec![1.0]);
+        assert!(quotes.slice(3..10).len() == 1);
+    }
+
+    #[test]
+    fn merge_prefers_fresh_bars() {
+        let start = chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 1, 1, 0, 0, 0).unwrap();
+        let quotes = |days: &[i64], close: &[f64]| Quotes {
+            id: "1".into(),
+            open: close.to_vec(),
+            high: close.to_vec(),
+            low: close.to_vec(),
+            close: close.to_vec(),
+            volume: Some(vec![1.0; close.len()]),
+            time: days
+                .iter()
+                .map(|d| start + chrono::Duration::days(*d))
+                .collect(),
+        };
+        let mut xs = quotes(&[0, 1, 2], &[1.0, 2.0, 3.0]);
+        xs.merge(quotes(&[2, 3], &[3.5, 4.0])).unwrap();
+        assert_eq!(xs.close, vec![1.0, 2.0, 3.5, 4.0]);
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs.volume.as_ref().map(Vec::len), Some(4));
+
+        let weekly = quotes(&[7, 14], &[5.0, 6.0]);
+        assert!(xs.clone().merge(weekly).is_err());
+        let other = Quotes {
+            id: "2".into(),
+            ..quotes(&[5], &[1.0])
+        };
+        assert!(xs.merge(other).is_err());
+
+        let mut empty = Quotes::default();
+        empty.merge(xs.clone()).unwrap();
+        assert_eq!(empty.close, xs.close);
+        assert_eq!(empty.id, "1");
+    }
+
+    #[test]
+    fn closed_bars_and_tail() {
+        let today = chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 3, 15, 0, 0, 0).unwrap();
+        let day = |i: i64| today + chrono::Duration::days(i);
+        let quotes = Quotes {
+            close: vec![1.0; 4],
+            time: vec![day(-3), day(-2), day(-1), day(0)],
+            ..Default::default()
+        };
+        assert_eq!(closed_len(&quotes, Period::P1D, today), 3);
+        assert_eq!(closed_len(&quotes, Period::P1W, today), 0);
+
+        let now = today + chrono::Duration::hours(12);
+        assert_eq!(tail_period(now, day(-1)), Some(Period::P1W));
+        assert_eq!(tail_period(now, day(0)), Some(Period::P1D));
+        assert_eq!(tail_period(now, day(-40)), Some(Period::P3M));
+    }
+
+    #[test]
+    fn series_identifier() {
+        let id: SeriesIdentifier = "issueid:360015751".parse().unwrap();
+        assert_eq!(id, SeriesIdentifier::IssueId("360015751".into()));
+        assert_eq!(id.to_string(), "issueid:360015751");
+        assert_eq!(id.series(SeriesKind::Ohlc), "ohlc:issueid:360015751");
+        assert_eq!(
+            "VWDKEY:AAPL.NASDAQ".parse::<SeriesIdentifier>().unwrap(),
+            SeriesIdentifier::VwdKey("AAPL.NASDAQ".into())
+        );
+        assert!("issueid:".parse::<SeriesIdentifier>().is_err());
+        assert!("ohlc:issueid:1".parse::<SeriesIdentifier>().is_err());
+        assert!("12345".parse::<SeriesIdentifier>().is_err());
+    }
 
     #[tokio::test]
     async fn test_quotes() {
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::{Client, ClientError};
+
+use super::portfolio::{PositionDetails, PositionType};
+
+/// Relative difference in break-even price tolerated by [`Client::reconcile`].
+pub const DEFAULT_COST_TOLERANCE: f64 = 0.0001;
+
+/// A position as recorded outside DEGIRO.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpectedPosition {
+    pub product_id: String,
+    /// Signed, negative for shorts.
+    pub size: f64,
+    /// Cost basis per unit; not compared when missing.
+    pub break_even_price: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    /// Expected but not held.
+    Missing(ExpectedPosition),
+    /// Held but not expected.
+    Unexpected { product_id: String, size: f64 },
+    Size {
+        product_id: String,
+        expected: f64,
+        actual: f64,
+    },
+    CostBasis {
+        product_id: String,
+        expected: f64,
+        actual: f64,
+    },
+}
+
+impl Mismatch {
+    pub fn product_id(&self) -> &str {
+        match self {
+            Mismatch::Missing(x) => &x.product_id,
+            Mismatch::Unexpected { product_id, .. }
+            | Mismatch::Size { product_id, .. }
+            | Mismatch::CostBasis { product_id, .. } => product_id,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReconcileReport {
+    /// Products found on both sides without differences.
+    pub matched: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ReconcileReport {
+    /// Compares open product positions of `actual` with `expected`;
+    /// `cost_tolerance` is the relative break-even difference still accepted.
+    pub fn new<'a>(
+        expected: &[ExpectedPosition],
+        actual: impl IntoIterator<Item = &'a PositionDetails>,
+        cost_tolerance: f64,
+    ) -> Self {
+        let mut held = actual
+            .into_iter()
+            .filter(|p| p.position_type == PositionType::Product && p.is_open())
+            .map(|p| (p.id.as_str(), p))
+            .collect::<BTreeMap<_, _>>();
+        let mut report = Self::default();
+        for x in expected {
+            let Some(p) = held.remove(x.product_id.as_str()) else {
+                if x.size != 0.0 {
+                    report.mismatches.push(Mismatch::Missing(x.clone()));
+                }
+                continue;
+            };
+            let before = report.mismatches.len();
+            if (p.size - x.size).abs() > 1e-9 {
+                report.mismatches.push(Mismatch::Size {
+                    product_id: x.product_id.clone(),
+                    expected: x.size,
+                    actual: p.size,
+                });
+            }
+            if let Some(expected) = x.break_even_price {
+                let diff = (p.break_even_price - expected).abs();
+                if diff > expected.abs() * cost_tolerance {
+                    report.mismatches.push(Mismatch::CostBasis {
+                        product_id: x.product_id.clone(),
+                        expected,
+                        actual: p.break_even_price,
+                    });
+                }
+            }
+            if report.mismatches.len() == before {
+                report.matched += 1;
+            }
+        }
+        report
+            .mismatches
+            .extend(held.into_values().map(|p| Mismatch::Unexpected {
+                product_id: p.id.clone(),
+                size: p.size,
+            }));
+        report
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl Client {
+    /// Checks external book-keeping against the live portfolio.
+    pub async fn reconcile(
+        &self,
+        expected: &[ExpectedPosition],
+    ) -> Result<ReconcileReport, ClientError> {
+        let portfolio = self.portfolio().await?;
+        Ok(ReconcileReport::new(
+            expected,
+            portfolio.iter().map(|p| &p.inner),
+            DEFAULT_COST_TOLERANCE,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_mismatches() {
+        let held = |id: &str, size: f64, bep: f64| PositionDetails {
+            id: id.to_string(),
+            position_type: PositionType::Product,
+            size,
+            break_even_price: bep,
+            ..Default::default()
+        };
+        let actual = [
+            held("1", 10.0, 100.0),
+            held("2", 5.0, 50.0),
+            held("3", 7.0, 20.0),
+            held("4", 0.0, 0.0),
+        ];
+        let expected = |id: &str, size: f64, bep: Option<f64>| ExpectedPosition {
+            product_id: id.to_string(),
+            size,
+            break_even_price: bep,
+        };
+        let report = ReconcileReport::new(
+            &[
+                expected("1", 10.0, Some(100.005)),
+                expected("2", 6.0, Some(55.0)),
+                expected("5", 1.0, None),
+            ],
+            &actual,
+            DEFAULT_COST_TOLERANCE,
+        );
+        assert_eq!(report.matched, 1);
+        assert!(!report.is_clean());
+        let ids = report
+            .mismatches
+            .iter()
+            .map(Mismatch::product_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["2", "2", "5", "3"]);
+        assert!(matches!(
+            report.mismatches[1],
+            Mismatch::CostBasis { actual, .. } if actual == 50.0
+        ));
+    }
+}
@@ -1,15 +1,26 @@
 use chrono::NaiveDate;
 use derivative::Derivative;
+use futures::{future, Stream, StreamExt, TryStreamExt};
 use reqwest::{header, Url};
 use serde::Deserialize;
 use serde_json::Value;
 
 use crate::{
-    client::{Client, ClientError, ClientStatus},
-    util::{AllowedOrderTypes, OrderTimeTypes, ProductCategory},
+    client::{Client, ClientError},
+    http::{paginate, Endpoint, Page},
+    util::{AllowedOrderTypes, OrderTimeTypes, ProductCategory, ProductType},
 };
 
-use super::product::Product;
+use super::{
+    dictionary::{CountryId, IndexId, StockMarketId},
+    product::{Product, Products},
+};
+
+/// Matches [`Client::search_products`] returns at most.
+pub const SEARCH_PRODUCTS_LIMIT: u32 = 100;
+
+/// Page size used by [`QueryBuilder::products`].
+const SEARCH_PAGE_SIZE: u32 = 50;
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -18,6 +29,10 @@ pub struct QueryBuilder {
     symbol: Option<String>,
     limit: u32,
     offset: u32,
+    country: Option<CountryId>,
+    index: Option<IndexId>,
+    stock_market: Option<StockMarketId>,
+    product_type: Option<ProductType>,
     client: Client,
 }
 
@@ -76,68 +91,143 @@ impl QueryBuilder {
         self.offset = offset;
         self
     }
+    /// Limits the search to stocks of a country; ids come from
+    /// [`Client::dictionary`]. Any of the stock filters switches to the
+    /// stock search endpoint.
+    pub fn country(mut self, country: CountryId) -> Self {
+        self.country = Some(country);
+        self
+    }
+    pub fn index(mut self, index: IndexId) -> Self {
+        self.index = Some(index);
+        self
+    }
+    pub fn stock_market(mut self, stock_market: StockMarketId) -> Self {
+        self.stock_market = Some(stock_market);
+        self
+    }
+    pub fn product_type(mut self, product_type: ProductType) -> Self {
+        self.product_type = Some(product_type);
+        self
+    }
 
     pub async fn send(&self) -> Result<Vec<QueryProduct>, ClientError> {
-        if self.client.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
+        let (products, _) = self.fetch(self.offset, self.limit).await?;
+        Ok(products
+            .into_iter()
+            .filter(|p| self.matches_symbol(p))
+            .collect())
+    }
+
+    /// Every match from `offset` on, fetched `page_size` at a time while the
+    /// stream is polled; `limit` is ignored. Cap broad searches with
+    /// [`futures::StreamExt::take`].
+    pub fn stream(
+        &self,
+        page_size: u32,
+    ) -> impl Stream<Item = Result<QueryProduct, ClientError>> + '_ {
+        paginate(page_size, move |offset, limit| async move {
+            // No `products` key past the last match.
+            let (products, total) = match self.fetch(self.offset + offset, limit).await {
+                Err(ClientError::ProductSearchError) if offset > 0 => (Vec::new(), None),
+                res => res?,
+            };
+            Ok(Page::new(
+                products,
+                total.map(|x| x.saturating_sub(self.offset as usize)),
+            ))
+        })
+        .try_filter(move |p| future::ready(self.matches_symbol(p)))
+    }
+
+    /// Full details of up to `limit` matches from `offset` on, paging
+    /// through the results as needed.
+    pub async fn products(&self) -> Result<Products, ClientError> {
+        let matches = self
+            .stream(SEARCH_PAGE_SIZE.min(self.limit.max(1)))
+            .take(self.limit as usize)
+            .try_collect::<Vec<_>>()
+            .await?;
+        if matches.is_empty() {
+            return Ok(Products(Default::default()));
         }
+        let ids = matches.into_iter().map(|p| p.inner.id).collect::<Vec<_>>();
+        self.client.products(&ids).await
+    }
+
+    fn matches_symbol(&self, product: &QueryProduct) -> bool {
+        self.symbol
+            .as_ref()
+            .is_none_or(|symbol| product.inner.symbol == *symbol)
+    }
+
+    /// One unfiltered page of results and the total when reported.
+    async fn fetch(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<(Vec<QueryProduct>, Option<usize>), ClientError> {
+        self.client.require(Endpoint::Search)?;
         let req = {
-            let inner = self.client.inner.try_lock().unwrap();
+            let inner = self.client.inner.lock().unwrap();
             let base_url = &inner.account_config.product_search_url;
-            let url = Url::parse(base_url)
-                .unwrap()
-                .join("v5/products/lookup")
-                .unwrap();
+            let mut query = vec![("limit", limit.to_string()), ("offset", offset.to_string())];
+            if let Some(product_type) = self.product_type {
+                query.push(("productTypeId", product_type.id().to_string()));
+            }
+            let filters = [
+                ("stockCountryId", self.country.map(|x| x.0)),
+                ("indexId", self.index.map(|x| x.0)),
+                ("exchangeId", self.stock_market.map(|x| x.0)),
+            ]
+            .into_iter()
+            .filter_map(|(k, v)| Some((k, v?.to_string())))
+            .collect::<Vec<_>>();
+            let path = if filters.is_empty() {
+                query.push(("searchText", self.query.clone()));
+                "v5/products/lookup"
+            } else {
+                if !self.query.is_empty() {
+                    query.push(("searchText", self.query.clone()));
+                }
+                query.extend(filters);
+                query.push(("requireTotal", "true".to_string()));
+                "v5/stocks"
+            };
+            let url = Url::parse(base_url).unwrap().join(path).unwrap();
 
             inner
                 .http_client
                 .get(url)
-                .query(&[
-                    ("intAccount", &inner.int_account.to_string()),
-                    ("sessionId", &inner.session_id),
-                    ("searchText", &self.query),
-                    ("limit", &self.limit.to_string()),
-                    ("offset", &self.offset.to_string()),
-                ])
+                .query(&query)
                 .header(header::REFERER, &inner.referer)
         };
 
-        let res = req.send().await.unwrap();
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut body = res.json::<Value>().await.unwrap();
-                if let Some(products) = body.get_mut("products") {
-                    let products_inner =
-                        serde_json::from_value::<Vec<QueryProductDetails>>(products.take())
-                            .unwrap();
-                    let mut products = Vec::new();
-                    for p in products_inner {
-                        products.push(QueryProduct {
-                            inner: p,
-                            client: self.client.clone(),
-                        })
-                    }
-                    if let Some(symbol) = &self.symbol {
-                        Ok(products
-                            .into_iter()
-                            .filter(|p| p.inner.symbol == symbol.to_uppercase())
-                            .collect())
-                    } else {
-                        Ok(products)
-                    }
-                } else {
-                    Err(ClientError::ProductSearchError)
+        let res = self.client.send(Endpoint::Search, req).await?;
+        let mut body = res.json::<Value>().await?;
+        let total = body
+            .get("total")
+            .and_then(Value::as_u64)
+            .map(|x| x as usize);
+        if let Some(products) = body.get_mut("products") {
+            let products_inner =
+                serde_json::from_value::<Vec<QueryProductDetails>>(products.take())?;
+            {
+                let mut inner = self.client.inner.lock().unwrap();
+                for p in &products_inner {
+                    inner.product_index.insert(p);
                 }
             }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => {
-                    self.client.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
-                }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
+            let mut products = Vec::new();
+            for p in products_inner {
+                products.push(QueryProduct {
+                    inner: p,
+                    client: self.client.clone(),
+                })
+            }
+            Ok((products, total))
+        } else {
+            Err(ClientError::ProductSearchError)
         }
     }
 }
@@ -149,9 +239,24 @@ impl Client {
             symbol: None,
             limit: 1,
             offset: 0,
+            country: None,
+            index: None,
+            stock_market: None,
+            product_type: None,
             client: self.clone(),
         }
     }
+
+    /// Products matching `query` by name, ticker or ISIN, up to
+    /// [`SEARCH_PRODUCTS_LIMIT`]. Use [`Client::search`] and
+    /// [`QueryBuilder::products`] for filters.
+    pub async fn search_products(&self, query: &str) -> Result<Products, ClientError> {
+        self.search()
+            .query(query)
+            .limit(SEARCH_PRODUCTS_LIMIT)
+            .products()
+            .await
+    }
 }
 
 impl QueryProduct {
@@ -162,7 +267,9 @@ impl QueryProduct {
 
 #[cfg(test)]
 mod test {
-    use crate::client::Client;
+    use futures::{StreamExt, TryStreamExt};
+
+    use crate::{client::Client, util::ProductType};
 
     #[tokio::test]
     async fn search() {
@@ -179,4 +286,37 @@ mod test {
             .unwrap();
         dbg!(products.first().unwrap());
     }
+
+    #[tokio::test]
+    async fn search_products() {
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let products = client.search_products("ishares core").await.unwrap();
+        assert!(!products.is_empty());
+        let etfs = client
+            .search()
+            .query("ishares core")
+            .product_type(ProductType::Etf)
+            .limit(5)
+            .products()
+            .await
+            .unwrap();
+        assert!(etfs.len() <= 5);
+    }
+
+    #[tokio::test]
+    async fn search_stream() {
+        let client = Client::new_from_env();
+        client.login().await.unwrap();
+        client.account_config().await.unwrap();
+        let query = client.search().query("ishares");
+        let products = query
+            .stream(10)
+            .take(25)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(products.len(), 25);
+    }
 }
@@ -2,9 +2,8 @@ use chrono::{DateTime, FixedOffset, NaiveDate};
 use reqwest::{header, Url};
 use serde::{Deserialize, Serialize};
 
-use std::collections::HashMap;
-
-use crate::client::{Client, ClientError, ClientStatus};
+use crate::client::{Client, ClientError};
+use crate::http::{read_json, Data, Endpoint};
 use crate::util::TransactionType;
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -118,9 +117,7 @@ impl Client {
         from_date: impl Into<NaiveDate> + Send,
         to_date: impl Into<NaiveDate> + Send,
     ) -> Result<Transactions, ClientError> {
-        if self.inner.lock().unwrap().status != ClientStatus::Authorized {
-            return Err(ClientError::Unauthorized);
-        }
+        self.require(Endpoint::Transactions)?;
         let req = {
             let inner = self.inner.lock().unwrap();
             let base_url = &inner.account_config.reporting_url;
@@ -131,46 +128,22 @@ impl Client {
                 .http_client
                 .get(url)
                 .query(&[
-                    ("sessionId", &inner.session_id),
-                    ("intAccount", &format!("{}", inner.int_account)),
                     ("fromDate", &from_date.into().format("%d/%m/%Y").to_string()),
                     ("toDate", &to_date.into().format("%d/%m/%Y").to_string()),
                     ("groupTransactionsByOrder", &"1".to_string()),
                 ])
                 .header(header::REFERER, &inner.referer)
         };
-        let rate_limiter = {
-            let inner = self.inner.lock().unwrap();
-            inner.rate_limiter.clone()
-        };
-        rate_limiter.acquire_one().await;
 
-        let res = req.send().await?;
+        let res = self.send(Endpoint::Transactions, req).await?;
 
-        match res.error_for_status() {
-            Ok(res) => {
-                let mut m = res
-                    .json::<HashMap<String, Vec<TransactionDetails>>>()
-                    .await
-                    .unwrap();
-                let data = m.remove("data").unwrap();
-                let xs: Vec<_> = {
-                    data.into_iter()
-                        .map(|x| Transaction::new(x, self.clone()))
-                        .collect()
-                };
-                Ok(Transactions::new(xs))
-            }
-            Err(err) => match err.status().unwrap().as_u16() {
-                401 => {
-                    self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
-                    Err(ClientError::Unauthorized)
-                }
-                _ => Err(ClientError::UnexpectedError {
-                    source: Box::new(err),
-                }),
-            },
-        }
+        let Data { data } = read_json::<Data<Vec<TransactionDetails>>>(res).await?;
+        let xs: Vec<_> = {
+            data.into_iter()
+                .map(|x| Transaction::new(x, self.clone()))
+                .collect()
+        };
+        Ok(Transactions::new(xs))
     }
 }
 
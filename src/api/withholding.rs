@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{
+    client::{Client, ClientError},
+    export::{Cell, Csv, Export},
+    money::{Currency, Money, MoneyBag},
+};
+
+use super::account::{CashMovement, CashMovementType};
+
+/// Country used for dividends whose product is unknown.
+pub const UNKNOWN_COUNTRY: &str = "XX";
+
+/// Treaty withholding rates, as fractions, keyed by the source country's
+/// ISIN prefix (`US`, `DE`, ...).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreatyRates {
+    rates: HashMap<String, f64>,
+    default: Option<f64>,
+}
+
+impl TreatyRates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, country: &str, rate: f64) -> Self {
+        self.rates.insert(country.to_uppercase(), rate);
+        self
+    }
+
+    /// Rate for countries without an explicit entry; without one nothing is
+    /// reclaimable from them.
+    pub fn default_rate(mut self, rate: f64) -> Self {
+        self.default = Some(rate);
+        self
+    }
+
+    pub fn rate(&self, country: &str) -> Option<f64> {
+        self.rates.get(country).copied().or(self.default)
+    }
+}
+
+/// Dividends and tax withheld from one country in one currency.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WhtLine {
+    pub country: String,
+    pub currency: String,
+    pub gross: f64,
+    /// Positive amount of tax withheld at source.
+    pub withheld: f64,
+    pub treaty_rate: Option<f64>,
+    /// Withheld above the treaty rate, never negative.
+    pub reclaimable: f64,
+}
+
+impl WhtLine {
+    /// Rate actually withheld, as a fraction of the gross dividend.
+    pub fn withheld_rate(&self) -> Option<f64> {
+        (self.gross > 0.0).then(|| self.withheld / self.gross)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct WhtReport {
+    pub lines: Vec<WhtLine>,
+}
+
+impl WhtReport {
+    /// Groups dividend and dividend tax movements by the country of their
+    /// product; `countries` maps product ids to ISIN country prefixes.
+    pub fn new<'a>(
+        movements: impl IntoIterator<Item = &'a CashMovement>,
+        countries: &HashMap<i32, String>,
+        rates: &TreatyRates,
+    ) -> Self {
+        let mut groups: BTreeMap<(String, String), (f64, f64)> = BTreeMap::new();
+        for m in movements {
+            let is_dividend = matches!(m.movement_type, CashMovementType::Dividend(_));
+            let is_tax = matches!(m.movement_type, CashMovementType::DividentFee(_));
+            if !is_dividend && !is_tax {
+                continue;
+            }
+            let country = m
+                .product_id
+                .and_then(|id| countries.get(&id))
+                .map_or(UNKNOWN_COUNTRY, String::as_str);
+            let (gross, withheld) = groups
+                .entry((country.to_string(), m.currency.clone()))
+                .or_default();
+            if is_dividend {
+                *gross += m.change;
+            } else {
+                *withheld -= m.change;
+            }
+        }
+        let lines = groups
+            .into_iter()
+            .map(|((country, currency), (gross, withheld))| {
+                let treaty_rate = rates.rate(&country);
+                let reclaimable =
+                    treaty_rate.map_or(0.0, |rate| (withheld - gross * rate).max(0.0));
+                WhtLine {
+                    country,
+                    currency,
+                    gross,
+                    withheld,
+                    treaty_rate,
+                    reclaimable,
+                }
+            })
+            .collect();
+        Self { lines }
+    }
+
+    pub fn total_reclaimable(&self) -> MoneyBag {
+        self.lines
+            .iter()
+            .filter_map(|x| {
+                let currency = x.currency.parse::<Currency>().ok()?;
+                Some(Money::new(currency, x.reclaimable))
+            })
+            .collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        Csv.to_string(self)
+    }
+}
+
+impl Export for WhtReport {
+    fn header(&self) -> &'static [&'static str] {
+        &[
+            "country",
+            "currency",
+            "gross",
+            "withheld",
+            "withheld_rate",
+            "treaty_rate",
+            "reclaimable",
+        ]
+    }
+
+    fn rows(&self) -> Vec<Vec<Cell>> {
+        let rate = |r: Option<f64>| Cell::value(r.map(|r| format!("{r:.4}")).unwrap_or_default());
+        self.lines
+            .iter()
+            .map(|x| {
+                vec![
+                    Cell::value(&x.country),
+                    Cell::value(&x.currency),
+                    Cell::value(format!("{:.2}", x.gross)),
+                    Cell::value(format!("{:.2}", x.withheld)),
+                    rate(x.withheld_rate()),
+                    rate(x.treaty_rate),
+                    Cell::value(format!("{:.2}", x.reclaimable)),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Client {
+    /// Withholding tax reclaim report for dividends booked between `from` and `to`.
+    pub async fn wht_report(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        rates: &TreatyRates,
+    ) -> Result<WhtReport, ClientError> {
+        let state = self.account_state(&from, &to).await?;
+        let mut ids = state
+            .iter()
+            .filter(|m| {
+                matches!(
+                    m.movement_type,
+                    CashMovementType::Dividend(_) | CashMovementType::DividentFee(_)
+                )
+            })
+            .filter_map(|m| m.product_id.map(|id| id.to_string()))
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        let countries = if ids.is_empty() {
+            HashMap::new()
+        } else {
+            self.products(&ids)
+                .await?
+                .iter()
+                .filter_map(|(id, product)| {
+                    let country = product.inner.isin.get(..2)?;
+                    Some((id.parse().ok()?, country.to_uppercase()))
+                })
+                .collect()
+        };
+        Ok(WhtReport::new(&state, &countries, rates))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::account::fixtures::movement;
+
+    #[test]
+    fn reclaimable_above_treaty_rate() {
+        let movement = |id, product_id, description, change| {
+            movement(id, "2024-02-10", description, change).with_product(product_id)
+        };
+        let movements = vec![
+            movement(1, 1, "Dywidenda", 100.0),
+            movement(2, 1, "Podatek Dywidendowy", -27.0),
+            movement(3, 2, "Dywidenda", 50.0),
+            movement(4, 2, "Podatek Dywidendowy", -7.5),
+            movement(5, 3, "Depozyt", 1000.0),
+        ];
+        let countries = HashMap::from([(1, "DE".to_string()), (2, "US".to_string())]);
+        let rates = TreatyRates::new().with("DE", 0.15).with("US", 0.15);
+
+        let report = WhtReport::new(&movements, &countries, &rates);
+        assert_eq!(report.lines.len(), 2);
+        let de = &report.lines[0];
+        assert_eq!(de.country, "DE");
+        assert!((de.reclaimable - 12.0).abs() < 1e-9);
+        assert_eq!(report.lines[1].reclaimable, 0.0);
+        assert!(report
+            .to_csv()
+            .contains("DE,EUR,100.00,27.00,0.2700,0.1500,12.00"));
+    }
+}
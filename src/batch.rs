@@ -0,0 +1,170 @@
+use std::{future::Future, time::Duration};
+
+use futures::{stream, StreamExt};
+
+use crate::client::{Client, ClientError};
+
+/// How transient failures are retried: `max_retries` extra attempts, waiting
+/// `base_backoff` doubled after every failure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_backoff: Duration,
+    /// Log in again and replay a GET that hit an expired session, once.
+    pub replay_unauthorized: bool,
+    /// Consecutive failed logins after which logging in pauses for
+    /// `login_cooldown`, well before DEGIRO locks the account.
+    pub max_login_failures: u32,
+    pub login_cooldown: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_backoff: Duration::from_millis(500),
+            replay_unauthorized: true,
+            max_login_failures: 3,
+            login_cooldown: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retries without waiting, so failure paths run instantly in tests.
+    pub fn immediate(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            base_backoff: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    pub fn replay_unauthorized(mut self, replay: bool) -> Self {
+        self.replay_unauthorized = replay;
+        self
+    }
+
+    pub fn login_lockout(mut self, max_failures: u32, cooldown: Duration) -> Self {
+        self.max_login_failures = max_failures;
+        self.login_cooldown = cooldown;
+        self
+    }
+
+    pub fn no_retries() -> Self {
+        Self::immediate(0)
+    }
+
+    /// Wait before retry number `attempt`, counting from zero.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.base_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+    }
+}
+
+impl Client {
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        self.inner.lock().unwrap().retry_policy = policy;
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.inner.lock().unwrap().retry_policy
+    }
+
+    /// Runs `f` for every item with at most `concurrency` calls in flight.
+    ///
+    /// Every API call still goes through the shared rate limiter, so a high
+    /// `concurrency` only queues requests instead of tripping DEGIRO's
+    /// throttling. Transient failures (timeouts, connection errors, 429 and
+    /// 5xx) are retried with exponential backoff, see [`RetryPolicy`].
+    /// Results keep input order.
+    pub async fn batched<T, R, F, Fut>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        concurrency: usize,
+        f: F,
+    ) -> Vec<Result<R, ClientError>>
+    where
+        T: Clone,
+        F: Fn(Client, T) -> Fut,
+        Fut: Future<Output = Result<R, ClientError>>,
+    {
+        let f = &f;
+        let policy = self.retry_policy();
+        stream::iter(items)
+            .map(|item| async move {
+                let mut attempt = 0;
+                loop {
+                    match f(self.clone(), item.clone()).await {
+                        Err(err) if err.is_transient() && attempt < policy.max_retries => {
+                            let backoff = policy
+                                .backoff(attempt)
+                                .max(err.retry_after().unwrap_or_default());
+                            if !backoff.is_zero() {
+                                tokio::time::sleep(backoff).await;
+                            }
+                            attempt += 1;
+                        }
+                        res => return res,
+                    }
+                }
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::RetryPolicy;
+    use crate::client::{Client, ClientError};
+
+    #[tokio::test]
+    async fn batched_keeps_order() {
+        let client = Client::new("", "", reqwest::Client::new(), Arc::default());
+        let xs = client
+            .batched(0..10, 3, |_, x| async move { Ok::<_, ClientError>(x * 2) })
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(xs, (0..10).map(|x| x * 2).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn immediate_retries_transient_failures() {
+        let client = Client::new("", "", reqwest::Client::new(), Arc::default());
+        client.set_retry_policy(RetryPolicy::immediate(2));
+        let err = reqwest::get("http://127.0.0.1:1").await.unwrap_err();
+        assert!(ClientError::from(err).is_transient());
+
+        let calls = AtomicU32::new(0);
+        let calls = &calls;
+        let res = client
+            .batched([()], 1, |_, _| async move {
+                calls.fetch_add(1, Ordering::Relaxed);
+                let err = reqwest::get("http://127.0.0.1:1").await.unwrap_err();
+                Err::<(), _>(ClientError::from(err))
+            })
+            .await;
+        assert!(res[0].is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(RetryPolicy::no_retries().backoff(5), Duration::ZERO);
+    }
+}
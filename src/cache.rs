@@ -0,0 +1,429 @@
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::{Client, ClientError};
+
+/// How long company data stays fresh unless configured otherwise.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::days(7);
+
+/// Products asked for in one request by [`Client::warm_cache`].
+const WARM_UP_CHUNK: usize = 50;
+
+/// What [`Client::warm_cache`] loaded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WarmUpReport {
+    /// Products fetched, vwd ids included.
+    pub products: usize,
+    /// Products that were in memory already.
+    pub already_cached: usize,
+    /// Company profiles stored in the cache backend.
+    pub profiles: usize,
+    /// Ids and isins that failed to load.
+    pub failed: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub stored_at: DateTime<Utc>,
+    pub value: Value,
+}
+
+impl CacheEntry {
+    /// Entry stored at `stored_at`, usually [`Client::now`].
+    pub fn new(value: Value, stored_at: DateTime<Utc>) -> Self {
+        Self { stored_at, value }
+    }
+
+    pub fn is_fresh(&self, ttl: Duration, now: DateTime<Utc>) -> bool {
+        now - self.stored_at < ttl
+    }
+}
+
+/// Storage for responses that rarely change, such as company data.
+///
+/// Backends are best effort: a failing read is a miss and a failing write is
+/// dropped, the data is always refetched from the API.
+pub trait CacheBackend: Send + Sync {
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+    fn put(&self, key: &str, entry: &CacheEntry);
+    fn remove(&self, key: &str);
+    fn clear(&self);
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryCache(Mutex<HashMap<String, CacheEntry>>);
+
+impl CacheBackend for MemoryCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.0.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, entry: &CacheEntry) {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), entry.clone());
+    }
+
+    fn remove(&self, key: &str) {
+        self.0.lock().unwrap().remove(key);
+    }
+
+    fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// One json file per key in a directory, so data survives between runs.
+#[derive(Clone, Debug)]
+pub struct FileCache {
+    dir: PathBuf,
+}
+
+impl FileCache {
+    pub fn new(dir: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let name = key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        self.dir.join(format!("{name}.json"))
+    }
+}
+
+impl CacheBackend for FileCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn put(&self, key: &str, entry: &CacheEntry) {
+        if let Ok(bytes) = serde_json::to_vec(entry) {
+            // Write then rename so a crash never leaves a half written entry.
+            let path = self.path(key);
+            let tmp = path.with_extension("json.tmp");
+            if fs::write(&tmp, bytes).is_ok() {
+                let _ = fs::rename(tmp, path);
+            }
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+    }
+
+    fn clear(&self) {
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    let _ = fs::remove_file(path);
+                }
+            }
+        }
+    }
+}
+
+/// Decides whether a fetch is worth attempting, remembering what failed.
+///
+/// Lets batch jobs skip instruments that have no data instead of asking for
+/// them again on every run.
+pub trait FetchPolicy: Send + Sync {
+    fn should_fetch(&self, key: &str, now: DateTime<Utc>) -> bool;
+    fn record_success(&self, key: &str);
+    fn record_failure(&self, key: &str, err: &ClientError, now: DateTime<Utc>);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Failure {
+    pub at: DateTime<Utc>,
+    pub count: u32,
+}
+
+/// Negative cache: after a permanent failure the key is skipped for `ttl`,
+/// doubling with each consecutive failure up to `max_ttl`.
+///
+/// Transient errors and lost sessions are not remembered, they say nothing
+/// about the data itself.
+#[derive(Debug)]
+pub struct NegativeCache {
+    ttl: Duration,
+    max_ttl: Duration,
+    failures: Mutex<HashMap<String, Failure>>,
+}
+
+impl Default for NegativeCache {
+    fn default() -> Self {
+        Self::new(Duration::hours(1), Duration::days(7))
+    }
+}
+
+impl NegativeCache {
+    pub fn new(ttl: Duration, max_ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_ttl,
+            failures: Default::default(),
+        }
+    }
+
+    pub fn failure(&self, key: &str) -> Option<Failure> {
+        self.failures.lock().unwrap().get(key).copied()
+    }
+
+    /// How long `failure` keeps its key skipped.
+    pub fn backoff(&self, failure: &Failure) -> Duration {
+        let factor = 2i32.saturating_pow(failure.count.saturating_sub(1).min(30));
+        self.ttl
+            .checked_mul(factor)
+            .map_or(self.max_ttl, |ttl| ttl.min(self.max_ttl))
+    }
+
+    pub fn clear(&self) {
+        self.failures.lock().unwrap().clear();
+    }
+}
+
+impl FetchPolicy for NegativeCache {
+    fn should_fetch(&self, key: &str, now: DateTime<Utc>) -> bool {
+        match self.failure(key) {
+            Some(failure) => now - failure.at >= self.backoff(&failure),
+            None => true,
+        }
+    }
+
+    fn record_success(&self, key: &str) {
+        self.failures.lock().unwrap().remove(key);
+    }
+
+    fn record_failure(&self, key: &str, err: &ClientError, now: DateTime<Utc>) {
+        if err.is_transient() || matches!(err, ClientError::Unauthorized) {
+            return;
+        }
+        let mut failures = self.failures.lock().unwrap();
+        let failure = failures
+            .entry(key.to_string())
+            .or_insert(Failure { at: now, count: 0 });
+        failure.at = now;
+        failure.count += 1;
+    }
+}
+
+impl Client {
+    /// Caches company data in `backend`, keeping entries for `ttl`.
+    pub fn set_cache(&self, backend: Arc<dyn CacheBackend>, ttl: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.cache = Some(backend);
+        inner.cache_ttl = ttl;
+    }
+
+    pub fn cache(&self) -> Option<Arc<dyn CacheBackend>> {
+        self.inner.lock().unwrap().cache.clone()
+    }
+
+    /// Loads products, and so their vwd ids, in batches ahead of use. Company
+    /// profiles are fetched too when a cache backend is set; without one they
+    /// would be thrown away. Only `Unauthorized` fails the warm-up, anything
+    /// else ends up in [`WarmUpReport::failed`].
+    pub async fn warm_cache<T: AsRef<str>>(
+        &self,
+        ids: impl IntoIterator<Item = T>,
+    ) -> Result<WarmUpReport, ClientError> {
+        let mut report = WarmUpReport::default();
+        let mut missing = Vec::new();
+        for id in ids {
+            let id = id.as_ref();
+            if self.fresh_product(id).is_some() {
+                report.already_cached += 1;
+            } else if !missing.iter().any(|x| x == id) {
+                missing.push(id.to_string());
+            }
+        }
+
+        let chunks = missing
+            .chunks(WARM_UP_CHUNK)
+            .map(<[String]>::to_vec)
+            .collect::<Vec<_>>();
+        let results = self
+            .batched(chunks.clone(), 2, |client, chunk| async move {
+                client.products(chunk).await
+            })
+            .await;
+        let mut isins = Vec::new();
+        for (chunk, res) in chunks.into_iter().zip(results) {
+            match res {
+                Ok(products) => {
+                    for id in chunk {
+                        match products.get(&id) {
+                            Some(p) => {
+                                report.products += 1;
+                                isins.push(p.inner.isin.clone());
+                            }
+                            None => report.failed.push(id),
+                        }
+                    }
+                }
+                Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+                Err(_) => report.failed.extend(chunk),
+            }
+        }
+
+        if self.cache().is_some() {
+            isins.retain(|x| !x.is_empty());
+            isins.sort();
+            isins.dedup();
+            let results = self
+                .batched(isins.clone(), 4, |client, isin| async move {
+                    client.company_profile(isin).await
+                })
+                .await;
+            for (isin, res) in isins.into_iter().zip(results) {
+                match res {
+                    Ok(_) => report.profiles += 1,
+                    Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+                    Err(_) => report.failed.push(isin),
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Consults `policy` before company data and quote fetches.
+    pub fn set_fetch_policy(&self, policy: Arc<dyn FetchPolicy>) {
+        self.inner.lock().unwrap().fetch_policy = Some(policy);
+    }
+
+    /// Runs `fetch` unless the fetch policy says `key` recently failed.
+    pub(crate) async fn guarded<T, F>(&self, key: &str, fetch: F) -> Result<T, ClientError>
+    where
+        F: Future<Output = Result<T, ClientError>>,
+    {
+        let policy = self.inner.lock().unwrap().fetch_policy.clone();
+        let Some(policy) = policy else {
+            return fetch.await;
+        };
+        if !policy.should_fetch(key, self.now()) {
+            return Err(ClientError::Skipped(key.to_string()));
+        }
+        let res = fetch.await;
+        match &res {
+            Ok(_) => policy.record_success(key),
+            Err(err) => policy.record_failure(key, err, self.now()),
+        }
+        res
+    }
+
+    /// Returns a fresh cached value for `key` or runs `fetch` and stores its result.
+    pub(crate) async fn cached<F>(&self, key: &str, fetch: F) -> Result<Value, ClientError>
+    where
+        F: Future<Output = Result<Value, ClientError>>,
+    {
+        let (cache, ttl) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.cache.clone(), inner.cache_ttl)
+        };
+        let Some(cache) = cache else {
+            return self.guarded(key, fetch).await;
+        };
+        if let Some(entry) = cache.get(key) {
+            if entry.is_fresh(ttl, self.now()) {
+                return Ok(entry.value);
+            }
+        }
+        let value = self.guarded(key, fetch).await?;
+        cache.put(
+            key,
+            &CacheEntry {
+                stored_at: self.now(),
+                value: value.clone(),
+            },
+        );
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn file_cache_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("degiro-cache-{}", std::process::id()));
+        let cache = FileCache::new(&dir).unwrap();
+        let entry = CacheEntry::new(json!({ "employees": 10 }), Utc::now());
+
+        cache.put("company_profile/US0378331005", &entry);
+        assert_eq!(
+            cache.get("company_profile/US0378331005"),
+            Some(entry.clone())
+        );
+        assert!(cache.get("company_profile/other").is_none());
+
+        cache.remove("company_profile/US0378331005");
+        assert!(cache.get("company_profile/US0378331005").is_none());
+
+        cache.put("a", &entry);
+        cache.clear();
+        assert!(cache.get("a").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn negative_cache_backoff() {
+        let policy = NegativeCache::new(Duration::hours(1), Duration::hours(3));
+        let now = Utc::now();
+        let key = "company_ratios/XX0000000000";
+        assert!(policy.should_fetch(key, now));
+
+        policy.record_failure(key, &ClientError::NoData, now);
+        assert!(!policy.should_fetch(key, now + Duration::minutes(59)));
+        assert!(policy.should_fetch(key, now + Duration::hours(1)));
+
+        policy.record_failure(key, &ClientError::NoData, now);
+        policy.record_failure(key, &ClientError::NoData, now);
+        assert_eq!(
+            policy.backoff(&policy.failure(key).unwrap()),
+            Duration::hours(3)
+        );
+
+        policy.record_success(key);
+        assert!(policy.should_fetch(key, now));
+
+        policy.record_failure(key, &ClientError::Unauthorized, now);
+        assert!(policy.should_fetch(key, now));
+    }
+
+    #[test]
+    fn entry_freshness() {
+        let entry = CacheEntry::new(Value::Null, Utc::now());
+        let now = entry.stored_at;
+        assert!(entry.is_fresh(Duration::hours(1), now + Duration::minutes(59)));
+        assert!(!entry.is_fresh(Duration::hours(1), now + Duration::hours(1)));
+    }
+}
@@ -1,13 +1,36 @@
 use std::{
-    sync::{Arc, Mutex},
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::Duration,
 };
 
+use chrono::{DateTime, NaiveDate, Utc};
 use derivative::Derivative;
 use leaky_bucket::RateLimiter;
 use thiserror::Error;
-
-use crate::api::account::AccountConfig;
+use tokio::sync::broadcast;
+
+use crate::{
+    api::{
+        account::AccountConfig,
+        dictionary::Dictionary,
+        orders::OrderRejection,
+        product::{CachedProduct, DEFAULT_PRODUCT_TTL},
+    },
+    batch::RetryPolicy,
+    cache::{CacheBackend, FetchPolicy, DEFAULT_CACHE_TTL},
+    clock::{Clock, SystemClock},
+    events::ClientEvent,
+    http::{InFlight, ResponseError},
+    money::{Currency, MoneyError},
+    product_index::ProductIndex,
+    risk::{PreTradeRisk, RiskLimits},
+    safety::{KillSwitch, KillSwitchLimits, TripReason},
+    transport::{HttpTransport, Transport},
+};
 
 #[allow(dead_code)]
 #[derive(Clone, Debug, Derivative)]
@@ -86,9 +109,25 @@ pub enum ClientError {
     #[error("request error: {0}")]
     RequestError(#[from] reqwest::Error),
 
+    /// Any other error status, with headers and body for reporting.
+    #[error("http error: {0}")]
+    Http(ResponseError),
+
+    /// An HTML page came back where json was expected, usually a
+    /// maintenance or block page.
+    #[error("DEGIRO is in maintenance or blocked the request: {snippet}")]
+    MaintenanceOrBlocked { snippet: String },
+
+    /// The server answered 429; `retry_after` is its `Retry-After` hint.
+    #[error("rate limited, retry after {retry_after:?}")]
+    RateLimited { retry_after: Option<Duration> },
+
     #[error("serialization/deserialization error: {0}")]
     SerdeError(#[from] serde_json::Error),
 
+    #[error("money error: {0}")]
+    MoneyError(#[from] MoneyError),
+
     #[error("order not found: {0}")]
     OrderNotFoundError(String),
 
@@ -100,6 +139,71 @@ pub enum ClientError {
 
     #[error("DegiroError: {0}")]
     Descripted(String),
+
+    #[error("order rejected: {}", .0.message)]
+    OrderRejected(OrderRejection),
+
+    #[error("invalid position: {0}")]
+    InvalidPosition(String),
+
+    #[error("skipped after a recent failure: {0}")]
+    Skipped(String),
+
+    #[error("not authorized within {0:?}")]
+    AuthTimeout(Duration),
+
+    #[error("read-only mode: {0}")]
+    ReadOnly(TripReason),
+
+    #[error("risk limit breached: {0}")]
+    RiskLimitBreached(String),
+
+    #[error("too many failed logins, next attempt allowed at {retry_at}")]
+    TooManyLoginAttempts { retry_at: DateTime<Utc> },
+}
+
+impl ClientError {
+    /// Whether retrying the same request later has a reasonable chance to succeed.
+    pub fn is_transient(&self) -> bool {
+        fn transient(err: &reqwest::Error) -> bool {
+            err.is_timeout()
+                || err.is_connect()
+                || err
+                    .status()
+                    .is_some_and(|s| s.as_u16() == 429 || s.is_server_error())
+        }
+
+        match self {
+            ClientError::RateLimited { .. } | ClientError::MaintenanceOrBlocked { .. } => true,
+            ClientError::Http(err) => err.status.is_server_error(),
+            ClientError::RequestError(err) => transient(err),
+            ClientError::UnexpectedError { source } => source
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(transient),
+            _ => false,
+        }
+    }
+
+    /// Status, headers and body of the failed response, when there was one.
+    pub fn response(&self) -> Option<&ResponseError> {
+        match self {
+            ClientError::Http(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// DEGIRO's id of the failed request, to quote in support tickets.
+    pub fn request_id(&self) -> Option<&str> {
+        self.response()?.request_id.as_deref()
+    }
+
+    /// How long the server asked to wait before the next request.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ClientError::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -123,9 +227,52 @@ pub struct ClientRef {
     pub(crate) referer: String,
     pub account_config: AccountConfig,
     pub(crate) http_client: reqwest::Client,
+    #[derivative(Debug = "ignore")]
+    pub(crate) transport: Arc<dyn Transport>,
     pub cookie_jar: Arc<reqwest_cookie_store::CookieStoreMutex>,
     #[derivative(Debug = "ignore")]
     pub(crate) rate_limiter: Arc<RateLimiter>,
+    pub(crate) rate_policy: RatePolicy,
+    /// Requests currently waiting for a rate limiter token.
+    pub(crate) limiter_queue: Arc<AtomicUsize>,
+    #[derivative(Debug = "ignore")]
+    pub(crate) cache: Option<Arc<dyn CacheBackend>>,
+    pub(crate) cache_ttl: chrono::Duration,
+    #[derivative(Debug = "ignore")]
+    pub(crate) fetch_policy: Option<Arc<dyn FetchPolicy>>,
+    /// Reads in progress, shared by callers issuing the same request.
+    #[derivative(Debug = "ignore")]
+    pub(crate) in_flight: HashMap<String, InFlight>,
+    #[derivative(Debug = "ignore")]
+    pub(crate) product_cache: HashMap<String, CachedProduct>,
+    pub(crate) product_ttl: chrono::Duration,
+    #[derivative(Debug = "ignore")]
+    pub(crate) product_index: ProductIndex,
+    /// Loaded by the first [`Client::dictionary`] call.
+    #[derivative(Debug = "ignore")]
+    pub(crate) dictionary: Option<Arc<Dictionary>>,
+    /// Daily FX closes keyed by pair code, filled by historical conversions.
+    #[derivative(Debug = "ignore")]
+    pub(crate) fx_history: HashMap<String, BTreeMap<NaiveDate, f64>>,
+    /// Base currency of the last account info, to notice it changing.
+    pub(crate) base_currency: Option<Currency>,
+    /// When the current session ends, from server hints or the default TTL.
+    pub session_expires_at: Option<DateTime<Utc>>,
+    pub(crate) expiry_warned: bool,
+    #[derivative(Debug = "ignore")]
+    pub(crate) events: broadcast::Sender<ClientEvent>,
+    /// Held while logging in so concurrent callers don't race each other.
+    #[derivative(Debug = "ignore")]
+    pub(crate) auth_lock: Arc<tokio::sync::Mutex<()>>,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) kill_switch: KillSwitch,
+    /// Limits orders are checked against before they are placed, if any.
+    pub(crate) pre_trade_risk: Option<PreTradeRisk>,
+    /// Failed logins in a row, reset by a successful one.
+    pub(crate) login_failures: u32,
+    pub(crate) login_blocked_until: Option<DateTime<Utc>>,
+    pub(crate) stream: StreamHealth,
 }
 
 #[derive(Clone, Debug)]
@@ -133,12 +280,57 @@ pub struct Client {
     pub inner: Arc<Mutex<ClientRef>>,
 }
 
-#[derive(Debug, Default)]
+/// Snapshot of the client's state, for back-pressure and monitoring.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthStatus {
+    pub status: ClientStatus,
+    /// Requests waiting for a rate limiter token.
+    pub queue_depth: usize,
+    /// Tokens that can be taken right now without waiting.
+    pub available_tokens: usize,
+    /// Whether waiting requests are served in FIFO order.
+    pub fair: bool,
+    pub session_expires_at: Option<DateTime<Utc>>,
+    pub stream: StreamHealth,
+}
+
+/// State of the quotecast stream, updated by
+/// [`crate::api::quotecast::SubscriptionManager`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StreamHealth {
+    pub connected: bool,
+    /// Any message, heartbeats included.
+    pub last_message_at: Option<DateTime<Utc>>,
+    pub last_tick_at: Option<DateTime<Utc>>,
+    /// Sessions opened, the first one included.
+    pub reconnects: u64,
+    pub errors: u64,
+}
+
+impl StreamHealth {
+    /// Connected but without a tick for longer than `max_age`, or never had one.
+    pub fn is_stale(&self, now: DateTime<Utc>, max_age: chrono::Duration) -> bool {
+        self.connected && self.last_tick_at.is_none_or(|at| now - at > max_age)
+    }
+}
+
+#[derive(Derivative, Default)]
+#[derivative(Debug)]
 pub struct ClientBuilder {
     pub username: Option<String>,
     pub password: Option<String>,
     pub secret_key: Option<String>,
     pub cookie_jar: Option<Arc<reqwest_cookie_store::CookieStoreMutex>>,
+    pub fair_rate_limit: Option<bool>,
+    pub rate_policy: Option<RatePolicy>,
+    #[derivative(Debug = "ignore")]
+    pub cache: Option<Arc<dyn CacheBackend>>,
+    pub clock: Option<Arc<dyn Clock>>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub kill_switch: Option<KillSwitchLimits>,
+    pub risk_limits: Option<(RiskLimits, i64)>,
+    #[derivative(Debug = "ignore")]
+    pub transport: Option<Arc<dyn Transport>>,
 }
 
 impl ClientBuilder {
@@ -159,6 +351,58 @@ impl ClientBuilder {
         self
     }
 
+    /// Cache company data in `backend` with the default TTL.
+    pub fn cache(mut self, backend: Arc<dyn CacheBackend>) -> Self {
+        self.cache = Some(backend);
+        self
+    }
+
+    /// Serve requests waiting on the rate limiter in arrival order (the
+    /// default). Unfair mode has a bit less overhead but lets some callers
+    /// starve under bursts.
+    pub fn fair_rate_limit(mut self, fair: bool) -> Self {
+        self.fair_rate_limit = Some(fair);
+        self
+    }
+
+    /// Pace requests with `policy` instead of [`RatePolicy::default`].
+    pub fn rate_policy(mut self, policy: RatePolicy) -> Self {
+        self.rate_policy = Some(policy);
+        self
+    }
+
+    /// Read the time from `clock` instead of the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Retry transient failures of batched calls with `policy`.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Send requests through `transport` instead of the network, see
+    /// [`crate::transport::MockTransport`].
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Stop placing orders once `limits` are broken.
+    pub fn kill_switch(mut self, limits: KillSwitchLimits) -> Self {
+        self.kill_switch = Some(limits);
+        self
+    }
+
+    /// Refuse orders breaking `limits`, counting earnings within
+    /// `event_days` as event risk; see [`Client::set_risk_limits`].
+    pub fn risk_limits(mut self, limits: RiskLimits, event_days: i64) -> Self {
+        self.risk_limits = Some((limits, event_days));
+        self
+    }
+
     pub fn from_env() -> Self {
         let username = std::env::var("DEGIRO_USERNAME").expect("DEGIRO_USERNAME not found");
         let password = std::env::var("DEGIRO_PASSWORD").expect("DEGIRO_PASSWORD not found");
@@ -169,6 +413,14 @@ impl ClientBuilder {
             password: Some(password),
             secret_key: Some(secret),
             cookie_jar: None,
+            fair_rate_limit: None,
+            rate_policy: None,
+            cache: None,
+            clock: None,
+            retry_policy: None,
+            kill_switch: None,
+            risk_limits: None,
+            transport: None,
         }
     }
 
@@ -185,11 +437,101 @@ impl ClientBuilder {
             http_client,
             cookie_jar,
         );
+        if self.rate_policy.is_some() || self.fair_rate_limit.is_some() {
+            let mut policy = self.rate_policy.take().unwrap_or_default();
+            if let Some(fair) = self.fair_rate_limit {
+                policy.fair = fair;
+            }
+            client.set_rate_policy(policy);
+        }
+        if let Some(cache) = self.cache.take() {
+            client.set_cache(cache, DEFAULT_CACHE_TTL);
+        }
+        if let Some(clock) = self.clock.take() {
+            client.set_clock(clock);
+        }
+        if let Some(policy) = self.retry_policy.take() {
+            client.set_retry_policy(policy);
+        }
+        if let Some(limits) = self.kill_switch.take() {
+            client.set_kill_switch(limits);
+        }
+        if let Some((limits, event_days)) = self.risk_limits.take() {
+            client.set_risk_limits(limits, event_days);
+        }
+        if let Some(transport) = self.transport.take() {
+            client.set_transport(transport);
+        }
 
         Ok(client)
     }
 }
 
+/// Token bucket pacing the requests of one client: up to `burst` at once,
+/// then `refill` more every `interval`.
+///
+/// DEGIRO doesn't publish its limits, so none of the numbers below come
+/// from one. The default is the pace the client has always used; the
+/// presets are slower and faster variations of it. Going faster means more
+/// risk of `429`s, surfaced as [`ClientError::RateLimited`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RatePolicy {
+    pub burst: usize,
+    pub refill: usize,
+    pub interval: Duration,
+    /// Serve waiting requests in arrival order.
+    pub fair: bool,
+}
+
+impl Default for RatePolicy {
+    /// 12 requests a second.
+    fn default() -> Self {
+        Self {
+            burst: 12,
+            refill: 12,
+            interval: Duration::from_millis(1000),
+            fair: true,
+        }
+    }
+}
+
+impl RatePolicy {
+    /// 5 requests a second, for long running jobs sharing the account with
+    /// the web trader or other sessions.
+    pub fn conservative() -> Self {
+        Self {
+            burst: 5,
+            refill: 5,
+            ..Default::default()
+        }
+    }
+
+    /// 18 requests a second with bursts of 24, for short interactive jobs
+    /// that can handle [`ClientError::RateLimited`].
+    pub fn aggressive() -> Self {
+        Self {
+            burst: 24,
+            refill: 18,
+            ..Default::default()
+        }
+    }
+
+    fn limiter(&self) -> RateLimiter {
+        self.limiter_with(self.burst)
+    }
+
+    /// Bucket starting with `initial` tokens, capped at `burst`.
+    fn limiter_with(&self, initial: usize) -> RateLimiter {
+        RateLimiter::builder()
+            .initial(initial.min(self.burst))
+            .max(self.burst)
+            .refill(self.refill)
+            .interval(self.interval)
+            .fair(self.fair)
+            .build()
+    }
+}
+
 impl ClientRef {
     pub fn new(
         username: impl Into<String>,
@@ -203,6 +545,7 @@ impl ClientRef {
             status: ClientStatus::Unauthorized,
             username,
             password,
+            transport: Arc::new(HttpTransport(http_client.clone())),
             http_client,
             cookie_jar,
             session_id: Default::default(),
@@ -211,14 +554,30 @@ impl ClientRef {
             base_api_url: "https://trader.degiro.nl/".to_string(),
             referer: "https://trader.degiro.nl/trader/".to_string(),
             account_config: Default::default(),
-            rate_limiter: Arc::new(
-                RateLimiter::builder()
-                    .initial(12)
-                    .max(12)
-                    .refill(12)
-                    .interval(Duration::from_millis(1000))
-                    .build(),
-            ),
+            rate_limiter: Arc::new(RatePolicy::default().limiter()),
+            rate_policy: RatePolicy::default(),
+            limiter_queue: Default::default(),
+            in_flight: Default::default(),
+            cache: None,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            fetch_policy: None,
+            product_cache: Default::default(),
+            product_ttl: DEFAULT_PRODUCT_TTL,
+            product_index: Default::default(),
+            dictionary: None,
+            fx_history: Default::default(),
+            base_currency: None,
+            session_expires_at: None,
+            expiry_warned: false,
+            events: broadcast::channel(64).0,
+            auth_lock: Default::default(),
+            clock: Arc::new(SystemClock),
+            retry_policy: Default::default(),
+            kill_switch: Default::default(),
+            pre_trade_risk: None,
+            login_failures: 0,
+            login_blocked_until: None,
+            stream: Default::default(),
         }
     }
 }
@@ -252,4 +611,46 @@ impl Client {
 
         Self::new(username, password, http_client, cookie_jar)
     }
+
+    /// Switches to `policy` at runtime. Requests already waiting finish on
+    /// the old limiter, new ones wait on a bucket holding whatever tokens
+    /// the old one had left, so switching never grants an extra burst.
+    pub fn set_rate_policy(&self, policy: RatePolicy) {
+        let mut inner = self.inner.lock().unwrap();
+        let balance = inner.rate_limiter.balance();
+        inner.rate_limiter = Arc::new(policy.limiter_with(balance));
+        inner.rate_policy = policy;
+    }
+
+    pub fn rate_policy(&self) -> RatePolicy {
+        self.inner.lock().unwrap().rate_policy
+    }
+
+    pub fn health(&self) -> HealthStatus {
+        let inner = self.inner.lock().unwrap();
+        HealthStatus {
+            status: inner.status,
+            queue_depth: inner.limiter_queue.load(Ordering::Relaxed),
+            available_tokens: inner.rate_limiter.balance(),
+            fair: inner.rate_limiter.is_fair(),
+            session_expires_at: inner.session_expires_at,
+            stream: inner.stream.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_policy_keeps_balance() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        let limiter = client.inner.lock().unwrap().rate_limiter.clone();
+        assert!(limiter.try_acquire(limiter.balance()));
+
+        client.set_rate_policy(RatePolicy::aggressive());
+        assert_eq!(client.rate_policy(), RatePolicy::aggressive());
+        assert_eq!(client.health().available_tokens, 0);
+    }
 }
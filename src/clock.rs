@@ -0,0 +1,133 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::client::Client;
+
+/// Source of the current time for session tracking, caches and schedulers.
+///
+/// The client reads time only through its clock, so tests and backtests can
+/// drive it with a [`ManualClock`].
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock that only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock(Mutex<DateTime<Utc>>);
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(Mutex::new(now))
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+impl Client {
+    /// Current time according to the client's clock.
+    pub fn now(&self) -> DateTime<Utc> {
+        let clock = self.inner.lock().unwrap().clock.clone();
+        clock.now()
+    }
+
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        self.inner.lock().unwrap().clock = clock;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use reqwest::header::{HeaderMap, HeaderValue, SET_COOKIE};
+
+    use super::*;
+    use crate::{events::ClientEvent, money::Currency};
+
+    #[test]
+    fn manual_clock_drives_session_tracking() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        client.set_clock(clock.clone());
+        assert_eq!(client.now(), start);
+
+        let mut events = client.events();
+        let mut headers = HeaderMap::new();
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static("JSESSIONID=abc; Max-Age=3600"),
+        );
+        client.track_session(&headers);
+        assert_eq!(
+            client.session_expires_at(),
+            Some(start + Duration::hours(1))
+        );
+        assert!(events.try_recv().is_err());
+
+        clock.advance(Duration::minutes(56));
+        client.track_session(&HeaderMap::new());
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ClientEvent::SessionExpiringSoon { .. })
+        ));
+    }
+
+    #[test]
+    fn idle_client_gets_expiry_warning() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        client.set_clock(clock.clone());
+        client.inner.lock().unwrap().session_expires_at = Some(start + Duration::hours(1));
+
+        let mut events = client.events();
+        assert!(!client.check_session_expiry());
+        assert!(events.try_recv().is_err());
+
+        clock.advance(Duration::minutes(56));
+        assert!(client.check_session_expiry());
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ClientEvent::SessionExpiringSoon { .. })
+        ));
+        assert!(!client.check_session_expiry());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn rates_are_stamped_by_the_client_clock() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        client.set_clock(Arc::new(ManualClock::new(start)));
+        let rate = client
+            .exchange_rate(Currency::EUR, Currency::EUR)
+            .await
+            .unwrap();
+        assert_eq!(rate.timestamp, start);
+    }
+}
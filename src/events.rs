@@ -0,0 +1,162 @@
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use tokio::sync::broadcast;
+
+use crate::{
+    client::Client,
+    money::Currency,
+    safety::{Drawdown, TripReason},
+};
+
+/// Cookie carrying the DEGIRO session.
+pub const SESSION_COOKIE: &str = "JSESSIONID";
+
+/// Used when the server gives no hint about the session lifetime.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::hours(24);
+
+/// How long before expiry [`ClientEvent::SessionExpiringSoon`] is sent.
+pub const SESSION_EXPIRY_WARNING: Duration = Duration::minutes(5);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientEvent {
+    /// The session ends at `expires_at`; log in again to keep going.
+    SessionExpiringSoon { expires_at: DateTime<Utc> },
+    /// The server answered 401.
+    SessionExpired,
+    /// Login and account config went through.
+    Authorized,
+    /// A kill switch limit was hit; orders are refused until it is reset.
+    KillSwitchTripped(TripReason),
+    /// A [`crate::safety::DrawdownMonitor`] saw the account value fall too
+    /// far below its peak.
+    DrawdownExceeded(Drawdown),
+    /// The account info reports a different base currency than before;
+    /// cached FX rates were dropped.
+    BaseCurrencyChanged { from: Currency, to: Currency },
+}
+
+/// Reads the session lifetime from `Set-Cookie` headers, preferring
+/// `Max-Age` over `Expires` like browsers do.
+pub fn session_expiry_from_headers(
+    headers: &HeaderMap,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter(|v| v.trim_start().starts_with(&format!("{SESSION_COOKIE}=")))
+        .find_map(|cookie| {
+            let attrs = cookie
+                .split(';')
+                .skip(1)
+                .filter_map(|attr| attr.split_once('='))
+                .map(|(k, v)| (k.trim().to_lowercase(), v.trim()))
+                .collect::<Vec<_>>();
+            let max_age = attrs
+                .iter()
+                .find(|(k, _)| k == "max-age")
+                .and_then(|(_, v)| v.parse::<i64>().ok())
+                .map(|secs| now + Duration::seconds(secs));
+            max_age.or_else(|| {
+                attrs
+                    .iter()
+                    .find(|(k, _)| k == "expires")
+                    .and_then(|(_, v)| DateTime::parse_from_rfc2822(v).ok())
+                    .map(|dt| dt.with_timezone(&Utc))
+            })
+        })
+}
+
+impl Client {
+    /// Subscribes to client events; events sent before subscribing are not replayed.
+    pub fn events(&self) -> broadcast::Receiver<ClientEvent> {
+        self.inner.lock().unwrap().events.subscribe()
+    }
+
+    pub fn session_expires_at(&self) -> Option<DateTime<Utc>> {
+        self.inner.lock().unwrap().session_expires_at
+    }
+
+    pub(crate) fn emit(&self, event: ClientEvent) {
+        // No receivers is fine, nobody is listening.
+        let _ = self.inner.lock().unwrap().events.send(event);
+    }
+
+    /// Updates the session expiry from a response and warns once when it gets close.
+    pub(crate) fn track_session(&self, headers: &HeaderMap) {
+        if let Some(expires_at) = session_expiry_from_headers(headers, self.now()) {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.session_expires_at != Some(expires_at) {
+                inner.expiry_warned = false;
+            }
+            inner.session_expires_at = Some(expires_at);
+        }
+        self.check_session_expiry();
+    }
+
+    /// Sends [`ClientEvent::SessionExpiringSoon`] if the session ends within
+    /// [`SESSION_EXPIRY_WARNING`] of [`Client::now`], once per session.
+    ///
+    /// Responses run this check already; a client that sits idle has to call
+    /// it on its own timer to get the warning in time. Returns whether the
+    /// event was sent.
+    pub fn check_session_expiry(&self) -> bool {
+        let now = self.now();
+        let warn = {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.session_expires_at {
+                Some(expires_at)
+                    if !inner.expiry_warned && expires_at - now <= SESSION_EXPIRY_WARNING =>
+                {
+                    inner.expiry_warned = true;
+                    Some(expires_at)
+                }
+                _ => None,
+            }
+        };
+        if let Some(expires_at) = warn {
+            self.emit(ClientEvent::SessionExpiringSoon { expires_at });
+        }
+        warn.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn session_expiry_from_cookie() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, HeaderValue::from_static("other=1; Max-Age=10"));
+        assert_eq!(session_expiry_from_headers(&headers, now), None);
+
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static(
+                "JSESSIONID=abc; Path=/; Expires=Mon, 01 Jan 2024 13:00:00 GMT; HttpOnly",
+            ),
+        );
+        assert_eq!(
+            session_expiry_from_headers(&headers, now),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 13, 0, 0).unwrap())
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static(
+                "JSESSIONID=abc; Expires=Mon, 01 Jan 2024 13:00:00 GMT; Max-Age=1800",
+            ),
+        );
+        assert_eq!(
+            session_expiry_from_headers(&headers, now),
+            Some(now + Duration::minutes(30))
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use serde::Serialize;
+
+use crate::client::ClientError;
+
+/// Cell of a tabular export.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cell {
+    /// Numbers, codes and dates, written as they are.
+    Value(String),
+    /// Free text, always quoted.
+    Text(String),
+}
+
+impl Cell {
+    pub fn value(x: impl ToString) -> Self {
+        Self::Value(x.to_string())
+    }
+
+    pub fn text(x: impl Into<String>) -> Self {
+        Self::Text(x.into())
+    }
+}
+
+/// Report that any [`Exporter`] can write: structured formats use its serde
+/// representation, tabular ones its header and rows.
+pub trait Export: Serialize {
+    fn header(&self) -> &'static [&'static str];
+    fn rows(&self) -> Vec<Vec<Cell>>;
+}
+
+/// Output format for [`Export`] reports.
+pub trait Exporter {
+    fn export<T: Export + ?Sized>(&self, data: &T) -> Result<Vec<u8>, ClientError>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl Json {
+    pub fn to_string<T: Export + ?Sized>(&self, data: &T) -> Result<String, ClientError> {
+        Ok(serde_json::to_string(data)?)
+    }
+}
+
+impl Exporter for Json {
+    fn export<T: Export + ?Sized>(&self, data: &T) -> Result<Vec<u8>, ClientError> {
+        Ok(serde_json::to_vec(data)?)
+    }
+}
+
+/// Comma separated, one line per row after the header.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Csv;
+
+impl Csv {
+    pub fn to_string<T: Export + ?Sized>(&self, data: &T) -> String {
+        let mut out = data.header().join(",");
+        out.push('\n');
+        for row in data.rows() {
+            let cells = row
+                .iter()
+                .map(|cell| match cell {
+                    Cell::Value(x) => x.clone(),
+                    Cell::Text(x) => format!("\"{}\"", x.replace('"', "\"\"")),
+                })
+                .collect::<Vec<_>>();
+            out.push_str(&cells.join(","));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Splits CSV text as written by [`Csv`] into rows of fields: quoted fields
+/// may hold commas, newlines and doubled quotes, blank lines are skipped.
+pub(crate) fn read_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|x| !x.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    row.push(field);
+    if row.iter().any(|x| !x.is_empty()) {
+        rows.push(row);
+    }
+    rows
+}
+
+impl Exporter for Csv {
+    fn export<T: Export + ?Sized>(&self, data: &T) -> Result<Vec<u8>, ClientError> {
+        Ok(self.to_string(data).into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Notes(Vec<(u32, String)>);
+
+    impl Export for Notes {
+        fn header(&self) -> &'static [&'static str] {
+            &["id", "note"]
+        }
+
+        fn rows(&self) -> Vec<Vec<Cell>> {
+            self.0
+                .iter()
+                .map(|(id, note)| vec![Cell::value(id), Cell::text(note)])
+                .collect()
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let notes = Notes(vec![(1, "say \"hi\", twice".to_string())]);
+        assert_eq!(
+            Csv.to_string(&notes),
+            "id,note\n1,\"say \"\"hi\"\", twice\"\n"
+        );
+        assert_eq!(
+            Json.export(&notes).unwrap(),
+            br#"[[1,"say \"hi\", twice"]]"#.to_vec()
+        );
+        assert_eq!(
+            read_csv(&Csv.to_string(&notes)),
+            vec![vec!["id", "note"], vec!["1", "say \"hi\", twice"]]
+        );
+    }
+}
@@ -0,0 +1,846 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{
+    future::{self, BoxFuture, Shared},
+    stream, FutureExt, Stream,
+};
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER},
+    Method, Request, RequestBuilder, Response, StatusCode, Url,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::{
+    client::{Client, ClientError, ClientStatus},
+    events::ClientEvent,
+};
+
+/// How far the session has to be set up before a request can go out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuthLevel {
+    /// No session needed.
+    None,
+    /// Logged in; a session id is enough.
+    Restricted,
+    /// Logged in and account config loaded, so service urls, `client_id`
+    /// and `int_account` are known.
+    Authorized,
+}
+
+impl AuthLevel {
+    pub fn satisfied_by(self, status: ClientStatus) -> bool {
+        match self {
+            AuthLevel::None => true,
+            AuthLevel::Restricted => {
+                matches!(status, ClientStatus::Restricted | ClientStatus::Authorized)
+            }
+            AuthLevel::Authorized => status == ClientStatus::Authorized,
+        }
+    }
+}
+
+/// Every endpoint the client talks to, with the auth level it really needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Login,
+    AccountConfig,
+    AccountData,
+    AccountInfo,
+    AccountState,
+    Transactions,
+    Portfolio,
+    Orders,
+    OrderHistory,
+    PlaceOrder,
+    Products,
+    Search,
+    Quotes,
+    Quotecast,
+    News,
+    Gossips,
+    Agenda,
+    CuratedLists,
+    Dictionary,
+    /// Company profile, ratios, financial statements, ESG and estimates.
+    Refinitiv,
+}
+
+impl Endpoint {
+    pub fn auth_level(self) -> AuthLevel {
+        match self {
+            Endpoint::Login => AuthLevel::None,
+            // Only needs the session id; it is what loads everything else.
+            Endpoint::AccountConfig => AuthLevel::Restricted,
+            // The rest is addressed through urls or ids from the account config.
+            Endpoint::AccountData
+            | Endpoint::AccountInfo
+            | Endpoint::AccountState
+            | Endpoint::Transactions
+            | Endpoint::Portfolio
+            | Endpoint::Orders
+            | Endpoint::OrderHistory
+            | Endpoint::PlaceOrder
+            | Endpoint::Products
+            | Endpoint::Search
+            | Endpoint::Quotes
+            | Endpoint::Quotecast
+            | Endpoint::News
+            | Endpoint::Gossips
+            | Endpoint::Agenda
+            | Endpoint::CuratedLists
+            | Endpoint::Dictionary
+            | Endpoint::Refinitiv => AuthLevel::Authorized,
+        }
+    }
+
+    pub fn session_params(self) -> SessionParams {
+        match self {
+            // Portfolio and orders carry the session as `;jsessionid=` in the
+            // path, quotes and quotecast authenticate with the user token.
+            Endpoint::Login
+            | Endpoint::AccountConfig
+            | Endpoint::Portfolio
+            | Endpoint::Orders
+            | Endpoint::Quotes
+            | Endpoint::Quotecast => SessionParams::None,
+            Endpoint::AccountData | Endpoint::AccountInfo => SessionParams::SessionId,
+            Endpoint::AccountState
+            | Endpoint::Transactions
+            | Endpoint::OrderHistory
+            | Endpoint::PlaceOrder
+            | Endpoint::Products
+            | Endpoint::Search
+            | Endpoint::News
+            | Endpoint::Gossips
+            | Endpoint::Agenda
+            | Endpoint::CuratedLists
+            | Endpoint::Dictionary
+            | Endpoint::Refinitiv => SessionParams::SessionIdAndAccount,
+        }
+    }
+}
+
+/// Session values an endpoint expects in its query string; the executor
+/// adds them when the request goes out, see [`Client::send`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionParams {
+    None,
+    /// `sessionId`.
+    SessionId,
+    /// `intAccount` and `sessionId`.
+    SessionIdAndAccount,
+}
+
+/// `req` with the query parameters of `params` set to the given session,
+/// replacing any already there.
+fn inject_session(
+    mut req: Request,
+    params: SessionParams,
+    session_id: &str,
+    int_account: i32,
+) -> Request {
+    if params == SessionParams::None {
+        return req;
+    }
+    let rest = req
+        .url()
+        .query_pairs()
+        .filter(|(k, _)| k != "intAccount" && k != "sessionId")
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect::<Vec<_>>();
+    let mut query = req.url_mut().query_pairs_mut();
+    query.clear();
+    if params == SessionParams::SessionIdAndAccount {
+        query.append_pair("intAccount", &int_account.to_string());
+    }
+    query.append_pair("sessionId", session_id);
+    query.extend_pairs(rest);
+    drop(query);
+    req
+}
+
+pub(crate) type InFlight = Shared<BoxFuture<'static, Result<Arc<Value>, Arc<ClientError>>>>;
+
+/// Key identifying a request for coalescing: method, url with query and body.
+fn coalesce_key(req: &RequestBuilder) -> Option<String> {
+    let req = req.try_clone()?.build().ok()?;
+    let body = match req.body() {
+        Some(body) => String::from_utf8_lossy(body.as_bytes()?).into_owned(),
+        None => String::new(),
+    };
+    Some(format!("{} {} {}", req.method(), req.url(), body))
+}
+
+/// Hands a shared error back to one of the callers waiting on it.
+fn unshare(err: Arc<ClientError>) -> ClientError {
+    match *err {
+        ClientError::Unauthorized => return ClientError::Unauthorized,
+        ClientError::RateLimited { retry_after } => {
+            return ClientError::RateLimited { retry_after }
+        }
+        _ => {}
+    }
+    Arc::try_unwrap(err).unwrap_or_else(|err| ClientError::UnexpectedError {
+        source: Box::new(err),
+    })
+}
+
+/// Counts a request as queued on the limiter until dropped, so cancelled
+/// requests don't leak into the queue depth.
+struct QueueTicket<'a>(&'a AtomicUsize);
+
+impl<'a> QueueTicket<'a> {
+    fn new(queue: &'a AtomicUsize) -> Self {
+        queue.fetch_add(1, Ordering::Relaxed);
+        Self(queue)
+    }
+}
+
+impl Drop for QueueTicket<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// How long a replayed request waits for the new session.
+const REAUTH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `req` with the session id `old` in its url swapped for `new`, for the
+/// `;jsessionid=` path parameter; query parameters are set again on sending.
+fn with_session(mut req: Request, old: &str, new: &str) -> Request {
+    if !old.is_empty() {
+        if let Ok(url) = Url::parse(&req.url().as_str().replace(old, new)) {
+            *req.url_mut() = url;
+        }
+    }
+    req
+}
+
+/// Body of the reporting endpoints, `{"data": ...}`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Data<T> {
+    pub data: T,
+}
+
+/// Chunks downloaded ahead of the parser before the download waits.
+const READ_AHEAD_CHUNKS: usize = 16;
+
+/// Deserializes the body straight into `T` while it downloads.
+///
+/// Multi-year reporting responses run into tens of MB. Chunks go over a
+/// channel to a `serde_json` reader on the blocking pool, so only the chunks
+/// in flight and `T` are held, never the whole document, and fields `T`
+/// doesn't name are skipped without being allocated.
+pub(crate) async fn read_json<T>(mut res: Response) -> Result<T, ClientError>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(READ_AHEAD_CHUNKS);
+    let parse = tokio::task::spawn_blocking(move || {
+        let mut reader = ChunkReader::new(rx);
+        let out = serde_json::from_reader::<_, T>(&mut reader);
+        (out, reader.head)
+    });
+    let download = async move {
+        while let Some(chunk) = res.chunk().await? {
+            // The parser only hangs up once it has failed.
+            if tx.send(chunk).await.is_err() {
+                break;
+            }
+        }
+        Ok::<_, ClientError>(())
+    };
+    let (downloaded, parsed) = future::join(download, parse).await;
+    downloaded?;
+    let (out, head) = parsed.map_err(|err| ClientError::UnexpectedError {
+        source: Box::new(err),
+    })?;
+    out.map_err(|err| {
+        let body = String::from_utf8_lossy(&head);
+        if body.trim_start().starts_with('<') {
+            ClientError::MaintenanceOrBlocked {
+                snippet: html_snippet(&body),
+            }
+        } else {
+            err.into()
+        }
+    })
+}
+
+/// Blocking [`Read`] over the chunks of a body, keeping its start for
+/// error reports.
+struct ChunkReader {
+    rx: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+    head: Vec<u8>,
+}
+
+impl ChunkReader {
+    fn new(rx: mpsc::Receiver<Bytes>) -> Self {
+        Self {
+            rx,
+            chunk: Bytes::new(),
+            head: Vec::new(),
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            let Some(chunk) = self.rx.blocking_recv() else {
+                return Ok(0);
+            };
+            let room = ERROR_BODY_LIMIT.saturating_sub(self.head.len());
+            self.head.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            self.chunk = chunk;
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+/// Readable gist of an HTML page: its title, or else its text without
+/// tags, cut to 200 characters.
+pub(crate) fn html_snippet(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let title = lower
+        .find("<title>")
+        .map(|i| i + "<title>".len())
+        .and_then(|start| Some((start, start + lower[start..].find("</title>")?)))
+        .map(|(start, end)| &html[start..end]);
+    let source = title.unwrap_or(html);
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in source.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(200)
+        .collect()
+}
+
+impl Client {
+    /// Fails with [`ClientError::Unauthorized`] unless the session is far
+    /// enough along for `endpoint`.
+    pub(crate) fn require(&self, endpoint: Endpoint) -> Result<(), ClientError> {
+        let status = self.inner.lock().unwrap().status;
+        if endpoint.auth_level().satisfied_by(status) {
+            Ok(())
+        } else {
+            Err(ClientError::Unauthorized)
+        }
+    }
+
+    /// Sends a request through the shared pipeline: sets the session
+    /// parameters `endpoint` needs, waits for a rate limiter token, then maps
+    /// the response status.
+    ///
+    /// A GET sent with an authorized session that comes back 401 is replayed
+    /// once after logging in again, unless the retry policy turns that off.
+    /// The replay carries the new session.
+    pub(crate) async fn send(
+        &self,
+        endpoint: Endpoint,
+        req: RequestBuilder,
+    ) -> Result<Response, ClientError> {
+        let (replay, session_id) = {
+            let inner = self.inner.lock().unwrap();
+            let replay =
+                inner.retry_policy.replay_unauthorized && inner.status == ClientStatus::Authorized;
+            (replay, inner.session_id.clone())
+        };
+        let retry = replay
+            .then(|| req.try_clone()?.build().ok())
+            .flatten()
+            .filter(|req| req.method() == Method::GET);
+        let res = self.send_unchecked(endpoint, req).await?;
+        match (self.check_status(res).await, retry) {
+            (Err(ClientError::Unauthorized), Some(retry)) => {
+                self.ensure_authorized(REAUTH_TIMEOUT).await?;
+                let (http_client, new_session_id) = {
+                    let inner = self.inner.lock().unwrap();
+                    (inner.http_client.clone(), inner.session_id.clone())
+                };
+                let retry = with_session(retry, &session_id, &new_session_id);
+                let res = self
+                    .send_unchecked(endpoint, RequestBuilder::from_parts(http_client, retry))
+                    .await?;
+                self.check_status(res).await
+            }
+            (res, _) => res,
+        }
+    }
+
+    /// [`Client::send`] without the replay, for the requests that log in;
+    /// they can't wait on a login themselves.
+    pub(crate) async fn send_once(
+        &self,
+        endpoint: Endpoint,
+        req: RequestBuilder,
+    ) -> Result<Response, ClientError> {
+        let res = self.send_unchecked(endpoint, req).await?;
+        self.check_status(res).await
+    }
+
+    /// Rate limited like [`Client::send`] but hands back error responses, for
+    /// endpoints whose error bodies carry information; pass the response
+    /// to [`Client::check_status`] once done with it.
+    pub(crate) async fn send_unchecked(
+        &self,
+        endpoint: Endpoint,
+        req: RequestBuilder,
+    ) -> Result<Response, ClientError> {
+        let (rate_limiter, queue) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.rate_limiter.clone(), inner.limiter_queue.clone())
+        };
+        {
+            let _ticket = QueueTicket::new(&queue);
+            rate_limiter.acquire_one().await;
+        }
+        // Read the session only now, a login may have finished while waiting.
+        let req = self.with_session_params(endpoint, req)?;
+
+        self.dispatch(req).await
+    }
+
+    fn with_session_params(
+        &self,
+        endpoint: Endpoint,
+        req: RequestBuilder,
+    ) -> Result<RequestBuilder, ClientError> {
+        let params = endpoint.session_params();
+        if params == SessionParams::None {
+            return Ok(req);
+        }
+        let (session_id, int_account) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.session_id.clone(), inner.int_account)
+        };
+        let (http_client, req) = req.build_split();
+        let req = inject_session(req?, params, &session_id, int_account);
+        Ok(RequestBuilder::from_parts(http_client, req))
+    }
+
+    /// Sends a read request and parses its body as json, sharing one response
+    /// between concurrent callers asking for exactly the same thing.
+    ///
+    /// Only use this for reads; a second identical write would be swallowed.
+    pub(crate) async fn send_json_coalesced(
+        &self,
+        endpoint: Endpoint,
+        req: RequestBuilder,
+    ) -> Result<Value, ClientError> {
+        let Some(key) = coalesce_key(&req) else {
+            return Ok(self.send(endpoint, req).await?.json::<Value>().await?);
+        };
+        let fut = {
+            let mut inner = self.inner.lock().unwrap();
+            inner
+                .in_flight
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    let client = self.clone();
+                    async move {
+                        let res = client.send(endpoint, req).await.map_err(Arc::new)?;
+                        let json = res.json::<Value>().await;
+                        json.map(Arc::new).map_err(|err| Arc::new(err.into()))
+                    }
+                    .boxed()
+                    .shared()
+                })
+                .clone()
+        };
+        let out = fut.clone().await;
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner
+                .in_flight
+                .get(&key)
+                .is_some_and(|x| Shared::ptr_eq(x, &fut))
+            {
+                inner.in_flight.remove(&key);
+            }
+        }
+        drop(fut);
+        match out {
+            Ok(json) => Ok(Arc::unwrap_or_clone(json)),
+            Err(err) => Err(unshare(err)),
+        }
+    }
+
+    /// Same as [`Client::send`] without taking a rate limiter token, for
+    /// requests paced by the server such as long polls.
+    pub(crate) async fn execute(&self, req: RequestBuilder) -> Result<Response, ClientError> {
+        self.check_status(self.dispatch(req).await?).await
+    }
+
+    /// A 401 marks the client as unauthorized and yields
+    /// [`ClientError::Unauthorized`], a 429 yields
+    /// [`ClientError::RateLimited`]; any other error status is returned as
+    /// [`ClientError::Http`] with the headers and start of the body.
+    pub(crate) async fn check_status(&self, res: Response) -> Result<Response, ClientError> {
+        let status = res.status();
+        if status == StatusCode::UNAUTHORIZED {
+            self.inner.lock().unwrap().status = ClientStatus::Unauthorized;
+            self.emit(ClientEvent::SessionExpired);
+            return Err(ClientError::Unauthorized);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(ClientError::RateLimited {
+                retry_after: retry_after(res.headers(), self.now()),
+            });
+        }
+        if status.is_client_error() || status.is_server_error() {
+            let headers = res.headers().clone();
+            let body = res.text().await.unwrap_or_default();
+            return Err(ClientError::Http(ResponseError::new(
+                status, &headers, &body,
+            )));
+        }
+        self.track_session(res.headers());
+        // Maintenance and block pages come back as HTML with a 200.
+        let html = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim_start().starts_with("text/html"));
+        if html {
+            let body = res.text().await.unwrap_or_default();
+            return Err(ClientError::MaintenanceOrBlocked {
+                snippet: html_snippet(&body),
+            });
+        }
+        Ok(res)
+    }
+}
+
+/// Bodies kept in a [`ResponseError`] are cut to this many bytes.
+const ERROR_BODY_LIMIT: usize = 2048;
+
+/// Headers naming a request on DEGIRO's side, most specific first.
+const REQUEST_ID_HEADERS: [&str; 3] = ["x-request-id", "x-correlation-id", "request-id"];
+
+/// An error status with what is needed to report it to DEGIRO.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseError {
+    pub status: StatusCode,
+    pub request_id: Option<String>,
+    /// Raw `Retry-After` header.
+    pub retry_after: Option<String>,
+    pub content_type: Option<String>,
+    /// Start of the body, see [`ERROR_BODY_LIMIT`].
+    pub body: String,
+}
+
+impl ResponseError {
+    pub fn new(status: StatusCode, headers: &HeaderMap, body: &str) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let mut end = body.len().min(ERROR_BODY_LIMIT);
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        Self {
+            status,
+            request_id: REQUEST_ID_HEADERS.iter().find_map(|name| header(name)),
+            retry_after: header(RETRY_AFTER.as_str()),
+            content_type: header(CONTENT_TYPE.as_str()),
+            body: body[..end].to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.status)?;
+        if let Some(id) = &self.request_id {
+            write!(f, " (request {id})")?;
+        }
+        if !self.body.is_empty() {
+            write!(f, ": {}", self.body)?;
+        }
+        Ok(())
+    }
+}
+
+/// `Retry-After` as a wait from `now`; the header holds seconds or an HTTP
+/// date.
+pub(crate) fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+}
+
+/// One page of an offset/limit endpoint.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Total number of items when the endpoint reports it.
+    pub total: Option<usize>,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: Option<usize>) -> Self {
+        Self { items, total }
+    }
+}
+
+struct PaginateState<T, F> {
+    fetch: F,
+    offset: u32,
+    page_size: u32,
+    buffer: VecDeque<T>,
+    done: bool,
+}
+
+/// Drives an offset/limit endpoint and yields its items one by one.
+///
+/// `fetch` receives `(offset, limit)` and is only called when the previous
+/// page has been consumed, so requests go through the endpoint's own rate
+/// limiting one at a time. Paging stops on a short or empty page, once
+/// `total` is reached, or after the first error.
+pub fn paginate<T, F, Fut>(page_size: u32, fetch: F) -> impl Stream<Item = Result<T, ClientError>>
+where
+    F: FnMut(u32, u32) -> Fut,
+    Fut: Future<Output = Result<Page<T>, ClientError>>,
+{
+    let state = PaginateState {
+        fetch,
+        offset: 0,
+        page_size: page_size.max(1),
+        buffer: VecDeque::new(),
+        done: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.buffer.pop_front() {
+                return Some((Ok(item), state));
+            }
+            if state.done {
+                return None;
+            }
+            match (state.fetch)(state.offset, state.page_size).await {
+                Ok(page) => {
+                    let n = page.items.len() as u32;
+                    state.offset += n;
+                    state.done = n < state.page_size
+                        || page
+                            .total
+                            .is_some_and(|total| state.offset as usize >= total);
+                    state.buffer.extend(page.items);
+                }
+                Err(err) => {
+                    state.done = true;
+                    return Some((Err(err), state));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn read_json_across_chunks() {
+        let (tx, rx) = mpsc::channel(4);
+        for chunk in [r#"{"data": [1, "#, "2, ", "3], \"extra\": {}}"] {
+            tx.send(Bytes::from(chunk)).await.unwrap();
+        }
+        drop(tx);
+        let parsed = tokio::task::spawn_blocking(move || {
+            serde_json::from_reader::<_, Data<Vec<i32>>>(ChunkReader::new(rx))
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(parsed.data, [1, 2, 3]);
+
+        let res = |body: &'static str| Response::from(http::Response::new(body));
+        let Data { data } = read_json::<Data<Vec<i32>>>(res(r#"{"data": [4]}"#))
+            .await
+            .unwrap();
+        assert_eq!(data, [4]);
+        assert!(matches!(
+            read_json::<Data<Vec<i32>>>(res("<html><title>Maintenance</title></html>")).await,
+            Err(ClientError::MaintenanceOrBlocked { snippet }) if snippet == "Maintenance"
+        ));
+    }
+
+    #[test]
+    fn response_error_keeps_context() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", "abc-123".parse().unwrap());
+        headers.insert(CONTENT_TYPE, "text/html".parse().unwrap());
+        let body = "é".repeat(ERROR_BODY_LIMIT);
+        let err = ResponseError::new(StatusCode::BAD_GATEWAY, &headers, &body);
+        assert_eq!(err.request_id.as_deref(), Some("abc-123"));
+        assert_eq!(err.content_type.as_deref(), Some("text/html"));
+        assert_eq!(err.body.len(), ERROR_BODY_LIMIT);
+        let err = ClientError::Http(err);
+        assert!(err.is_transient());
+        assert_eq!(err.request_id(), Some("abc-123"));
+    }
+
+    #[test]
+    fn html_snippets() {
+        let page = "<!DOCTYPE html><html><head><TITLE>Scheduled\n maintenance</TITLE></head>\
+                    <body><h1>Back soon</h1></body></html>";
+        assert_eq!(html_snippet(page), "Scheduled maintenance");
+        assert_eq!(
+            html_snippet("<html><body><p>Access <b>denied</b></p></body></html>"),
+            "Access denied"
+        );
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers, now), None);
+        headers.insert(RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(120)));
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:30 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(30)));
+        assert!(ClientError::RateLimited { retry_after: None }.is_transient());
+    }
+
+    #[test]
+    fn replay_swaps_session_id() {
+        let req = reqwest::Client::new()
+            .get("https://trader.degiro.nl/trading/secure/v5/update/1;jsessionid=OLD.prod")
+            .query(&[("intAccount", "1"), ("sessionId", "OLD.prod")])
+            .build()
+            .unwrap();
+        let req = with_session(req, "OLD.prod", "NEW.prod");
+        assert_eq!(
+            req.url().as_str(),
+            "https://trader.degiro.nl/trading/secure/v5/update/1;jsessionid=NEW.prod\
+             ?intAccount=1&sessionId=NEW.prod"
+        );
+    }
+
+    #[test]
+    fn injects_session_params() {
+        let http = reqwest::Client::new();
+        let req = http
+            .get("https://example.com/v5/stocks")
+            .query(&[("sessionId", "OLD"), ("limit", "10")])
+            .build()
+            .unwrap();
+        let req = inject_session(req, SessionParams::SessionIdAndAccount, "NEW", 7);
+        assert_eq!(
+            req.url().as_str(),
+            "https://example.com/v5/stocks?intAccount=7&sessionId=NEW&limit=10"
+        );
+
+        let req = http.get("https://example.com/info").build().unwrap();
+        let req = inject_session(req, SessionParams::SessionId, "NEW", 7);
+        assert_eq!(req.url().as_str(), "https://example.com/info?sessionId=NEW");
+
+        let req = http.get("https://example.com/chart").build().unwrap();
+        let req = inject_session(req, SessionParams::None, "NEW", 7);
+        assert_eq!(req.url().query(), None);
+        assert_eq!(Endpoint::Portfolio.session_params(), SessionParams::None);
+    }
+
+    #[test]
+    fn queue_ticket_counts_waiters() {
+        let queue = AtomicUsize::new(0);
+        {
+            let _a = QueueTicket::new(&queue);
+            let _b = QueueTicket::new(&queue);
+            assert_eq!(queue.load(Ordering::Relaxed), 2);
+        }
+        assert_eq!(queue.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn coalesce_key_covers_query_and_body() {
+        let http = reqwest::Client::new();
+        let get = |id: &str| http.get("https://example.com/quotes").query(&[("id", id)]);
+        assert_eq!(coalesce_key(&get("1")), coalesce_key(&get("1")));
+        assert_ne!(coalesce_key(&get("1")), coalesce_key(&get("2")));
+
+        let post = |ids: &[&str]| http.post("https://example.com/info").json(&ids);
+        assert_eq!(coalesce_key(&post(&["1"])), coalesce_key(&post(&["1"])));
+        assert_ne!(coalesce_key(&post(&["1"])), coalesce_key(&post(&["2"])));
+    }
+
+    #[test]
+    fn endpoint_auth_levels() {
+        assert!(Endpoint::Login
+            .auth_level()
+            .satisfied_by(ClientStatus::Unauthorized));
+        assert!(Endpoint::AccountConfig
+            .auth_level()
+            .satisfied_by(ClientStatus::Restricted));
+        assert!(!Endpoint::Portfolio
+            .auth_level()
+            .satisfied_by(ClientStatus::Restricted));
+        assert!(Endpoint::Portfolio
+            .auth_level()
+            .satisfied_by(ClientStatus::Authorized));
+    }
+
+    #[tokio::test]
+    async fn paginate_until_short_page() {
+        let calls = std::cell::Cell::new(0);
+        let xs = paginate(2, |offset, limit| {
+            calls.set(calls.get() + 1);
+            async move {
+                let items = (offset..(offset + limit).min(5)).collect::<Vec<_>>();
+                Ok(Page::new(items, None))
+            }
+        })
+        .collect::<Vec<_>>()
+        .await;
+        let xs = xs.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(xs, vec![0, 1, 2, 3, 4]);
+        assert_eq!(calls.get(), 3);
+    }
+}
@@ -1,6 +1,20 @@
 pub mod api;
+pub mod batch;
+pub mod cache;
 pub mod client;
+pub mod clock;
+pub mod events;
+pub mod export;
+pub mod http;
 pub mod money;
+pub mod named_values;
+pub mod product_index;
+pub mod risk;
+pub mod safety;
+pub mod schedule;
+pub mod screener;
+pub mod strategy;
+pub mod transport;
 pub mod util;
 
 pub mod prelude {
@@ -1,9 +1,14 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::HashMap, fmt::Display, iter::Sum};
 
 use serde::{Deserialize, Serialize};
 use strum::EnumString;
 use thiserror::Error;
 
+use crate::{
+    api::account::AccountInfo,
+    client::{Client, ClientError},
+};
+
 #[derive(
     Debug,
     Default,
@@ -27,7 +32,17 @@ pub enum Currency {
     GBP,
 }
 
-#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+impl Currency {
+    /// Digits after the decimal point of the currency's smallest unit.
+    pub fn decimals(&self) -> u32 {
+        match self {
+            Currency::JPY => 0,
+            _ => 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
 pub struct Money {
     pub currency: Currency,
     pub amount: f64,
@@ -61,6 +76,56 @@ impl Money {
             ..*self
         }
     }
+
+    /// Amount in the currency's smallest unit (cents), rounded.
+    pub fn minor_units(&self) -> i64 {
+        (self.amount * 10f64.powi(self.currency.decimals() as i32)).round() as i64
+    }
+
+    pub fn from_minor_units(currency: Currency, units: i64) -> Self {
+        Self::new(
+            currency,
+            units as f64 / 10f64.powi(currency.decimals() as i32),
+        )
+    }
+
+    /// Splits the amount proportionally to `weights` in whole minor units.
+    ///
+    /// Leftover units go to the parts with the largest rounding remainders,
+    /// so the parts always sum to exactly [`Money::minor_units`]. `None` when
+    /// a weight is negative or not finite, or all weights are zero.
+    pub fn allocate(&self, weights: &[f64]) -> Option<Vec<Money>> {
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return None;
+        }
+        let sum = weights.iter().sum::<f64>();
+        if sum <= 0.0 {
+            return None;
+        }
+        let total = self.minor_units();
+        let exact = weights
+            .iter()
+            .map(|w| total as f64 * w / sum)
+            .collect::<Vec<_>>();
+        let mut units = exact.iter().map(|x| x.trunc() as i64).collect::<Vec<_>>();
+        let mut order = (0..units.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            let ra = (exact[a] - units[a] as f64).abs();
+            let rb = (exact[b] - units[b] as f64).abs();
+            rb.total_cmp(&ra)
+        });
+        let step = total.signum();
+        let leftover = total - units.iter().sum::<i64>();
+        for &i in order.iter().cycle().take(leftover.unsigned_abs() as usize) {
+            units[i] += step;
+        }
+        Some(
+            units
+                .into_iter()
+                .map(|x| Money::from_minor_units(self.currency, x))
+                .collect(),
+        )
+    }
 }
 
 impl Display for Money {
@@ -171,6 +236,121 @@ impl std::ops::Div for Money {
     }
 }
 
+/// Per-currency accumulator for summing `Money` of mixed currencies.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MoneyBag(HashMap<Currency, f64>);
+
+impl MoneyBag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, money: Money) {
+        *self.0.entry(money.currency).or_default() += money.amount;
+    }
+
+    pub fn get(&self, currency: Currency) -> Money {
+        Money::new(currency, self.0.get(&currency).copied().unwrap_or_default())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Money> + '_ {
+        self.0
+            .iter()
+            .map(|(currency, amount)| Money::new(*currency, *amount))
+    }
+
+    pub fn currencies(&self) -> impl Iterator<Item = Currency> + '_ {
+        self.0.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Single currency total, `None` when the bag holds several currencies.
+    pub fn single(&self) -> Option<Money> {
+        match self.0.len() {
+            1 => self.iter().next(),
+            _ => None,
+        }
+    }
+
+    /// Converts every currency with the given account FX pairs and sums them.
+    pub fn total_in_with(&self, to: Currency, info: &AccountInfo) -> Result<Money, ClientError> {
+        let mut total = Money::new(to, 0.0);
+        for money in self.iter() {
+            total.amount += info.convert(money, to)?.amount;
+        }
+        Ok(total)
+    }
+
+    pub async fn total_in(&self, to: Currency, client: &Client) -> Result<Money, ClientError> {
+        if self.0.keys().all(|currency| *currency == to) {
+            return Ok(self.get(to));
+        }
+        let info = client.account_info().await?;
+        self.total_in_with(to, &info)
+    }
+}
+
+impl From<Money> for MoneyBag {
+    fn from(money: Money) -> Self {
+        let mut bag = MoneyBag::new();
+        bag.add(money);
+        bag
+    }
+}
+
+impl std::ops::AddAssign<Money> for MoneyBag {
+    fn add_assign(&mut self, rhs: Money) {
+        self.add(rhs);
+    }
+}
+
+impl Extend<Money> for MoneyBag {
+    fn extend<T: IntoIterator<Item = Money>>(&mut self, iter: T) {
+        iter.into_iter().for_each(|money| self.add(money));
+    }
+}
+
+impl FromIterator<Money> for MoneyBag {
+    fn from_iter<T: IntoIterator<Item = Money>>(iter: T) -> Self {
+        let mut bag = MoneyBag::new();
+        bag.extend(iter);
+        bag
+    }
+}
+
+impl Sum<Money> for MoneyBag {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Self {
+        iter.collect()
+    }
+}
+
+impl<'a> Sum<&'a Money> for MoneyBag {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Self {
+        iter.copied().collect()
+    }
+}
+
+impl Display for MoneyBag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut xs = self.iter().collect::<Vec<_>>();
+        xs.sort_by_key(|money| money.currency.to_string());
+        for (i, money) in xs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{money}")?;
+        }
+        Ok(())
+    }
+}
+
 impl TryFrom<HashMap<String, f64>> for Money {
     type Error = MoneyError;
 
@@ -188,3 +368,96 @@ impl TryFrom<HashMap<String, f64>> for Money {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn money_bag_keeps_currencies_apart() {
+        let bag: MoneyBag = [
+            Money::new(Currency::EUR, 10.0),
+            Money::new(Currency::USD, 5.0),
+            Money::new(Currency::EUR, 2.5),
+        ]
+        .into_iter()
+        .sum();
+        assert_eq!(bag.get(Currency::EUR), Money::new(Currency::EUR, 12.5));
+        assert_eq!(bag.get(Currency::USD), Money::new(Currency::USD, 5.0));
+        assert_eq!(bag.single(), None);
+        assert_eq!(bag.to_string(), "12.50 EUR, 5.00 USD");
+    }
+
+    #[test]
+    fn allocate_spreads_leftover_cents() {
+        let parts = Money::new(Currency::EUR, 100.0)
+            .allocate(&[1.0, 1.0, 1.0])
+            .unwrap();
+        let cents = parts.iter().map(Money::minor_units).collect::<Vec<_>>();
+        assert_eq!(cents, vec![3334, 3333, 3333]);
+        assert_eq!(Money::new(Currency::EUR, 1.0).allocate(&[0.0]), None);
+        assert_eq!(Money::new(Currency::EUR, 1.0).allocate(&[-1.0, 2.0]), None);
+    }
+
+    const CURRENCIES: [Currency; 6] = [
+        Currency::USD,
+        Currency::EUR,
+        Currency::CHF,
+        Currency::JPY,
+        Currency::PLN,
+        Currency::GBP,
+    ];
+
+    proptest! {
+        #[test]
+        fn add_sub_round_trip(
+            a in -1e12..1e12f64,
+            b in -1e12..1e12f64,
+            currency in prop::sample::select(CURRENCIES.to_vec()),
+        ) {
+            let x = Money::new(currency, a);
+            let y = Money::new(currency, b);
+            let back = ((x + y).unwrap() - y).unwrap();
+            prop_assert_eq!(back.currency, currency);
+            prop_assert!((back.amount - a).abs() <= 1e-3);
+            prop_assert_eq!(-(-x), x);
+        }
+
+        #[test]
+        fn mixed_currencies_never_combine(
+            a in -1e6..1e6f64,
+            i in 0..6usize,
+            j in 0..6usize,
+        ) {
+            prop_assume!(i != j);
+            let x = Money::new(CURRENCIES[i], a);
+            let y = Money::new(CURRENCIES[j], a);
+            prop_assert!((x + y).is_err());
+            prop_assert!((x - y).is_err());
+        }
+
+        #[test]
+        fn allocation_preserves_total(
+            amount in -1e9..1e9f64,
+            weights in prop::collection::vec(0.0..1000.0f64, 1..20),
+            currency in prop::sample::select(CURRENCIES.to_vec()),
+        ) {
+            prop_assume!(weights.iter().sum::<f64>() > 0.0);
+            let money = Money::new(currency, amount);
+            let parts = money.allocate(&weights).unwrap();
+            prop_assert_eq!(parts.len(), weights.len());
+            prop_assert_eq!(
+                parts.iter().map(Money::minor_units).sum::<i64>(),
+                money.minor_units()
+            );
+            let sum = weights.iter().sum::<f64>();
+            for (part, w) in parts.iter().zip(&weights) {
+                let exact = money.minor_units() as f64 * w / sum;
+                prop_assert!((part.minor_units() as f64 - exact).abs() < 1.0 + 1e-6);
+                prop_assert_eq!(part.currency, currency);
+            }
+        }
+    }
+}
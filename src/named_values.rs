@@ -0,0 +1,139 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::ClientError;
+
+/// One `{"name": ..., "value": ...}` entry.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct NamedValue {
+    pub name: String,
+    #[serde(default)]
+    pub value: Option<Value>,
+}
+
+/// DEGIRO's `[{"name": ..., "value": ...}]` wire format, used for portfolio
+/// rows and orders, with typed lookups by name.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct NamedValues(pub Vec<NamedValue>);
+
+impl NamedValues {
+    /// Raw value of `name`; missing and `null` values are both `None`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0
+            .iter()
+            .find(|x| x.name == name)
+            .and_then(|x| x.value.as_ref())
+            .filter(|x| !x.is_null())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|x| x.name.as_str())
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, NamedValue> {
+        self.0.iter()
+    }
+
+    fn missing(name: &str) -> ClientError {
+        ClientError::ParseError(format!("Cannot find key: {name}"))
+    }
+
+    /// Deserializes `name`, failing when it is missing.
+    pub fn field<'a, T: Deserialize<'a>>(&'a self, name: &str) -> Result<T, ClientError> {
+        self.field_with(name, T::deserialize)
+    }
+
+    /// Like [`NamedValues::field`] but `None` when missing.
+    pub fn optional<'a, T: Deserialize<'a>>(
+        &'a self,
+        name: &str,
+    ) -> Result<Option<T>, ClientError> {
+        self.get(name)
+            .map(|x| T::deserialize(x).map_err(ClientError::from))
+            .transpose()
+    }
+
+    /// Reads `name` with a custom deserializer, e.g. one of the `*_id`
+    /// modules in [`crate::util`].
+    pub fn field_with<'a, T, E>(
+        &'a self,
+        name: &str,
+        f: impl FnOnce(&'a Value) -> Result<T, E>,
+    ) -> Result<T, ClientError>
+    where
+        ClientError: From<E>,
+    {
+        let value = self.get(name).ok_or_else(|| Self::missing(name))?;
+        Ok(f(value)?)
+    }
+
+    /// Parses a string value of `name` with [`FromStr`].
+    pub fn parse<T>(&self, name: &str) -> Result<T, ClientError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let s = self
+            .get(name)
+            .ok_or_else(|| Self::missing(name))?
+            .as_str()
+            .ok_or_else(|| ClientError::ParseError(format!("{name} is not a string")))?;
+        s.parse()
+            .map_err(|err| ClientError::ParseError(format!("{name}: {err}")))
+    }
+}
+
+impl<'a> IntoIterator for &'a NamedValues {
+    type Item = &'a NamedValue;
+    type IntoIter = std::slice::Iter<'a, NamedValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::{order_type_id, OrderType};
+
+    #[test]
+    fn typed_lookups() {
+        let values: NamedValues = serde_json::from_value(serde_json::json!([
+            {"name": "id", "value": "123"},
+            {"name": "size", "value": 10.5},
+            {"name": "orderTypeId", "value": 0},
+            {"name": "stopPrice", "value": null},
+            {"name": "empty"},
+        ]))
+        .unwrap();
+
+        assert_eq!(values.field::<&str>("id").unwrap(), "123");
+        assert_eq!(values.parse::<i64>("id").unwrap(), 123);
+        assert_eq!(values.field::<f64>("size").unwrap(), 10.5);
+        assert_eq!(
+            values
+                .field_with("orderTypeId", order_type_id::deserialize)
+                .unwrap(),
+            OrderType::Limit
+        );
+        assert_eq!(values.optional::<f64>("stopPrice").unwrap(), None);
+        assert_eq!(values.optional::<f64>("empty").unwrap(), None);
+        assert!(matches!(
+            values.field::<f64>("price"),
+            Err(ClientError::ParseError(_))
+        ));
+        assert!(matches!(
+            values.field::<f64>("id"),
+            Err(ClientError::SerdeError(_))
+        ));
+        assert_eq!(values.names().count(), 5);
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{product::ProductDetails, search::QueryProductDetails},
+    client::Client,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexedProduct {
+    pub id: String,
+    pub symbol: String,
+    pub name: String,
+    pub isin: String,
+}
+
+impl From<&ProductDetails> for IndexedProduct {
+    fn from(p: &ProductDetails) -> Self {
+        Self {
+            id: p.id.clone(),
+            symbol: p.symbol.clone(),
+            name: p.name.clone(),
+            isin: p.isin.clone(),
+        }
+    }
+}
+
+impl From<&QueryProductDetails> for IndexedProduct {
+    fn from(p: &QueryProductDetails) -> Self {
+        Self {
+            id: p.id.clone(),
+            symbol: p.symbol.clone(),
+            name: p.name.clone(),
+            isin: p.isin.clone(),
+        }
+    }
+}
+
+/// In-memory index of every product the client has seen, searchable offline.
+#[derive(Clone, Debug, Default)]
+pub struct ProductIndex {
+    entries: HashMap<String, IndexedProduct>,
+}
+
+impl ProductIndex {
+    pub fn insert(&mut self, product: impl Into<IndexedProduct>) {
+        let product = product.into();
+        self.entries.insert(product.id.clone(), product);
+    }
+    pub fn get(&self, id: &str) -> Option<&IndexedProduct> {
+        self.entries.get(id)
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &IndexedProduct> {
+        self.entries.values()
+    }
+
+    /// Best matches for `query` by symbol, ISIN or name, tolerating small typos.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<&IndexedProduct> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut xs = self
+            .entries
+            .values()
+            .filter_map(|p| score(&query, p).map(|s| (s, p)))
+            .collect::<Vec<_>>();
+        xs.sort_by(|(a, pa), (b, pb)| b.cmp(a).then_with(|| pa.symbol.cmp(&pb.symbol)));
+        xs.into_iter().take(limit).map(|(_, p)| p).collect()
+    }
+}
+
+fn score(query: &str, p: &IndexedProduct) -> Option<u32> {
+    let symbol = p.symbol.to_lowercase();
+    let name = p.name.to_lowercase();
+    let isin = p.isin.to_lowercase();
+
+    if symbol == query {
+        return Some(1000);
+    }
+    if isin == query {
+        return Some(950);
+    }
+    if symbol.starts_with(query) {
+        return Some(800 - (symbol.len() - query.len()).min(100) as u32);
+    }
+    if name.starts_with(query) {
+        return Some(600);
+    }
+    if name.split_whitespace().any(|word| word.starts_with(query)) {
+        return Some(500);
+    }
+    if symbol.contains(query) || name.contains(query) {
+        return Some(400);
+    }
+    if query.len() > 2 && levenshtein(query, &symbol) <= 1 {
+        return Some(300);
+    }
+    subsequence_gaps(query, &name).map(|gaps| 200 - gaps.min(199) as u32)
+}
+
+/// Number of skipped characters when `needle` is matched in order inside `haystack`.
+fn subsequence_gaps(needle: &str, haystack: &str) -> Option<usize> {
+    let mut chars = haystack.chars();
+    let mut gaps = 0;
+    for c in needle.chars() {
+        loop {
+            let h = chars.next()?;
+            if h == c {
+                break;
+            }
+            gaps += 1;
+        }
+    }
+    Some(gaps)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+impl Client {
+    pub fn product_index(&self) -> ProductIndex {
+        self.inner.lock().unwrap().product_index.clone()
+    }
+
+    /// Fuzzy lookup over products seen so far, without any API call.
+    pub fn search_local(&self, query: &str, limit: usize) -> Vec<IndexedProduct> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .product_index
+            .search(query, limit)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn index() -> ProductIndex {
+        let mut index = ProductIndex::default();
+        for (id, symbol, name, isin) in [
+            ("1", "MSFT", "Microsoft Corp", "US5949181045"),
+            ("2", "AAPL", "Apple Inc", "US0378331005"),
+            ("3", "PG", "Procter & Gamble Co", "US7427181091"),
+        ] {
+            index.insert(IndexedProduct {
+                id: id.to_string(),
+                symbol: symbol.to_string(),
+                name: name.to_string(),
+                isin: isin.to_string(),
+            });
+        }
+        index
+    }
+
+    #[test]
+    fn fuzzy_search() {
+        let index = index();
+        assert_eq!(index.search("msft", 1)[0].id, "1");
+        assert_eq!(index.search("US0378331005", 1)[0].id, "2");
+        assert_eq!(index.search("gamble", 1)[0].id, "3");
+        assert_eq!(index.search("MSFY", 1)[0].id, "1");
+        assert_eq!(index.search("mcrsft", 1)[0].id, "1");
+        assert!(index.search("zzz", 5).is_empty());
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::Duration;
+use serde::Serialize;
+
+use crate::{
+    api::{orders::CreateOrderRequest, product::LiquidityScore},
+    client::{Client, ClientError},
+    money::Money,
+    util::TransactionType,
+};
+
+/// One held product as seen by the risk calculator. Values are in the
+/// account base currency.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RiskPosition {
+    pub product_id: String,
+    /// Signed, negative for shorts.
+    pub value: f64,
+    pub sector: Option<String>,
+    /// Earnings or another scheduled event falls within the horizon.
+    pub has_event: bool,
+    /// Average value traded per day, when known.
+    pub avg_daily_value: Option<f64>,
+    /// [`LiquidityScore`] of the product, used when the average daily value
+    /// is unknown.
+    pub liquidity_score: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RiskData {
+    pub positions: Vec<RiskPosition>,
+}
+
+impl RiskData {
+    pub fn new(positions: Vec<RiskPosition>) -> Self {
+        Self { positions }
+    }
+
+    pub fn gross_exposure(&self) -> f64 {
+        self.positions.iter().map(|p| p.value.abs()).sum()
+    }
+
+    pub fn net_exposure(&self) -> f64 {
+        self.positions.iter().map(|p| p.value).sum()
+    }
+
+    /// Copy with `allocation` (signed, base currency) added to `product`, as
+    /// if an order for it had filled. A product not held yet comes in with
+    /// the sector, event flag and volume of `product`.
+    pub fn with_hypothetical(&self, product: RiskPosition, allocation: f64) -> Self {
+        let mut data = self.clone();
+        match data
+            .positions
+            .iter_mut()
+            .find(|p| p.product_id == product.product_id)
+        {
+            Some(p) => p.value += allocation,
+            None => data.positions.push(RiskPosition {
+                value: allocation,
+                ..product
+            }),
+        }
+        data
+    }
+}
+
+/// Portfolio wide risk figures.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioRisk {
+    pub gross_exposure: f64,
+    pub net_exposure: f64,
+    /// Share of the gross exposure in products with an upcoming event.
+    pub event_risk: f64,
+    /// Herfindahl index of sector weights, from `1 / sectors` when spread
+    /// evenly up to 1.0 for a single sector. Products without a sector count
+    /// as a sector of their own.
+    pub sector_risk: f64,
+    pub largest_sector: Option<(String, f64)>,
+    /// Exposure weighted days needed to unwind every position.
+    pub liquidity_days: f64,
+}
+
+impl PortfolioRisk {
+    pub fn get(&self, kind: RiskKind) -> f64 {
+        match kind {
+            RiskKind::Event => self.event_risk,
+            RiskKind::Sector => self.sector_risk,
+            RiskKind::Liquidity => self.liquidity_days,
+        }
+    }
+}
+
+/// Share of a single position in each [`PortfolioRisk`] figure; summed over
+/// all positions they give the portfolio figure back.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RiskContribution {
+    pub product_id: String,
+    /// Share of the gross exposure.
+    pub weight: f64,
+    pub event: f64,
+    pub sector: f64,
+    pub liquidity_days: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RiskKind {
+    Event,
+    Sector,
+    Liquidity,
+}
+
+impl RiskContribution {
+    pub fn get(&self, kind: RiskKind) -> f64 {
+        match kind {
+            RiskKind::Event => self.event,
+            RiskKind::Sector => self.sector,
+            RiskKind::Liquidity => self.liquidity_days,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RiskBreakdown {
+    pub risk: PortfolioRisk,
+    pub contributions: Vec<RiskContribution>,
+}
+
+impl RiskBreakdown {
+    /// Positions adding most to `kind` first, the ones to trim.
+    pub fn ranked(&self, kind: RiskKind) -> Vec<&RiskContribution> {
+        let mut xs = self.contributions.iter().collect::<Vec<_>>();
+        xs.sort_by(|a, b| b.get(kind).total_cmp(&a.get(kind)));
+        xs
+    }
+}
+
+/// Upper bounds for pre-trade checks; unset ones are not checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RiskLimits {
+    pub max_event_risk: Option<f64>,
+    pub max_sector_risk: Option<f64>,
+    pub max_liquidity_days: Option<f64>,
+}
+
+impl RiskLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_event_risk(mut self, max: f64) -> Self {
+        self.max_event_risk = Some(max);
+        self
+    }
+
+    pub fn max_sector_risk(mut self, max: f64) -> Self {
+        self.max_sector_risk = Some(max);
+        self
+    }
+
+    pub fn max_liquidity_days(mut self, max: f64) -> Self {
+        self.max_liquidity_days = Some(max);
+        self
+    }
+
+    fn get(&self, kind: RiskKind) -> Option<f64> {
+        match kind {
+            RiskKind::Event => self.max_event_risk,
+            RiskKind::Sector => self.max_sector_risk,
+            RiskKind::Liquidity => self.max_liquidity_days,
+        }
+    }
+}
+
+/// Limits set with [`Client::set_risk_limits`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PreTradeRisk {
+    pub(crate) limits: RiskLimits,
+    pub(crate) event_days: i64,
+}
+
+/// Portfolio risk before and after a simulated order.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RiskImpact {
+    pub before: PortfolioRisk,
+    pub after: PortfolioRisk,
+}
+
+impl RiskImpact {
+    pub fn delta(&self, kind: RiskKind) -> f64 {
+        self.after.get(kind) - self.before.get(kind)
+    }
+
+    /// Fails when the order takes a figure over its limit or raises one that
+    /// is over it already; orders reducing risk always pass.
+    pub fn check(&self, limits: &RiskLimits) -> Result<(), ClientError> {
+        for kind in [RiskKind::Event, RiskKind::Sector, RiskKind::Liquidity] {
+            let Some(max) = limits.get(kind) else {
+                continue;
+            };
+            let after = self.after.get(kind);
+            if after > max && self.delta(kind) > 0.0 {
+                return Err(ClientError::RiskLimitBreached(format!(
+                    "{kind:?} risk {after:.4} over {max}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RiskCalculator {
+    /// Share of the average daily value that can be traded without moving
+    /// the price.
+    pub participation: f64,
+    /// Days assumed for positions without volume data, scaled down by their
+    /// liquidity score when there is one.
+    pub unknown_liquidity_days: f64,
+}
+
+impl Default for RiskCalculator {
+    fn default() -> Self {
+        Self {
+            participation: 0.1,
+            unknown_liquidity_days: 5.0,
+        }
+    }
+}
+
+impl RiskCalculator {
+    pub fn portfolio_risk(&self, data: &RiskData) -> PortfolioRisk {
+        self.breakdown(data).risk
+    }
+
+    fn liquidity_days(&self, p: &RiskPosition) -> f64 {
+        match p.avg_daily_value {
+            Some(adv) if adv > 0.0 && self.participation > 0.0 => {
+                p.value.abs() / (adv * self.participation)
+            }
+            _ => self.unknown_liquidity_days * (1.0 - p.liquidity_score.unwrap_or(0.0)),
+        }
+    }
+
+    /// Portfolio risk together with every position's contribution to it.
+    pub fn breakdown(&self, data: &RiskData) -> RiskBreakdown {
+        let gross = data.gross_exposure();
+        if gross == 0.0 {
+            return RiskBreakdown::default();
+        }
+        let sector_key =
+            |p: &RiskPosition| p.sector.clone().unwrap_or_else(|| p.product_id.clone());
+        let mut sectors: BTreeMap<String, f64> = BTreeMap::new();
+        for p in &data.positions {
+            *sectors.entry(sector_key(p)).or_default() += p.value.abs() / gross;
+        }
+
+        let contributions = data
+            .positions
+            .iter()
+            .map(|p| {
+                let weight = p.value.abs() / gross;
+                RiskContribution {
+                    product_id: p.product_id.clone(),
+                    weight,
+                    event: if p.has_event { weight } else { 0.0 },
+                    // Euler split of the Herfindahl index: weight times the
+                    // weight of the whole sector.
+                    sector: weight * sectors[&sector_key(p)],
+                    liquidity_days: weight * self.liquidity_days(p),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let largest_sector = data
+            .positions
+            .iter()
+            .filter_map(|p| p.sector.as_ref())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|s| (s.clone(), sectors[s]))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let risk = PortfolioRisk {
+            gross_exposure: gross,
+            net_exposure: data.net_exposure(),
+            event_risk: contributions.iter().map(|x| x.event).sum(),
+            sector_risk: contributions.iter().map(|x| x.sector).sum(),
+            largest_sector,
+            liquidity_days: contributions.iter().map(|x| x.liquidity_days).sum(),
+        };
+        RiskBreakdown {
+            risk,
+            contributions,
+        }
+    }
+}
+
+impl Client {
+    /// Sector and average daily volume in shares, `None` where the product has
+    /// no such data.
+    async fn risk_inputs(
+        &self,
+        id: &str,
+        isin: &str,
+    ) -> Result<(Option<String>, Option<f64>), ClientError> {
+        let sector = match self.company_profile(isin).await {
+            Ok(profile) => Some(profile.sector).filter(|s| !s.is_empty()),
+            Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+            Err(_) => None,
+        };
+        let volume = match self.company_ratios(id, isin).await {
+            // Reported in millions of shares.
+            Ok(ratios) => ratios.current_ratios.volume_avg_10d.value.map(|v| v * 1e6),
+            Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+            Err(_) => None,
+        };
+        Ok((sector, volume))
+    }
+
+    /// Risk inputs for every open product position: sectors from company
+    /// profiles, volume from company ratios and earnings within `event_days`.
+    ///
+    /// Products without a profile or ratios, like most ETFs, are kept without
+    /// sector or volume.
+    pub async fn risk_data(&self, event_days: i64) -> Result<RiskData, ClientError> {
+        let portfolio = self.portfolio().await?.current().products();
+        if portfolio.is_empty() {
+            return Ok(RiskData::default());
+        }
+        let ids = portfolio
+            .iter()
+            .map(|p| p.inner.id.clone())
+            .collect::<Vec<_>>();
+        let products = self.products(&ids).await?;
+        let events = self
+            .upcoming_earnings(event_days)
+            .await?
+            .into_iter()
+            .map(|x| x.product_id)
+            .collect::<HashSet<_>>();
+
+        let positions = portfolio
+            .iter()
+            .filter_map(|p| {
+                let product = products.get(&p.inner.id)?;
+                let has_event = events.contains(&p.inner.id);
+                Some((
+                    p.inner.clone(),
+                    product.inner.isin.clone(),
+                    product.inner.order_book_depth,
+                    has_event,
+                ))
+            })
+            .collect::<Vec<_>>();
+        let results = self
+            .batched(
+                positions,
+                4,
+                |client, (p, isin, depth, has_event)| async move {
+                    let (sector, volume) = client.risk_inputs(&p.id, &isin).await?;
+                    // Value over size is the price per share in the base currency.
+                    let avg_daily_value = volume
+                        .filter(|_| p.size != 0.0)
+                        .map(|v| v * (p.value.amount / p.size).abs());
+                    let liquidity_score = LiquidityScore::new(depth, avg_daily_value, None).score;
+                    Ok(RiskPosition {
+                        has_event,
+                        product_id: p.id,
+                        value: p.value.amount,
+                        sector,
+                        avg_daily_value,
+                        liquidity_score,
+                    })
+                },
+            )
+            .await;
+        Ok(RiskData::new(
+            results.into_iter().collect::<Result<_, _>>()?,
+        ))
+    }
+
+    pub async fn portfolio_risk(&self, event_days: i64) -> Result<RiskBreakdown, ClientError> {
+        let data = self.risk_data(event_days).await?;
+        Ok(RiskCalculator::default().breakdown(&data))
+    }
+
+    /// Has every order placed through [`Client::confirm_checked_order`],
+    /// and so [`CreateOrderRequest::place`], simulated first and refused with
+    /// [`ClientError::RiskLimitBreached`] when it breaks `limits`. Earnings
+    /// within `event_days` count as event risk. An order whose risk can't be
+    /// simulated isn't placed either.
+    pub fn set_risk_limits(&self, limits: RiskLimits, event_days: i64) {
+        self.inner.lock().unwrap().pre_trade_risk = Some(PreTradeRisk { limits, event_days });
+    }
+
+    /// Places orders without checking them against risk limits again.
+    pub fn clear_risk_limits(&self) {
+        self.inner.lock().unwrap().pre_trade_risk = None;
+    }
+
+    pub fn risk_limits(&self) -> Option<RiskLimits> {
+        self.inner.lock().unwrap().pre_trade_risk.map(|x| x.limits)
+    }
+
+    /// Checks `order` against the limits set with
+    /// [`Client::set_risk_limits`], passing when there are none.
+    pub(crate) async fn check_order_risk(
+        &self,
+        order: &CreateOrderRequest,
+    ) -> Result<(), ClientError> {
+        let Some(PreTradeRisk { limits, event_days }) = self.inner.lock().unwrap().pre_trade_risk
+        else {
+            return Ok(());
+        };
+        self.simulate_order_risk(order, event_days)
+            .await?
+            .check(&limits)
+    }
+
+    /// Portfolio risk as if `order` had filled at its limit price, or at the
+    /// last close for orders without one. Check the result against
+    /// [`RiskLimits`] before sending the order, or let
+    /// [`Client::set_risk_limits`] do it.
+    pub async fn simulate_order_risk(
+        &self,
+        order: &CreateOrderRequest,
+        event_days: i64,
+    ) -> Result<RiskImpact, ClientError> {
+        let data = self.risk_data(event_days).await?;
+        let product = self.product(order.product_id()).await?;
+        let info = self.account_info().await?;
+        let close = product.close_price_money().ok_or_else(|| {
+            ClientError::Descripted(format!("unknown currency of {}", order.product_id()))
+        })?;
+        let price = order
+            .price()
+            .map_or(close, |price| Money::new(close.currency, price));
+        let price = info.convert(price, info.base_currency()?)?.amount;
+        let allocation = match order.transaction_type() {
+            TransactionType::Buy => price * order.size() as f64,
+            TransactionType::Sell => -price * order.size() as f64,
+        };
+
+        let held = data
+            .positions
+            .iter()
+            .any(|p| p.product_id == order.product_id());
+        let position = if held {
+            RiskPosition {
+                product_id: order.product_id().to_string(),
+                ..Default::default()
+            }
+        } else {
+            let isin = &product.inner.isin;
+            let (sector, volume) = self.risk_inputs(order.product_id(), isin).await?;
+            let from = self.now();
+            let events = self
+                .earnings_calendar(Some(isin), from, from + Duration::days(event_days))
+                .await?;
+            RiskPosition {
+                product_id: order.product_id().to_string(),
+                value: 0.0,
+                sector,
+                has_event: !events.is_empty(),
+                avg_daily_value: volume.map(|v| v * price),
+                liquidity_score: LiquidityScore::new(
+                    product.inner.order_book_depth,
+                    volume.map(|v| v * price),
+                    None,
+                )
+                .score,
+            }
+        };
+
+        let calculator = RiskCalculator::default();
+        Ok(RiskImpact {
+            before: calculator.portfolio_risk(&data),
+            after: calculator.portfolio_risk(&data.with_hypothetical(position, allocation)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        client::ClientStatus,
+        transport::MockTransport,
+        util::{OrderTimeType, OrderType},
+    };
+
+    fn position(id: &str, value: f64, sector: Option<&str>, adv: Option<f64>) -> RiskPosition {
+        RiskPosition {
+            product_id: id.to_string(),
+            value,
+            sector: sector.map(str::to_string),
+            has_event: id == "b",
+            avg_daily_value: adv,
+            liquidity_score: None,
+        }
+    }
+
+    #[test]
+    fn contributions_add_up() {
+        let data = RiskData::new(vec![
+            position("a", 500.0, Some("Tech"), Some(100_000.0)),
+            position("b", 300.0, Some("Tech"), Some(1_000.0)),
+            position("c", -200.0, None, None),
+        ]);
+        let out = RiskCalculator::default().breakdown(&data);
+        let risk = &out.risk;
+
+        assert_eq!(risk.gross_exposure, 1000.0);
+        assert_eq!(risk.net_exposure, 600.0);
+        assert!((risk.event_risk - 0.3).abs() < 1e-12);
+        // Tech 0.8, unclassified 0.2.
+        assert!((risk.sector_risk - (0.64 + 0.04)).abs() < 1e-12);
+        assert_eq!(risk.largest_sector.as_ref().unwrap().0, "Tech");
+        // a: 500 / 10_000 days, b: 300 / 100 days, c: 5 days.
+        assert!((risk.liquidity_days - (0.5 * 0.05 + 0.3 * 3.0 + 0.2 * 5.0)).abs() < 1e-12);
+
+        for kind in [RiskKind::Event, RiskKind::Sector, RiskKind::Liquidity] {
+            let total: f64 = out.contributions.iter().map(|x| x.get(kind)).sum();
+            let expected = match kind {
+                RiskKind::Event => risk.event_risk,
+                RiskKind::Sector => risk.sector_risk,
+                RiskKind::Liquidity => risk.liquidity_days,
+            };
+            assert!((total - expected).abs() < 1e-12);
+        }
+        assert_eq!(out.ranked(RiskKind::Liquidity)[0].product_id, "c");
+        assert_eq!(out.ranked(RiskKind::Sector)[0].product_id, "a");
+
+        let scored = RiskPosition {
+            liquidity_score: Some(0.6),
+            ..position("c", 200.0, None, None)
+        };
+        assert!((RiskCalculator::default().liquidity_days(&scored) - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hypothetical_order() {
+        let calculator = RiskCalculator::default();
+        let data = RiskData::new(vec![
+            position("a", 500.0, Some("Tech"), Some(100_000.0)),
+            position("c", 500.0, Some("Energy"), Some(100_000.0)),
+        ]);
+        let new = position("d", 0.0, Some("Tech"), Some(100_000.0));
+        let impact = RiskImpact {
+            before: calculator.portfolio_risk(&data),
+            after: calculator.portfolio_risk(&data.with_hypothetical(new, 1000.0)),
+        };
+        assert_eq!(impact.after.gross_exposure, 2000.0);
+        assert!((impact.after.sector_risk - (0.75f64.powi(2) + 0.25f64.powi(2))).abs() < 1e-12);
+        assert!(impact.delta(RiskKind::Sector) > 0.0);
+        assert!(impact
+            .check(&RiskLimits::new().max_sector_risk(0.6))
+            .is_err());
+        assert!(impact
+            .check(&RiskLimits::new().max_sector_risk(0.7))
+            .is_ok());
+
+        let trimmed = data.with_hypothetical(position("a", 0.0, None, None), -250.0);
+        assert_eq!(trimmed.positions.len(), 2);
+        assert_eq!(trimmed.positions[0].value, 250.0);
+        assert_eq!(trimmed.positions[0].sector.as_deref(), Some("Tech"));
+    }
+
+    #[tokio::test]
+    async fn place_checks_risk_limits() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        let mock = Arc::new(MockTransport::new());
+        mock.json(
+            Method::POST,
+            "v5/checkOrder",
+            &json!({ "data": { "confirmationId": "c0ffee" } }),
+        )
+        .json(
+            Method::POST,
+            "v5/order/",
+            &json!({ "data": { "orderId": "6126ef1a" } }),
+        );
+        client.set_transport(mock.clone());
+        {
+            let mut inner = client.inner.lock().unwrap();
+            inner.status = ClientStatus::Authorized;
+            inner.session_id = "abc".to_string();
+            inner.account_config.trading_url = "https://trader.degiro.nl/trading/secure/".into();
+        }
+        let order = client
+            .create_order()
+            .product_id("1")
+            .transaction_type(TransactionType::Buy)
+            .size(1)
+            .time_type(OrderTimeType::Day)
+            .order_type(OrderType::Market)
+            .build()
+            .unwrap();
+        let confirmed = || {
+            mock.requests()
+                .iter()
+                .any(|x| x.url.path().contains("v5/order/"))
+        };
+
+        client.set_risk_limits(RiskLimits::new().max_sector_risk(0.5), 7);
+        // The portfolio can't be loaded, so the risk is unknown and the order
+        // stays unplaced.
+        assert!(order.place().await.is_err());
+        assert!(!confirmed());
+
+        client.clear_risk_limits();
+        order.place().await.unwrap();
+        assert!(confirmed());
+    }
+}
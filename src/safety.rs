@@ -0,0 +1,405 @@
+use std::{collections::VecDeque, fmt};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::{
+    api::equity::EquityCurve,
+    client::{Client, ClientError},
+    events::ClientEvent,
+    money::Money,
+};
+
+/// Thresholds after which the client stops placing and modifying orders.
+/// Unset limits are not checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KillSwitchLimits {
+    /// Largest loss of the day, as a positive amount in the base currency.
+    pub max_daily_loss: Option<f64>,
+    pub max_orders_per_day: Option<u32>,
+    pub max_orders_per_minute: Option<u32>,
+}
+
+impl KillSwitchLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_daily_loss(mut self, loss: f64) -> Self {
+        self.max_daily_loss = Some(loss.abs());
+        self
+    }
+
+    pub fn max_orders_per_day(mut self, count: u32) -> Self {
+        self.max_orders_per_day = Some(count);
+        self
+    }
+
+    pub fn max_orders_per_minute(mut self, count: u32) -> Self {
+        self.max_orders_per_minute = Some(count);
+        self
+    }
+}
+
+/// Why the kill switch tripped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TripReason {
+    DailyLoss {
+        pnl: Money,
+        limit: f64,
+    },
+    DailyOrders {
+        limit: u32,
+    },
+    OrderRate {
+        limit: u32,
+    },
+    /// Account value fell further below its peak than a
+    /// [`DrawdownMonitor`] allows; both as fractions.
+    Drawdown {
+        drawdown: f64,
+        limit: f64,
+    },
+    /// Tripped by hand with [`Client::trip_kill_switch`].
+    Manual(String),
+}
+
+impl fmt::Display for TripReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TripReason::DailyLoss { pnl, limit } => {
+                write!(f, "daily P&L {pnl} is below -{limit}")
+            }
+            TripReason::DailyOrders { limit } => write!(f, "more than {limit} orders today"),
+            TripReason::OrderRate { limit } => write!(f, "more than {limit} orders in a minute"),
+            TripReason::Drawdown { drawdown, limit } => write!(
+                f,
+                "drawdown of {:.1}% is over {:.1}%",
+                drawdown * 100.0,
+                limit * 100.0
+            ),
+            TripReason::Manual(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// Account value that fell too far below its peak.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Drawdown {
+    pub peak: f64,
+    pub value: f64,
+    /// Fall from the peak as a fraction of it.
+    pub drawdown: f64,
+}
+
+/// Trailing stop on the whole account: follows the peak account value and
+/// raises an alarm once the value falls more than `max_drawdown` (a
+/// fraction, `0.1` = 10%) below it. The alarm fires once per peak.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawdownMonitor {
+    max_drawdown: f64,
+    read_only: bool,
+    peak: Option<f64>,
+    alarmed: bool,
+}
+
+impl DrawdownMonitor {
+    pub fn new(max_drawdown: f64) -> Self {
+        Self {
+            max_drawdown: max_drawdown.abs(),
+            read_only: false,
+            peak: None,
+            alarmed: false,
+        }
+    }
+
+    /// Trips the kill switch along with the alarm.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Starts from the highest value of `curve`, so a drawdown already in
+    /// progress is noticed on the first update.
+    pub fn with_curve(mut self, curve: &EquityCurve) -> Self {
+        for point in &curve.points {
+            let value = point.value();
+            self.peak = Some(self.peak.map_or(value, |peak| peak.max(value)));
+        }
+        self
+    }
+
+    pub fn peak(&self) -> Option<f64> {
+        self.peak
+    }
+
+    /// Moves the peak by a deposit or, when negative, a withdrawal, so that
+    /// money moved in or out isn't taken for performance.
+    pub fn record_flow(&mut self, amount: f64) {
+        if let Some(peak) = &mut self.peak {
+            *peak += amount;
+        }
+    }
+
+    /// Feeds the current account value, returning the drawdown when it
+    /// crosses the limit.
+    pub fn update(&mut self, value: f64) -> Option<Drawdown> {
+        let peak = self.peak.map_or(value, |peak| peak.max(value));
+        self.peak = Some(peak);
+        if value >= peak {
+            self.alarmed = false;
+        }
+        if peak <= 0.0 || self.alarmed {
+            return None;
+        }
+        let drawdown = (peak - value) / peak;
+        (drawdown > self.max_drawdown).then(|| {
+            self.alarmed = true;
+            Drawdown {
+                peak,
+                value,
+                drawdown,
+            }
+        })
+    }
+}
+
+/// Order counts and trip state kept by the client.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct KillSwitch {
+    limits: KillSwitchLimits,
+    day: Option<NaiveDate>,
+    orders_today: u32,
+    /// Orders sent within the last minute.
+    recent: VecDeque<DateTime<Utc>>,
+    tripped: Option<TripReason>,
+}
+
+impl KillSwitch {
+    /// Counts an order about to be sent, refusing it when it would break a
+    /// limit. Returns the reason the first time the switch trips.
+    fn record_order(&mut self, now: DateTime<Utc>) -> Result<(), (TripReason, bool)> {
+        if let Some(reason) = &self.tripped {
+            return Err((reason.clone(), false));
+        }
+        if self.day != Some(now.date_naive()) {
+            self.day = Some(now.date_naive());
+            self.orders_today = 0;
+        }
+        while self
+            .recent
+            .front()
+            .is_some_and(|t| now - *t >= Duration::minutes(1))
+        {
+            self.recent.pop_front();
+        }
+        let reason = match self.limits {
+            KillSwitchLimits {
+                max_orders_per_day: Some(limit),
+                ..
+            } if self.orders_today >= limit => Some(TripReason::DailyOrders { limit }),
+            KillSwitchLimits {
+                max_orders_per_minute: Some(limit),
+                ..
+            } if self.recent.len() >= limit as usize => Some(TripReason::OrderRate { limit }),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            self.tripped = Some(reason.clone());
+            return Err((reason, true));
+        }
+        self.orders_today += 1;
+        self.recent.push_back(now);
+        Ok(())
+    }
+
+    /// Trips on a loss larger than the limit; `pnl` is today's profit.
+    fn record_pnl(&mut self, pnl: Money) -> Option<TripReason> {
+        if self.tripped.is_some() {
+            return None;
+        }
+        let limit = self.limits.max_daily_loss?;
+        (pnl.amount < -limit).then(|| {
+            let reason = TripReason::DailyLoss { pnl, limit };
+            self.tripped = Some(reason.clone());
+            reason
+        })
+    }
+}
+
+impl Client {
+    pub fn set_kill_switch(&self, limits: KillSwitchLimits) {
+        self.inner.lock().unwrap().kill_switch.limits = limits;
+    }
+
+    pub fn kill_switch_limits(&self) -> KillSwitchLimits {
+        self.inner.lock().unwrap().kill_switch.limits
+    }
+
+    /// Whether the kill switch tripped; orders can still be deleted.
+    pub fn is_read_only(&self) -> bool {
+        self.inner.lock().unwrap().kill_switch.tripped.is_some()
+    }
+
+    pub fn trip_reason(&self) -> Option<TripReason> {
+        self.inner.lock().unwrap().kill_switch.tripped.clone()
+    }
+
+    /// Puts the client into read-only mode by hand.
+    pub fn trip_kill_switch(&self, reason: impl Into<String>) {
+        self.trip(TripReason::Manual(reason.into()));
+    }
+
+    /// Leaves read-only mode. Order counts are kept.
+    pub fn reset_kill_switch(&self) {
+        self.inner.lock().unwrap().kill_switch.tripped = None;
+    }
+
+    fn trip(&self, reason: TripReason) {
+        let first = {
+            let mut inner = self.inner.lock().unwrap();
+            let first = inner.kill_switch.tripped.is_none();
+            inner.kill_switch.tripped.get_or_insert(reason.clone());
+            first
+        };
+        if first {
+            self.emit(ClientEvent::KillSwitchTripped(reason));
+        }
+    }
+
+    /// Counts an order against the limits, failing in read-only mode.
+    pub(crate) fn guard_order(&self) -> Result<(), ClientError> {
+        let now = self.now();
+        let res = self.inner.lock().unwrap().kill_switch.record_order(now);
+        match res {
+            Ok(()) => Ok(()),
+            Err((reason, first)) => {
+                if first {
+                    self.emit(ClientEvent::KillSwitchTripped(reason.clone()));
+                }
+                Err(ClientError::ReadOnly(reason))
+            }
+        }
+    }
+
+    /// Today's realized and unrealized profit in the base currency, tripping
+    /// the kill switch when the loss is over the limit. Call it periodically
+    /// while automation runs.
+    pub async fn check_daily_loss(&self) -> Result<Money, ClientError> {
+        let portfolio = self.portfolio().await?;
+        let info = self.account_info().await?;
+        let pnl = portfolio.today_pl_in(info.base_currency()?, &info)?;
+        let tripped = self.inner.lock().unwrap().kill_switch.record_pnl(pnl);
+        if let Some(reason) = tripped {
+            self.emit(ClientEvent::KillSwitchTripped(reason));
+        }
+        Ok(pnl)
+    }
+
+    /// Feeds the current account value in the base currency to `monitor`,
+    /// sending [`ClientEvent::DrawdownExceeded`] on an alarm. Call it
+    /// periodically like [`Client::check_daily_loss`].
+    pub async fn check_drawdown(
+        &self,
+        monitor: &mut DrawdownMonitor,
+    ) -> Result<Money, ClientError> {
+        let value = self.total_portfolio_value().await?;
+        if let Some(drawdown) = monitor.update(value.amount) {
+            self.emit(ClientEvent::DrawdownExceeded(drawdown));
+            if monitor.read_only {
+                self.trip(TripReason::Drawdown {
+                    drawdown: drawdown.drawdown,
+                    limit: monitor.max_drawdown,
+                });
+            }
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::money::Currency;
+
+    #[test]
+    fn trips_on_limits() {
+        let t0 = Utc.with_ymd_and_hms(2024, 1, 2, 10, 0, 0).unwrap();
+        let mut ks = KillSwitch {
+            limits: KillSwitchLimits::new()
+                .max_orders_per_minute(2)
+                .max_orders_per_day(3)
+                .max_daily_loss(100.0),
+            ..Default::default()
+        };
+        assert!(ks.record_order(t0).is_ok());
+        assert!(ks.record_order(t0 + Duration::seconds(10)).is_ok());
+        assert!(ks.record_order(t0 + Duration::seconds(61)).is_ok());
+        assert_eq!(
+            ks.record_order(t0 + Duration::minutes(5)),
+            Err((TripReason::DailyOrders { limit: 3 }, true))
+        );
+        assert!(ks
+            .record_order(t0 + Duration::minutes(6))
+            .is_err_and(|(_, first)| !first));
+
+        let mut ks = KillSwitch {
+            limits: ks.limits,
+            ..Default::default()
+        };
+        assert!(ks.record_order(t0).is_ok());
+        assert!(ks.record_order(t0).is_ok());
+        assert_eq!(
+            ks.record_order(t0 + Duration::seconds(30)),
+            Err((TripReason::OrderRate { limit: 2 }, true))
+        );
+
+        let mut ks = KillSwitch {
+            limits: ks.limits,
+            ..Default::default()
+        };
+        assert_eq!(ks.record_pnl(Money::new(Currency::EUR, -99.0)), None);
+        assert!(ks.record_pnl(Money::new(Currency::EUR, -101.0)).is_some());
+        assert!(ks.record_order(t0).is_err());
+    }
+
+    #[test]
+    fn drawdown_alarm() {
+        let mut monitor = DrawdownMonitor::new(0.1);
+        assert_eq!(monitor.update(1000.0), None);
+        assert_eq!(monitor.update(950.0), None);
+        let alarm = monitor.update(850.0).unwrap();
+        assert_eq!(alarm.peak, 1000.0);
+        assert!((alarm.drawdown - 0.15).abs() < 1e-9);
+        // Once per peak.
+        assert_eq!(monitor.update(800.0), None);
+        assert_eq!(monitor.update(1100.0), None);
+        assert!(monitor.update(980.0).is_some());
+
+        // A withdrawal isn't a loss.
+        let mut monitor = DrawdownMonitor::new(0.1);
+        monitor.update(1000.0);
+        monitor.record_flow(-500.0);
+        assert_eq!(monitor.update(500.0), None);
+        assert_eq!(monitor.peak(), Some(500.0));
+    }
+
+    #[tokio::test]
+    async fn read_only_blocks_orders() {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let mut events = client.events();
+        client.trip_kill_switch("maintenance");
+        assert!(client.is_read_only());
+        assert!(matches!(
+            client.guard_order(),
+            Err(ClientError::ReadOnly(TripReason::Manual(_)))
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Ok(ClientEvent::KillSwitchTripped(TripReason::Manual(_)))
+        ));
+        client.reset_kill_switch();
+        assert!(client.guard_order().is_ok());
+    }
+}
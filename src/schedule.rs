@@ -0,0 +1,255 @@
+use std::{collections::BTreeSet, future::Future};
+
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc, Weekday,
+};
+
+use crate::{client::Client, util::Exchange};
+
+/// Daylight saving rule of an exchange's time zone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DstRule {
+    None,
+    /// Last Sunday of March to the last Sunday of October, 01:00 UTC.
+    Eu,
+    /// Second Sunday of March to the first Sunday of November, 02:00 local.
+    Us,
+    /// First Sunday of October to the first Sunday of April, 02:00 local
+    /// standard time.
+    Au,
+}
+
+/// Regular session hours of an exchange. Only weekends are closed unless
+/// holidays are added; lunch breaks are not modelled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TradingCalendar {
+    /// Offset from UTC outside daylight saving time, in hours.
+    pub utc_offset: i32,
+    pub dst: DstRule,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    pub holidays: BTreeSet<NaiveDate>,
+}
+
+fn time(h: u32, m: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(h, m, 0).unwrap()
+}
+
+fn nth_sunday(year: i32, month: u32, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, n).unwrap()
+}
+
+fn last_sunday(year: i32, month: u32) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, 5)
+        .unwrap_or_else(|| nth_sunday(year, month, 4))
+}
+
+impl TradingCalendar {
+    pub fn new(utc_offset: i32, dst: DstRule, open: NaiveTime, close: NaiveTime) -> Self {
+        Self {
+            utc_offset,
+            dst,
+            open,
+            close,
+            holidays: BTreeSet::new(),
+        }
+    }
+
+    pub fn holidays(mut self, dates: impl IntoIterator<Item = NaiveDate>) -> Self {
+        self.holidays.extend(dates);
+        self
+    }
+
+    /// Regular hours of the exchanges DEGIRO lists, `None` for unknown ones.
+    pub fn for_exchange(exchange: Exchange) -> Option<Self> {
+        let (offset, dst, open, close) = match exchange {
+            Exchange::NSDQ | Exchange::NSY | Exchange::ASE | Exchange::TOR | Exchange::TSV => {
+                (-5, DstRule::Us, time(9, 30), time(16, 0))
+            }
+            Exchange::EAM | Exchange::XET | Exchange::EPA | Exchange::SWX | Exchange::OMX => {
+                (1, DstRule::Eu, time(9, 0), time(17, 30))
+            }
+            Exchange::TDG => (1, DstRule::Eu, time(8, 0), time(22, 0)),
+            Exchange::WSE => (1, DstRule::Eu, time(9, 0), time(17, 0)),
+            Exchange::OSL => (1, DstRule::Eu, time(9, 0), time(16, 20)),
+            Exchange::ATH => (2, DstRule::Eu, time(10, 0), time(17, 20)),
+            Exchange::LSE => (0, DstRule::Eu, time(8, 0), time(16, 30)),
+            Exchange::TSE => (9, DstRule::None, time(9, 0), time(15, 0)),
+            Exchange::HKS => (8, DstRule::None, time(9, 30), time(16, 0)),
+            Exchange::ASX => (10, DstRule::Au, time(10, 0), time(16, 0)),
+            Exchange::Unknown(_) => return None,
+        };
+        Some(Self::new(offset, dst, open, close))
+    }
+
+    fn is_dst(&self, at: DateTime<Utc>) -> bool {
+        let year = at.year();
+        let std = Duration::hours(self.utc_offset as i64);
+        let utc = |date: NaiveDate, h: u32| Utc.from_utc_datetime(&date.and_time(time(h, 0)));
+        match self.dst {
+            DstRule::None => false,
+            DstRule::Eu => utc(last_sunday(year, 3), 1) <= at && at < utc(last_sunday(year, 10), 1),
+            DstRule::Us => {
+                let start = utc(nth_sunday(year, 3, 2), 2) - std;
+                let end = utc(nth_sunday(year, 11, 1), 1) - std;
+                start <= at && at < end
+            }
+            DstRule::Au => {
+                let end = utc(nth_sunday(year, 4, 1), 2) - std;
+                let start = utc(nth_sunday(year, 10, 1), 2) - std;
+                at < end || start <= at
+            }
+        }
+    }
+
+    /// Offset of local time from UTC at `at`.
+    pub fn offset_at(&self, at: DateTime<Utc>) -> FixedOffset {
+        let hours = self.utc_offset + self.is_dst(at) as i32;
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    /// Weekday that isn't a holiday.
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays.contains(&date)
+    }
+
+    /// `time` on local `date` as UTC. Clocks change on Sunday nights, so the
+    /// offset at noon holds for the whole trading day.
+    fn at(&self, date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+        let noon = Utc.from_utc_datetime(&date.and_time(self::time(12, 0)));
+        let offset = self.offset_at(noon);
+        offset
+            .from_local_datetime(&date.and_time(time))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn local_date(&self, at: DateTime<Utc>) -> NaiveDate {
+        at.with_timezone(&self.offset_at(at)).date_naive()
+    }
+
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        let date = self.local_date(at);
+        self.is_trading_day(date)
+            && self.at(date, self.open) <= at
+            && at < self.at(date, self.close)
+    }
+
+    /// First session start after `after`, `None` when there is none within
+    /// a year.
+    pub fn next_open(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next(after, self.open)
+    }
+
+    /// First session end after `after`, `None` when there is none within a
+    /// year.
+    pub fn next_close(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next(after, self.close)
+    }
+
+    fn next(&self, after: DateTime<Utc>, time: NaiveTime) -> Option<DateTime<Utc>> {
+        self.local_date(after)
+            .iter_days()
+            .take(367)
+            .filter(|date| self.is_trading_day(*date))
+            .map(|date| self.at(date, time))
+            .find(|t| *t > after)
+    }
+}
+
+/// Next regular session start of `exchange` after `after`.
+pub fn next_market_open(exchange: Exchange, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    TradingCalendar::for_exchange(exchange)?.next_open(after)
+}
+
+impl Client {
+    /// Sleeps until the client's clock reaches `at`, in steps of at most a
+    /// minute so that a changed clock is noticed.
+    pub async fn sleep_until(&self, at: DateTime<Utc>) {
+        loop {
+            let Ok(left) = (at - self.now()).to_std() else {
+                return;
+            };
+            if left.is_zero() {
+                return;
+            }
+            tokio::time::sleep(left.min(std::time::Duration::from_secs(60))).await;
+        }
+    }
+
+    /// Runs `job` `delay` after every session start of `calendar`, until the
+    /// job fails or no session is left within a year.
+    pub async fn run_at_market_open<F, Fut, E>(
+        &self,
+        calendar: &TradingCalendar,
+        delay: Duration,
+        mut job: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+    {
+        // Start from `delay` earlier, so a job due right now isn't skipped.
+        while let Some(open) = calendar.next_open(self.now() - delay) {
+            self.sleep_until(open + delay).await;
+            job().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn session_times() {
+        let xet = TradingCalendar::for_exchange(Exchange::XET).unwrap();
+        // Friday evening in winter, next open is Monday 9:00 CET.
+        assert_eq!(
+            xet.next_open(utc(2024, 1, 5, 18, 0)),
+            Some(utc(2024, 1, 8, 8, 0))
+        );
+        // Summer time.
+        assert_eq!(
+            xet.next_open(utc(2024, 7, 1, 6, 0)),
+            Some(utc(2024, 7, 1, 7, 0))
+        );
+        assert!(xet.is_open(utc(2024, 7, 1, 7, 0)));
+        assert!(!xet.is_open(utc(2024, 7, 1, 15, 30)));
+
+        // New York switched on March 10th 2024, Europe only on the 31st.
+        let nyse = TradingCalendar::for_exchange(Exchange::NSY).unwrap();
+        assert_eq!(
+            nyse.next_open(utc(2024, 3, 11, 0, 0)),
+            Some(utc(2024, 3, 11, 13, 30))
+        );
+        assert_eq!(
+            next_market_open(Exchange::NSY, utc(2024, 3, 8, 0, 0)),
+            Some(utc(2024, 3, 8, 14, 30))
+        );
+
+        let holiday = NaiveDate::from_ymd_opt(2024, 12, 25).unwrap();
+        let lse = TradingCalendar::for_exchange(Exchange::LSE)
+            .unwrap()
+            .holidays([holiday]);
+        assert_eq!(
+            lse.next_close(utc(2024, 12, 24, 17, 0)),
+            Some(utc(2024, 12, 26, 16, 30))
+        );
+
+        let asx = TradingCalendar::for_exchange(Exchange::ASX).unwrap();
+        assert_eq!(
+            asx.next_open(utc(2024, 1, 8, 0, 0)),
+            Some(utc(2024, 1, 8, 23, 0))
+        );
+        assert_eq!(
+            next_market_open(Exchange::Unknown(1), utc(2024, 1, 1, 0, 0)),
+            None
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use std::{cmp::Ordering, fmt, sync::Arc};
+
+use crate::{
+    api::{
+        company_ratios::{CompanyRatios, RatioId},
+        financial_statements::{FinancialReports, Report},
+        product::{Product, Products},
+    },
+    client::{Client, ClientError},
+};
+
+/// Requests in flight while [`Screener::run`] loads fundamentals.
+const SCREEN_CONCURRENCY: usize = 4;
+
+/// What a screen is evaluated on: the product with its ratios and, when a
+/// metric asks for them, its financial statements.
+#[derive(Clone, Debug)]
+pub struct Fundamentals {
+    pub product: Product,
+    pub ratios: Option<CompanyRatios>,
+    pub statements: Option<FinancialReports>,
+    /// Trailing twelve months built from `statements`.
+    pub ttm: Option<Report>,
+}
+
+impl Fundamentals {
+    pub fn new(
+        product: Product,
+        ratios: Option<CompanyRatios>,
+        statements: Option<FinancialReports>,
+    ) -> Self {
+        let ttm = statements.as_ref().and_then(FinancialReports::ttm);
+        Self {
+            product,
+            ratios,
+            statements,
+            ttm,
+        }
+    }
+
+    pub fn ratio(&self, id: &RatioId) -> Option<f64> {
+        self.ratios.as_ref()?.ratios.value(id)
+    }
+}
+
+type MetricFn = dyn Fn(&Fundamentals) -> Option<f64> + Send + Sync;
+
+/// Named number computed from [`Fundamentals`], `None` when the inputs are
+/// missing.
+#[derive(Clone)]
+pub struct Metric {
+    pub name: String,
+    f: Arc<MetricFn>,
+    statements: bool,
+}
+
+impl fmt::Debug for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metric").field("name", &self.name).finish()
+    }
+}
+
+impl Metric {
+    /// Metric reading ratios only.
+    pub fn new(
+        name: impl Into<String>,
+        f: impl Fn(&Fundamentals) -> Option<f64> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            f: Arc::new(f),
+            statements: false,
+        }
+    }
+
+    /// Metric that needs the financial statements, fetched only for screens
+    /// using one.
+    pub fn from_statements(
+        name: impl Into<String>,
+        f: impl Fn(&Fundamentals) -> Option<f64> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            statements: true,
+            ..Self::new(name, f)
+        }
+    }
+
+    pub fn ratio(id: RatioId) -> Self {
+        let name = String::from(id.clone());
+        Self::new(name, move |x| x.ratio(&id))
+    }
+
+    /// Return on investment over the trailing twelve months, in percent.
+    pub fn roic() -> Self {
+        Self::new("roic", |x| x.ratio(&RatioId::ReturnOnInvestmentTtm))
+    }
+
+    /// Debt less cash and short term investments over trailing EBITDA;
+    /// `None` when EBITDA isn't positive.
+    pub fn net_debt_to_ebitda() -> Self {
+        Self::from_statements("net_debt_to_ebitda", |x| {
+            let ttm = x.ttm.as_ref()?;
+            let ebitda = ttm.ebitda();
+            (ebitda > 0.0)
+                .then(|| (ttm.total_debt() - ttm.cash_and_short_term_investments()) / ebitda)
+        })
+    }
+
+    pub fn value(&self, x: &Fundamentals) -> Option<f64> {
+        (self.f)(x).filter(|x| x.is_finite())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Bound {
+    Above(f64),
+    Below(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rank {
+    Highest,
+    Lowest,
+}
+
+/// Product that passed a screen, with the value of every criterion.
+#[derive(Clone, Debug)]
+pub struct ScreenResult {
+    pub product: Product,
+    pub values: Vec<(String, f64)>,
+    /// Ranking metric, `None` when not ranked or missing.
+    pub score: Option<f64>,
+}
+
+/// Filters products by fundamental criteria and ranks the ones passing.
+/// A product missing a criterion's value doesn't pass it.
+#[derive(Clone, Debug, Default)]
+pub struct Screener {
+    criteria: Vec<(Metric, Bound)>,
+    rank: Option<(Metric, Rank)>,
+}
+
+impl Screener {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps products with `metric` above `min`.
+    pub fn above(mut self, metric: Metric, min: f64) -> Self {
+        self.criteria.push((metric, Bound::Above(min)));
+        self
+    }
+
+    /// Keeps products with `metric` below `max`.
+    pub fn below(mut self, metric: Metric, max: f64) -> Self {
+        self.criteria.push((metric, Bound::Below(max)));
+        self
+    }
+
+    /// Orders the results by `metric`, products without it last.
+    pub fn rank_by(mut self, metric: Metric, rank: Rank) -> Self {
+        self.rank = Some((metric, rank));
+        self
+    }
+
+    fn needs_statements(&self) -> bool {
+        self.criteria
+            .iter()
+            .map(|(metric, _)| metric)
+            .chain(self.rank.as_ref().map(|(metric, _)| metric))
+            .any(|metric| metric.statements)
+    }
+
+    /// Applies the screen to fundamentals loaded elsewhere.
+    pub fn screen(&self, xs: impl IntoIterator<Item = Fundamentals>) -> Vec<ScreenResult> {
+        let mut results = Vec::new();
+        'products: for x in xs {
+            let mut values = Vec::with_capacity(self.criteria.len());
+            for (metric, bound) in &self.criteria {
+                let Some(value) = metric.value(&x) else {
+                    continue 'products;
+                };
+                let passes = match bound {
+                    Bound::Above(min) => value > *min,
+                    Bound::Below(max) => value < *max,
+                };
+                if !passes {
+                    continue 'products;
+                }
+                values.push((metric.name.clone(), value));
+            }
+            let score = self.rank.as_ref().and_then(|(metric, _)| metric.value(&x));
+            results.push(ScreenResult {
+                product: x.product,
+                values,
+                score,
+            });
+        }
+        if let Some((_, rank)) = &self.rank {
+            results.sort_by(|a, b| match (a.score, b.score) {
+                (Some(a), Some(b)) => match rank {
+                    Rank::Highest => b.total_cmp(&a),
+                    Rank::Lowest => a.total_cmp(&b),
+                },
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            });
+        }
+        results
+    }
+
+    /// Loads ratios, and statements when a metric needs them, for every
+    /// product and screens them. Products whose data can't be loaded are
+    /// screened without it; only [`ClientError::Unauthorized`] fails.
+    pub async fn run(
+        &self,
+        client: &Client,
+        products: Products,
+    ) -> Result<Vec<ScreenResult>, ClientError> {
+        let statements = self.needs_statements();
+        let products = products
+            .0
+            .into_values()
+            .filter(|p| !p.inner.isin.is_empty())
+            .collect::<Vec<_>>();
+        let loaded = client
+            .batched(products, SCREEN_CONCURRENCY, |client, product| async move {
+                let (id, isin) = (&product.inner.id, &product.inner.isin);
+                let ratios = match client.company_ratios(id, isin).await {
+                    Ok(x) => Some(x),
+                    Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+                    Err(_) => None,
+                };
+                let reports = if statements {
+                    match client.financial_statements(id, isin).await {
+                        Ok(x) => Some(x),
+                        Err(ClientError::Unauthorized) => return Err(ClientError::Unauthorized),
+                        Err(_) => None,
+                    }
+                } else {
+                    None
+                };
+                Ok(Fundamentals::new(product, ratios, reports))
+            })
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.screen(loaded))
+    }
+
+    /// Screens the products found by `query`, see [`Client::search_products`].
+    pub async fn run_search(
+        &self,
+        client: &Client,
+        query: &str,
+    ) -> Result<Vec<ScreenResult>, ClientError> {
+        let products = client.search_products(query).await?;
+        self.run(client, products).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::api::{company_ratios::RatioValue, product::ProductDetails};
+
+    fn company(id: &str, roic: Option<f64>) -> Fundamentals {
+        let client = Client::new("", "", reqwest::Client::new(), Default::default());
+        let inner: ProductDetails = serde_json::from_value(json!({
+            "category": "A",
+            "closePrice": 10.0,
+            "closePriceDate": "2024-01-31",
+            "contractSize": 1.0,
+            "exchangeId": "663",
+            "id": id,
+            "isin": format!("US{id}"),
+            "name": id,
+            "productType": "STOCK",
+            "productTypeId": 1,
+            "symbol": id,
+        }))
+        .unwrap();
+        let mut ratios = CompanyRatios::default();
+        ratios.ratios.insert(
+            RatioId::ReturnOnInvestmentTtm,
+            RatioValue {
+                meaning: String::new(),
+                value: roic.map(|x| x.to_string()),
+            },
+        );
+        Fundamentals::new(Product { inner, client }, Some(ratios), None)
+    }
+
+    #[test]
+    fn screen_filters_and_ranks() {
+        let screener = Screener::new()
+            .above(Metric::roic(), 15.0)
+            .rank_by(Metric::roic(), Rank::Highest);
+        assert!(!screener.needs_statements());
+        let results = screener.screen([
+            company("1", Some(18.0)),
+            company("2", Some(12.0)),
+            company("3", None),
+            company("4", Some(30.0)),
+        ]);
+        let ids = results
+            .iter()
+            .map(|x| x.product.inner.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["4", "1"]);
+        assert_eq!(results[0].values, [("roic".to_string(), 30.0)]);
+        assert_eq!(results[0].score, Some(30.0));
+
+        let screener = Screener::new().below(Metric::net_debt_to_ebitda(), 2.0);
+        assert!(screener.needs_statements());
+        // No statements, so no value and no pass.
+        assert!(screener.screen([company("1", Some(18.0))]).is_empty());
+    }
+}
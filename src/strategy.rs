@@ -0,0 +1,704 @@
+use std::{
+    collections::{HashMap, HashSet},
+    pin::pin,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use futures::future::{self, Either};
+use tokio::sync::mpsc;
+
+use crate::{
+    api::{
+        orders::OrderStyle,
+        quotecast::{PriceStream, QuoteField, QuoteTick},
+        quotes::Quotes,
+    },
+    client::{Client, ClientError},
+    clock::{Clock, SystemClock},
+    util::{OrderTimeType, TransactionType},
+};
+
+/// One OHLC bar of a product.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candle {
+    pub product_id: String,
+    /// Start of the bar.
+    pub time: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+impl Candle {
+    pub fn from_quotes(product_id: &str, quotes: &Quotes) -> Vec<Candle> {
+        (0..quotes.time.len())
+            .map(|i| Candle {
+                product_id: product_id.to_string(),
+                time: quotes.time[i],
+                open: quotes.open[i],
+                high: quotes.high[i],
+                low: quotes.low[i],
+                close: quotes.close[i],
+            })
+            .collect()
+    }
+
+    fn update(&mut self, price: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+    }
+}
+
+/// Order a strategy wants placed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderIntent {
+    pub product_id: String,
+    pub side: TransactionType,
+    pub size: u64,
+    pub style: OrderStyle,
+}
+
+/// Executed (part of an) order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub product_id: String,
+    pub side: TransactionType,
+    pub size: u64,
+    pub price: f64,
+    pub time: DateTime<Utc>,
+}
+
+/// What a strategy sees and acts through. Orders are queued here and handed
+/// to the broker once the callback returns.
+#[derive(Debug, Default)]
+pub struct Context {
+    now: Option<DateTime<Utc>>,
+    positions: HashMap<String, i64>,
+    orders: Vec<OrderIntent>,
+}
+
+impl Context {
+    /// Time of the event being handled.
+    pub fn now(&self) -> Option<DateTime<Utc>> {
+        self.now
+    }
+
+    /// Signed position built from the fills seen so far.
+    pub fn position(&self, product_id: &str) -> i64 {
+        self.positions.get(product_id).copied().unwrap_or_default()
+    }
+
+    pub fn submit(&mut self, order: OrderIntent) {
+        self.orders.push(order);
+    }
+
+    pub fn buy(&mut self, product_id: impl ToString, size: u64, style: OrderStyle) {
+        self.submit(OrderIntent {
+            product_id: product_id.to_string(),
+            side: TransactionType::Buy,
+            size,
+            style,
+        });
+    }
+
+    pub fn sell(&mut self, product_id: impl ToString, size: u64, style: OrderStyle) {
+        self.submit(OrderIntent {
+            product_id: product_id.to_string(),
+            side: TransactionType::Sell,
+            size,
+            style,
+        });
+    }
+
+    fn apply(&mut self, fill: &Fill) {
+        let position = self.positions.entry(fill.product_id.clone()).or_default();
+        match fill.side {
+            TransactionType::Buy => *position += fill.size as i64,
+            TransactionType::Sell => *position -= fill.size as i64,
+        }
+    }
+}
+
+/// Event driven trading logic. Callbacks are synchronous; orders go through
+/// the [`Context`].
+pub trait Strategy: Send {
+    fn on_candle(&mut self, ctx: &mut Context, candle: &Candle);
+
+    fn on_fill(&mut self, _ctx: &mut Context, _fill: &Fill) {}
+
+    fn on_timer(&mut self, _ctx: &mut Context) {}
+}
+
+/// Where orders go and fills come from.
+#[async_trait]
+pub trait Broker: Send {
+    async fn submit(&mut self, order: OrderIntent) -> Result<(), ClientError>;
+
+    /// Fills since the last call. `candle` is the bar just closed, which
+    /// simulated brokers match pending orders against.
+    async fn fills(&mut self, candle: Option<&Candle>) -> Result<Vec<Fill>, ClientError>;
+}
+
+/// Simulated broker filling orders against the next bar of their product.
+///
+/// Market orders fill at the open. Limits fill when the bar trades through
+/// them, at the open if it gapped past the limit. Stops trigger the same way
+/// and a triggered stop limit rests as a limit from the same bar on. Trailing
+/// stops follow the best open, high or low seen since the order was placed.
+#[derive(Clone, Debug, Default)]
+pub struct PaperBroker {
+    pending: Vec<OrderIntent>,
+    /// Best price seen by each pending order, for trailing stops.
+    marks: Vec<Option<f64>>,
+    /// Starting cash moved by every fill, fees included.
+    pub cash: f64,
+    /// Flat fee charged per fill.
+    pub fee: f64,
+}
+
+impl PaperBroker {
+    pub fn new(cash: f64) -> Self {
+        Self {
+            cash,
+            ..Default::default()
+        }
+    }
+
+    pub fn fee(mut self, fee: f64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    pub fn pending(&self) -> &[OrderIntent] {
+        &self.pending
+    }
+
+    fn fill_price(order: &mut OrderIntent, mark: &mut Option<f64>, candle: &Candle) -> Option<f64> {
+        let buy = order.side == TransactionType::Buy;
+        let touched = |price: f64| {
+            if buy {
+                candle.high >= price
+            } else {
+                candle.low <= price
+            }
+        };
+        // Buys fill at the better of open and limit, stops at the worse.
+        let limit_fill = |limit: f64| {
+            if buy {
+                candle.open.min(limit)
+            } else {
+                candle.open.max(limit)
+            }
+        };
+        let stop_fill = |stop: f64| {
+            if buy {
+                candle.open.max(stop)
+            } else {
+                candle.open.min(stop)
+            }
+        };
+        match order.style {
+            OrderStyle::Market => Some(candle.open),
+            OrderStyle::Limit(limit) => {
+                let crossed = if buy {
+                    candle.low <= limit
+                } else {
+                    candle.high >= limit
+                };
+                crossed.then(|| limit_fill(limit))
+            }
+            OrderStyle::StopLoss(stop) => touched(stop).then(|| stop_fill(stop)),
+            OrderStyle::StopLimit { stop, limit } => {
+                if !touched(stop) {
+                    return None;
+                }
+                order.style = OrderStyle::Limit(limit);
+                Self::fill_price(order, mark, candle)
+            }
+            OrderStyle::TrailingStop { distance } => {
+                // Selling trails the highs, buying back trails the lows.
+                let best = |a: f64, b: f64| if buy { a.min(b) } else { a.max(b) };
+                let open = mark.map_or(candle.open, |x| best(x, candle.open));
+                let stop = if buy {
+                    open + distance
+                } else {
+                    open - distance
+                };
+                if touched(stop) {
+                    return Some(stop_fill(stop));
+                }
+                *mark = Some(best(open, if buy { candle.low } else { candle.high }));
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Broker for PaperBroker {
+    async fn submit(&mut self, order: OrderIntent) -> Result<(), ClientError> {
+        self.pending.push(order);
+        self.marks.push(None);
+        Ok(())
+    }
+
+    async fn fills(&mut self, candle: Option<&Candle>) -> Result<Vec<Fill>, ClientError> {
+        let Some(candle) = candle else {
+            return Ok(Vec::new());
+        };
+        let mut fills = Vec::new();
+        let mut pending = Vec::new();
+        let mut marks = Vec::new();
+        let orders = std::mem::take(&mut self.pending);
+        for (mut order, mut mark) in orders.into_iter().zip(std::mem::take(&mut self.marks)) {
+            let price = if order.product_id == candle.product_id {
+                Self::fill_price(&mut order, &mut mark, candle)
+            } else {
+                None
+            };
+            let Some(price) = price else {
+                pending.push(order);
+                marks.push(mark);
+                continue;
+            };
+            let value = price * order.size as f64;
+            match order.side {
+                TransactionType::Buy => self.cash -= value,
+                TransactionType::Sell => self.cash += value,
+            }
+            self.cash -= self.fee;
+            fills.push(Fill {
+                product_id: order.product_id,
+                side: order.side,
+                size: order.size,
+                price,
+                time: candle.time,
+            });
+        }
+        self.pending = pending;
+        self.marks = marks;
+        Ok(fills)
+    }
+}
+
+/// Broker placing day orders through the account and reading fills back from
+/// today's transactions.
+#[derive(Debug)]
+pub struct LiveBroker {
+    client: Client,
+    seen: HashSet<i32>,
+}
+
+impl LiveBroker {
+    /// Transactions already booked today are not reported as fills.
+    pub async fn new(client: &Client) -> Result<Self, ClientError> {
+        let today = client.now().date_naive();
+        let seen = client
+            .transactions(today, today)
+            .await?
+            .iter()
+            .map(|x| x.inner.id)
+            .collect();
+        Ok(Self {
+            client: client.clone(),
+            seen,
+        })
+    }
+}
+
+#[async_trait]
+impl Broker for LiveBroker {
+    async fn submit(&mut self, order: OrderIntent) -> Result<(), ClientError> {
+        let builder = self
+            .client
+            .create_order()
+            .product_id(&order.product_id)
+            .transaction_type(order.side)
+            .size(order.size)
+            .time_type(OrderTimeType::Day)
+            .style(order.style);
+        builder
+            .build()
+            .map_err(|err| ClientError::Descripted(err.to_string()))?
+            .place()
+            .await?;
+        Ok(())
+    }
+
+    async fn fills(&mut self, _candle: Option<&Candle>) -> Result<Vec<Fill>, ClientError> {
+        let today = self.client.now().date_naive();
+        let transactions = self.client.transactions(today, today).await?;
+        let mut fills = Vec::new();
+        for tx in transactions.iter().map(|x| &x.inner) {
+            if !self.seen.insert(tx.id) {
+                continue;
+            }
+            fills.push(Fill {
+                product_id: tx.product_id.to_string(),
+                side: tx.transaction_type,
+                size: u64::from(tx.quantity.unsigned_abs()),
+                price: tx.price,
+                time: tx.date.with_timezone(&Utc),
+            });
+        }
+        Ok(fills)
+    }
+}
+
+/// Ticks [`Runner::run_live`] buffers while the runner is busy.
+const TICK_BUFFER: usize = 1024;
+
+/// Drives a [`Strategy`] with candles and timers, passing its orders to a
+/// [`Broker`].
+///
+/// Fills are collected before the strategy sees a candle, so orders placed on
+/// one bar can at the earliest fill on the next.
+#[derive(Debug)]
+pub struct Runner<S, B> {
+    pub strategy: S,
+    pub broker: B,
+    ctx: Context,
+    clock: Arc<dyn Clock>,
+}
+
+impl<S: Strategy, B: Broker> Runner<S, B> {
+    pub fn new(strategy: S, broker: B) -> Self {
+        Self {
+            strategy,
+            broker,
+            ctx: Context::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Clock used for timer events in [`Runner::run_live`].
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.ctx
+    }
+
+    async fn collect_fills(&mut self, candle: Option<&Candle>) -> Result<(), ClientError> {
+        for fill in self.broker.fills(candle).await? {
+            self.ctx.apply(&fill);
+            self.strategy.on_fill(&mut self.ctx, &fill);
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ClientError> {
+        for order in std::mem::take(&mut self.ctx.orders) {
+            self.broker.submit(order).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn on_candle(&mut self, candle: &Candle) -> Result<(), ClientError> {
+        self.ctx.now = Some(candle.time);
+        self.collect_fills(Some(candle)).await?;
+        self.strategy.on_candle(&mut self.ctx, candle);
+        self.flush().await
+    }
+
+    pub async fn on_timer(&mut self, now: DateTime<Utc>) -> Result<(), ClientError> {
+        self.ctx.now = Some(now);
+        self.collect_fills(None).await?;
+        self.strategy.on_timer(&mut self.ctx);
+        self.flush().await
+    }
+
+    /// Replays `candles` in order.
+    pub async fn backtest(&mut self, candles: &[Candle]) -> Result<(), ClientError> {
+        for candle in candles {
+            self.on_candle(candle).await?;
+        }
+        Ok(())
+    }
+
+    /// Builds `bar` long candles from last price ticks and fires the timer
+    /// every `timer`, until the stream ends.
+    ///
+    /// `products` maps vwd ids to the product ids orders are placed with;
+    /// ticks of other instruments are ignored.
+    ///
+    /// The stream is driven next to the runner and hands ticks over through
+    /// a channel, so a firing timer never cancels a poll halfway and loses
+    /// what it already received.
+    pub async fn run_live(
+        &mut self,
+        stream: &mut (dyn PriceStream + '_),
+        products: &HashMap<String, String>,
+        bar: Duration,
+        timer: std::time::Duration,
+    ) -> Result<(), ClientError> {
+        let (tx, mut ticks) = mpsc::channel(TICK_BUFFER);
+        let feed = pin!(async move {
+            while let Some(tick) = stream.next_tick().await {
+                if tx.send(tick).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let run = pin!(self.run_ticks(&mut ticks, products, bar, timer));
+        match future::select(feed, run).await {
+            // The sender is gone, the runner ends once the buffer is drained.
+            Either::Left(((), run)) => run.await,
+            Either::Right((res, _)) => res,
+        }
+    }
+
+    async fn run_ticks(
+        &mut self,
+        ticks: &mut mpsc::Receiver<Result<QuoteTick, ClientError>>,
+        products: &HashMap<String, String>,
+        bar: Duration,
+        timer: std::time::Duration,
+    ) -> Result<(), ClientError> {
+        let mut bars: HashMap<String, Candle> = HashMap::new();
+        let mut next_timer = tokio::time::Instant::now() + timer;
+        loop {
+            let wait = next_timer.saturating_duration_since(tokio::time::Instant::now());
+            // Receiving is cancel safe, a tick is either taken or left queued.
+            match tokio::time::timeout(wait, ticks.recv()).await {
+                Ok(None) => return Ok(()),
+                Ok(Some(tick)) => {
+                    let tick = tick?;
+                    if tick.field != QuoteField::LastPrice {
+                        continue;
+                    }
+                    let (Some(product_id), Some(price)) =
+                        (products.get(&tick.vwd_id), tick.value.as_f64())
+                    else {
+                        continue;
+                    };
+                    let start = tick.received_at.duration_trunc(bar).map_err(|err| {
+                        ClientError::Descripted(format!("invalid bar length: {err}"))
+                    })?;
+                    match bars.get_mut(product_id) {
+                        Some(candle) if candle.time == start => candle.update(price),
+                        _ => {
+                            let candle = Candle {
+                                product_id: product_id.clone(),
+                                time: start,
+                                open: price,
+                                high: price,
+                                low: price,
+                                close: price,
+                            };
+                            if let Some(done) = bars.insert(product_id.clone(), candle) {
+                                self.on_candle(&done).await?;
+                            }
+                        }
+                    }
+                }
+                Err(_) => {
+                    let now = self.clock.now();
+                    let done = bars
+                        .iter()
+                        .filter(|(_, candle)| candle.time + bar <= now)
+                        .map(|(id, _)| id.clone())
+                        .collect::<Vec<_>>();
+                    for id in done {
+                        if let Some(candle) = bars.remove(&id) {
+                            self.on_candle(&candle).await?;
+                        }
+                    }
+                    self.on_timer(now).await?;
+                    next_timer = tokio::time::Instant::now() + timer;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use reqwest::Method;
+    use serde_json::json;
+
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::{
+        api::quotecast::TickValue, client::ClientStatus, clock::ManualClock,
+        transport::MockTransport,
+    };
+
+    /// Buys below 10 with a limit, sells with a market order once long.
+    #[derive(Default)]
+    struct Dip {
+        entered: bool,
+        fills: usize,
+    }
+
+    impl Strategy for Dip {
+        fn on_candle(&mut self, ctx: &mut Context, candle: &Candle) {
+            if !self.entered && candle.close < 10.0 {
+                self.entered = true;
+                ctx.buy(&candle.product_id, 5, OrderStyle::Limit(9.0));
+            } else if ctx.position(&candle.product_id) > 0 {
+                ctx.sell(&candle.product_id, 5, OrderStyle::Market);
+            }
+        }
+
+        fn on_fill(&mut self, _ctx: &mut Context, _fill: &Fill) {
+            self.fills += 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn paper_backtest() {
+        let bar = |day: u32, open: f64, high: f64, low: f64, close: f64| Candle {
+            product_id: "1".to_string(),
+            time: Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+            open,
+            high,
+            low,
+            close,
+        };
+        let candles = [
+            bar(1, 10.0, 10.5, 9.5, 9.8),
+            // Limit at 9 not reached.
+            bar(2, 9.8, 10.0, 9.2, 9.5),
+            // Gaps below the limit, fills at the open.
+            bar(3, 8.5, 9.0, 8.0, 8.8),
+            bar(4, 11.0, 12.0, 10.5, 11.5),
+        ];
+        let mut runner = Runner::new(Dip::default(), PaperBroker::new(100.0).fee(1.0));
+        runner.backtest(&candles).await.unwrap();
+
+        assert_eq!(runner.strategy.fills, 2);
+        assert_eq!(runner.context().position("1"), 0);
+        assert_eq!(runner.broker.cash, 100.0 - 5.0 * 8.5 + 5.0 * 11.0 - 2.0);
+        assert!(runner.broker.pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn paper_trailing_stop() {
+        let bar = |day: u32, open: f64, high: f64, low: f64| Candle {
+            product_id: "1".to_string(),
+            time: Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+            open,
+            high,
+            low,
+            close: open,
+        };
+        let mut broker = PaperBroker::new(0.0);
+        broker
+            .submit(OrderIntent {
+                product_id: "1".to_string(),
+                side: TransactionType::Sell,
+                size: 1,
+                style: OrderStyle::TrailingStop { distance: 1.0 },
+            })
+            .await
+            .unwrap();
+        // Stop at 9, then raised to 11 by the high.
+        let fills = broker.fills(Some(&bar(1, 10.0, 12.0, 10.0))).await.unwrap();
+        assert!(fills.is_empty());
+        let fills = broker.fills(Some(&bar(2, 11.8, 12.0, 10.5))).await.unwrap();
+        assert_eq!(fills[0].price, 11.0);
+        assert!(broker.pending().is_empty());
+    }
+
+    /// Takes each tick before waiting, like a long poll that already has
+    /// the server's answer, so cancelling it loses the tick.
+    struct SlowTicks(VecDeque<QuoteTick>);
+
+    #[async_trait]
+    impl PriceStream for SlowTicks {
+        async fn next_tick(&mut self) -> Option<Result<QuoteTick, ClientError>> {
+            let tick = self.0.pop_front()?;
+            tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+            Some(Ok(tick))
+        }
+    }
+
+    #[derive(Default)]
+    struct Closes(Vec<f64>);
+
+    impl Strategy for Closes {
+        fn on_candle(&mut self, _ctx: &mut Context, candle: &Candle) {
+            self.0.push(candle.close);
+        }
+    }
+
+    #[tokio::test]
+    async fn live_timer_keeps_ticks() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 10, 0, 0).unwrap();
+        let ticks = (0..3)
+            .map(|i| QuoteTick {
+                vwd_id: "360015751".to_string(),
+                field: QuoteField::LastPrice,
+                value: TickValue::Number(f64::from(i + 1)),
+                received_at: start + Duration::minutes(i.into()),
+            })
+            .collect();
+        let products = HashMap::from([("360015751".to_string(), "1".to_string())]);
+        let clock = Arc::new(ManualClock::new(start + Duration::days(1)));
+        let mut runner = Runner::new(Closes::default(), PaperBroker::new(100.0)).clock(clock);
+        runner
+            .run_live(
+                &mut SlowTicks(ticks),
+                &products,
+                Duration::minutes(1),
+                std::time::Duration::from_millis(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(runner.strategy.0, [1.0, 2.0]);
+    }
+
+    #[tokio::test]
+    async fn live_broker_confirms_orders() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        let mock = Arc::new(MockTransport::new());
+        mock.json(
+            Method::POST,
+            "v5/checkOrder",
+            &json!({ "data": { "confirmationId": "c0ffee", "freeSpaceNew": 90.0 } }),
+        )
+        .json(
+            Method::POST,
+            "v5/order/",
+            &json!({ "data": { "orderId": "6126ef1a" } }),
+        );
+        client.set_transport(mock.clone());
+        {
+            let mut inner = client.inner.lock().unwrap();
+            inner.status = ClientStatus::Authorized;
+            inner.session_id = "abc".to_string();
+            inner.account_config.trading_url = "https://trader.degiro.nl/trading/secure/".into();
+        }
+        let mut broker = LiveBroker {
+            client,
+            seen: HashSet::new(),
+        };
+        broker
+            .submit(OrderIntent {
+                product_id: "1".to_string(),
+                side: TransactionType::Buy,
+                size: 1,
+                style: OrderStyle::Limit(9.0),
+            })
+            .await
+            .unwrap();
+
+        let paths = mock
+            .requests()
+            .into_iter()
+            .map(|x| x.url.path().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].contains("v5/checkOrder"));
+        assert!(paths[1].contains("v5/order/c0ffee"));
+    }
+}
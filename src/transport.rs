@@ -0,0 +1,181 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use reqwest::{header::CONTENT_TYPE, Method, Request, RequestBuilder, Response, Url};
+
+use crate::client::{Client, ClientError};
+
+/// Sends the requests built by the client.
+///
+/// Everything goes through the client's transport after session parameters
+/// and rate limiting are applied, so a [`MockTransport`] can stand in for
+/// DEGIRO in tests of code that takes a [`Client`].
+#[async_trait]
+pub trait Transport: fmt::Debug + Send + Sync {
+    async fn execute(&self, req: Request) -> Result<Response, ClientError>;
+}
+
+/// Sends over the network with a `reqwest` client.
+#[derive(Clone, Debug)]
+pub struct HttpTransport(pub reqwest::Client);
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn execute(&self, req: Request) -> Result<Response, ClientError> {
+        Ok(self.0.execute(req).await?)
+    }
+}
+
+/// Request seen by a [`MockTransport`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub body: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug)]
+struct MockRoute {
+    method: Method,
+    path: String,
+    status: u16,
+    body: String,
+}
+
+/// Answers with canned bodies by method and url path, recording every
+/// request. The last route added wins when several match; a request no
+/// route matches fails with [`ClientError::Descripted`].
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    routes: Mutex<Vec<MockRoute>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers requests whose url path contains `path` with `status` and
+    /// `body`.
+    pub fn on(
+        &self,
+        method: Method,
+        path: impl Into<String>,
+        status: u16,
+        body: impl Into<String>,
+    ) -> &Self {
+        self.routes.lock().unwrap().push(MockRoute {
+            method,
+            path: path.into(),
+            status,
+            body: body.into(),
+        });
+        self
+    }
+
+    /// Answers with `200` and `body` serialized to json.
+    pub fn json(&self, method: Method, path: impl Into<String>, body: &serde_json::Value) -> &Self {
+        self.on(method, path, 200, body.to_string())
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn execute(&self, req: Request) -> Result<Response, ClientError> {
+        let recorded = RecordedRequest {
+            method: req.method().clone(),
+            url: req.url().clone(),
+            body: req.body().and_then(|x| x.as_bytes()).map(<[u8]>::to_vec),
+        };
+        let route = self
+            .routes
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|x| x.method == recorded.method && recorded.url.path().contains(&x.path))
+            .cloned();
+        let url = recorded.url.clone();
+        self.requests.lock().unwrap().push(recorded);
+        let Some(route) = route else {
+            return Err(ClientError::Descripted(format!(
+                "no mock for {} {url}",
+                req.method()
+            )));
+        };
+        let res = http::Response::builder()
+            .status(route.status)
+            .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(route.body)
+            .map_err(|err| ClientError::Descripted(err.to_string()))?;
+        Ok(Response::from(res))
+    }
+}
+
+impl Client {
+    pub fn set_transport(&self, transport: Arc<dyn Transport>) {
+        self.inner.lock().unwrap().transport = transport;
+    }
+
+    /// Builds `req` and hands it to the transport.
+    pub(crate) async fn dispatch(&self, req: RequestBuilder) -> Result<Response, ClientError> {
+        let req = req.build()?;
+        let transport = self.inner.lock().unwrap().transport.clone();
+        transport.execute(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::client::ClientStatus;
+
+    #[tokio::test]
+    async fn mock_transport_serves_fixtures() {
+        let client = Client::new("user", "pass", reqwest::Client::new(), Default::default());
+        let mock = Arc::new(MockTransport::new());
+        mock.json(
+            Method::GET,
+            "v5/update/",
+            &json!({ "portfolio": { "value": [
+                { "name": "positionrow", "value": [
+                    { "name": "id", "value": "332111" },
+                    { "name": "positionType", "value": "PRODUCT" },
+                    { "name": "size", "value": 10.0 },
+                    { "name": "price", "value": 12.0 },
+                    { "name": "value", "value": 120.0 },
+                    { "name": "plBase", "value": { "EUR": -100.0 } },
+                ]},
+            ]}}),
+        );
+        client.set_transport(mock.clone());
+        {
+            let mut inner = client.inner.lock().unwrap();
+            inner.status = ClientStatus::Authorized;
+            inner.session_id = "abc".to_string();
+            inner.account_config.trading_url = "https://trader.degiro.nl/trading/secure/".into();
+        }
+
+        let portfolio = client.portfolio().await.unwrap();
+        assert_eq!(portfolio.len(), 1);
+        assert_eq!(portfolio.0[0].inner.size, 10.0);
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].url.path().ends_with(";jsessionid=abc"));
+
+        assert!(matches!(
+            client.account_info().await,
+            Err(ClientError::Descripted(_))
+        ));
+    }
+}
@@ -116,6 +116,50 @@ impl std::ops::Add<Period> for chrono::NaiveDate {
     }
 }
 
+impl std::ops::Sub<Period> for chrono::DateTime<chrono::Utc> {
+    type Output = chrono::DateTime<chrono::Utc>;
+
+    /// Inverse of adding `rhs`, except calendar periods clamp to the end of a
+    /// shorter month, so `(Mar 31 + P1M) - P1M` is Mar 30.
+    fn sub(self, rhs: Period) -> Self::Output {
+        match rhs {
+            Period::PT1S => self - chrono::Duration::seconds(1),
+            Period::PT1M => self - chrono::Duration::minutes(1),
+            Period::PT1H => self - chrono::Duration::hours(1),
+            Period::P1D => self - chrono::Duration::days(1),
+            Period::P1W => self - chrono::Duration::weeks(1),
+            Period::P1M => chronoutil::delta::shift_months(self, -1),
+            Period::P3M => chronoutil::delta::shift_months(self, -3),
+            Period::P6M => chronoutil::delta::shift_months(self, -6),
+            Period::P1Y => chronoutil::delta::shift_years(self, -1),
+            Period::P3Y => chronoutil::delta::shift_years(self, -3),
+            Period::P5Y => chronoutil::delta::shift_years(self, -5),
+            Period::P50Y => chronoutil::delta::shift_years(self, -50),
+        }
+    }
+}
+
+impl std::ops::Sub<Period> for chrono::NaiveDate {
+    type Output = chrono::NaiveDate;
+
+    fn sub(self, rhs: Period) -> Self::Output {
+        match rhs {
+            Period::PT1S => self - chrono::Duration::seconds(1),
+            Period::PT1M => self - chrono::Duration::minutes(1),
+            Period::PT1H => self - chrono::Duration::hours(1),
+            Period::P1D => self - chrono::Duration::days(1),
+            Period::P1W => self - chrono::Duration::weeks(1),
+            Period::P1M => chronoutil::delta::shift_months(self, -1),
+            Period::P3M => chronoutil::delta::shift_months(self, -3),
+            Period::P6M => chronoutil::delta::shift_months(self, -6),
+            Period::P1Y => chronoutil::delta::shift_years(self, -1),
+            Period::P3Y => chronoutil::delta::shift_years(self, -3),
+            Period::P5Y => chronoutil::delta::shift_years(self, -5),
+            Period::P50Y => chronoutil::delta::shift_years(self, -50),
+        }
+    }
+}
+
 #[derive(
     Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, EnumString, Clone, Copy, Display,
 )]
@@ -131,18 +175,48 @@ pub enum OrderType {
     StandardSize,
 }
 
-impl From<OrderType> for u8 {
-    fn from(value: OrderType) -> Self {
-        match value {
+impl OrderType {
+    /// Numeric id used by the trading API (`orderType`, `orderTypeId`).
+    pub fn id(self) -> u8 {
+        match self {
             OrderType::Limit => 0,
             OrderType::StopLimit => 1,
             OrderType::Market => 2,
             OrderType::StopLoss => 3,
-            _ => unimplemented!(),
+            OrderType::TrailingStop => 13,
+            OrderType::StandardAmount => 14,
+            OrderType::StandardSize => 15,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(OrderType::Limit),
+            1 => Some(OrderType::StopLimit),
+            2 => Some(OrderType::Market),
+            3 => Some(OrderType::StopLoss),
+            13 => Some(OrderType::TrailingStop),
+            14 => Some(OrderType::StandardAmount),
+            15 => Some(OrderType::StandardSize),
+            _ => None,
         }
     }
 }
 
+impl From<OrderType> for u8 {
+    fn from(value: OrderType) -> Self {
+        value.id()
+    }
+}
+
+impl TryFrom<u8> for OrderType {
+    type Error = u8;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        OrderType::from_id(id).ok_or(id)
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct AllowedOrderTypes(HashSet<OrderType>);
@@ -195,15 +269,62 @@ pub enum OrderTimeType {
     Gtc,
 }
 
-impl From<OrderTimeType> for u8 {
-    fn from(value: OrderTimeType) -> Self {
-        match value {
+impl OrderTimeType {
+    /// Numeric id used by the trading API (`timeType`, `orderTimeTypeId`).
+    pub fn id(self) -> u8 {
+        match self {
             OrderTimeType::Day => 1,
             OrderTimeType::Gtc => 3,
         }
     }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(OrderTimeType::Day),
+            3 => Some(OrderTimeType::Gtc),
+            _ => None,
+        }
+    }
+}
+
+impl From<OrderTimeType> for u8 {
+    fn from(value: OrderTimeType) -> Self {
+        value.id()
+    }
 }
 
+impl TryFrom<u8> for OrderTimeType {
+    type Error = u8;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        OrderTimeType::from_id(id).ok_or(id)
+    }
+}
+
+/// Defines a serde module (de)serializing an enum as its wire id, for use
+/// with `#[serde(with = "...")]`.
+macro_rules! wire_id {
+    ($name:ident, $ty:ty) => {
+        pub mod $name {
+            use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+            pub fn serialize<S: Serializer>(x: &$ty, s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_u8(x.id())
+            }
+
+            pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<$ty, D::Error> {
+                let id = u8::deserialize(d)?;
+                <$ty>::from_id(id).ok_or_else(|| {
+                    D::Error::custom(format!("unknown {} id: {id}", stringify!($name)))
+                })
+            }
+        }
+    };
+}
+
+wire_id!(order_type_id, super::OrderType);
+wire_id!(order_time_type_id, super::OrderTimeType);
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct OrderTimeTypes(HashSet<OrderTimeType>);
 
@@ -222,10 +343,41 @@ impl OrderTimeTypes {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum ProductType {
     Stock,
+    Bond,
+    Future,
+    Option,
+    Fund,
+    LeveragedProduct,
+    Etf,
+    Cfd,
+    Warrant,
+}
+
+impl ProductType {
+    const IDS: [(ProductType, i32); 9] = [
+        (Self::Stock, 1),
+        (Self::Bond, 2),
+        (Self::Future, 7),
+        (Self::Option, 8),
+        (Self::Fund, 13),
+        (Self::LeveragedProduct, 14),
+        (Self::Etf, 131),
+        (Self::Cfd, 535),
+        (Self::Warrant, 536),
+    ];
+
+    /// DEGIRO's `productTypeId`.
+    pub fn id(self) -> i32 {
+        Self::IDS.iter().find(|(x, _)| *x == self).unwrap().1
+    }
+
+    pub fn from_id(id: i32) -> Option<Self> {
+        Self::IDS.iter().find(|(_, x)| *x == id).map(|(x, _)| *x)
+    }
 }
 
 #[derive(
@@ -321,3 +473,136 @@ impl fmt::Display for Exchange {
         }
     }
 }
+
+/// Rounds `price` onto the `tick` grid, `up` selects the direction.
+pub fn round_to_tick(price: f64, tick: f64, up: bool) -> f64 {
+    if tick <= 0.0 {
+        return price;
+    }
+    // Guard against 10.02 / 0.01 = 1001.9999999.
+    let steps = price / tick;
+    let steps = if (steps - steps.round()).abs() < 1e-9 {
+        steps.round()
+    } else if up {
+        steps.ceil()
+    } else {
+        steps.floor()
+    };
+    let factor = (0..10)
+        .map(|d| 10f64.powi(d))
+        .find(|f| ((tick * f).round() - tick * f).abs() < 1e-9)
+        .unwrap_or(1e10);
+    (steps * tick * factor).round() / factor
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+    use proptest::prelude::*;
+
+    use super::{Period, ProductType};
+
+    const FIXED: [Period; 5] = [
+        Period::PT1S,
+        Period::PT1M,
+        Period::PT1H,
+        Period::P1D,
+        Period::P1W,
+    ];
+
+    const CALENDAR: [Period; 7] = [
+        Period::P1M,
+        Period::P3M,
+        Period::P6M,
+        Period::P1Y,
+        Period::P3Y,
+        Period::P5Y,
+        Period::P50Y,
+    ];
+
+    fn date(days: i64) -> NaiveDate {
+        NaiveDate::from_ymd_opt(1990, 1, 1).unwrap() + Duration::days(days)
+    }
+
+    fn datetime(days: i64, secs: i64) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&date(days).and_hms_opt(0, 0, 0).unwrap()) + Duration::seconds(secs)
+    }
+
+    #[test]
+    fn product_type_ids() {
+        for (x, id) in ProductType::IDS {
+            assert_eq!(x.id(), id);
+            assert_eq!(ProductType::from_id(id), Some(x));
+        }
+        assert_eq!(ProductType::from_id(0), None);
+    }
+
+    #[test]
+    fn month_end_clamps() {
+        let d = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        assert_eq!(
+            d - Period::P1M,
+            NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()
+        );
+        assert_eq!(
+            (d - Period::P1M) + Period::P1M,
+            NaiveDate::from_ymd_opt(2024, 3, 29).unwrap()
+        );
+        let leap = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(
+            leap + Period::P1Y,
+            NaiveDate::from_ymd_opt(2025, 2, 28).unwrap()
+        );
+    }
+
+    proptest! {
+        // Utc has no DST, so a day is always 24h and fixed periods are exact.
+        #[test]
+        fn fixed_periods_are_symmetric(
+            days in 0..20_000i64,
+            secs in 0..86_400i64,
+            p in prop::sample::select(FIXED.to_vec()),
+        ) {
+            let t = datetime(days, secs);
+            prop_assert_eq!((t + p) - p, t);
+            prop_assert_eq!((t - p) + p, t);
+            prop_assert_eq!((t + p) - t, p.to_duration());
+        }
+
+        #[test]
+        fn calendar_periods_are_symmetric_before_the_29th(
+            days in 0..20_000i64,
+            secs in 0..86_400i64,
+            p in prop::sample::select(CALENDAR.to_vec()),
+        ) {
+            let t = datetime(days, secs);
+            prop_assume!(t.day() <= 28);
+            prop_assert_eq!((t + p) - p, t);
+            prop_assert_eq!((t - p) + p, t);
+            prop_assert_eq!((date(days) + p) - p, date(days));
+        }
+
+        #[test]
+        fn calendar_periods_clamp_at_month_end(
+            days in 0..20_000i64,
+            p in prop::sample::select(CALENDAR.to_vec()),
+        ) {
+            let d = date(days);
+            let there = d + p;
+            let back = there - p;
+            prop_assert!(there.day() <= d.day());
+            prop_assert_eq!((back.year(), back.month()), (d.year(), d.month()));
+            prop_assert!(back <= d && d.day() - back.day() <= 3);
+            let months = (there.year() - d.year()) * 12 + there.month() as i32 - d.month() as i32;
+            prop_assert_eq!(months, match p {
+                Period::P1M => 1,
+                Period::P3M => 3,
+                Period::P6M => 6,
+                Period::P1Y => 12,
+                Period::P3Y => 36,
+                Period::P5Y => 60,
+                _ => 600,
+            });
+        }
+    }
+}